@@ -240,6 +240,9 @@ wasmtime_option_group! {
         pub log_to_files: Option<bool>,
         /// Enable coredump generation to this file after a WebAssembly trap.
         pub coredump: Option<String>,
+        /// Write a snapshot of the GC heap to this file, in V8's
+        /// `.heapsnapshot` format, once the program finishes running.
+        pub gc_heap_snapshot: Option<String>,
     }
 
     enum Debug {
@@ -382,6 +385,16 @@ wasmtime_option_group! {
         pub nn: Option<bool>,
         /// Enable support for WASI threading imports (experimental). Implies preview2=false.
         pub threads: Option<bool>,
+        /// Maximum number of wasi-threads which can run at the same time,
+        /// beyond which spawning a thread fails.
+        pub max_threads: Option<usize>,
+        /// Number of idle OS threads kept around to run the next spawned
+        /// wasi-threads instead of creating a new OS thread for each.
+        pub thread_pool: Option<usize>,
+        /// Run wasi-threads as green threads multiplexed on this many OS
+        /// threads, suspending threads which wait on shared memory instead of
+        /// blocking their OS thread.
+        pub green_threads: Option<usize>,
         /// Enable support for WASI HTTP imports
         pub http: Option<bool>,
         /// Number of distinct write calls to the outgoing body's output-stream
@@ -391,6 +404,47 @@ wasmtime_option_group! {
         /// Maximum size allowed in a write call to the outgoing body's output-stream.
         /// Default: 1024 * 1024.
         pub http_outgoing_body_chunk_size: Option<usize>,
+        /// Trust the certificates in the given PEM file for outgoing HTTPS
+        /// requests, in addition to the built-in roots.
+        pub http_tls_root_cert: Vec<String>,
+        /// Present the certificate chain in the given PEM file to HTTPS servers
+        /// requesting client authentication. Requires `http-tls-client-key`.
+        pub http_tls_client_cert: Option<String>,
+        /// The private key, in a PEM file, of `http-tls-client-cert`.
+        pub http_tls_client_key: Option<String>,
+        /// Use `NAME` for SNI and certificate verification when connecting to
+        /// `HOST` over HTTPS, given as `HOST=NAME`.
+        pub http_tls_server_name: Vec<KeyValuePair>,
+        /// Don't verify the certificates of HTTPS servers. Only use this for
+        /// testing.
+        pub http_tls_insecure_skip_verify: Option<bool>,
+        /// Only allow outgoing HTTP requests to authorities matching the
+        /// given pattern, such as `example.com`, `*.example.com` or
+        /// `example.com:8080`.
+        pub http_allow_authority: Vec<String>,
+        /// Only allow outgoing HTTP requests to the given port.
+        pub http_allow_port: Vec<u16>,
+        /// Only allow outgoing HTTP requests using the given scheme, `http` or
+        /// `https`.
+        pub http_allow_scheme: Vec<String>,
+        /// Maximum time to wait when connecting for an outgoing HTTP request,
+        /// regardless of the timeout requested by the guest (1, 2s, 100ms,
+        /// etc).
+        pub http_max_connect_timeout: Option<Duration>,
+        /// Maximum time to wait for the first byte of the response to an
+        /// outgoing HTTP request, regardless of the timeout requested by the
+        /// guest.
+        pub http_max_first_byte_timeout: Option<Duration>,
+        /// Maximum time to wait between the chunks of the response body of an
+        /// outgoing HTTP request, regardless of the timeout requested by the
+        /// guest.
+        pub http_max_between_bytes_timeout: Option<Duration>,
+        /// Maximum size, in bytes, of the response body of an outgoing HTTP
+        /// request.
+        pub http_max_response_body_size: Option<u64>,
+        /// Maximum number of outgoing HTTP requests in flight at once. With
+        /// `wasmtime serve` this applies to all instances together.
+        pub http_max_concurrent_requests: Option<usize>,
         /// Enable support for WASI config imports (experimental)
         pub config: Option<bool>,
         /// Enable support for WASI key-value imports (experimental)
@@ -412,6 +466,12 @@ wasmtime_option_group! {
         /// available is dependent on the backends implemented in the
         /// `wasmtime_wasi_nn` crate.
         pub nn_graph: Vec<WasiNnGraph>,
+        /// Unload the least recently used wasi-nn graphs once the loaded ones
+        /// exceed this many bytes, as estimated from the size of their files.
+        ///
+        /// Only applies to `wasmtime serve`, which loads graphs on first use
+        /// and loads them again when their files change.
+        pub nn_graph_memory_budget: Option<u64>,
         /// Flag for WASI preview2 to inherit the host's network within the
         /// guest so it has full access to all addresses/ports/etc.
         pub inherit_network: Option<bool>,
@@ -431,8 +491,20 @@ wasmtime_option_group! {
         pub inherit_env: Option<bool>,
         /// Pass a wasi config variable to the program.
         pub config_var: Vec<KeyValuePair>,
+        /// Read wasi config variables from this TOML or JSON file. `wasmtime
+        /// serve` reads it again when it changes. Variables given with
+        /// `config-var` take precedence.
+        pub config_file: Option<String>,
+        /// Pass the host's environment variables starting with this prefix
+        /// as wasi config variables, with the prefix removed from their
+        /// names. They take precedence over `config-file` but not
+        /// `config-var`.
+        pub config_env_prefix: Option<String>,
         /// Preset data for the In-Memory provider of WASI key-value API.
         pub keyvalue_in_memory_data: Vec<KeyValuePair>,
+        /// Persist WASI key-value buckets in the given host directory, with one
+        /// subdirectory per bucket, instead of keeping them in memory.
+        pub keyvalue_dir: Option<String>,
     }
 
     enum Wasi {
@@ -300,6 +300,17 @@ impl WasmtimeOptionValue for String {
     }
 }
 
+impl WasmtimeOptionValue for u16 {
+    const VAL_HELP: &'static str = "=N";
+    fn parse(val: Option<&str>) -> Result<Self> {
+        let val = String::parse(val)?.replace(IGNORED_NUMBER_CHARS, "");
+        match val.strip_prefix("0x") {
+            Some(hex) => Ok(u16::from_str_radix(hex, 16)?),
+            None => Ok(val.parse()?),
+        }
+    }
+}
+
 impl WasmtimeOptionValue for u32 {
     const VAL_HELP: &'static str = "=N";
     fn parse(val: Option<&str>) -> Result<Self> {
@@ -449,4 +460,10 @@ mod tests {
         assert!(<u32 as WasmtimeOptionValue>::parse(Some("123")).is_ok_and(|v| v == 123));
         assert!(<u32 as WasmtimeOptionValue>::parse(Some("1_2_3")).is_ok_and(|v| v == 123));
     }
+
+    #[test]
+    fn ports_out_of_range() {
+        assert!(<u16 as WasmtimeOptionValue>::parse(Some("65535")).is_ok_and(|v| v == 65535));
+        assert!(<u16 as WasmtimeOptionValue>::parse(Some("65536")).is_err());
+    }
 }
@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use test_programs::proxy;
+use test_programs::wasi::http::types::{
+    Fields, IncomingRequest, OutgoingResponse, ResponseOutparam,
+};
+
+struct T;
+
+proxy::export!(T);
+
+/// The number of requests handled by this instance.
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+impl proxy::exports::wasi::http::incoming_handler::Guest for T {
+    fn handle(request: IncomingRequest, outparam: ResponseOutparam) {
+        let requests = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if request.path_with_query().as_deref() == Some("/trap") {
+            panic!("trapping after {requests} requests");
+        }
+
+        let fields = Fields::new();
+        fields
+            .set(
+                &"requests".to_string(),
+                &[requests.to_string().into_bytes()],
+            )
+            .unwrap();
+        let resp = OutgoingResponse::new(fields);
+        ResponseOutparam::set(outparam, Ok(resp));
+    }
+}
+
+fn main() {}
@@ -1,4 +1,5 @@
 use test_programs::wasi::keyvalue::{atomics, batch, store};
+use test_programs::wasmtime::wasi_keyvalue::cas;
 
 fn main() {
     let bucket = store::open("").unwrap();
@@ -41,4 +42,35 @@ fn main() {
             None
         ]
     );
+
+    // A CAS operation succeeds if nothing changed in the meantime.
+    let cas = cas::Cas::new(&bucket, "b1").unwrap();
+    assert_eq!(cas.current().unwrap(), Some("v1".as_bytes().to_vec()));
+    cas::swap(cas, "v2".as_bytes()).unwrap();
+    assert_eq!(bucket.get("b1").unwrap(), Some("v2".as_bytes().to_vec()));
+
+    // And fails with a fresh CAS operation to retry with otherwise.
+    let cas = cas::Cas::new(&bucket, "b1").unwrap();
+    bucket.set("b1", "v3".as_bytes()).unwrap();
+    match cas::swap(cas, "v4".as_bytes()) {
+        Err(cas::CasError::CasFailed(cas)) => {
+            assert_eq!(cas.current().unwrap(), Some("v3".as_bytes().to_vec()));
+            cas::swap(cas, "v1".as_bytes()).unwrap();
+        }
+        _ => panic!("CAS of a changed value should fail"),
+    }
+    assert_eq!(bucket.get("b1").unwrap(), Some("v1".as_bytes().to_vec()));
+
+    // Listing keys page by page visits every key once, in order.
+    let mut keys = Vec::new();
+    let mut cursor = None;
+    loop {
+        let resp = bucket.list_keys(cursor).unwrap();
+        keys.extend(resp.keys);
+        cursor = resp.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(keys, ["atomics_key", "b1"]);
 }
@@ -0,0 +1,23 @@
+use test_programs::keyvalue_watcher::exports::wasi::keyvalue::watcher::Guest;
+use test_programs::wasi::keyvalue::store::Bucket;
+
+struct T;
+
+test_programs::keyvalue_watcher::export!(T);
+
+/// Mirrors every change to a key `k` into the key `seen-k`.
+impl Guest for T {
+    fn on_set(bucket: Bucket, key: String, value: Vec<u8>) {
+        if !key.starts_with("seen-") {
+            bucket.set(&format!("seen-{key}"), &value).unwrap();
+        }
+    }
+
+    fn on_delete(bucket: Bucket, key: String) {
+        if !key.starts_with("seen-") {
+            bucket.delete(&format!("seen-{key}")).unwrap();
+        }
+    }
+}
+
+fn main() {}
@@ -12,6 +12,7 @@ wit_bindgen::generate!({
             include wasi:http/imports@0.2.3;
             include wasi:config/imports@0.2.0-draft;
             include wasi:keyvalue/imports@0.2.0-draft;
+            import wasmtime:wasi-keyvalue/cas;
         }
     ",
     path: [
@@ -45,3 +46,17 @@ pub mod proxy {
         },
     });
 }
+
+pub mod keyvalue_watcher {
+    wit_bindgen::generate!({
+        path: "../wasi-keyvalue/wit",
+        world: "wasi:keyvalue/watch-service",
+        default_bindings_module: "test_programs::keyvalue_watcher",
+        pub_export_macro: true,
+        with: {
+            "wasi:keyvalue/store@0.2.0-draft": crate::wasi::keyvalue::store,
+            "wasi:keyvalue/atomics@0.2.0-draft": crate::wasi::keyvalue::atomics,
+            "wasi:keyvalue/batch@0.2.0-draft": crate::wasi::keyvalue::batch,
+        },
+    });
+}
@@ -102,12 +102,14 @@ pub use table::Table;
 // The only difference between these definitions for sync vs async is whether
 // the wasmtime::Funcs generated are async (& therefore need an async Store and an executor to run)
 // or whether they have an internal "dummy executor" that expects the implementation of all
-// the async funcs to poll to Ready immediately.
+// the async funcs to poll to Ready immediately. Async definitions may also name
+// a function through which the future of each host call is passed, e.g. to run
+// it as blocking code.
 #[cfg(feature = "wasmtime")]
 #[doc(hidden)]
 #[macro_export]
 macro_rules! define_wasi {
-    ($async_mode:tt $($bounds:tt)*) => {
+    ($async_mode:tt $([$($offload:tt)*])? $(where $($bounds:tt)*)?) => {
 
     use wasmtime::Linker;
 
@@ -118,7 +120,7 @@ macro_rules! define_wasi {
         where U: Send
                 + crate::snapshots::preview_0::wasi_unstable::WasiUnstable
                 + crate::snapshots::preview_1::wasi_snapshot_preview1::WasiSnapshotPreview1,
-            $($bounds)*
+            $($($bounds)*)?
     {
         snapshots::preview_1::add_wasi_snapshot_preview1_to_linker(linker, get_cx)?;
         snapshots::preview_0::add_wasi_unstable_to_linker(linker, get_cx)?;
@@ -132,7 +134,7 @@ macro_rules! define_wasi {
                 target: crate::snapshots::preview_1,
                 witx: ["$CARGO_MANIFEST_DIR/witx/preview1/wasi_snapshot_preview1.witx"],
                 errors: { errno => trappable Error },
-                $async_mode: *
+                $async_mode $([$($offload)*])?: *
             });
         }
         pub mod preview_0 {
@@ -141,7 +143,7 @@ macro_rules! define_wasi {
                 target: crate::snapshots::preview_0,
                 witx: ["$CARGO_MANIFEST_DIR/witx/preview0/wasi_unstable.witx"],
                 errors: { errno => trappable Error },
-                $async_mode: *
+                $async_mode $([$($offload)*])?: *
             });
         }
     }
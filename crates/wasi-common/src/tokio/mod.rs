@@ -132,4 +132,80 @@ where
 }
 
 #[cfg(feature = "wasmtime")]
-super::define_wasi!(async T: Send);
+super::define_wasi!(async where T: Send);
+
+/// The same bindings as [`add_to_linker`](crate::tokio::add_to_linker),
+/// except that host calls performing filesystem I/O run as blocking code on
+/// the multi-threaded tokio runtime.
+///
+/// Filesystem syscalls block the executor thread they're made on, stalling
+/// the executor's other tasks. With these bindings each poll of such a call is
+/// wrapped in [`tokio::task::block_in_place`], which moves those tasks to
+/// another thread first.
+///
+/// The default bindings don't do this because the files and directories of
+/// this module, such as those of [`WasiCtxBuilder::preopened_dir`], already
+/// run each of their syscalls in `block_in_place`, and doing it again for the
+/// whole host call would only add to the cost of every call. These bindings
+/// are for contexts holding other files or directories which block without
+/// telling tokio, e.g. those of [`crate::sync`] or of the embedder.
+#[cfg(feature = "wasmtime")]
+pub mod offload {
+    crate::define_wasi!(async[crate::tokio::offload_filesystem_io] where T: Send);
+}
+
+// Preview 0 and 1 functions which perform blocking filesystem syscalls.
+#[cfg(feature = "wasmtime")]
+const FILESYSTEM_IO: &[&str] = &[
+    "fd_advise",
+    "fd_allocate",
+    "fd_datasync",
+    "fd_filestat_get",
+    "fd_filestat_set_size",
+    "fd_filestat_set_times",
+    "fd_pread",
+    "fd_pwrite",
+    "fd_read",
+    "fd_readdir",
+    "fd_sync",
+    "fd_write",
+    "path_create_directory",
+    "path_filestat_get",
+    "path_filestat_set_times",
+    "path_link",
+    "path_open",
+    "path_readlink",
+    "path_remove_directory",
+    "path_rename",
+    "path_symlink",
+    "path_unlink_file",
+];
+
+// Passes the futures of the `FILESYSTEM_IO` host calls through
+// `block_in_place` on each poll. The future borrows the store, so it can't
+// move to `spawn_blocking`'s thread pool instead. `block_in_place` is only
+// available on the multi-threaded runtime; on others, and for all other host
+// calls, the future is polled as-is.
+#[cfg(feature = "wasmtime")]
+pub(crate) fn offload_filesystem_io<F>(
+    _module: &'static str,
+    func: &'static str,
+    future: F,
+) -> impl Future<Output = F::Output> + Send
+where
+    F: Future + Send,
+{
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    let blocking = FILESYSTEM_IO.contains(&func)
+        && Handle::try_current()
+            .is_ok_and(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread);
+    let mut future = Box::pin(future);
+    std::future::poll_fn(move |cx| {
+        if blocking {
+            tokio::task::block_in_place(|| future.as_mut().poll(cx))
+        } else {
+            future.as_mut().poll(cx)
+        }
+    })
+}
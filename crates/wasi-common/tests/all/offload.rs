@@ -0,0 +1,56 @@
+use super::*;
+use test_programs_artifacts::*;
+use wasi_common::tokio::{offload::add_to_linker, WasiCtxBuilder};
+
+// The offloading bindings are meant for contexts with files and directories
+// which block without telling tokio, such as those of `wasi_common::sync`, so
+// that's what these tests preopen.
+async fn run(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    let workspace = prepare_workspace(name)?;
+    let engine = test_programs_artifacts::engine(|config| {
+        config.async_support(true);
+    });
+    let mut linker = Linker::new(&engine);
+    add_to_linker(&mut linker, |cx| cx)?;
+
+    let mut builder = WasiCtxBuilder::new();
+    builder.inherit_stdio().arg(name)?.arg(".")?;
+    for (var, val) in test_programs_artifacts::wasi_tests_environment() {
+        builder.env(var, val)?;
+    }
+    let ctx = builder.build();
+    let preopen_dir =
+        cap_std::fs::Dir::open_ambient_dir(workspace.path(), cap_std::ambient_authority())?;
+    ctx.push_preopened_dir(
+        Box::new(wasi_common::sync::Dir::from_cap_std(preopen_dir)),
+        ".",
+    )?;
+
+    let mut store = Store::new(&engine, ctx);
+    let module = Module::from_file(&engine, path)?;
+    let instance = linker.instantiate_async(&mut store, &module).await?;
+    let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+    start.call_async(&mut store, ()).await?;
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn offload_file_read_write() {
+    run(PREVIEW1_FILE_READ_WRITE).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn offload_file_pread_pwrite() {
+    run(PREVIEW1_FILE_PREAD_PWRITE).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn offload_fd_readdir() {
+    run(PREVIEW1_FD_READDIR).await.unwrap()
+}
+// `block_in_place` isn't available on the current-thread runtime, where host
+// calls are polled as-is instead.
+#[test_log::test(tokio::test(flavor = "current_thread"))]
+async fn offload_current_thread_runtime() {
+    run(PREVIEW1_PATH_OPEN_READ_WRITE).await.unwrap()
+}
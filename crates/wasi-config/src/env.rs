@@ -0,0 +1,46 @@
+//! Configuration variables taken from the host's environment.
+
+use crate::provider::ConfigProvider;
+use crate::Error;
+
+/// A [`ConfigProvider`] which provides the host's environment variables
+/// whose names start with a prefix.
+///
+/// The prefix is removed from the name of each environment variable to form
+/// the name of its configuration variable, so with the prefix `APP_` the
+/// environment variable `APP_db_url` provides the configuration variable
+/// `db_url`. The environment is read on every lookup, so changes to it made
+/// by the host are observed by components.
+///
+/// Environment variables whose names or values aren't valid Unicode are
+/// ignored.
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl EnvProvider {
+    /// Create a provider for the environment variables starting with
+    /// `prefix`.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConfigProvider for EnvProvider {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(std::env::var(format!("{}{key}", self.prefix)).ok())
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(std::env::vars_os()
+            .filter_map(|(name, value)| {
+                let name = name.into_string().ok()?;
+                let key = name.strip_prefix(&self.prefix)?;
+                Some((key.to_string(), value.into_string().ok()?))
+            })
+            .collect())
+    }
+}
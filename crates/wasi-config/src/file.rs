@@ -0,0 +1,227 @@
+//! Configuration variables read from a TOML or JSON file.
+
+use crate::provider::ConfigProvider;
+use crate::Error;
+use anyhow::{bail, Context};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The formats of configuration files understood by [`FileProvider`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    /// A TOML document.
+    Toml,
+    /// A JSON document whose top-level value is an object.
+    Json,
+}
+
+impl FileFormat {
+    /// Guess the format of the file at `path` from its extension.
+    pub fn from_path(path: &Path) -> Option<FileFormat> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(FileFormat::Toml),
+            "json" => Some(FileFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A [`ConfigProvider`] which provides the variables defined in a TOML or
+/// JSON file, which can be reloaded when the file changes.
+///
+/// Each string, number or boolean in the file defines a variable, whose
+/// value is its textual representation. Variables within nested tables (or
+/// objects) are named by joining the keys leading to them with `.`, so the
+/// TOML document
+///
+/// ```toml
+/// name = "example"
+///
+/// [db]
+/// url = "postgres://localhost"
+/// pool_size = 4
+/// ```
+///
+/// defines the variables `name`, `db.url` and `db.pool_size`. Arrays are
+/// rejected.
+///
+/// The file is read when the provider is created, and lookups are served from
+/// memory without touching the file system. To pick up changes to the file,
+/// call [`FileProvider::reload`] whenever appropriate, for example
+/// periodically from a task on an async runtime's blocking thread pool.
+pub struct FileProvider {
+    path: PathBuf,
+    format: FileFormat,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    /// The modification time and size of the file when it was last read.
+    stamp: (SystemTime, u64),
+    vars: Arc<BTreeMap<String, String>>,
+}
+
+impl FileProvider {
+    /// Create a provider for the variables defined in the file at `path`,
+    /// whose format is guessed from its extension.
+    ///
+    /// Returns an error if the extension is neither `.toml` nor `.json`, or
+    /// if the file can't be read or parsed.
+    pub fn new(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let Some(format) = FileFormat::from_path(&path) else {
+            bail!(
+                "cannot tell the format of config file `{}`: expected a `.toml` or `.json` extension",
+                path.display()
+            );
+        };
+        Self::with_format(path, format)
+    }
+
+    /// Create a provider for the variables defined in the file at `path`,
+    /// which is in the given `format`.
+    ///
+    /// Returns an error if the file can't be read or parsed.
+    pub fn with_format(path: impl Into<PathBuf>, format: FileFormat) -> anyhow::Result<Self> {
+        let path = path.into();
+        let stamp = stamp(&path)?;
+        let vars = read(&path, format)?;
+        Ok(Self {
+            path,
+            format,
+            state: Mutex::new(FileState {
+                stamp,
+                vars: Arc::new(vars),
+            }),
+        })
+    }
+
+    /// Read the file again if its modification time or size changed since it
+    /// was last read, returning whether it was read again.
+    ///
+    /// If the changed file can't be read or parsed, an error is returned and
+    /// the variables it defined previously keep being provided, so that
+    /// partially written files don't disrupt components. It is then read
+    /// again once it changes again.
+    ///
+    /// This performs blocking file system operations, so async embedders
+    /// should call it from a blocking context such as
+    /// `tokio::task::spawn_blocking`. Lookups made in the meantime are served
+    /// with the previous variables.
+    pub fn reload(&self) -> anyhow::Result<bool> {
+        let stamp = stamp(&self.path)?;
+        if self.state.lock().unwrap().stamp == stamp {
+            return Ok(false);
+        }
+        let vars = read(&self.path, self.format);
+        let mut state = self.state.lock().unwrap();
+        state.stamp = stamp;
+        state.vars = Arc::new(vars?);
+        Ok(true)
+    }
+
+    fn vars(&self) -> Arc<BTreeMap<String, String>> {
+        self.state.lock().unwrap().vars.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl ConfigProvider for FileProvider {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.vars().get(key).cloned())
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .vars()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+}
+
+fn stamp(path: &Path) -> anyhow::Result<(SystemTime, u64)> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+fn read(path: &Path, format: FileFormat) -> anyhow::Result<BTreeMap<String, String>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+    let mut vars = BTreeMap::new();
+    match format {
+        FileFormat::Toml => {
+            let table: toml::Table = contents
+                .parse()
+                .with_context(|| format!("failed to parse `{}` as TOML", path.display()))?;
+            flatten_toml("", &toml::Value::Table(table), &mut vars)?;
+        }
+        FileFormat::Json => {
+            let value: serde_json::Value = serde_json::from_str(&contents)
+                .with_context(|| format!("failed to parse `{}` as JSON", path.display()))?;
+            if !value.is_object() {
+                bail!("expected `{}` to contain a JSON object", path.display());
+            }
+            flatten_json("", &value, &mut vars)?;
+        }
+    }
+    Ok(vars)
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+fn flatten_toml(
+    name: &str,
+    value: &toml::Value,
+    vars: &mut BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    let value = match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten_toml(&join(name, key), value, vars)?;
+            }
+            return Ok(());
+        }
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        toml::Value::Datetime(d) => d.to_string(),
+        toml::Value::Array(_) => bail!("config variable `{name}` cannot be an array"),
+    };
+    vars.insert(name.to_string(), value);
+    Ok(())
+}
+
+fn flatten_json(
+    name: &str,
+    value: &serde_json::Value,
+    vars: &mut BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    let value = match value {
+        serde_json::Value::Object(object) => {
+            for (key, value) in object {
+                flatten_json(&join(name, key), value, vars)?;
+            }
+            return Ok(());
+        }
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::Bool(b) => b.to_string(),
+        // A `null` leaves the variable undefined.
+        serde_json::Value::Null => return Ok(()),
+        serde_json::Value::Array(_) => bail!("config variable `{name}` cannot be an array"),
+    };
+    vars.insert(name.to_string(), value);
+    Ok(())
+}
@@ -38,7 +38,7 @@
 //!     let mut linker = Linker::<Ctx>::new(&engine);
 //!     wasmtime_wasi::add_to_linker_async(&mut linker)?;
 //!     // add `wasi-config` world's interfaces to the linker
-//!     wasmtime_wasi_config::add_to_linker(&mut linker, |h: &mut Ctx| {
+//!     wasmtime_wasi_config::add_to_linker_async(&mut linker, |h: &mut Ctx| {
 //!         WasiConfig::from(&h.wasi_config_vars)
 //!     })?;
 //!
@@ -61,6 +61,13 @@
 //! }
 //! ```
 //!
+//! Variables don't have to be fixed when the store is created: anything
+//! implementing [`ConfigProvider`] can supply them, such as an
+//! [`EnvProvider`] for the host's environment, a [`FileProvider`] for a TOML
+//! or JSON file which can be reloaded when it changes, a provider looking
+//! values up asynchronously in some remote service, or a [`Layered`]
+//! combination of several of them.
+//!
 //! [wasi-config]: https://github.com/WebAssembly/wasi-config
 //! [wasi:cli]: https://docs.rs/wasmtime-wasi/latest
 //! [wasi:http]: https://docs.rs/wasmtime-wasi-http/latest
@@ -69,6 +76,15 @@
 
 use anyhow::Result;
 use std::collections::HashMap;
+use wasmtime_wasi::runtime::in_tokio;
+
+mod env;
+mod file;
+mod provider;
+
+pub use self::env::EnvProvider;
+pub use self::file::{FileFormat, FileProvider};
+pub use self::provider::{ConfigProvider, Layered};
 
 mod gen_ {
     wasmtime::component::bindgen!({
@@ -79,7 +95,64 @@ mod gen_ {
 }
 use self::gen_::wasi::config::store as generated;
 
+mod gen_async {
+    wasmtime::component::bindgen!({
+        path: "wit",
+        world: "wasi:config/imports",
+        trappable_imports: true,
+        async: true,
+    });
+}
+use self::gen_async::wasi::config::store as generated_async;
+
+/// Errors returned by [`ConfigProvider`]s.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// An error occurred in the service or system providing the variables.
+    Upstream(String),
+    /// An I/O error occurred while looking up the variables.
+    Io(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Upstream(e) => write!(f, "upstream error: {e}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err.to_string())
+    }
+}
+
+impl From<Error> for generated::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Upstream(e) => generated::Error::Upstream(e),
+            Error::Io(e) => generated::Error::Io(e),
+        }
+    }
+}
+
+impl From<Error> for generated_async::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Upstream(e) => generated_async::Error::Upstream(e),
+            Error::Io(e) => generated_async::Error::Io(e),
+        }
+    }
+}
+
 /// Capture the state necessary for use in the `wasi-config` API implementation.
+///
+/// This is the simplest [`ConfigProvider`], defining a fixed set of
+/// variables.
 #[derive(Default)]
 pub struct WasiConfigVariables(HashMap<String, String>);
 
@@ -106,40 +179,70 @@ impl WasiConfigVariables {
     }
 }
 
+#[async_trait::async_trait]
+impl ConfigProvider for WasiConfigVariables {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.0.get(key).cloned())
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .0
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect())
+    }
+}
+
 /// A wrapper capturing the needed internal `wasi-config` state.
 pub struct WasiConfig<'a> {
-    vars: &'a WasiConfigVariables,
+    provider: &'a dyn ConfigProvider,
 }
 
-impl<'a> From<&'a WasiConfigVariables> for WasiConfig<'a> {
-    fn from(vars: &'a WasiConfigVariables) -> Self {
-        Self { vars }
+impl<'a, P: ConfigProvider> From<&'a P> for WasiConfig<'a> {
+    fn from(provider: &'a P) -> Self {
+        Self { provider }
     }
 }
 
 impl<'a> WasiConfig<'a> {
-    /// Create a new view into the `wasi-config` state.
-    pub fn new(vars: &'a WasiConfigVariables) -> Self {
-        Self { vars }
+    /// Create a new view into the `wasi-config` state, whose variables are
+    /// supplied by `provider`.
+    pub fn new(provider: &'a dyn ConfigProvider) -> Self {
+        Self { provider }
     }
 }
 
 impl generated::Host for WasiConfig<'_> {
     fn get(&mut self, key: String) -> Result<Result<Option<String>, generated::Error>> {
-        Ok(Ok(self.vars.0.get(&key).map(|s| s.to_owned())))
+        Ok(in_tokio(self.provider.get(&key)).map_err(Into::into))
     }
 
     fn get_all(&mut self) -> Result<Result<Vec<(String, String)>, generated::Error>> {
-        Ok(Ok(self
-            .vars
-            .0
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect()))
+        Ok(in_tokio(self.provider.get_all()).map_err(Into::into))
+    }
+}
+
+impl generated_async::Host for WasiConfig<'_> {
+    async fn get(&mut self, key: String) -> Result<Result<Option<String>, generated_async::Error>> {
+        Ok(self.provider.get(&key).await.map_err(Into::into))
+    }
+
+    async fn get_all(&mut self) -> Result<Result<Vec<(String, String)>, generated_async::Error>> {
+        Ok(self.provider.get_all().await.map_err(Into::into))
     }
 }
 
 /// Add all the `wasi-config` world's interfaces to a [`wasmtime::component::Linker`].
+///
+/// This function will add the synchronous variant of all interfaces into the
+/// [`Linker`] provided. Lookups block the calling thread until the
+/// [`ConfigProvider`] returns, running it on the ambient tokio runtime (or on
+/// a shared one if there is none), so providers may rely on tokio. Providers
+/// which take a while to respond are better used with
+/// [`add_to_linker_async`], which doesn't block the thread.
+///
+/// [`Linker`]: wasmtime::component::Linker
 pub fn add_to_linker<T>(
     l: &mut wasmtime::component::Linker<T>,
     f: impl Fn(&mut T) -> WasiConfig<'_> + Send + Sync + Copy + 'static,
@@ -147,3 +250,16 @@ pub fn add_to_linker<T>(
     generated::add_to_linker_get_host(l, f)?;
     Ok(())
 }
+
+/// Add all the `wasi-config` world's interfaces to a [`wasmtime::component::Linker`],
+/// looking variables up asynchronously.
+///
+/// The linker's engine must have
+/// [`async_support`](wasmtime::Config::async_support) enabled.
+pub fn add_to_linker_async<T: Send>(
+    l: &mut wasmtime::component::Linker<T>,
+    f: impl Fn(&mut T) -> WasiConfig<'_> + Send + Sync + Copy + 'static,
+) -> Result<()> {
+    generated_async::add_to_linker_get_host(l, f)?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! The trait that sources of configuration values implement to provide
+//! `wasi-config` variables.
+
+use crate::Error;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A source of configuration values for the `wasi-config` API.
+///
+/// Lookups are asynchronous so that providers may consult remote services,
+/// such as a secrets manager, when a component asks for a value. Providers
+/// are shared by every store configured with them, so they must be
+/// `Send + Sync`.
+#[async_trait::async_trait]
+pub trait ConfigProvider: Send + Sync + 'static {
+    /// Get the value of the configuration variable `key`, if it is defined.
+    async fn get(&self, key: &str) -> Result<Option<String>, Error>;
+
+    /// Get all configuration variables defined by this provider.
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error>;
+}
+
+#[async_trait::async_trait]
+impl<P: ConfigProvider + ?Sized> ConfigProvider for Arc<P> {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        (**self).get(key).await
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error> {
+        (**self).get_all().await
+    }
+}
+
+/// A [`ConfigProvider`] which combines several other providers.
+///
+/// A variable is looked up in each provider in turn, in the order in which
+/// they were added, and the first value found is used. Variables defined by
+/// several providers are therefore taken from the earliest of them.
+#[derive(Default, Clone)]
+pub struct Layered {
+    providers: Vec<Arc<dyn ConfigProvider>>,
+}
+
+impl Layered {
+    /// Create a provider which doesn't define any variables yet.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add `provider`, whose variables take precedence over those of
+    /// providers added after it but not those added before it.
+    pub fn push(&mut self, provider: impl ConfigProvider) -> &mut Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl ConfigProvider for Layered {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        for provider in &self.providers {
+            if let Some(value) = provider.get(key).await? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut all = BTreeMap::new();
+        for provider in self.providers.iter().rev() {
+            all.extend(provider.get_all().await?);
+        }
+        Ok(all.into_iter().collect())
+    }
+}
@@ -1,4 +1,6 @@
 use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use std::time::Duration;
 use test_programs_artifacts::{foreach_config, CONFIG_GET_COMPONENT};
 use wasmtime::{
     component::{Component, Linker, ResourceTable},
@@ -7,12 +9,14 @@ use wasmtime::{
 use wasmtime_wasi::{
     add_to_linker_async, bindings::Command, IoView, WasiCtx, WasiCtxBuilder, WasiView,
 };
-use wasmtime_wasi_config::{WasiConfig, WasiConfigVariables};
+use wasmtime_wasi_config::{
+    ConfigProvider, Error, FileProvider, Layered, WasiConfig, WasiConfigVariables,
+};
 
 struct Ctx {
     table: ResourceTable,
     wasi_ctx: WasiCtx,
-    wasi_config_vars: WasiConfigVariables,
+    wasi_config: Arc<dyn ConfigProvider>,
 }
 
 impl IoView for Ctx {
@@ -35,8 +39,8 @@ async fn run_wasi(path: &str, ctx: Ctx) -> Result<()> {
 
     let mut linker = Linker::new(&engine);
     add_to_linker_async(&mut linker)?;
-    wasmtime_wasi_config::add_to_linker(&mut linker, |h: &mut Ctx| {
-        WasiConfig::from(&h.wasi_config_vars)
+    wasmtime_wasi_config::add_to_linker_async(&mut linker, |h: &mut Ctx| {
+        WasiConfig::new(&*h.wasi_config)
     })?;
 
     let command = Command::instantiate_async(&mut store, &component, &linker).await?;
@@ -47,6 +51,25 @@ async fn run_wasi(path: &str, ctx: Ctx) -> Result<()> {
         .map_err(|()| anyhow!("command returned with failing exit status"))
 }
 
+fn run_wasi_sync(path: &str, ctx: Ctx) -> Result<()> {
+    let engine = test_programs_artifacts::engine(|_| {});
+    let mut store = Store::new(&engine, ctx);
+    let component = Component::from_file(&engine, path)?;
+
+    let mut linker = Linker::new(&engine);
+    wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+    wasmtime_wasi_config::add_to_linker(&mut linker, |h: &mut Ctx| {
+        WasiConfig::new(&*h.wasi_config)
+    })?;
+
+    let command =
+        wasmtime_wasi::bindings::sync::Command::instantiate(&mut store, &component, &linker)?;
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)?
+        .map_err(|()| anyhow!("command returned with failing exit status"))
+}
+
 macro_rules! assert_test_exists {
     ($name:ident) => {
         #[expect(unused_imports, reason = "only here to ensure name exists")]
@@ -63,8 +86,123 @@ async fn config_get() -> Result<()> {
         Ctx {
             table: ResourceTable::new(),
             wasi_ctx: WasiCtxBuilder::new().build(),
-            wasi_config_vars: WasiConfigVariables::from_iter(vec![("hello", "world")]),
+            wasi_config: Arc::new(WasiConfigVariables::from_iter(vec![("hello", "world")])),
+        },
+    )
+    .await
+}
+
+#[test]
+fn config_get_sync() -> Result<()> {
+    run_wasi_sync(
+        CONFIG_GET_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().build(),
+            wasi_config: Arc::new(WasiConfigVariables::from_iter(vec![("hello", "world")])),
+        },
+    )
+}
+
+/// A provider which relies on the tokio reactor to look variables up.
+struct TokioProvider(WasiConfigVariables);
+
+#[async_trait::async_trait]
+impl ConfigProvider for TokioProvider {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        self.0.get(key).await
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>, Error> {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        self.0.get_all().await
+    }
+}
+
+#[test]
+fn config_get_sync_from_tokio_provider() -> Result<()> {
+    run_wasi_sync(
+        CONFIG_GET_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().build(),
+            wasi_config: Arc::new(TokioProvider(WasiConfigVariables::from_iter(vec![(
+                "hello", "world",
+            )]))),
+        },
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn config_get_from_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, "hello = \"world\"\n")?;
+    run_wasi(
+        CONFIG_GET_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().build(),
+            wasi_config: Arc::new(FileProvider::new(&path)?),
+        },
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn config_get_from_layered() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("config.json");
+    std::fs::write(&path, r#"{"hello": "everyone"}"#)?;
+
+    // Variables defined by several providers are taken from the first.
+    let mut layered = Layered::new();
+    layered
+        .push(WasiConfigVariables::from_iter(vec![("hello", "world")]))
+        .push(FileProvider::new(&path)?);
+    run_wasi(
+        CONFIG_GET_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().build(),
+            wasi_config: Arc::new(layered),
         },
     )
     .await
 }
+
+#[tokio::test]
+async fn file_provider_reloads() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, "a = 1\n[b]\nc = true\n")?;
+    let provider = FileProvider::new(&path)?;
+    assert_eq!(
+        provider.get_all().await?,
+        [
+            ("a".to_string(), "1".to_string()),
+            ("b.c".to_string(), "true".to_string())
+        ]
+    );
+
+    // The file is only read again when reloaded, and only once it changes.
+    std::fs::write(&path, "a = \"changed\"\n")?;
+    assert_eq!(provider.get("a").await?.as_deref(), Some("1"));
+    assert!(provider.reload()?);
+    assert_eq!(provider.get("a").await?.as_deref(), Some("changed"));
+    assert_eq!(provider.get("b.c").await?, None);
+    assert!(!provider.reload()?);
+
+    // An invalid file leaves the previous variables in place.
+    std::fs::write(&path, "a = [not toml")?;
+    assert!(provider.reload().is_err());
+    assert_eq!(provider.get("a").await?.as_deref(), Some("changed"));
+
+    // Unknown formats and arrays are rejected up front.
+    assert!(FileProvider::new(dir.path().join("config.yaml")).is_err());
+    let path = dir.path().join("array.json");
+    std::fs::write(&path, r#"{"a": [1, 2]}"#)?;
+    assert!(FileProvider::new(&path).is_err());
+    Ok(())
+}
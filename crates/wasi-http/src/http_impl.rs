@@ -27,17 +27,26 @@ where
     ) -> crate::HttpResult<Resource<HostFutureIncomingResponse>> {
         let opts = options.and_then(|opts| self.table().get(&opts).ok());
 
-        let connect_timeout = opts
-            .and_then(|opts| opts.connect_timeout)
-            .unwrap_or(std::time::Duration::from_secs(600));
+        let policy = self.ctx().egress_policy().clone();
 
-        let first_byte_timeout = opts
-            .and_then(|opts| opts.first_byte_timeout)
-            .unwrap_or(std::time::Duration::from_secs(600));
+        let connect_timeout = policy.limit_connect_timeout(
+            opts.and_then(|opts| opts.connect_timeout)
+                .unwrap_or(std::time::Duration::from_secs(600)),
+        );
 
-        let between_bytes_timeout = opts
-            .and_then(|opts| opts.between_bytes_timeout)
-            .unwrap_or(std::time::Duration::from_secs(600));
+        let first_byte_timeout = policy.limit_first_byte_timeout(
+            opts.and_then(|opts| opts.first_byte_timeout)
+                .unwrap_or(std::time::Duration::from_secs(600)),
+        );
+
+        let between_bytes_timeout = policy.limit_between_bytes_timeout(
+            opts.and_then(|opts| opts.between_bytes_timeout)
+                .unwrap_or(std::time::Duration::from_secs(600)),
+        );
+
+        let pool = self.ctx().connection_pool().cloned();
+        let pool_limits = self.ctx().connection_pool_limits();
+        let tls_config = self.ctx().tls_config().clone();
 
         let req = self.table().delete(request_id)?;
         let mut builder = hyper::Request::builder();
@@ -68,6 +77,8 @@ where
 
         let authority = req.authority.unwrap_or_else(String::new);
 
+        policy.check(scheme.as_str(), &authority)?;
+
         builder = builder.header(hyper::header::HOST, &authority);
 
         let mut uri = http::Uri::builder()
@@ -94,6 +105,7 @@ where
             .body(body)
             .map_err(|err| internal_error(err.to_string()))?;
 
+        let permit = policy.start_request()?;
         let future = self.send_request(
             request,
             OutgoingRequestConfig {
@@ -101,8 +113,12 @@ where
                 connect_timeout,
                 first_byte_timeout,
                 between_bytes_timeout,
+                pool,
+                pool_limits,
+                tls_config,
             },
         )?;
+        let future = policy.limit_response(future, permit);
 
         Ok(self.table().push(future)?)
     }
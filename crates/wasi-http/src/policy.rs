@@ -0,0 +1,395 @@
+//! A declarative policy restricting the outgoing requests made by guests.
+//!
+//! An [`EgressPolicy`] configured on a [`WasiHttpCtx`](crate::WasiHttpCtx) is
+//! enforced by the `wasi:http/outgoing-handler` implementation before
+//! [`WasiHttpView::send_request`](crate::WasiHttpView::send_request) is
+//! called, so it applies to custom implementations of `send_request` too.
+//! Requests which the policy forbids fail with
+//! `error-code::HTTP-request-denied` without connecting anywhere.
+
+use crate::bindings::http::types::ErrorCode;
+use crate::body::HyperIncomingBody;
+use crate::types::{HostFutureIncomingResponse, IncomingResponse};
+use anyhow::{bail, Context as _};
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Restrictions on the outgoing requests made by guests.
+///
+/// The default policy allows every request. Each `allow_*` method restricts
+/// requests to those matching one of the values passed to it, so for example
+/// after calling [`EgressPolicy::allow_authority`] only requests to the given
+/// authorities are allowed.
+///
+/// Cloning a policy is cheap, and clones share the count of requests in
+/// flight limited by [`EgressPolicy::max_concurrent_requests`].
+#[derive(Clone, Debug, Default)]
+pub struct EgressPolicy {
+    authorities: Vec<AuthorityPattern>,
+    ports: Vec<u16>,
+    schemes: Vec<String>,
+    max_connect_timeout: Option<Duration>,
+    max_first_byte_timeout: Option<Duration>,
+    max_between_bytes_timeout: Option<Duration>,
+    max_response_body_size: Option<u64>,
+    max_concurrent_requests: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// A host, or all subdomains of a host, with an optional port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct AuthorityPattern {
+    host: String,
+    subdomains: bool,
+    port: Option<u16>,
+}
+
+impl AuthorityPattern {
+    fn parse(pattern: &str) -> anyhow::Result<Self> {
+        let (subdomains, rest) = match pattern.strip_prefix("*.") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let authority = rest
+            .parse::<http::uri::Authority>()
+            .with_context(|| format!("invalid authority pattern `{pattern}`"))?;
+        if authority.host().is_empty() || authority.as_str().contains('@') {
+            bail!("invalid authority pattern `{pattern}`");
+        }
+        Ok(Self {
+            host: authority.host().to_ascii_lowercase(),
+            subdomains,
+            port: authority.port_u16(),
+        })
+    }
+
+    fn matches(&self, host: &str, port: u16) -> bool {
+        if self.port.is_some_and(|p| p != port) {
+            return false;
+        }
+        if self.subdomains {
+            host.strip_suffix(self.host.as_str())
+                .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.'))
+        } else {
+            host == self.host
+        }
+    }
+}
+
+impl EgressPolicy {
+    /// Create a new policy which allows every request.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Allow requests to authorities matching `pattern`.
+    ///
+    /// The pattern is a host name or IP address, such as `example.com`, or
+    /// `*.example.com` to match all subdomains of `example.com` but not
+    /// `example.com` itself. It may be followed by a port, such as
+    /// `example.com:8080`, to only allow requests to that port.
+    pub fn allow_authority(&mut self, pattern: &str) -> anyhow::Result<&mut Self> {
+        self.authorities.push(AuthorityPattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Allow requests to `port`, on any allowed authority.
+    pub fn allow_port(&mut self, port: u16) -> &mut Self {
+        self.ports.push(port);
+        self
+    }
+
+    /// Allow requests using `scheme`, either `http` or `https`.
+    pub fn allow_scheme(&mut self, scheme: &str) -> &mut Self {
+        self.schemes.push(scheme.to_ascii_lowercase());
+        self
+    }
+
+    /// Limit the time spent connecting to a server, regardless of the
+    /// timeout requested by the guest.
+    pub fn max_connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.max_connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the time spent waiting for the first byte of a response,
+    /// regardless of the timeout requested by the guest.
+    pub fn max_first_byte_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.max_first_byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the time spent waiting between the chunks of a response body,
+    /// regardless of the timeout requested by the guest.
+    pub fn max_between_bytes_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.max_between_bytes_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the size of response bodies, in bytes.
+    ///
+    /// Larger responses fail with `error-code::HTTP-response-body-size`,
+    /// either immediately if their `content-length` is too large, or once
+    /// the guest has read up to the limit.
+    pub fn max_response_body_size(&mut self, size: u64) -> &mut Self {
+        self.max_response_body_size = Some(size);
+        self
+    }
+
+    /// Limit the number of requests in flight at once.
+    ///
+    /// A request is in flight until its response body has been read to the
+    /// end or dropped. Further requests fail with
+    /// `error-code::connection-limit-reached`.
+    pub fn max_concurrent_requests(&mut self, max: usize) -> &mut Self {
+        self.max_concurrent_requests = Some(max);
+        self
+    }
+
+    /// The number of requests currently in flight under this policy and its
+    /// clones.
+    pub fn requests_in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Check that a request using `scheme` may be sent to `authority`.
+    pub(crate) fn check(&self, scheme: &str, authority: &str) -> Result<(), ErrorCode> {
+        if !self.schemes.is_empty() && !self.schemes.iter().any(|s| s == scheme) {
+            tracing::debug!("denied request with scheme `{scheme}`");
+            return Err(ErrorCode::HttpRequestDenied);
+        }
+        if self.authorities.is_empty() && self.ports.is_empty() {
+            return Ok(());
+        }
+
+        let authority = authority
+            .parse::<http::uri::Authority>()
+            .map_err(|_| ErrorCode::HttpRequestUriInvalid)?;
+        let host = authority.host().to_ascii_lowercase();
+        let port = match authority.port_u16() {
+            Some(port) => port,
+            None if scheme == "https" => 443,
+            None => 80,
+        };
+        let allowed = (self.ports.is_empty() || self.ports.contains(&port))
+            && (self.authorities.is_empty()
+                || self.authorities.iter().any(|a| a.matches(&host, port)));
+        if !allowed {
+            tracing::debug!("denied request to `{authority}`");
+            return Err(ErrorCode::HttpRequestDenied);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn limit_connect_timeout(&self, timeout: Duration) -> Duration {
+        cap(timeout, self.max_connect_timeout)
+    }
+
+    pub(crate) fn limit_first_byte_timeout(&self, timeout: Duration) -> Duration {
+        cap(timeout, self.max_first_byte_timeout)
+    }
+
+    pub(crate) fn limit_between_bytes_timeout(&self, timeout: Duration) -> Duration {
+        cap(timeout, self.max_between_bytes_timeout)
+    }
+
+    /// Count a new request in flight, failing if too many already are.
+    pub(crate) fn start_request(&self) -> Result<RequestPermit, ErrorCode> {
+        let max = self.max_concurrent_requests.unwrap_or(usize::MAX);
+        self.in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| ErrorCode::ConnectionLimitReached)?;
+        Ok(RequestPermit {
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    /// Apply the limits on responses to the response to a request, which
+    /// stays in flight until `permit` is dropped along with the response.
+    pub(crate) fn limit_response(
+        &self,
+        response: HostFutureIncomingResponse,
+        permit: RequestPermit,
+    ) -> HostFutureIncomingResponse {
+        let max_size = self.max_response_body_size;
+        match response {
+            HostFutureIncomingResponse::Pending(handle) => {
+                HostFutureIncomingResponse::pending(wasmtime_wasi::runtime::spawn(async move {
+                    Ok(handle.await?.and_then(|r| limit_body(r, max_size, permit)))
+                }))
+            }
+            HostFutureIncomingResponse::Ready(Ok(Ok(r))) => {
+                HostFutureIncomingResponse::ready(Ok(limit_body(r, max_size, permit)))
+            }
+            other => other,
+        }
+    }
+}
+
+fn cap(timeout: Duration, max: Option<Duration>) -> Duration {
+    match max {
+        Some(max) => timeout.min(max),
+        None => timeout,
+    }
+}
+
+fn limit_body(
+    response: IncomingResponse,
+    max_size: Option<u64>,
+    permit: RequestPermit,
+) -> Result<IncomingResponse, ErrorCode> {
+    let IncomingResponse {
+        resp,
+        worker,
+        between_bytes_timeout,
+    } = response;
+
+    if let Some(max) = max_size {
+        let content_length = resp
+            .headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if let Some(len) = content_length.filter(|len| *len > max) {
+            return Err(ErrorCode::HttpResponseBodySize(Some(len)));
+        }
+    }
+
+    Ok(IncomingResponse {
+        resp: resp.map(|body| {
+            LimitedBody {
+                body,
+                max_size,
+                size: 0,
+                _permit: permit,
+            }
+            .boxed()
+        }),
+        worker,
+        between_bytes_timeout,
+    })
+}
+
+/// Marks a request as in flight until dropped.
+#[derive(Debug)]
+pub(crate) struct RequestPermit {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A response body which fails once it exceeds `max_size`, and which keeps
+/// its request in flight until it is dropped.
+struct LimitedBody {
+    body: HyperIncomingBody,
+    max_size: Option<u64>,
+    size: u64,
+    _permit: RequestPermit,
+}
+
+impl Body for LimitedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        let frame = match Pin::new(&mut self.body).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            other => return other,
+        };
+        if let Some(data) = frame.data_ref() {
+            self.size += data.len() as u64;
+            if self.max_size.is_some_and(|max| self.size > max) {
+                return Poll::Ready(Some(Err(ErrorCode::HttpResponseBodySize(Some(self.size)))));
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorities() -> anyhow::Result<()> {
+        let mut policy = EgressPolicy::new();
+        policy
+            .allow_authority("example.com")?
+            .allow_authority("*.example.org")?
+            .allow_authority("localhost:8080")?;
+
+        assert!(policy.check("https", "example.com").is_ok());
+        assert!(policy.check("http", "EXAMPLE.com:1234").is_ok());
+        assert!(policy.check("https", "api.example.org").is_ok());
+        assert!(policy.check("https", "example.org").is_err());
+        assert!(policy.check("https", "badexample.org").is_err());
+        assert!(policy.check("https", "example.com.evil").is_err());
+        assert!(policy.check("http", "localhost:8080").is_ok());
+        assert!(policy.check("http", "localhost").is_err());
+
+        assert!(EgressPolicy::new().allow_authority("").is_err());
+        assert!(EgressPolicy::new().allow_authority("user@host").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ports_and_schemes() {
+        let mut policy = EgressPolicy::new();
+        policy.allow_port(443).allow_scheme("HTTPS");
+
+        assert!(policy.check("https", "example.com").is_ok());
+        assert!(policy.check("https", "example.com:443").is_ok());
+        assert!(matches!(
+            policy.check("https", "example.com:8443"),
+            Err(ErrorCode::HttpRequestDenied)
+        ));
+        assert!(matches!(
+            policy.check("http", "example.com:443"),
+            Err(ErrorCode::HttpRequestDenied)
+        ));
+    }
+
+    #[test]
+    fn concurrent_requests() {
+        let mut policy = EgressPolicy::new();
+        policy.max_concurrent_requests(1);
+        let clone = policy.clone();
+
+        let permit = policy.start_request().unwrap();
+        assert_eq!(clone.requests_in_flight(), 1);
+        assert!(matches!(
+            clone.start_request(),
+            Err(ErrorCode::ConnectionLimitReached)
+        ));
+        drop(permit);
+        assert_eq!(policy.requests_in_flight(), 0);
+        assert!(clone.start_request().is_ok());
+    }
+}
@@ -0,0 +1,295 @@
+//! Pooling of connections used to send outgoing requests.
+//!
+//! A [`ConnectionPool`] keeps connections to each authority alive after the
+//! requests sent over them complete, so that later requests to the same
+//! authority can skip establishing a new TCP (and TLS) connection. HTTP/1.1
+//! connections carry one request at a time and are kept idle in the pool
+//! between requests, while a single HTTP/2 connection per authority is shared
+//! by all concurrent requests.
+
+use crate::body::HyperOutgoingBody;
+use crate::tls::TlsConfig;
+use hyper::body::{Body, Frame, SizeHint};
+use hyper::client::conn::{http1, http2};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use wasmtime_wasi::runtime::AbortOnDropJoinHandle;
+
+/// Limits on the connections kept by a [`ConnectionPool`], configured with
+/// each outgoing request.
+///
+/// These only bound the connections kept idle for reuse, not the connections
+/// open at once: each concurrent HTTP/1.1 request to an authority still opens
+/// its own connection when no idle one is available. To bound those, limit
+/// the number of requests in flight with
+/// [`EgressPolicy::max_concurrent_requests`](crate::policy::EgressPolicy::max_concurrent_requests).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionPoolLimits {
+    /// The maximum number of idle HTTP/1.1 connections kept for each
+    /// authority, not counting connections with a request in flight. Zero
+    /// disables reuse of HTTP/1.1 connections.
+    pub max_idle_per_authority: usize,
+    /// How long a connection may stay idle in the pool before it is closed.
+    pub idle_timeout: Duration,
+    /// Whether to offer HTTP/2 when negotiating TLS connections with ALPN.
+    /// This is off by default.
+    pub http2: bool,
+}
+
+impl Default for ConnectionPoolLimits {
+    fn default() -> Self {
+        Self {
+            max_idle_per_authority: 32,
+            idle_timeout: Duration::from_secs(90),
+            http2: false,
+        }
+    }
+}
+
+/// A pool of connections that outgoing requests may reuse.
+///
+/// Cloning a pool is cheap, and the clone shares the original's connections,
+/// so a single pool may be shared by the [`WasiHttpCtx`](crate::WasiHttpCtx)
+/// of every store in an embedding.
+#[derive(Clone, Default)]
+pub struct ConnectionPool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    idle: HashMap<PoolKey, Vec<IdleConnection>>,
+    http2: HashMap<PoolKey, Http2Connection>,
+}
+
+/// Connections are only interchangeable when they go to the same authority,
+/// and either both don't use TLS or both use the same TLS configuration.
+#[derive(Clone, Debug)]
+pub(crate) struct PoolKey {
+    pub(crate) authority: String,
+    pub(crate) tls_config: Option<Arc<TlsConfig>>,
+}
+
+impl PoolKey {
+    fn tls_config_ptr(&self) -> Option<*const TlsConfig> {
+        self.tls_config.as_ref().map(Arc::as_ptr)
+    }
+}
+
+impl PartialEq for PoolKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.authority == other.authority && self.tls_config_ptr() == other.tls_config_ptr()
+    }
+}
+
+impl Eq for PoolKey {}
+
+impl Hash for PoolKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.authority.hash(state);
+        self.tls_config_ptr().hash(state);
+    }
+}
+
+struct IdleConnection {
+    sender: http1::SendRequest<HyperOutgoingBody>,
+    worker: AbortOnDropJoinHandle<()>,
+    idle_since: Instant,
+}
+
+#[derive(Clone)]
+struct Http2Connection {
+    sender: http2::SendRequest<HyperOutgoingBody>,
+    worker: Arc<AbortOnDropJoinHandle<()>>,
+}
+
+/// A connection ready to send a request, either fresh or from a pool.
+pub(crate) enum Connection {
+    Http1 {
+        sender: http1::SendRequest<HyperOutgoingBody>,
+        worker: AbortOnDropJoinHandle<()>,
+    },
+    Http2 {
+        sender: http2::SendRequest<HyperOutgoingBody>,
+        worker: Arc<AbortOnDropJoinHandle<()>>,
+    },
+}
+
+impl ConnectionPool {
+    /// Create a new, empty pool.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The number of idle HTTP/1.1 connections currently in the pool.
+    pub fn idle_connections(&self) -> usize {
+        self.state.lock().unwrap().idle.values().map(Vec::len).sum()
+    }
+
+    /// The number of HTTP/2 connections currently in the pool.
+    pub fn http2_connections(&self) -> usize {
+        self.state.lock().unwrap().http2.len()
+    }
+
+    /// Close all connections in the pool.
+    ///
+    /// Connections currently in use stay open until their requests complete.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.idle.clear();
+        state.http2.clear();
+    }
+
+    /// Take a connection to `key` out of the pool, if there is a usable one.
+    pub(crate) fn checkout(
+        &self,
+        key: &PoolKey,
+        limits: &ConnectionPoolLimits,
+    ) -> Option<Connection> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(conn) = state.http2.get(key) {
+            if !conn.sender.is_closed() {
+                return Some(Connection::Http2 {
+                    sender: conn.sender.clone(),
+                    worker: conn.worker.clone(),
+                });
+            }
+            state.http2.remove(key);
+        }
+
+        // Prefer the most recently used connection, which is the least likely
+        // to have been closed by the server in the meantime.
+        let idle = state.idle.get_mut(key)?;
+        while let Some(conn) = idle.pop() {
+            if conn.idle_since.elapsed() < limits.idle_timeout && conn.sender.is_ready() {
+                return Some(Connection::Http1 {
+                    sender: conn.sender,
+                    worker: conn.worker,
+                });
+            }
+        }
+        state.idle.remove(key);
+        None
+    }
+
+    /// Share a newly established HTTP/2 connection with later requests to
+    /// `key`.
+    pub(crate) fn insert_http2(
+        &self,
+        key: PoolKey,
+        sender: http2::SendRequest<HyperOutgoingBody>,
+        worker: Arc<AbortOnDropJoinHandle<()>>,
+    ) {
+        self.state
+            .lock()
+            .unwrap()
+            .http2
+            .insert(key, Http2Connection { sender, worker });
+    }
+
+    /// Return an HTTP/1.1 connection to the pool once the request in flight
+    /// on it, including its response body, has completed.
+    ///
+    /// Connections that the server closes, or that cannot be reused because
+    /// the response body was dropped before it was fully read, are discarded.
+    pub(crate) fn checkin_http1(
+        &self,
+        key: PoolKey,
+        mut sender: http1::SendRequest<HyperOutgoingBody>,
+        worker: AbortOnDropJoinHandle<()>,
+        limits: ConnectionPoolLimits,
+    ) {
+        debug_assert!(limits.max_idle_per_authority > 0);
+        let pool = self.clone();
+        spawn_detached(async move {
+            if sender.ready().await.is_err() {
+                return;
+            }
+            let mut state = pool.state.lock().unwrap();
+            state.idle.retain(|_, conns| {
+                conns.retain(|conn| {
+                    conn.idle_since.elapsed() < limits.idle_timeout && !conn.sender.is_closed()
+                });
+                !conns.is_empty()
+            });
+            let idle = state.idle.entry(key).or_default();
+            if idle.len() >= limits.max_idle_per_authority {
+                idle.remove(0);
+            }
+            idle.push(IdleConnection {
+                sender,
+                worker,
+                idle_since: Instant::now(),
+            });
+        });
+    }
+}
+
+impl std::fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("idle_connections", &self.idle_connections())
+            .field("http2_connections", &self.http2_connections())
+            .finish()
+    }
+}
+
+/// Spawn `future` on the ambient runtime without tying its lifetime to a
+/// handle.
+fn spawn_detached<F>(future: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    drop(wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
+        tokio::task::spawn(future)
+    }));
+}
+
+/// The executor with which HTTP/2 connections spawn the tasks driving their
+/// streams.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Executor;
+
+impl<F> hyper::rt::Executor<F> for Executor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        spawn_detached(future);
+    }
+}
+
+/// A response body received over a shared HTTP/2 connection, which keeps the
+/// connection open for as long as the body is being read.
+pub(crate) struct Http2Body<B> {
+    pub(crate) body: B,
+    pub(crate) _worker: Arc<AbortOnDropJoinHandle<()>>,
+}
+
+impl<B: Body + Unpin> Body for Http2Body<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
@@ -7,6 +7,9 @@ use crate::{
     body::{HostIncomingBody, HyperIncomingBody, HyperOutgoingBody},
     error::dns_error,
     hyper_request_error,
+    policy::EgressPolicy,
+    pool::{Connection, ConnectionPool, ConnectionPoolLimits, Executor, Http2Body, PoolKey},
+    tls::TlsConfig,
 };
 use anyhow::bail;
 use bytes::Bytes;
@@ -14,6 +17,9 @@ use http_body_util::BodyExt;
 use hyper::body::Body;
 use hyper::header::HeaderName;
 use std::any::Any;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::time::timeout;
@@ -23,13 +29,74 @@ use wasmtime_wasi::{runtime::AbortOnDropJoinHandle, IoImpl, IoView, Subscribe};
 /// Capture the state necessary for use in the wasi-http API implementation.
 #[derive(Debug)]
 pub struct WasiHttpCtx {
-    _priv: (),
+    connection_pool: Option<ConnectionPool>,
+    connection_pool_limits: ConnectionPoolLimits,
+    tls_config: Arc<TlsConfig>,
+    egress_policy: EgressPolicy,
 }
 
 impl WasiHttpCtx {
     /// Create a new context.
+    ///
+    /// Outgoing requests use a new connection each, unless a pool is
+    /// configured with [`WasiHttpCtx::set_connection_pool`].
     pub fn new() -> Self {
-        Self { _priv: () }
+        Self {
+            connection_pool: None,
+            connection_pool_limits: ConnectionPoolLimits::default(),
+            tls_config: Arc::new(TlsConfig::default()),
+            egress_policy: EgressPolicy::default(),
+        }
+    }
+
+    /// Configure the pool from which outgoing requests reuse connections.
+    ///
+    /// The pool may be shared with other contexts, so that requests made by
+    /// many short-lived stores can reuse each other's connections. With
+    /// `None`, every outgoing request uses a new connection.
+    pub fn set_connection_pool(&mut self, pool: Option<ConnectionPool>) {
+        self.connection_pool = pool;
+    }
+
+    /// The pool from which outgoing requests reuse connections, if any.
+    pub fn connection_pool(&self) -> Option<&ConnectionPool> {
+        self.connection_pool.as_ref()
+    }
+
+    /// Configure the limits on pooled connections applied to outgoing
+    /// requests.
+    pub fn set_connection_pool_limits(&mut self, limits: ConnectionPoolLimits) {
+        self.connection_pool_limits = limits;
+    }
+
+    /// The limits on pooled connections applied to outgoing requests.
+    pub fn connection_pool_limits(&self) -> ConnectionPoolLimits {
+        self.connection_pool_limits
+    }
+
+    /// Configure how outgoing requests using TLS verify servers and identify
+    /// themselves.
+    pub fn set_tls_config(&mut self, config: Arc<TlsConfig>) {
+        self.tls_config = config;
+    }
+
+    /// The TLS configuration of outgoing requests.
+    pub fn tls_config(&self) -> &Arc<TlsConfig> {
+        &self.tls_config
+    }
+
+    /// Configure which outgoing requests guests may send, and the limits
+    /// applied to them.
+    ///
+    /// The policy may be a clone of one shared with other contexts, in which
+    /// case its limit on concurrent requests applies to all of them together.
+    pub fn set_egress_policy(&mut self, policy: EgressPolicy) {
+        self.egress_policy = policy;
+    }
+
+    /// The policy applied to outgoing requests.
+    pub fn egress_policy(&self) -> &EgressPolicy {
+        &self.egress_policy
     }
 }
 
@@ -305,6 +372,9 @@ pub(crate) fn remove_forbidden_headers(
 }
 
 /// Configuration for an outgoing request.
+///
+/// Create one with [`OutgoingRequestConfig::new`] and then set any of the
+/// remaining fields.
 pub struct OutgoingRequestConfig {
     /// Whether to use TLS for the request.
     pub use_tls: bool,
@@ -314,6 +384,37 @@ pub struct OutgoingRequestConfig {
     pub first_byte_timeout: Duration,
     /// The timeout between chunks of a streaming body
     pub between_bytes_timeout: Duration,
+    /// The pool to reuse a connection from, and to return the connection to
+    /// afterwards. With `None`, a new connection is used for just this
+    /// request.
+    pub pool: Option<ConnectionPool>,
+    /// The limits applied to pooled connections, including whether HTTP/2
+    /// may be negotiated.
+    pub pool_limits: ConnectionPoolLimits,
+    /// How to verify the server and identify the client, if using TLS.
+    pub tls_config: Arc<TlsConfig>,
+}
+
+impl OutgoingRequestConfig {
+    /// Configuration for a request with the given timeouts, sent over a new
+    /// connection that isn't pooled and, if using TLS, verified against the
+    /// default [`TlsConfig`].
+    pub fn new(
+        use_tls: bool,
+        connect_timeout: Duration,
+        first_byte_timeout: Duration,
+        between_bytes_timeout: Duration,
+    ) -> Self {
+        Self {
+            use_tls,
+            connect_timeout,
+            first_byte_timeout,
+            between_bytes_timeout,
+            pool: None,
+            pool_limits: ConnectionPoolLimits::default(),
+            tls_config: Default::default(),
+        }
+    }
 }
 
 /// The default implementation of how an outgoing request is sent.
@@ -341,6 +442,9 @@ pub async fn default_send_request_handler(
         connect_timeout,
         first_byte_timeout,
         between_bytes_timeout,
+        pool,
+        pool_limits,
+        tls_config,
     }: OutgoingRequestConfig,
 ) -> Result<IncomingResponse, types::ErrorCode> {
     let authority = if let Some(authority) = request.uri().authority() {
@@ -353,7 +457,218 @@ pub async fn default_send_request_handler(
     } else {
         return Err(types::ErrorCode::HttpRequestUriInvalid);
     };
-    let tcp_stream = timeout(connect_timeout, TcpStream::connect(&authority))
+    let key = PoolKey {
+        authority,
+        tls_config: use_tls.then_some(tls_config),
+    };
+
+    let (conn, pooled) = match pool
+        .as_ref()
+        .and_then(|pool| pool.checkout(&key, &pool_limits))
+    {
+        Some(conn) => (conn, true),
+        None => {
+            let conn = connect(&key, connect_timeout, pool_limits.http2).await?;
+            if let (Some(pool), Connection::Http2 { sender, worker }) = (&pool, &conn) {
+                pool.insert_http2(key.clone(), sender.clone(), worker.clone());
+            }
+            (conn, false)
+        }
+    };
+
+    match conn {
+        Connection::Http1 {
+            mut sender,
+            mut worker,
+        } => {
+            // at this point, the request contains the scheme and the authority, but
+            // the http packet should only include those if addressing a proxy, so
+            // remove them here, since SendRequest::send_request does not do it for us
+            *request.uri_mut() = http::Uri::builder()
+                .path_and_query(
+                    request
+                        .uri()
+                        .path_and_query()
+                        .map(|p| p.as_str())
+                        .unwrap_or("/"),
+                )
+                .build()
+                .expect("comes from valid request");
+
+            let resp = if pooled {
+                send_pooled_http1(
+                    &key,
+                    connect_timeout,
+                    first_byte_timeout,
+                    &mut sender,
+                    &mut worker,
+                    request,
+                )
+                .await?
+            } else {
+                timeout(first_byte_timeout, sender.send_request(request))
+                    .await
+                    .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+                    .map_err(hyper_request_error)?
+            };
+
+            // A pooled connection is kept alive by the pool, rather than by
+            // the response body.
+            let worker = match pool {
+                Some(pool) if pool_limits.max_idle_per_authority > 0 => {
+                    pool.checkin_http1(key, sender, worker, pool_limits);
+                    None
+                }
+                _ => Some(worker),
+            };
+            Ok(IncomingResponse {
+                resp: resp.map(|body| body.map_err(hyper_request_error).boxed()),
+                worker,
+                between_bytes_timeout,
+            })
+        }
+        Connection::Http2 { mut sender, worker } => {
+            // HTTP/2 carries the authority in the request's URI, which must
+            // stay in absolute form, instead of in a `host` header.
+            request.headers_mut().remove(hyper::header::HOST);
+
+            let resp = timeout(first_byte_timeout, sender.send_request(request))
+                .await
+                .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+                .map_err(hyper_request_error)?;
+
+            // The connection may be shared with other requests, so each
+            // response body keeps it alive instead of owning it.
+            Ok(IncomingResponse {
+                resp: resp.map(|body| {
+                    Http2Body {
+                        body,
+                        _worker: worker,
+                    }
+                    .map_err(hyper_request_error)
+                    .boxed()
+                }),
+                worker: None,
+                between_bytes_timeout,
+            })
+        }
+    }
+}
+
+/// Send `request` over an HTTP/1.1 connection that was taken from a pool.
+///
+/// The server may have closed the connection while it sat idle in the pool, in
+/// which case sending the request fails. If that happens before hyper started
+/// sending the request's body, the request is sent once more over a new
+/// connection, which then replaces `sender` and `worker`. Requests whose body
+/// was already being sent can't be replayed, so they fail as usual.
+async fn send_pooled_http1(
+    key: &PoolKey,
+    connect_timeout: Duration,
+    first_byte_timeout: Duration,
+    sender: &mut hyper::client::conn::http1::SendRequest<HyperOutgoingBody>,
+    worker: &mut AbortOnDropJoinHandle<()>,
+    request: hyper::Request<HyperOutgoingBody>,
+) -> Result<hyper::Response<hyper::body::Incoming>, types::ErrorCode> {
+    let (parts, body) = request.into_parts();
+    let retry = hyper::Request::builder()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version);
+    let headers = parts.headers.clone();
+    let unsent = Arc::new(Mutex::new(Some(body)));
+    let body = UnsentBody {
+        unsent: unsent.clone(),
+        body: None,
+    };
+    let request = hyper::Request::from_parts(parts, body.boxed());
+
+    let error = match timeout(first_byte_timeout, sender.send_request(request))
+        .await
+        .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+    {
+        Ok(resp) => return Ok(resp),
+        Err(e) => e,
+    };
+    let Some(body) = unsent.lock().unwrap().take() else {
+        return Err(hyper_request_error(error));
+    };
+    tracing::debug!("pooled connection failed, retrying on a new connection: {error}");
+
+    let Connection::Http1 {
+        sender: new_sender,
+        worker: new_worker,
+    } = connect(key, connect_timeout, false).await?
+    else {
+        unreachable!("HTTP/2 is only used when offered");
+    };
+    *sender = new_sender;
+    *worker = new_worker;
+
+    let mut request = retry.body(body).expect("comes from valid request");
+    *request.headers_mut() = headers;
+    timeout(first_byte_timeout, sender.send_request(request))
+        .await
+        .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+        .map_err(hyper_request_error)
+}
+
+/// A request body which stays in `unsent` until hyper first polls it, so that
+/// the request can be retried if sending it fails before then.
+struct UnsentBody {
+    unsent: Arc<Mutex<Option<HyperOutgoingBody>>>,
+    body: Option<HyperOutgoingBody>,
+}
+
+impl Body for UnsentBody {
+    type Data = Bytes;
+    type Error = types::ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<hyper::body::Frame<Bytes>, types::ErrorCode>>> {
+        if self.body.is_none() {
+            self.body = self.unsent.lock().unwrap().take();
+        }
+        match &mut self.body {
+            Some(body) => Pin::new(body).poll_frame(cx),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.body {
+            Some(body) => body.is_end_stream(),
+            None => self
+                .unsent
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_none_or(|body| body.is_end_stream()),
+        }
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        match &self.body {
+            Some(body) => body.size_hint(),
+            None => self.unsent.lock().unwrap().as_ref().map_or_else(
+                || hyper::body::SizeHint::with_exact(0),
+                |body| body.size_hint(),
+            ),
+        }
+    }
+}
+
+/// Establish a new connection to `key`'s authority, negotiating HTTP/2 with
+/// ALPN if it is enabled and the connection uses TLS.
+async fn connect(
+    key: &PoolKey,
+    connect_timeout: Duration,
+    http2: bool,
+) -> Result<Connection, types::ErrorCode> {
+    let authority = &key.authority;
+    let tcp_stream = timeout(connect_timeout, TcpStream::connect(authority))
         .await
         .map_err(|_| types::ErrorCode::ConnectionTimeout)?
         .map_err(|e| match e.kind() {
@@ -372,105 +687,101 @@ pub async fn default_send_request_handler(
             }
         })?;
 
-    let (mut sender, worker) = if use_tls {
-        #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
-        {
-            return Err(crate::bindings::http::types::ErrorCode::InternalError(
-                Some("unsupported architecture for SSL".to_string()),
-            ));
-        }
+    let Some(tls_config) = &key.tls_config else {
+        return handshake_http1(TokioIo::new(tcp_stream), connect_timeout).await;
+    };
 
-        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
-        {
-            use rustls::pki_types::ServerName;
+    #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+    {
+        let _ = (tls_config, http2);
+        return Err(crate::bindings::http::types::ErrorCode::InternalError(
+            Some("unsupported architecture for SSL".to_string()),
+        ));
+    }
 
-            // derived from https://github.com/rustls/rustls/blob/main/examples/src/bin/simpleclient.rs
-            let root_cert_store = rustls::RootCertStore {
-                roots: webpki_roots::TLS_SERVER_ROOTS.into(),
-            };
-            let config = rustls::ClientConfig::builder()
-                .with_root_certificates(root_cert_store)
-                .with_no_client_auth();
-            let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
-            let mut parts = authority.split(":");
-            let host = parts.next().unwrap_or(&authority);
-            let domain = ServerName::try_from(host)
-                .map_err(|e| {
-                    tracing::warn!("dns lookup error: {e:?}");
-                    dns_error("invalid dns name".to_string(), 0)
-                })?
-                .to_owned();
-            let stream = connector.connect(domain, tcp_stream).await.map_err(|e| {
-                tracing::warn!("tls protocol error: {e:?}");
-                types::ErrorCode::TlsProtocolError
-            })?;
-            let stream = TokioIo::new(stream);
-
-            let (sender, conn) = timeout(
-                connect_timeout,
-                hyper::client::conn::http1::handshake(stream),
-            )
-            .await
-            .map_err(|_| types::ErrorCode::ConnectionTimeout)?
-            .map_err(hyper_request_error)?;
-
-            let worker = wasmtime_wasi::runtime::spawn(async move {
-                match conn.await {
-                    Ok(()) => {}
-                    // TODO: shouldn't throw away this error and ideally should
-                    // surface somewhere.
-                    Err(e) => tracing::warn!("dropping error {e}"),
-                }
-            });
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    {
+        use rustls::pki_types::ServerName;
+
+        // derived from https://github.com/rustls/rustls/blob/main/examples/src/bin/simpleclient.rs
+        let connector = tokio_rustls::TlsConnector::from(tls_config.client_config(http2)?);
+        let mut parts = authority.split(":");
+        let host = parts.next().unwrap_or(authority);
+        let domain = ServerName::try_from(tls_config.server_name_for(host))
+            .map_err(|e| {
+                tracing::warn!("dns lookup error: {e:?}");
+                dns_error("invalid dns name".to_string(), 0)
+            })?
+            .to_owned();
+        let stream = connector.connect(domain, tcp_stream).await.map_err(|e| {
+            tracing::warn!("tls protocol error: {e:?}");
+            types::ErrorCode::TlsProtocolError
+        })?;
 
-            (sender, worker)
+        let negotiated_http2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+        let stream = TokioIo::new(stream);
+        if negotiated_http2 {
+            handshake_http2(stream, connect_timeout).await
+        } else {
+            handshake_http1(stream, connect_timeout).await
         }
-    } else {
-        let tcp_stream = TokioIo::new(tcp_stream);
-        let (sender, conn) = timeout(
-            connect_timeout,
-            // TODO: we should plumb the builder through the http context, and use it here
-            hyper::client::conn::http1::handshake(tcp_stream),
-        )
-        .await
-        .map_err(|_| types::ErrorCode::ConnectionTimeout)?
-        .map_err(hyper_request_error)?;
+    }
+}
 
-        let worker = wasmtime_wasi::runtime::spawn(async move {
-            match conn.await {
-                Ok(()) => {}
-                // TODO: same as above, shouldn't throw this error away.
-                Err(e) => tracing::warn!("dropping error {e}"),
-            }
-        });
+async fn handshake_http1<T>(
+    io: T,
+    connect_timeout: Duration,
+) -> Result<Connection, types::ErrorCode>
+where
+    T: hyper::rt::Read + hyper::rt::Write + Send + Unpin + 'static,
+{
+    let (sender, conn) = timeout(
+        connect_timeout,
+        // TODO: we should plumb the builder through the http context, and use it here
+        hyper::client::conn::http1::handshake(io),
+    )
+    .await
+    .map_err(|_| types::ErrorCode::ConnectionTimeout)?
+    .map_err(hyper_request_error)?;
+
+    let worker = wasmtime_wasi::runtime::spawn(async move {
+        match conn.await {
+            Ok(()) => {}
+            // TODO: shouldn't throw away this error and ideally should
+            // surface somewhere.
+            Err(e) => tracing::warn!("dropping error {e}"),
+        }
+    });
 
-        (sender, worker)
-    };
+    Ok(Connection::Http1 { sender, worker })
+}
 
-    // at this point, the request contains the scheme and the authority, but
-    // the http packet should only include those if addressing a proxy, so
-    // remove them here, since SendRequest::send_request does not do it for us
-    *request.uri_mut() = http::Uri::builder()
-        .path_and_query(
-            request
-                .uri()
-                .path_and_query()
-                .map(|p| p.as_str())
-                .unwrap_or("/"),
-        )
-        .build()
-        .expect("comes from valid request");
-
-    let resp = timeout(first_byte_timeout, sender.send_request(request))
-        .await
-        .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
-        .map_err(hyper_request_error)?
-        .map(|body| body.map_err(hyper_request_error).boxed());
+async fn handshake_http2<T>(
+    io: T,
+    connect_timeout: Duration,
+) -> Result<Connection, types::ErrorCode>
+where
+    T: hyper::rt::Read + hyper::rt::Write + Send + Unpin + 'static,
+{
+    let (sender, conn) = timeout(
+        connect_timeout,
+        hyper::client::conn::http2::handshake(Executor, io),
+    )
+    .await
+    .map_err(|_| types::ErrorCode::ConnectionTimeout)?
+    .map_err(hyper_request_error)?;
+
+    let worker = wasmtime_wasi::runtime::spawn(async move {
+        match conn.await {
+            Ok(()) => {}
+            // TODO: same as above, shouldn't throw this error away.
+            Err(e) => tracing::warn!("dropping error {e}"),
+        }
+    });
 
-    Ok(IncomingResponse {
-        resp,
-        worker: Some(worker),
-        between_bytes_timeout,
+    Ok(Connection::Http2 {
+        sender,
+        worker: Arc::new(worker),
     })
 }
 
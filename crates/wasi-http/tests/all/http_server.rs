@@ -34,6 +34,13 @@ impl Server {
     fn new<F>(
         run: impl FnOnce(TokioIo<tokio::net::TcpStream>) -> F + Send + 'static,
     ) -> Result<Self>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        Self::new_tcp(|stream| run(TokioIo::new(stream)))
+    }
+
+    fn new_tcp<F>(run: impl FnOnce(tokio::net::TcpStream) -> F + Send + 'static) -> Result<Self>
     where
         F: Future<Output = Result<()>>,
     {
@@ -55,7 +62,7 @@ impl Server {
             rt.block_on(async move {
                 tracing::debug!("preparing to accept connection");
                 let (stream, _) = listener.accept().await.map_err(anyhow::Error::from)?;
-                run(TokioIo::new(stream)).await
+                run(stream).await
             })
         });
         Ok(Self {
@@ -78,6 +85,21 @@ impl Server {
         })
     }
 
+    /// An HTTP/1.1 server which keeps its single connection alive for
+    /// multiple requests.
+    pub fn http1_keep_alive() -> Result<Self> {
+        tracing::debug!("initializing http1 keep-alive server");
+        Self::new(|io| async move {
+            let conn = hyper::server::conn::http1::Builder::new()
+                .keep_alive(true)
+                .serve_connection(io, service_fn(test))
+                .await;
+            tracing::trace!("connection result {:?}", conn);
+            conn?;
+            Ok(())
+        })
+    }
+
     pub fn http2() -> Result<Self> {
         tracing::debug!("initializing http2 server");
         Self::new(|io| async move {
@@ -100,6 +122,26 @@ impl Server {
         })
     }
 
+    /// An HTTP/1.1 server which accepts a single TLS connection using
+    /// `config`.
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    pub fn https(config: std::sync::Arc<rustls::ServerConfig>) -> Result<Self> {
+        tracing::debug!("initializing https server");
+        Self::new_tcp(|stream| async move {
+            let stream = tokio_rustls::TlsAcceptor::from(config)
+                .accept(stream)
+                .await
+                .context("TLS handshake failed")?;
+            let conn = hyper::server::conn::http1::Builder::new()
+                .keep_alive(false)
+                .serve_connection(TokioIo::new(stream), service_fn(test))
+                .await;
+            tracing::trace!("connection result {:?}", conn);
+            conn?;
+            Ok(())
+        })
+    }
+
     pub fn addr(&self) -> String {
         format!("localhost:{}", self.addr.port())
     }
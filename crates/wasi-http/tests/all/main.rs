@@ -5,7 +5,14 @@ use http_body::Frame;
 use http_body_util::{combinators::BoxBody, BodyExt, Collected, Empty, StreamBody};
 use hyper::{body::Bytes, server::conn::http1, service::service_fn, Method, StatusCode};
 use sha2::{Digest, Sha256};
-use std::{collections::HashMap, iter, net::Ipv4Addr, str, sync::Arc};
+use std::{
+    collections::HashMap,
+    iter,
+    net::Ipv4Addr,
+    str,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::task;
 use wasmtime::{
     component::{Component, Linker, ResourceTable},
@@ -16,11 +23,16 @@ use wasmtime_wasi_http::{
     bindings::http::types::{ErrorCode, Scheme},
     body::HyperOutgoingBody,
     io::TokioIo,
+    policy::EgressPolicy,
+    pool::ConnectionPool,
     types::{self, HostFutureIncomingResponse, IncomingResponse, OutgoingRequestConfig},
     HttpResult, WasiHttpCtx, WasiHttpView,
 };
 
 mod http_server;
+// TLS isn't supported on riscv/s390x yet
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+mod tls;
 
 type RequestSender = Arc<
     dyn Fn(hyper::Request<HyperOutgoingBody>, OutgoingRequestConfig) -> HostFutureIncomingResponse
@@ -129,6 +141,23 @@ async fn run_wasi_http(
     req: hyper::Request<BoxBody<Bytes, hyper::Error>>,
     send_request: Option<RequestSender>,
     rejected_authority: Option<String>,
+) -> anyhow::Result<Result<hyper::Response<Collected<Bytes>>, ErrorCode>> {
+    run_wasi_http_with_ctx(
+        component_filename,
+        req,
+        send_request,
+        rejected_authority,
+        WasiHttpCtx::new(),
+    )
+    .await
+}
+
+async fn run_wasi_http_with_ctx(
+    component_filename: &str,
+    req: hyper::Request<BoxBody<Bytes, hyper::Error>>,
+    send_request: Option<RequestSender>,
+    rejected_authority: Option<String>,
+    http: WasiHttpCtx,
 ) -> anyhow::Result<Result<hyper::Response<Collected<Bytes>>, ErrorCode>> {
     let stdout = MemoryOutputPipe::new(4096);
     let stderr = MemoryOutputPipe::new(4096);
@@ -146,7 +175,6 @@ async fn run_wasi_http(
     builder.stdout(stdout.clone());
     builder.stderr(stderr.clone());
     let wasi = builder.build();
-    let http = WasiHttpCtx::new();
     let ctx = Ctx {
         table,
         wasi,
@@ -406,6 +434,93 @@ async fn wasi_http_hash_all_with_reject() -> Result<()> {
     Ok(())
 }
 
+#[test_log::test(tokio::test)]
+async fn wasi_http_hash_all_with_egress_policy() -> Result<()> {
+    const BODY: &str = "All mimsy were the borogoves,";
+
+    // Respond to every request with `BODY`, announcing its length only for
+    // requests to `/with-length`.
+    let send_request = Arc::new(
+        |request: hyper::Request<HyperOutgoingBody>, config: OutgoingRequestConfig| {
+            let mut resp = hyper::Response::new(
+                body::full(Bytes::from_static(BODY.as_bytes()))
+                    .map_err(wasmtime_wasi_http::hyper_response_error)
+                    .boxed(),
+            );
+            if request.uri().path() == "/with-length" {
+                resp.headers_mut()
+                    .insert(hyper::header::CONTENT_LENGTH, BODY.len().into());
+            }
+            HostFutureIncomingResponse::ready(Ok(Ok(IncomingResponse {
+                resp,
+                worker: None,
+                between_bytes_timeout: config.between_bytes_timeout,
+            })))
+        },
+    ) as RequestSender;
+
+    let mut policy = EgressPolicy::new();
+    policy
+        .allow_authority("*.example.com")?
+        .allow_port(80)
+        .allow_scheme("http")
+        .max_response_body_size(10);
+    let mut http = WasiHttpCtx::new();
+    http.set_egress_policy(policy.clone());
+
+    let request = hyper::Request::builder()
+        .method(http::Method::GET)
+        .uri("http://example.com:8080/hash-all")
+        .header("url", "http://forbidden.com/")
+        .header("url", "http://api.example.com:8080/")
+        .header("url", "https://api.example.com/")
+        .header("url", "http://api.example.com/with-length")
+        .header("url", "http://api.example.com/streaming")
+        .body(body::empty())?;
+
+    let response = run_wasi_http_with_ctx(
+        test_programs_artifacts::API_PROXY_STREAMING_COMPONENT,
+        request,
+        Some(send_request),
+        None,
+        http,
+    )
+    .await??;
+
+    let body = response.into_body().to_bytes();
+    let body = str::from_utf8(&body)?;
+    let mut lines = 0;
+    for line in body.lines() {
+        println!("{line}");
+        lines += 1;
+        let (url, result) = line.split_once(": ").unwrap();
+        match url {
+            "http://forbidden.com/"
+            | "http://api.example.com:8080/"
+            | "https://api.example.com/" => {
+                assert!(result.contains("HttpRequestDenied"))
+            }
+            "http://api.example.com/with-length" => {
+                assert!(result.contains("HttpResponseBodySize(Some(29))"))
+            }
+            "http://api.example.com/streaming" => {
+                let mut hasher = Sha256::new();
+                hasher.update(BODY);
+                use base64::Engine;
+                let hash =
+                    base64::engine::general_purpose::STANDARD_NO_PAD.encode(hasher.finalize());
+                assert_ne!(result, hash);
+            }
+            _ => panic!("unexpected url: {url}"),
+        }
+    }
+    assert_eq!(lines, 5);
+
+    // Every request has finished, including those whose bodies failed.
+    assert_eq!(policy.requests_in_flight(), 0);
+    Ok(())
+}
+
 #[test_log::test(tokio::test)]
 async fn wasi_http_echo() -> Result<()> {
     do_wasi_http_echo("echo", None).await
@@ -551,6 +666,99 @@ async fn wasi_http_without_port() -> Result<()> {
     Ok(())
 }
 
+#[test_log::test(tokio::test)]
+async fn outgoing_requests_reuse_pooled_connections() -> Result<()> {
+    // The server only accepts a single connection, so the second request can
+    // only succeed by reusing the first request's connection.
+    let server = Server::http1_keep_alive()?;
+    let pool = ConnectionPool::new();
+
+    for _ in 0..2 {
+        let request = hyper::Request::get(format!("http://{}/", server.addr()))
+            .body(body::empty().map_err(|_| unreachable!()).boxed())?;
+        let mut config = OutgoingRequestConfig::new(
+            false,
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        );
+        config.pool = Some(pool.clone());
+        let response = types::default_send_request_handler(request, config).await?;
+        assert_eq!(response.resp.status(), StatusCode::OK);
+        assert!(response.worker.is_none());
+        response.resp.into_body().collect().await?;
+
+        // The connection returns to the pool once the response is complete.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.idle_connections() == 0 {
+            assert!(Instant::now() < deadline, "connection was not pooled");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+    assert_eq!(pool.idle_connections(), 1);
+
+    // Close the pooled connection so that the server can shut down.
+    pool.clear();
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn outgoing_requests_retry_closed_pooled_connections() -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Each connection serves a single request, and is then closed by the
+    // server once the client has pooled it.
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let (close_tx, mut close_rx) = tokio::sync::mpsc::channel::<()>(1);
+    let server = task::spawn(async move {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().await?;
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await?;
+                anyhow::ensure!(n > 0, "connection closed mid-request");
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            close_rx.recv().await;
+        }
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let pool = ConnectionPool::new();
+    for _ in 0..2 {
+        let request = hyper::Request::get(format!("http://{addr}/"))
+            .body(body::empty().map_err(|_| unreachable!()).boxed())?;
+        let mut config = OutgoingRequestConfig::new(
+            false,
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        );
+        config.pool = Some(pool.clone());
+        let response = types::default_send_request_handler(request, config).await?;
+        assert_eq!(response.resp.status(), StatusCode::OK);
+        response.resp.into_body().collect().await?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.idle_connections() == 0 {
+            assert!(Instant::now() < deadline, "connection was not pooled");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Close the pooled connection behind the client's back.
+        close_tx.send(()).await?;
+    }
+
+    // Both requests were served, each on its own connection.
+    server.await??;
+    Ok(())
+}
+
 mod body {
     use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
     use hyper::body::Bytes;
@@ -0,0 +1,136 @@
+//! Tests of outgoing requests over TLS to a local server whose certificate is
+//! issued by a private certificate authority, and which requires clients to
+//! present a certificate from the same authority.
+//!
+//! The certificates in `tls/` were generated with `openssl`. The server's
+//! certificate is issued for `test.internal`.
+
+use crate::http_server::Server;
+use anyhow::Result;
+use base64::Engine;
+use http_body_util::{BodyExt, Empty};
+use hyper::StatusCode;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::{sync::Arc, time::Duration};
+use wasmtime_wasi_http::{
+    bindings::http::types::ErrorCode,
+    tls::TlsConfig,
+    types::{self, IncomingResponse, OutgoingRequestConfig},
+};
+
+const CA: &str = include_str!("tls/ca.pem");
+const SERVER_CERT: &str = include_str!("tls/server.pem");
+const SERVER_KEY: &str = include_str!("tls/server-key.pem");
+const CLIENT_CERT: &str = include_str!("tls/client.pem");
+const CLIENT_KEY: &str = include_str!("tls/client-key.pem");
+
+/// Decode the single block in `pem`.
+fn der(pem: &str) -> Vec<u8> {
+    let encoded: String = pem.lines().filter(|l| !l.starts_with("-----")).collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap()
+}
+
+fn server() -> Result<Server> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from(der(CA)))?;
+    let verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(roots)).build()?;
+    let config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![CertificateDer::from(der(SERVER_CERT))],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(der(SERVER_KEY))),
+        )?;
+    Server::https(Arc::new(config))
+}
+
+async fn send(server: &Server, tls_config: TlsConfig) -> Result<IncomingResponse, ErrorCode> {
+    let request = hyper::Request::get(format!("https://{}/", server.addr()))
+        .body(Empty::new().map_err(|_| unreachable!()).boxed())
+        .unwrap();
+    let mut config = OutgoingRequestConfig::new(
+        true,
+        Duration::from_secs(5),
+        Duration::from_secs(5),
+        Duration::from_secs(5),
+    );
+    config.tls_config = Arc::new(tls_config);
+    types::default_send_request_handler(request, config).await
+}
+
+/// Trust the private CA, present the client certificate, and expect the
+/// certificate's name when connecting to `localhost`.
+fn trusted_config() -> Result<TlsConfig> {
+    let mut config = TlsConfig::new();
+    config
+        .builtin_roots(false)
+        .add_root_certificates_pem(CA.as_bytes())?
+        .client_identity_pem(CLIENT_CERT.as_bytes(), CLIENT_KEY.as_bytes())?
+        .server_name("localhost", "test.internal");
+    Ok(config)
+}
+
+#[test_log::test(tokio::test)]
+async fn private_ca_and_client_identity() -> Result<()> {
+    let server = server()?;
+    let response = send(&server, trusted_config()?).await?;
+    assert_eq!(response.resp.status(), StatusCode::OK);
+    response.resp.into_body().collect().await?;
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn untrusted_server_is_rejected() -> Result<()> {
+    let server = server()?;
+    let mut config = TlsConfig::new();
+    config
+        .client_identity_pem(CLIENT_CERT.as_bytes(), CLIENT_KEY.as_bytes())?
+        .server_name("localhost", "test.internal");
+    let result = send(&server, config).await;
+    assert!(matches!(result, Err(ErrorCode::TlsProtocolError)));
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn server_name_must_match() -> Result<()> {
+    let server = server()?;
+    let mut config = TlsConfig::new();
+    config
+        .add_root_certificates_pem(CA.as_bytes())?
+        .client_identity_pem(CLIENT_CERT.as_bytes(), CLIENT_KEY.as_bytes())?;
+    let result = send(&server, config).await;
+    assert!(matches!(result, Err(ErrorCode::TlsProtocolError)));
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn client_identity_is_required() -> Result<()> {
+    let server = server()?;
+    let mut config = TlsConfig::new();
+    config
+        .add_root_certificates_pem(CA.as_bytes())?
+        .server_name("localhost", "test.internal");
+
+    // With TLS 1.3 the server only rejects the client after the handshake has
+    // completed on the client's side, so the failure may surface while
+    // reading the response instead.
+    match send(&server, config).await {
+        Err(_) => {}
+        Ok(response) => assert!(response.resp.into_body().collect().await.is_err()),
+    }
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn insecure_skip_verify() -> Result<()> {
+    let server = server()?;
+    let mut config = TlsConfig::new();
+    config
+        .client_identity_pem(CLIENT_CERT.as_bytes(), CLIENT_KEY.as_bytes())?
+        .insecure_skip_verify(true);
+    let response = send(&server, config).await?;
+    assert_eq!(response.resp.status(), StatusCode::OK);
+    response.resp.into_body().collect().await?;
+    Ok(())
+}
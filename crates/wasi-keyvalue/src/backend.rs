@@ -0,0 +1,98 @@
+//! The traits that storage backends implement to provide `wasi-keyvalue`
+//! buckets.
+
+use crate::Error;
+use std::sync::Arc;
+
+/// A storage backend for the `wasi-keyvalue` API.
+///
+/// A backend provides access to any number of named buckets, which components
+/// open with `wasi:keyvalue/store.open`. A backend is shared by every store
+/// whose [`WasiKeyValueCtx`](crate::WasiKeyValueCtx) was created from it, so
+/// data written by one instance is visible to all others using the same
+/// backend.
+pub trait KeyValueBackend: Send + Sync + 'static {
+    /// Open the bucket named `identifier`.
+    ///
+    /// Backends should return [`Error::NoSuchStore`] for identifiers that do
+    /// not name a bucket they can provide.
+    fn open(&self, identifier: &str) -> Result<Arc<dyn KeyValueBucket>, Error>;
+}
+
+/// A single bucket of key-value pairs, provided by a [`KeyValueBackend`].
+///
+/// Each operation must be atomic with respect to other operations on the
+/// same bucket.
+pub trait KeyValueBucket: Send + Sync + 'static {
+    /// Get the value associated with `key`, if any.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Set the value associated with `key`, replacing any existing value.
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Error>;
+
+    /// Delete `key` and its value, if present.
+    fn delete(&self, key: &str) -> Result<(), Error>;
+
+    /// Is there a value associated with `key`?
+    fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// List all keys in this bucket, in a stable order.
+    fn keys(&self) -> Result<Vec<String>, Error>;
+
+    /// List at most `limit` keys in this bucket, in ascending order, starting
+    /// with the first key that sorts after `after` (or with the first key at
+    /// all, if `after` is `None`).
+    ///
+    /// This is used to paginate `list-keys`, so backends holding many keys
+    /// should override the default implementation, which lists every key with
+    /// [`KeyValueBucket::keys`].
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, Error> {
+        let mut keys = self.keys()?;
+        keys.sort();
+        let start = match after {
+            Some(after) => keys.partition_point(|key| key.as_str() <= after),
+            None => 0,
+        };
+        keys.truncate(start.saturating_add(limit));
+        keys.drain(..start);
+        Ok(keys)
+    }
+
+    /// Atomically increment the number stored at `key` by `delta`, returning
+    /// the new value.
+    ///
+    /// Values are stored as decimal strings, and a missing key is treated as
+    /// zero. See [`increment_value`] for a helper implementing these
+    /// semantics.
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error>;
+
+    /// Atomically set the value of `key` to `new`, but only if its value is
+    /// still `current` (where `None` means that the key does not exist).
+    ///
+    /// Returns whether the value was swapped.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, Error>;
+}
+
+/// Compute the result of incrementing the stored `current` value by `delta`,
+/// following the semantics of [`KeyValueBucket::increment`].
+///
+/// Returns the new value.
+pub fn increment_value(current: Option<&[u8]>, delta: u64) -> Result<u64, Error> {
+    let current = match current {
+        Some(bytes) => std::str::from_utf8(bytes)
+            .map_err(|e| Error::Other(e.to_string()))?
+            .parse::<u64>()
+            .map_err(|e| Error::Other(e.to_string()))?,
+        None => 0,
+    };
+    current
+        .checked_add(delta)
+        .ok_or_else(|| Error::Other("integer overflow incrementing value".to_string()))
+}
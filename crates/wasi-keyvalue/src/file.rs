@@ -0,0 +1,291 @@
+//! The persistent, file-backed storage backend.
+
+use crate::backend::{increment_value, KeyValueBackend, KeyValueBucket};
+use crate::Error;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A [`KeyValueBackend`] which persists buckets in a host directory.
+///
+/// Each bucket is a subdirectory of the backend's root directory, and each
+/// key-value pair is a file within its bucket's directory whose contents are
+/// the value. Bucket identifiers and keys are escaped to produce portable file
+/// names, so any identifier is accepted and buckets are created on demand.
+/// Identifiers and keys whose escaped form is longer than 255 bytes, the
+/// file name limit of most file systems, are rejected with [`Error::Other`].
+///
+/// Writes replace files atomically, so readers never observe partially
+/// written values, even from other processes. Read-modify-write operations
+/// such as `increment` and `compare_and_swap` are only atomic with respect to
+/// other users of the same `FileBackend`, however, so a bucket directory
+/// should not be shared by multiple processes that increment the same keys.
+pub struct FileBackend {
+    root: PathBuf,
+    buckets: Mutex<HashMap<String, Arc<FileBucket>>>,
+}
+
+impl FileBackend {
+    /// Create a new file-backed backend which stores its buckets in `root`.
+    ///
+    /// The directory is created if it does not exist yet.
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The directory in which this backend stores its buckets.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl KeyValueBackend for FileBackend {
+    fn open(&self, identifier: &str) -> Result<Arc<dyn KeyValueBucket>, Error> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get(identifier) {
+            return Ok(bucket.clone());
+        }
+
+        let dir = self.root.join(file_name("bucket identifier", identifier)?);
+        fs::create_dir_all(&dir)?;
+        let bucket = Arc::new(FileBucket {
+            dir,
+            lock: Mutex::new(()),
+        });
+        buckets.insert(identifier.to_string(), bucket.clone());
+        Ok(bucket)
+    }
+}
+
+struct FileBucket {
+    dir: PathBuf,
+    /// Serializes writes to this bucket, so that read-modify-write operations
+    /// like `increment` are atomic.
+    lock: Mutex<()>,
+}
+
+impl FileBucket {
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        Ok(self.dir.join(file_name("key", key)?))
+    }
+
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.path(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Atomically replace the value of `key` by writing a temporary file and
+    /// renaming it into place.
+    fn write(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let path = self.path(key)?;
+        static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+        let temp = self.dir.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, value)?;
+        if let Err(e) = fs::rename(&temp, path) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+impl KeyValueBucket for FileBucket {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.read(key)
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let _guard = self.lock.lock().unwrap();
+        self.write(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Error> {
+        let _guard = self.lock.lock().unwrap();
+        match fs::remove_file(self.path(key)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.path(key)?.try_exists()?)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            // Skip temporary files and anything else we didn't create.
+            if let Some(key) = name.to_str().and_then(decode_name) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, Error> {
+        // Directory entries come back in no particular order, so every entry
+        // has to be visited, but only the smallest `limit` keys after `after`
+        // are kept in memory, in a max-heap.
+        let mut page = BinaryHeap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let Some(key) = name.to_str().and_then(decode_name) else {
+                continue;
+            };
+            if after.is_some_and(|after| key.as_str() <= after) {
+                continue;
+            }
+            if page.len() < limit {
+                page.push(key);
+            } else if page.peek().is_some_and(|largest| key < *largest) {
+                page.pop();
+                page.push(key);
+            }
+        }
+        Ok(page.into_sorted_vec())
+    }
+
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error> {
+        let _guard = self.lock.lock().unwrap();
+        let current = self.read(key)?;
+        let new_value = increment_value(current.as_deref(), delta)?;
+        self.write(key, new_value.to_string().as_bytes())?;
+        Ok(new_value)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, Error> {
+        let _guard = self.lock.lock().unwrap();
+        if self.read(key)?.as_deref() != current {
+            return Ok(false);
+        }
+        self.write(key, new)?;
+        Ok(true)
+    }
+}
+
+/// The longest file name that `FileBackend` creates, which is the limit of
+/// most file systems.
+const MAX_FILE_NAME_LEN: usize = 255;
+
+/// Escape a bucket identifier or key into a file name with `encode_name`,
+/// failing if the result is too long to be a file name.
+fn file_name(what: &str, name: &str) -> Result<String, Error> {
+    let encoded = encode_name(name);
+    if encoded.len() > MAX_FILE_NAME_LEN {
+        return Err(Error::Other(format!(
+            "{what} is too long for the file backend: it is stored as a \
+             {}-byte file name, but at most {MAX_FILE_NAME_LEN} bytes are supported",
+            encoded.len()
+        )));
+    }
+    Ok(encoded)
+}
+
+/// Escape an arbitrary bucket identifier or key into a file name.
+///
+/// Every escaped name starts with `_`, so that empty names, `.`, and `..` are
+/// all valid file names and cannot collide with temporary files. Bytes other
+/// than lowercase ASCII letters, digits, `-`, `.`, and `_` are percent-encoded,
+/// which keeps names distinct on case-insensitive file systems too.
+fn encode_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len() + 1);
+    encoded.push('_');
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' => encoded.push(char::from(byte)),
+            _ => write!(encoded, "%{byte:02X}").unwrap(),
+        }
+    }
+    encoded
+}
+
+/// The inverse of `encode_name`, returning `None` for file names that it could
+/// not have produced.
+fn decode_name(file_name: &str) -> Option<String> {
+    let mut bytes = file_name.strip_prefix('_')?.bytes();
+    let mut decoded = Vec::new();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_round_trip() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "hello",
+            "Hello World",
+            "a/b\\c",
+            "%_",
+            "ünïcödé",
+        ] {
+            let encoded = encode_name(name);
+            assert!(encoded.starts_with('_'));
+            assert!(!encoded.contains(['/', '\\']));
+            assert_eq!(decode_name(&encoded).as_deref(), Some(name));
+        }
+        assert_ne!(encode_name("a"), encode_name("A"));
+        assert_eq!(decode_name(".tmp-1-2"), None);
+    }
+
+    #[test]
+    fn long_names_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = FileBackend::new(dir.path()).unwrap();
+        let bucket = backend.open("bucket").unwrap();
+
+        // 254 allowed bytes, plus the `_` prefix, is the longest file name.
+        let longest = "k".repeat(254);
+        bucket.set(&longest, b"value").unwrap();
+        assert_eq!(
+            bucket.get(&longest).unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+
+        let too_long = "k".repeat(255);
+        assert!(bucket.set(&too_long, b"value").is_err());
+        assert!(bucket.get(&too_long).is_err());
+        assert!(backend.open(&too_long).is_err());
+        match bucket.set(&"K".repeat(100), b"value") {
+            Err(Error::Other(msg)) => assert!(msg.contains("key is too long"), "{msg}"),
+            _ => panic!("escaped key of 301 bytes should be rejected"),
+        }
+    }
+}
@@ -4,8 +4,24 @@
 //! API. With this crate, the runtime can run components that call APIs in
 //! [wasi-keyvalue] and provide components with access to key-value storages.
 //!
-//! Currently supported storage backends:
-//! * In-Memory (empty identifier)
+//! Storage is provided by a [`KeyValueBackend`], which can provide any number
+//! of named buckets and is shared by every store using it. Built-in backends
+//! are:
+//! * [`InMemoryBackend`]: buckets live in memory (the default)
+//! * [`FileBackend`]: buckets persist in a host directory
+//!
+//! Custom storage can be plugged in by implementing [`KeyValueBackend`] and
+//! [`KeyValueBucket`].
+//!
+//! Changes that components make through a [`WasiKeyValueCtx`], or any of its
+//! clones, can be observed with [`WasiKeyValueCtx::watch`], and forwarded to
+//! components exporting the `wasi:keyvalue/watcher` interface with
+//! [`deliver_watch_event`]. Changes made by anything else sharing the same
+//! backend are not observed.
+//!
+//! Compare-and-swap operations are provided by the `wasmtime:wasi-keyvalue/cas`
+//! interface, as the vendored draft of `wasi:keyvalue/atomics` doesn't include
+//! them.
 //!
 //! # Examples
 //!
@@ -67,13 +83,31 @@
 
 #![deny(missing_docs)]
 
+mod backend;
+mod file;
+mod memory;
+mod watch;
+
+pub use self::backend::{increment_value, KeyValueBackend, KeyValueBucket};
+pub use self::file::FileBackend;
+pub use self::memory::InMemoryBackend;
+pub use self::watch::{deliver_watch_event, WatchEvent, WatchSubscription};
+
+#[allow(missing_docs)]
 mod generated {
     wasmtime::component::bindgen!({
         path: "wit",
-        world: "wasi:keyvalue/imports",
+        world: "wasmtime:wasi-keyvalue/watch-service",
+        // Flag this as "possibly async" which will cause the `watcher`
+        // exports to be generated as async, but none of the imports here are
+        // async.
+        async: {
+            only_imports: ["nonexistent"],
+        },
         trappable_imports: true,
         with: {
             "wasi:keyvalue/store/bucket": crate::Bucket,
+            "wasmtime:wasi-keyvalue/cas/cas": crate::Cas,
         },
         trappable_error_type: {
             "wasi:keyvalue/store/error" => crate::Error,
@@ -81,42 +115,132 @@ mod generated {
     });
 }
 
+/// Raw bindings to the `wasi:keyvalue/watcher` exports.
+pub use self::generated::exports;
+
+/// Bindings to the `wasi:keyvalue/watch-service` world, plus the
+/// `wasmtime:wasi-keyvalue/cas` interface, for instantiating components that
+/// export `wasi:keyvalue/watcher`.
+pub use self::generated::{WatchService, WatchServiceIndices, WatchServicePre};
+
 use self::generated::wasi::keyvalue;
+use self::generated::wasmtime::wasi_keyvalue::cas;
+use self::watch::Watchers;
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
 use wasmtime::component::{Resource, ResourceTable, ResourceTableError};
 
-#[doc(hidden)]
+/// Errors returned by [`KeyValueBackend`]s and [`KeyValueBucket`]s.
+#[derive(Debug)]
 pub enum Error {
+    /// The requested bucket does not exist.
     NoSuchStore,
+    /// Access to the requested bucket was denied.
     AccessDenied,
+    /// Some other, backend-specific error occurred.
     Other(String),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoSuchStore => f.write_str("no such store"),
+            Error::AccessDenied => f.write_str("access denied"),
+            Error::Other(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<ResourceTableError> for Error {
     fn from(err: ResourceTableError) -> Self {
         Self::Other(err.to_string())
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Other(err.to_string())
+    }
+}
+
 #[doc(hidden)]
 pub struct Bucket {
-    in_memory_data: HashMap<String, Vec<u8>>,
+    identifier: String,
+    inner: Arc<dyn KeyValueBucket>,
+    cursors: Cursors,
+}
+
+/// The most continuation cursors that a bucket handle remembers at once.
+///
+/// Guests may abandon a listing at any point, so older cursors are forgotten
+/// rather than kept alive for the lifetime of the handle.
+const MAX_CURSORS: usize = 16;
+
+/// The continuation cursors handed out by `list-keys` for one bucket handle.
+///
+/// Cursors are opaque to guests, and each one maps to the last key of the page
+/// that it continues, so that listing resumes at the right key even if keys
+/// are added or removed between pages.
+#[derive(Default)]
+struct Cursors {
+    next_cursor: u64,
+    recent: VecDeque<(u64, String)>,
+}
+
+impl Cursors {
+    fn insert(&mut self, last_key: String) -> u64 {
+        let cursor = self.next_cursor;
+        self.next_cursor += 1;
+        if self.recent.len() == MAX_CURSORS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((cursor, last_key));
+        cursor
+    }
+
+    fn get(&self, cursor: u64) -> Option<&str> {
+        self.recent
+            .iter()
+            .find(|(c, _)| *c == cursor)
+            .map(|(_, key)| key.as_str())
+    }
+}
+
+#[doc(hidden)]
+pub struct Cas {
+    bucket: String,
+    inner: Arc<dyn KeyValueBucket>,
+    key: String,
+    /// The value that the key had when this CAS operation was created, and
+    /// which it must still have for the swap to succeed.
+    current: Option<Vec<u8>>,
 }
 
 /// Builder-style structure used to create a [`WasiKeyValueCtx`].
 #[derive(Default)]
 pub struct WasiKeyValueCtxBuilder {
     in_memory_data: HashMap<String, Vec<u8>>,
+    backend: Option<Arc<dyn KeyValueBackend>>,
+    list_keys_page_size: Option<usize>,
 }
 
+/// The default maximum number of keys returned by one call to `list-keys`.
+const DEFAULT_LIST_KEYS_PAGE_SIZE: usize = 1000;
+
 impl WasiKeyValueCtxBuilder {
     /// Creates a builder for a new context with default parameters set.
     pub fn new() -> Self {
         Default::default()
     }
 
-    /// Preset data for the In-Memory provider.
+    /// Preset data for the empty-identifier bucket of the default
+    /// [`InMemoryBackend`].
+    ///
+    /// This is ignored if a custom backend is configured with
+    /// [`WasiKeyValueCtxBuilder::backend`].
     pub fn in_memory_data<I, K, V>(mut self, data: I) -> Self
     where
         I: IntoIterator<Item = (K, V)>,
@@ -130,17 +254,54 @@ impl WasiKeyValueCtxBuilder {
         self
     }
 
+    /// Use `backend` to provide buckets, instead of a new [`InMemoryBackend`].
+    ///
+    /// The backend may be shared with other contexts, which will then all see
+    /// the same data.
+    pub fn backend(mut self, backend: Arc<dyn KeyValueBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set the maximum number of keys returned by one call to `list-keys`.
+    ///
+    /// Larger buckets are listed over multiple calls, each continuing from the
+    /// cursor returned by the previous one. Defaults to 1000.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` is zero.
+    pub fn list_keys_page_size(mut self, page_size: usize) -> Self {
+        assert!(page_size > 0, "`list-keys` page size must be nonzero");
+        self.list_keys_page_size = Some(page_size);
+        self
+    }
+
     /// Uses the configured context so far to construct the final [`WasiKeyValueCtx`].
     pub fn build(self) -> WasiKeyValueCtx {
+        let backend = self.backend.unwrap_or_else(|| {
+            Arc::new(InMemoryBackend::new().with_bucket_data("", self.in_memory_data))
+        });
         WasiKeyValueCtx {
-            in_memory_data: self.in_memory_data,
+            backend,
+            watchers: Default::default(),
+            list_keys_page_size: self
+                .list_keys_page_size
+                .unwrap_or(DEFAULT_LIST_KEYS_PAGE_SIZE),
         }
     }
 }
 
 /// Capture the state necessary for use in the `wasi-keyvalue` API implementation.
+///
+/// Cloning a context is cheap, and the clone shares the original's backend and
+/// watchers. Give each component instance that should coordinate with others
+/// a clone of the same context.
+#[derive(Clone)]
 pub struct WasiKeyValueCtx {
-    in_memory_data: HashMap<String, Vec<u8>>,
+    backend: Arc<dyn KeyValueBackend>,
+    watchers: Arc<Watchers>,
+    list_keys_page_size: usize,
 }
 
 impl WasiKeyValueCtx {
@@ -148,6 +309,27 @@ impl WasiKeyValueCtx {
     pub fn builder() -> WasiKeyValueCtxBuilder {
         WasiKeyValueCtxBuilder::new()
     }
+
+    /// Subscribe `callback` to every change that components make through this
+    /// context or any of its clones.
+    ///
+    /// The callback is invoked synchronously, after the change has been made,
+    /// on the thread of the component that made it. It should not block; to
+    /// deliver events to a component exporting `wasi:keyvalue/watcher`, send
+    /// them to a task that calls [`deliver_watch_event`].
+    ///
+    /// Events are produced by this context rather than by its backend, so only
+    /// changes made through this context and its clones are observed. Changes
+    /// made directly through the [`KeyValueBackend`], through another context
+    /// built separately around the same backend, or by other processes sharing
+    /// a [`FileBackend`]'s directory are not. Share one context (by cloning)
+    /// between every component instance that should see the others' changes.
+    pub fn watch(
+        &self,
+        callback: impl Fn(&WatchEvent) + Send + Sync + 'static,
+    ) -> WatchSubscription {
+        self.watchers.subscribe(Arc::new(callback))
+    }
 }
 
 /// A wrapper capturing the needed internal `wasi-keyvalue` state.
@@ -165,44 +347,59 @@ impl<'a> WasiKeyValue<'a> {
 
 impl keyvalue::store::Host for WasiKeyValue<'_> {
     fn open(&mut self, identifier: String) -> Result<Resource<Bucket>, Error> {
-        match identifier.as_str() {
-            "" => Ok(self.table.push(Bucket {
-                in_memory_data: self.ctx.in_memory_data.clone(),
-            })?),
-            _ => Err(Error::NoSuchStore),
-        }
+        let inner = self.ctx.backend.open(&identifier)?;
+        Ok(self.table.push(Bucket {
+            identifier,
+            inner,
+            cursors: Cursors::default(),
+        })?)
     }
 
     fn convert_error(&mut self, err: Error) -> Result<keyvalue::store::Error> {
+        Ok(err.into())
+    }
+}
+
+impl From<Error> for keyvalue::store::Error {
+    fn from(err: Error) -> Self {
         match err {
-            Error::NoSuchStore => Ok(keyvalue::store::Error::NoSuchStore),
-            Error::AccessDenied => Ok(keyvalue::store::Error::AccessDenied),
-            Error::Other(e) => Ok(keyvalue::store::Error::Other(e)),
+            Error::NoSuchStore => keyvalue::store::Error::NoSuchStore,
+            Error::AccessDenied => keyvalue::store::Error::AccessDenied,
+            Error::Other(e) => keyvalue::store::Error::Other(e),
         }
     }
 }
 
 impl keyvalue::store::HostBucket for WasiKeyValue<'_> {
     fn get(&mut self, bucket: Resource<Bucket>, key: String) -> Result<Option<Vec<u8>>, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        Ok(bucket.in_memory_data.get(&key).cloned())
+        let bucket = self.table.get(&bucket)?;
+        bucket.inner.get(&key)
     }
 
     fn set(&mut self, bucket: Resource<Bucket>, key: String, value: Vec<u8>) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        bucket.in_memory_data.insert(key, value);
+        let bucket = self.table.get(&bucket)?;
+        bucket.inner.set(&key, &value)?;
+        self.ctx.watchers.notify(|| WatchEvent::Set {
+            bucket: bucket.identifier.clone(),
+            key,
+            value,
+        });
         Ok(())
     }
 
     fn delete(&mut self, bucket: Resource<Bucket>, key: String) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        bucket.in_memory_data.remove(&key);
+        let bucket = self.table.get(&bucket)?;
+        bucket.inner.delete(&key)?;
+        self.ctx.watchers.notify(|| WatchEvent::Delete {
+            bucket: bucket.identifier.clone(),
+            key,
+        });
         Ok(())
     }
 
     fn exists(&mut self, bucket: Resource<Bucket>, key: String) -> Result<bool, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        Ok(bucket.in_memory_data.contains_key(&key))
+        let bucket = self.table.get(&bucket)?;
+        bucket.inner.exists(&key)
     }
 
     fn list_keys(
@@ -211,13 +408,30 @@ impl keyvalue::store::HostBucket for WasiKeyValue<'_> {
         cursor: Option<u64>,
     ) -> Result<keyvalue::store::KeyResponse, Error> {
         let bucket = self.table.get_mut(&bucket)?;
-        let keys: Vec<String> = bucket.in_memory_data.keys().cloned().collect();
-        let cursor = cursor.unwrap_or(0) as usize;
-        let keys_slice = &keys[cursor..];
-        Ok(keyvalue::store::KeyResponse {
-            keys: keys_slice.to_vec(),
-            cursor: None,
-        })
+        let after = match cursor {
+            Some(cursor) => Some(
+                bucket
+                    .cursors
+                    .get(cursor)
+                    .ok_or_else(|| Error::Other(format!("invalid or expired cursor: {cursor}")))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+
+        // Ask for one key more than fits in the page, to find out whether
+        // there is another page after this one.
+        let page_size = self.ctx.list_keys_page_size;
+        let mut keys = bucket
+            .inner
+            .list_keys(after.as_deref(), page_size.saturating_add(1))?;
+        let cursor = if keys.len() > page_size {
+            keys.truncate(page_size);
+            Some(bucket.cursors.insert(keys[page_size - 1].clone()))
+        } else {
+            None
+        };
+        Ok(keyvalue::store::KeyResponse { keys, cursor })
     }
 
     fn drop(&mut self, bucket: Resource<Bucket>) -> Result<()> {
@@ -233,37 +447,79 @@ impl keyvalue::atomics::Host for WasiKeyValue<'_> {
         key: String,
         delta: u64,
     ) -> Result<u64, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        let value = bucket
-            .in_memory_data
-            .entry(key.clone())
-            .or_insert("0".to_string().into_bytes());
-        let current_value = String::from_utf8(value.clone())
-            .map_err(|e| Error::Other(e.to_string()))?
-            .parse::<u64>()
-            .map_err(|e| Error::Other(e.to_string()))?;
-        let new_value = current_value + delta;
-        *value = new_value.to_string().into_bytes();
+        let bucket = self.table.get(&bucket)?;
+        let new_value = bucket.inner.increment(&key, delta)?;
+        self.ctx.watchers.notify(|| WatchEvent::Set {
+            bucket: bucket.identifier.clone(),
+            key,
+            value: new_value.to_string().into_bytes(),
+        });
         Ok(new_value)
     }
 }
 
+impl cas::Host for WasiKeyValue<'_> {
+    fn swap(&mut self, cas: Resource<Cas>, value: Vec<u8>) -> Result<Result<(), cas::CasError>> {
+        let cas = self.table.delete(cas)?;
+        match cas
+            .inner
+            .compare_and_swap(&cas.key, cas.current.as_deref(), &value)
+        {
+            Ok(true) => {
+                self.ctx.watchers.notify(|| WatchEvent::Set {
+                    bucket: cas.bucket,
+                    key: cas.key,
+                    value,
+                });
+                Ok(Ok(()))
+            }
+            // The value changed since the CAS operation was created, so hand
+            // back a new one with the latest value for retrying.
+            Ok(false) => match cas.inner.get(&cas.key) {
+                Ok(current) => {
+                    let cas = self.table.push(Cas { current, ..cas })?;
+                    Ok(Err(cas::CasError::CasFailed(cas)))
+                }
+                Err(e) => Ok(Err(cas::CasError::StoreError(e.into()))),
+            },
+            Err(e) => Ok(Err(cas::CasError::StoreError(e.into()))),
+        }
+    }
+}
+
+impl cas::HostCas for WasiKeyValue<'_> {
+    fn new(&mut self, bucket: Resource<Bucket>, key: String) -> Result<Resource<Cas>, Error> {
+        let bucket = self.table.get(&bucket)?;
+        let cas = Cas {
+            bucket: bucket.identifier.clone(),
+            inner: bucket.inner.clone(),
+            current: bucket.inner.get(&key)?,
+            key,
+        };
+        Ok(self.table.push(cas)?)
+    }
+
+    fn current(&mut self, cas: Resource<Cas>) -> Result<Option<Vec<u8>>, Error> {
+        let cas = self.table.get(&cas)?;
+        Ok(cas.current.clone())
+    }
+
+    fn drop(&mut self, cas: Resource<Cas>) -> Result<()> {
+        self.table.delete(cas)?;
+        Ok(())
+    }
+}
+
 impl keyvalue::batch::Host for WasiKeyValue<'_> {
     fn get_many(
         &mut self,
         bucket: Resource<Bucket>,
         keys: Vec<String>,
     ) -> Result<Vec<Option<(String, Vec<u8>)>>, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        Ok(keys
-            .into_iter()
-            .map(|key| {
-                bucket
-                    .in_memory_data
-                    .get(&key)
-                    .map(|value| (key.clone(), value.clone()))
-            })
-            .collect())
+        let bucket = self.table.get(&bucket)?;
+        keys.into_iter()
+            .map(|key| Ok(bucket.inner.get(&key)?.map(|value| (key, value))))
+            .collect()
     }
 
     fn set_many(
@@ -271,17 +527,26 @@ impl keyvalue::batch::Host for WasiKeyValue<'_> {
         bucket: Resource<Bucket>,
         key_values: Vec<(String, Vec<u8>)>,
     ) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
+        let bucket = self.table.get(&bucket)?;
         for (key, value) in key_values {
-            bucket.in_memory_data.insert(key, value);
+            bucket.inner.set(&key, &value)?;
+            self.ctx.watchers.notify(|| WatchEvent::Set {
+                bucket: bucket.identifier.clone(),
+                key,
+                value,
+            });
         }
         Ok(())
     }
 
     fn delete_many(&mut self, bucket: Resource<Bucket>, keys: Vec<String>) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
+        let bucket = self.table.get(&bucket)?;
         for key in keys {
-            bucket.in_memory_data.remove(&key);
+            bucket.inner.delete(&key)?;
+            self.ctx.watchers.notify(|| WatchEvent::Delete {
+                bucket: bucket.identifier.clone(),
+                key,
+            });
         }
         Ok(())
     }
@@ -295,5 +560,6 @@ pub fn add_to_linker<T: Send>(
     keyvalue::store::add_to_linker_get_host(l, f)?;
     keyvalue::atomics::add_to_linker_get_host(l, f)?;
     keyvalue::batch::add_to_linker_get_host(l, f)?;
+    cas::add_to_linker_get_host(l, f)?;
     Ok(())
 }
@@ -0,0 +1,117 @@
+//! The in-memory storage backend.
+
+use crate::backend::{increment_value, KeyValueBackend, KeyValueBucket};
+use crate::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+
+/// A [`KeyValueBackend`] which keeps all buckets in memory.
+///
+/// Buckets are created on demand the first time they are opened, and live for
+/// as long as the backend does. Any bucket identifier is accepted.
+#[derive(Default)]
+pub struct InMemoryBackend {
+    buckets: Mutex<HashMap<String, Arc<InMemoryBucket>>>,
+}
+
+impl InMemoryBackend {
+    /// Create a new, empty in-memory backend.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Preset the contents of the bucket named `identifier`.
+    pub fn with_bucket_data<I, K, V>(self, identifier: impl Into<String>, data: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<Vec<u8>>,
+    {
+        let data = data
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        self.buckets.lock().unwrap().insert(
+            identifier.into(),
+            Arc::new(InMemoryBucket {
+                data: Mutex::new(data),
+            }),
+        );
+        self
+    }
+}
+
+impl KeyValueBackend for InMemoryBackend {
+    fn open(&self, identifier: &str) -> Result<Arc<dyn KeyValueBucket>, Error> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(identifier.to_string()).or_default().clone();
+        Ok(bucket)
+    }
+}
+
+#[derive(Default)]
+struct InMemoryBucket {
+    data: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl KeyValueBucket for InMemoryBucket {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.data.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.data
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Error> {
+        self.data.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.data.lock().unwrap().contains_key(key))
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        Ok(self.data.lock().unwrap().keys().cloned().collect())
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, Error> {
+        let data = self.data.lock().unwrap();
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        Ok(data
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+
+    fn increment(&self, key: &str, delta: u64) -> Result<u64, Error> {
+        let mut data = self.data.lock().unwrap();
+        let new_value = increment_value(data.get(key).map(|v| &v[..]), delta)?;
+        data.insert(key.to_string(), new_value.to_string().into_bytes());
+        Ok(new_value)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, Error> {
+        let mut data = self.data.lock().unwrap();
+        if data.get(key).map(|v| &v[..]) != current {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), new.to_vec());
+        Ok(true)
+    }
+}
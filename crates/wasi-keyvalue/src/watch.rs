@@ -0,0 +1,143 @@
+//! Change notifications for `wasi-keyvalue` buckets, and delivery of them to
+//! components exporting `wasi:keyvalue/watcher`.
+
+use crate::generated::{wasi::keyvalue, WatchService};
+use crate::WasiKeyValue;
+use anyhow::Result;
+use std::sync::{Arc, Mutex, Weak};
+use wasmtime::AsContextMut;
+
+/// A change made to a key-value pair by a component through `wasi-keyvalue`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// `key` in `bucket` was set to `value`.
+    Set {
+        /// The identifier of the bucket containing the key.
+        bucket: String,
+        /// The key that was set.
+        key: String,
+        /// The key's new value.
+        value: Vec<u8>,
+    },
+    /// `key` in `bucket` was deleted.
+    Delete {
+        /// The identifier of the bucket that contained the key.
+        bucket: String,
+        /// The key that was deleted.
+        key: String,
+    },
+}
+
+impl WatchEvent {
+    /// The identifier of the bucket that this event happened in.
+    pub fn bucket(&self) -> &str {
+        match self {
+            WatchEvent::Set { bucket, .. } | WatchEvent::Delete { bucket, .. } => bucket,
+        }
+    }
+
+    /// The key that this event is about.
+    pub fn key(&self) -> &str {
+        match self {
+            WatchEvent::Set { key, .. } | WatchEvent::Delete { key, .. } => key,
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn(&WatchEvent) + Send + Sync>;
+
+/// The set of callbacks subscribed to a context's [`WatchEvent`]s.
+#[derive(Default)]
+pub(crate) struct Watchers {
+    subscribers: Mutex<Subscribers>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: u64,
+    callbacks: Vec<(u64, Callback)>,
+}
+
+impl Watchers {
+    pub(crate) fn subscribe(self: &Arc<Self>, callback: Callback) -> WatchSubscription {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.callbacks.push((id, callback));
+        WatchSubscription {
+            watchers: Arc::downgrade(self),
+            id,
+        }
+    }
+
+    /// Notify all subscribers of the event produced by `event`, which is only
+    /// called if there are any subscribers.
+    pub(crate) fn notify(&self, event: impl FnOnce() -> WatchEvent) {
+        // Don't hold the lock while running callbacks, so that they may
+        // subscribe or unsubscribe.
+        let callbacks: Vec<Callback> = {
+            let subscribers = self.subscribers.lock().unwrap();
+            if subscribers.callbacks.is_empty() {
+                return;
+            }
+            subscribers
+                .callbacks
+                .iter()
+                .map(|(_, callback)| callback.clone())
+                .collect()
+        };
+        let event = event();
+        for callback in callbacks {
+            callback(&event);
+        }
+    }
+}
+
+/// A subscription to [`WatchEvent`]s, created by
+/// [`WasiKeyValueCtx::watch`](crate::WasiKeyValueCtx::watch).
+///
+/// The subscription's callback is unsubscribed when this is dropped.
+#[must_use = "dropping a `WatchSubscription` unsubscribes its callback"]
+pub struct WatchSubscription {
+    watchers: Weak<Watchers>,
+    id: u64,
+}
+
+impl Drop for WatchSubscription {
+    fn drop(&mut self) {
+        if let Some(watchers) = self.watchers.upgrade() {
+            watchers
+                .subscribers
+                .lock()
+                .unwrap()
+                .callbacks
+                .retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+/// Deliver `event` to a component instance exporting `wasi:keyvalue/watcher`,
+/// by calling its `on-set` or `on-delete` function.
+///
+/// The component is handed a new handle to the event's bucket, opened through
+/// the `wasi-keyvalue` state returned by `get`.
+pub async fn deliver_watch_event<T: Send>(
+    mut store: impl AsContextMut<Data = T>,
+    watcher: &WatchService,
+    event: &WatchEvent,
+    get: impl FnOnce(&mut T) -> WasiKeyValue<'_>,
+) -> Result<()> {
+    let mut store = store.as_context_mut();
+    let bucket = {
+        let mut view = get(store.data_mut());
+        keyvalue::store::Host::open(&mut view, event.bucket().to_string())?
+    };
+
+    let watcher = watcher.wasi_keyvalue_watcher();
+    match event {
+        WatchEvent::Set { key, value, .. } => {
+            watcher.call_on_set(&mut store, bucket, key, value).await
+        }
+        WatchEvent::Delete { key, .. } => watcher.call_on_delete(&mut store, bucket, key).await,
+    }
+}
@@ -1,11 +1,17 @@
 use anyhow::{anyhow, Result};
-use test_programs_artifacts::{foreach_keyvalue, KEYVALUE_MAIN_COMPONENT};
+use std::sync::{Arc, Mutex};
+use test_programs_artifacts::{
+    foreach_keyvalue, KEYVALUE_MAIN_COMPONENT, KEYVALUE_WATCHER_COMPONENT,
+};
 use wasmtime::{
     component::{Component, Linker, ResourceTable},
-    Store,
+    Engine, Store,
 };
 use wasmtime_wasi::{bindings::Command, IoView, WasiCtx, WasiCtxBuilder, WasiView};
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder};
+use wasmtime_wasi_keyvalue::{
+    deliver_watch_event, FileBackend, InMemoryBackend, KeyValueBackend, WasiKeyValue,
+    WasiKeyValueCtx, WasiKeyValueCtxBuilder, WatchEvent, WatchService,
+};
 
 struct Ctx {
     table: ResourceTable,
@@ -24,18 +30,26 @@ impl WasiView for Ctx {
     }
 }
 
-async fn run_wasi(path: &str, ctx: Ctx) -> Result<()> {
-    let engine = test_programs_artifacts::engine(|config| {
+fn engine() -> Engine {
+    test_programs_artifacts::engine(|config| {
         config.async_support(true);
-    });
-    let mut store = Store::new(&engine, ctx);
-    let component = Component::from_file(&engine, path)?;
+    })
+}
 
-    let mut linker = Linker::new(&engine);
+fn linker(engine: &Engine) -> Result<Linker<Ctx>> {
+    let mut linker = Linker::new(engine);
     wasmtime_wasi::add_to_linker_async(&mut linker)?;
     wasmtime_wasi_keyvalue::add_to_linker(&mut linker, |h: &mut Ctx| {
         WasiKeyValue::new(&h.wasi_keyvalue_ctx, &mut h.table)
     })?;
+    Ok(linker)
+}
+
+async fn run_wasi(path: &str, ctx: Ctx) -> Result<()> {
+    let engine = engine();
+    let mut store = Store::new(&engine, ctx);
+    let component = Component::from_file(&engine, path)?;
+    let linker = linker(&engine)?;
 
     let command = Command::instantiate_async(&mut store, &component, &linker).await?;
     command
@@ -68,3 +82,151 @@ async fn keyvalue_main() -> Result<()> {
     )
     .await
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keyvalue_main_file_backend() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backend = Arc::new(FileBackend::new(dir.path())?);
+    backend.open("")?.set("atomics_key", b"5")?;
+
+    run_wasi(
+        KEYVALUE_MAIN_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().inherit_stderr().build(),
+            // Use the smallest page size, so that listing keys has to follow
+            // cursors.
+            wasi_keyvalue_ctx: WasiKeyValueCtxBuilder::new()
+                .backend(backend)
+                .list_keys_page_size(1)
+                .build(),
+        },
+    )
+    .await?;
+
+    // The component's writes persist, and are visible to a new backend using
+    // the same directory.
+    let bucket = FileBackend::new(dir.path())?.open("")?;
+    assert_eq!(bucket.get("atomics_key")?.as_deref(), Some(&b"6"[..]));
+    assert_eq!(bucket.keys()?, ["atomics_key", "b1"]);
+    Ok(())
+}
+
+#[test]
+fn backends_share_buckets() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backends: [Arc<dyn KeyValueBackend>; 2] = [
+        Arc::new(InMemoryBackend::new()),
+        Arc::new(FileBackend::new(dir.path())?),
+    ];
+    for backend in backends {
+        // Writes are visible to later opens of the same bucket, but not to
+        // other buckets.
+        backend.open("a")?.set("key", b"value")?;
+        assert_eq!(
+            backend.open("a")?.get("key")?.as_deref(),
+            Some(&b"value"[..])
+        );
+        assert!(!backend.open("b")?.exists("key")?);
+
+        let bucket = backend.open("b")?;
+        assert_eq!(bucket.increment("counter", 2)?, 2);
+        assert_eq!(bucket.increment("counter", 3)?, 5);
+        bucket.set("not a number", b"x")?;
+        assert!(bucket.increment("not a number", 1).is_err());
+        bucket.delete("not a number")?;
+        assert_eq!(bucket.keys()?, ["counter"]);
+    }
+    Ok(())
+}
+
+#[test]
+fn backends_list_key_pages() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backends: [Arc<dyn KeyValueBackend>; 2] = [
+        Arc::new(InMemoryBackend::new()),
+        Arc::new(FileBackend::new(dir.path())?),
+    ];
+    for backend in backends {
+        let bucket = backend.open("")?;
+        for key in ["d", "b", "e", "a", "c"] {
+            bucket.set(key, b"")?;
+        }
+        assert_eq!(bucket.list_keys(None, 2)?, ["a", "b"]);
+        assert_eq!(bucket.list_keys(Some("b"), 2)?, ["c", "d"]);
+        assert_eq!(bucket.list_keys(Some("bb"), 10)?, ["c", "d", "e"]);
+        assert!(bucket.list_keys(Some("e"), 2)?.is_empty());
+        assert!(bucket.list_keys(None, 0)?.is_empty());
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keyvalue_watcher() -> Result<()> {
+    let backend = Arc::new(InMemoryBackend::new().with_bucket_data("", [("atomics_key", "5")]));
+    let wasi_keyvalue_ctx = WasiKeyValueCtxBuilder::new()
+        .backend(backend.clone())
+        .build();
+
+    // Record every change that `keyvalue_main` makes.
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let subscription = wasi_keyvalue_ctx.watch({
+        let events = events.clone();
+        move |event| events.lock().unwrap().push(event.clone())
+    });
+    run_wasi(
+        KEYVALUE_MAIN_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().inherit_stderr().build(),
+            wasi_keyvalue_ctx: wasi_keyvalue_ctx.clone(),
+        },
+    )
+    .await?;
+    drop(subscription);
+
+    let events = std::mem::take(&mut *events.lock().unwrap());
+    assert_eq!(
+        events[..2],
+        [
+            WatchEvent::Set {
+                bucket: String::new(),
+                key: "atomics_key".to_string(),
+                value: b"6".to_vec(),
+            },
+            WatchEvent::Set {
+                bucket: String::new(),
+                key: "hello".to_string(),
+                value: b"world".to_vec(),
+            },
+        ]
+    );
+
+    // Forward the changes to a watcher component sharing the same context,
+    // which mirrors each key `k` into `seen-k`.
+    let engine = engine();
+    let mut store = Store::new(
+        &engine,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().inherit_stderr().build(),
+            wasi_keyvalue_ctx: wasi_keyvalue_ctx.clone(),
+        },
+    );
+    let component = Component::from_file(&engine, KEYVALUE_WATCHER_COMPONENT)?;
+    let watcher =
+        WatchService::instantiate_async(&mut store, &component, &linker(&engine)?).await?;
+    for event in &events {
+        deliver_watch_event(&mut store, &watcher, event, |h: &mut Ctx| {
+            WasiKeyValue::new(&h.wasi_keyvalue_ctx, &mut h.table)
+        })
+        .await?;
+    }
+
+    let bucket = backend.open("")?;
+    assert_eq!(bucket.get("seen-atomics_key")?.as_deref(), Some(&b"6"[..]));
+    assert_eq!(bucket.get("seen-b1")?.as_deref(), Some(&b"v1"[..]));
+    assert!(!bucket.exists("seen-hello")?);
+    assert!(!bucket.exists("seen-a1")?);
+    Ok(())
+}
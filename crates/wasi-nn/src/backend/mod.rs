@@ -8,6 +8,8 @@ pub mod onnx;
 pub mod openvino;
 #[cfg(feature = "pytorch")]
 pub mod pytorch;
+#[cfg(feature = "tract")]
+pub mod tract;
 #[cfg(all(feature = "winml", target_os = "windows"))]
 pub mod winml;
 
@@ -17,6 +19,8 @@ use self::onnx::OnnxBackend;
 use self::openvino::OpenvinoBackend;
 #[cfg(feature = "pytorch")]
 use self::pytorch::PytorchBackend;
+#[cfg(feature = "tract")]
+use self::tract::TractBackend;
 #[cfg(all(feature = "winml", target_os = "windows"))]
 use self::winml::WinMLBackend;
 
@@ -29,6 +33,12 @@ use thiserror::Error;
 use wiggle::GuestError;
 
 /// Return a list of all available backend frameworks.
+///
+/// Backends are listed in order of preference: when several of them load the
+/// same encoding, as ONNX Runtime and tract both do for ONNX models, the first
+/// one is used by [`WasiNnCtx::new`](crate::wit::WasiNnCtx::new) and
+/// [`preload`](crate::preload). Embedders can pick a later one out of the
+/// list instead.
 pub fn list() -> Vec<Backend> {
     let mut backends = vec![];
     let _ = &mut backends; // silence warnings if none are enabled
@@ -48,6 +58,10 @@ pub fn list() -> Vec<Backend> {
     {
         backends.push(Backend::from(PytorchBackend::default()));
     }
+    #[cfg(feature = "tract")]
+    {
+        backends.push(Backend::from(TractBackend::default()));
+    }
     backends
 }
 
@@ -0,0 +1,322 @@
+//! Implements a `wasi-nn` [`BackendInner`] using ONNX and NNEF models via the
+//! pure-Rust `tract` crates.
+//!
+//! Unlike the other backends, this one requires no native library: models run
+//! on the CPU and the only requirement is to build with the `tract` feature.
+
+use super::{BackendError, BackendExecutionContext, BackendFromDir, BackendGraph, BackendInner};
+use crate::backend::{read, Id};
+use crate::wit::types::{ExecutionTarget, GraphEncoding, Tensor, TensorType};
+use crate::{ExecutionContext, Graph};
+use std::path::Path;
+use std::sync::Arc;
+use tract_onnx::prelude::{
+    tvec, DatumType, Framework, InferenceModelExt, IntoTValue, TDim, TypedFact, TypedModel,
+    TypedRunnableModel,
+};
+
+#[derive(Default)]
+pub struct TractBackend();
+
+impl BackendInner for TractBackend {
+    fn encoding(&self) -> GraphEncoding {
+        GraphEncoding::Onnx
+    }
+
+    fn load(&mut self, builders: &[&[u8]], target: ExecutionTarget) -> Result<Graph, BackendError> {
+        if builders.len() != 1 {
+            return Err(BackendError::InvalidNumberOfBuilders(1, builders.len()));
+        }
+        let model = tract_onnx::onnx()
+            .model_for_read(&mut &builders[0][..])?
+            .into_optimized()?;
+        TractGraph::new(model, target)
+    }
+
+    fn as_dir_loadable<'a>(&'a mut self) -> Option<&'a mut dyn BackendFromDir> {
+        Some(self)
+    }
+}
+
+impl BackendFromDir for TractBackend {
+    /// Load either a `model.onnx` file or, if there is none, an NNEF model in
+    /// a `model.nnef.tar` archive or a `model.nnef` directory.
+    fn load_from_dir(
+        &mut self,
+        path: &Path,
+        target: ExecutionTarget,
+    ) -> Result<Graph, BackendError> {
+        let onnx = path.join("model.onnx");
+        if onnx.is_file() {
+            let model = read(&onnx)?;
+            return self.load(&[&model], target);
+        }
+        let nnef = ["model.nnef.tar", "model.nnef"]
+            .iter()
+            .map(|name| path.join(name))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no `model.onnx`, `model.nnef.tar` or `model.nnef` in {}",
+                    path.display()
+                )
+            })?;
+        let model = tract_nnef::nnef()
+            .with_tract_core()
+            .model_for_path(&nnef)?
+            .into_optimized()?;
+        TractGraph::new(model, target)
+    }
+}
+
+struct TractGraph {
+    plan: Arc<TypedRunnableModel<TypedModel>>,
+    inputs: Vec<Shape>,
+    outputs: Vec<Shape>,
+}
+
+impl TractGraph {
+    fn new(model: TypedModel, target: ExecutionTarget) -> Result<Graph, BackendError> {
+        if target != ExecutionTarget::Cpu {
+            return Err(BackendError::BackendAccess(anyhow::anyhow!(
+                "unsupported execution target: {target:?}; only the CPU is supported"
+            )));
+        }
+
+        // Keep the names, dimensions and types of the inputs and outputs
+        // around for name-based lookup and for validation.
+        let mut inputs = vec![];
+        for (i, outlet) in model.inputs.iter().enumerate() {
+            let name = model.node(outlet.node).name.clone();
+            inputs.push(Shape::new(name, model.input_fact(i)?)?);
+        }
+        let mut outputs = vec![];
+        for (i, outlet) in model.outputs.iter().enumerate() {
+            let name = model.node(outlet.node).name.clone();
+            outputs.push(Shape::new(name, model.output_fact(i)?)?);
+        }
+
+        let box_: Box<dyn BackendGraph> = Box::new(TractGraph {
+            plan: Arc::new(model.into_runnable()?),
+            inputs,
+            outputs,
+        });
+        Ok(box_.into())
+    }
+}
+
+impl BackendGraph for TractGraph {
+    fn init_execution_context(&self) -> Result<ExecutionContext, BackendError> {
+        let box_: Box<dyn BackendExecutionContext> = Box::new(TractExecutionContext {
+            plan: self.plan.clone(),
+            inputs: self.inputs.iter().cloned().map(TensorSlot::from).collect(),
+            outputs: self.outputs.iter().cloned().map(TensorSlot::from).collect(),
+        });
+        Ok(box_.into())
+    }
+}
+
+struct TractExecutionContext {
+    plan: Arc<TypedRunnableModel<TypedModel>>,
+    inputs: Vec<TensorSlot>,
+    outputs: Vec<TensorSlot>,
+}
+
+impl TractExecutionContext {
+    /// Helper function for finding the internal index of a tensor by [`Id`].
+    fn find(&self, id: Id, list: &[TensorSlot]) -> Result<usize, BackendError> {
+        let index = match id {
+            Id::Index(i) => {
+                let i = i as usize;
+                if i < list.len() {
+                    i
+                } else {
+                    return Err(BackendError::BackendAccess(anyhow::anyhow!(
+                        "incorrect tensor index: {i} >= {}",
+                        list.len()
+                    )));
+                }
+            }
+            Id::Name(n) => list.iter().position(|s| s.shape.name == n).ok_or_else(|| {
+                BackendError::BackendAccess(anyhow::anyhow!("unknown tensor name: {n}"))
+            })?,
+        };
+        Ok(index)
+    }
+}
+
+impl BackendExecutionContext for TractExecutionContext {
+    fn set_input(&mut self, id: Id, tensor: &Tensor) -> Result<(), BackendError> {
+        let index = self.find(id, &self.inputs)?;
+        let input = &mut self.inputs[index];
+        input.shape.matches(tensor)?;
+        // Hold the tensor data on the context until `compute` is called.
+        input.tensor.replace(tensor.clone());
+        Ok(())
+    }
+
+    fn compute(&mut self) -> Result<(), BackendError> {
+        let mut inputs = tvec![];
+        for slot in &self.inputs {
+            let Some(tensor) = &slot.tensor else {
+                return Err(BackendError::BackendAccess(anyhow::anyhow!(
+                    "missing input tensor: {}",
+                    slot.shape.name
+                )));
+            };
+            inputs.push(to_tract_tensor(tensor)?.into_tvalue());
+        }
+        let results = self.plan.run(inputs)?;
+        for (output, result) in self.outputs.iter_mut().zip(results) {
+            output.tensor.replace(Tensor {
+                dimensions: result
+                    .shape()
+                    .iter()
+                    .map(|&d| convert_dimension(d))
+                    .collect::<Result<_, _>>()?,
+                ty: convert_datum_type(result.datum_type())?,
+                data: result.as_bytes().to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    fn get_output(&mut self, id: Id) -> Result<Tensor, BackendError> {
+        let index = self.find(id, &self.outputs)?;
+        let output = &self.outputs[index];
+        if let Some(tensor) = &output.tensor {
+            Ok(tensor.clone())
+        } else {
+            Err(BackendError::BackendAccess(anyhow::anyhow!(
+                "missing output tensor: {}; has `compute` been called?",
+                output.shape.name
+            )))
+        }
+    }
+}
+
+/// Holds a slot for the inputs and outputs of a model.
+struct TensorSlot {
+    shape: Shape,
+    tensor: Option<Tensor>,
+}
+
+impl From<Shape> for TensorSlot {
+    fn from(shape: Shape) -> Self {
+        Self {
+            shape,
+            tensor: None,
+        }
+    }
+}
+
+/// Describes a tensor of a model; dimensions are `None` when they're only
+/// known once the model runs (e.g., a batch size).
+#[derive(Clone)]
+struct Shape {
+    name: String,
+    dimensions: Vec<Option<u32>>,
+    ty: TensorType,
+}
+
+impl Shape {
+    fn new(name: String, fact: &TypedFact) -> Result<Self, BackendError> {
+        let dimensions = fact
+            .shape
+            .iter()
+            .map(|d| match d {
+                TDim::Val(d) => u32::try_from(*d).map(Some).map_err(|_| -> BackendError {
+                    anyhow::anyhow!("unable to convert dimension to u32: {d}").into()
+                }),
+                _ => Ok(None),
+            })
+            .collect::<Result<_, _>>()?;
+        let ty = convert_datum_type(fact.datum_type)?;
+        Ok(Self {
+            name,
+            dimensions,
+            ty,
+        })
+    }
+
+    fn matches(&self, tensor: &Tensor) -> anyhow::Result<()> {
+        let dimensions_match = self.dimensions.len() == tensor.dimensions.len()
+            && self
+                .dimensions
+                .iter()
+                .zip(&tensor.dimensions)
+                .all(|(shape_dim, tensor_dim)| shape_dim.is_none_or(|d| d == *tensor_dim));
+        if !dimensions_match {
+            return Err(anyhow::anyhow!(
+                "input tensor dimensions do not match model: {:?} != {:?}",
+                self.dimensions,
+                tensor.dimensions
+            ));
+        }
+        if self.ty != tensor.ty {
+            return Err(anyhow::anyhow!(
+                "input tensor type does not match model: {:?} != {:?}",
+                self.ty,
+                tensor.ty
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn to_tract_tensor(tensor: &Tensor) -> Result<tract_onnx::prelude::Tensor, BackendError> {
+    let ty = match tensor.ty {
+        TensorType::Fp16 => DatumType::F16,
+        TensorType::Fp32 => DatumType::F32,
+        TensorType::Fp64 => DatumType::F64,
+        TensorType::U8 => DatumType::U8,
+        TensorType::I32 => DatumType::I32,
+        TensorType::I64 => DatumType::I64,
+        TensorType::Bf16 => {
+            return Err(BackendError::UnsupportedTensorType(format!(
+                "{:?}",
+                tensor.ty
+            )));
+        }
+    };
+    let shape = tensor
+        .dimensions
+        .iter()
+        .map(|&d| d as usize)
+        .collect::<Vec<_>>();
+    let expected = shape
+        .iter()
+        .try_fold(ty.size_of(), |len, &d| len.checked_mul(d))
+        .ok_or_else(|| {
+            BackendError::BackendAccess(anyhow::anyhow!(
+                "input tensor dimensions {shape:?} are too large"
+            ))
+        })?;
+    if tensor.data.len() != expected {
+        return Err(BackendError::BackendAccess(anyhow::anyhow!(
+            "input tensor data has {} bytes but its dimensions require {expected}",
+            tensor.data.len()
+        )));
+    }
+    // SAFETY: all of the types above are plain numbers for which any bytes are
+    // valid, and the length of `data` was checked against the shape.
+    Ok(unsafe { tract_onnx::prelude::Tensor::from_raw_dt(ty, &shape, &tensor.data)? })
+}
+
+fn convert_datum_type(ty: DatumType) -> Result<TensorType, BackendError> {
+    match ty {
+        DatumType::F16 => Ok(TensorType::Fp16),
+        DatumType::F32 => Ok(TensorType::Fp32),
+        DatumType::F64 => Ok(TensorType::Fp64),
+        DatumType::U8 => Ok(TensorType::U8),
+        DatumType::I32 => Ok(TensorType::I32),
+        DatumType::I64 => Ok(TensorType::I64),
+        _ => Err(BackendError::UnsupportedTensorType(format!("{ty:?}"))),
+    }
+}
+
+fn convert_dimension(d: usize) -> Result<u32, BackendError> {
+    u32::try_from(d).map_err(|_| -> BackendError {
+        anyhow::anyhow!("unable to convert dimension to u32: {d}").into()
+    })
+}
@@ -5,7 +5,7 @@ pub mod witx;
 
 use anyhow::anyhow;
 use core::fmt;
-pub use registry::{GraphRegistry, InMemoryRegistry};
+pub use registry::{GraphRegistry, InMemoryRegistry, LazyRegistry};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -29,6 +29,16 @@ pub fn preload(preload_graphs: &[(String, String)]) -> anyhow::Result<(Vec<Backe
     Ok((backends, Registry::from(registry)))
 }
 
+/// Same as [`preload`], but graphs are only loaded when first used, by a
+/// [`LazyRegistry`] which can be shared by many stores.
+pub fn preload_lazy(preload_graphs: &[(String, String)]) -> anyhow::Result<LazyRegistry> {
+    let registry = LazyRegistry::new();
+    for (kind, path) in preload_graphs {
+        registry.add(kind.parse()?, Path::new(path))?;
+    }
+    Ok(registry)
+}
+
 /// A machine learning backend.
 pub struct Backend(Box<dyn backend::BackendInner>);
 impl std::ops::Deref for Backend {
@@ -0,0 +1,526 @@
+//! Implement a [`GraphRegistry`] which loads graphs from disk when first used.
+
+use super::{Graph, GraphRegistry};
+use crate::backend;
+use crate::wit::{ExecutionTarget, GraphEncoding};
+use crate::Backend;
+use anyhow::{anyhow, bail, Context};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// A registry of graphs stored in directories on disk.
+///
+/// Unlike [`InMemoryRegistry`](super::InMemoryRegistry), graphs are only
+/// loaded when first retrieved by name, and clones of this registry share the
+/// loaded graphs: e.g., all the stores of `wasmtime serve` use the same copy of
+/// each graph. Graphs which haven't been used recently are unloaded once the
+/// loaded graphs exceed the [memory budget](LazyRegistry::memory_budget), and a
+/// graph is loaded again when the files in its directory change, keeping the
+/// old graph if that fails.
+#[derive(Clone)]
+pub struct LazyRegistry {
+    models: Arc<Mutex<HashMap<String, Arc<Model>>>>,
+    /// Incremented on each retrieval; orders graphs by their last use.
+    clock: Arc<AtomicU64>,
+    memory_budget: Option<u64>,
+    reload_interval: Duration,
+    /// Creates the backends which graphs are loaded with.
+    backends: fn() -> Vec<Backend>,
+}
+
+/// A graph directory added to the registry.
+struct Model {
+    encoding: GraphEncoding,
+    path: PathBuf,
+    state: Mutex<ModelState>,
+}
+
+#[derive(Default)]
+struct ModelState {
+    loaded: Option<Loaded>,
+    last_used: u64,
+    last_checked: Option<Instant>,
+}
+
+struct Loaded {
+    graph: Graph,
+    fingerprint: Fingerprint,
+}
+
+/// A summary of the files of a graph directory, which changes when they do.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Fingerprint {
+    files: u64,
+    bytes: u64,
+    modified: Option<SystemTime>,
+}
+
+impl LazyRegistry {
+    pub fn new() -> Self {
+        Self {
+            models: Arc::default(),
+            clock: Arc::default(),
+            memory_budget: None,
+            reload_interval: Duration::from_secs(1),
+            backends: backend::list,
+        }
+    }
+
+    /// Unload the least recently used graphs once the loaded graphs exceed
+    /// `bytes`.
+    ///
+    /// The size of a graph is estimated as the size of the files in its
+    /// directory. A graph is never unloaded while it's being retrieved, and an
+    /// unloaded graph stays alive for as long as a store still uses it. By
+    /// default graphs are never unloaded.
+    pub fn memory_budget(&mut self, bytes: u64) -> &mut Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Check a graph directory for changes at most once per `interval` when
+    /// retrieving its graph; defaults to one second.
+    pub fn reload_interval(&mut self, interval: Duration) -> &mut Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    /// Add the graph stored in the `path` directory, to be loaded by the
+    /// backend of `encoding` when first retrieved.
+    ///
+    /// As with [`InMemoryRegistry::load`](super::InMemoryRegistry::load), the
+    /// name used in the registry is the directory's last suffix. Fails if
+    /// there's no backend for `encoding` which can load graphs from
+    /// directories.
+    pub fn add(&self, encoding: GraphEncoding, path: &Path) -> anyhow::Result<()> {
+        if !path.is_dir() {
+            bail!(
+                "preload directory is not a valid directory: {}",
+                path.display()
+            );
+        }
+        let name = path
+            .file_name()
+            .map(|s| s.to_string_lossy())
+            .ok_or(anyhow!("no file name in path"))?;
+        find_dir_loadable(&mut (self.backends)(), encoding)?;
+
+        let model = Model {
+            encoding,
+            path: path.to_path_buf(),
+            state: Mutex::default(),
+        };
+        self.models
+            .lock()
+            .unwrap()
+            .insert(name.into_owned(), Arc::new(model));
+        Ok(())
+    }
+
+    /// Unload least recently used graphs, other than `keep`, until the loaded
+    /// ones fit in the memory budget, if any.
+    fn evict(&self, keep: &Arc<Model>) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+        let models = self
+            .models
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Graphs being loaded or retrieved right now are skipped, and neither
+        // count towards the budget nor are unloaded.
+        let mut total = 0;
+        let mut candidates = vec![];
+        for model in &models {
+            let Ok(state) = model.state.try_lock() else {
+                continue;
+            };
+            if let Some(loaded) = &state.loaded {
+                total += loaded.fingerprint.bytes;
+                if !Arc::ptr_eq(model, keep) {
+                    candidates.push((state.last_used, loaded.fingerprint.bytes, model));
+                }
+            }
+        }
+        candidates.sort_by_key(|(last_used, _, _)| *last_used);
+
+        for (last_used, bytes, model) in candidates {
+            if total <= budget {
+                break;
+            }
+            let Ok(mut state) = model.state.try_lock() else {
+                continue;
+            };
+            // Leave alone a graph which was used since it was looked at.
+            if state.last_used == last_used && state.loaded.is_some() {
+                tracing::debug!("unloading graph: {}", model.path.display());
+                state.loaded = None;
+                total -= bytes;
+            }
+        }
+    }
+}
+
+impl Model {
+    /// Return the graph, loading it if it isn't yet or if its files changed.
+    ///
+    /// Returns whether the graph was loaded.
+    fn get(&self, registry: &LazyRegistry) -> anyhow::Result<(Graph, bool)> {
+        let mut state = self.state.lock().unwrap();
+        state.last_used = registry.clock.fetch_add(1, Ordering::Relaxed);
+
+        let now = Instant::now();
+        let check = state
+            .last_checked
+            .is_none_or(|last| now.duration_since(last) >= registry.reload_interval);
+        if let Some(loaded) = &state.loaded {
+            if !check {
+                return Ok((loaded.graph.clone(), false));
+            }
+        }
+        state.last_checked = Some(now);
+
+        // Compute the fingerprint before loading so that changes made while
+        // loading are picked up by the next check.
+        let path = self.path.clone();
+        let fingerprint = blocking(move || fingerprint(&path))?;
+        if let Some(loaded) = &state.loaded {
+            if loaded.fingerprint == fingerprint {
+                return Ok((loaded.graph.clone(), false));
+            }
+            tracing::debug!("reloading changed graph: {}", self.path.display());
+        }
+
+        let (backends, encoding, path) = (registry.backends, self.encoding, self.path.clone());
+        let graph = match blocking(move || load(backends, encoding, &path)) {
+            Ok(graph) => graph,
+            Err(e) => {
+                let Some(loaded) = &state.loaded else {
+                    return Err(e);
+                };
+                // The old graph keeps being used until its files are fixed.
+                tracing::warn!("failed to reload graph, keeping the old one: {e:?}");
+                return Ok((loaded.graph.clone(), false));
+            }
+        };
+        state.loaded = Some(Loaded {
+            graph: graph.clone(),
+            fingerprint,
+        });
+        Ok((graph, true))
+    }
+}
+
+/// Find the backend of `encoding`, which must support loading graphs from
+/// directories.
+fn find_dir_loadable(
+    backends: &mut [Backend],
+    encoding: GraphEncoding,
+) -> anyhow::Result<&mut dyn backend::BackendFromDir> {
+    backends
+        .iter_mut()
+        .find(|b| b.encoding() == encoding)
+        .ok_or(anyhow!("unsupported backend: {}", encoding))?
+        .as_dir_loadable()
+        .ok_or(anyhow!("{} does not support directory loading", encoding))
+}
+
+/// Load the graph in the `path` directory with the backend of `encoding`,
+/// created by `backends`.
+fn load(
+    backends: fn() -> Vec<Backend>,
+    encoding: GraphEncoding,
+    path: &Path,
+) -> anyhow::Result<Graph> {
+    // Backends are cheap to create, and a fresh one lets graphs load
+    // concurrently.
+    let mut backends = backends();
+    let graph = find_dir_loadable(&mut backends, encoding)?
+        .load_from_dir(path, ExecutionTarget::Cpu)
+        .with_context(|| format!("failed to load graph: {}", path.display()))?;
+    Ok(graph)
+}
+
+/// Run `f`, which reads from disk, on Tokio's blocking thread pool when called
+/// from a multi-threaded Tokio runtime, e.g. by the stores of `wasmtime
+/// serve`, so that loading a graph doesn't stall the other tasks of the worker
+/// thread; otherwise `f` runs on the current thread.
+fn blocking<R>(f: impl FnOnce() -> R + Send + 'static) -> R
+where
+    R: Send + 'static,
+{
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| handle.block_on(tokio::task::spawn_blocking(f)))
+                .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+        }
+        _ => f(),
+    }
+}
+
+/// Graphs aren't stored in the registry itself but loaded when fetched, so
+/// [`GraphRegistry::get`] and [`GraphRegistry::get_mut`] never find any.
+impl GraphRegistry for LazyRegistry {
+    fn get(&self, _name: &str) -> Option<&Graph> {
+        None
+    }
+
+    fn get_mut(&mut self, _name: &str) -> Option<&mut Graph> {
+        None
+    }
+
+    fn fetch(&self, name: &str) -> anyhow::Result<Option<Graph>> {
+        let Some(model) = self.models.lock().unwrap().get(name).cloned() else {
+            return Ok(None);
+        };
+        let (graph, loaded) = model.get(self)?;
+        if loaded {
+            self.evict(&model);
+        }
+        Ok(Some(graph))
+    }
+}
+
+/// Summarize the files in `dir` and its subdirectories.
+fn fingerprint(dir: &Path) -> anyhow::Result<Fingerprint> {
+    let mut fingerprint = Fingerprint {
+        files: 0,
+        bytes: 0,
+        modified: None,
+    };
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries =
+            fs::read_dir(&dir).with_context(|| format!("failed to read: {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            }
+            fingerprint.files += 1;
+            fingerprint.bytes += metadata.len();
+            let modified = metadata.modified().ok();
+            fingerprint.modified = fingerprint.modified.max(modified);
+        }
+    }
+    Ok(fingerprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{BackendError, BackendFromDir, BackendGraph, BackendInner};
+    use crate::ExecutionContext;
+    use std::cell::Cell;
+
+    thread_local! {
+        /// The number of graphs loaded by this test's backends.
+        static LOADS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// A backend for `Openvino` graphs, which are directories with a `model`
+    /// file that doesn't contain `bad`.
+    struct DirBackend;
+
+    impl BackendInner for DirBackend {
+        fn encoding(&self) -> GraphEncoding {
+            GraphEncoding::Openvino
+        }
+
+        fn load(&mut self, _: &[&[u8]], _: ExecutionTarget) -> Result<Graph, BackendError> {
+            unimplemented!()
+        }
+
+        fn as_dir_loadable(&mut self) -> Option<&mut dyn BackendFromDir> {
+            Some(self)
+        }
+    }
+
+    impl BackendFromDir for DirBackend {
+        fn load_from_dir(
+            &mut self,
+            path: &Path,
+            _: ExecutionTarget,
+        ) -> Result<Graph, BackendError> {
+            if fs::read(path.join("model")).map_err(anyhow::Error::from)? == b"bad" {
+                return Err(anyhow!("bad model").into());
+            }
+            LOADS.with(|loads| loads.set(loads.get() + 1));
+            Ok(Graph::from(Box::new(TestGraph) as Box<dyn BackendGraph>))
+        }
+    }
+
+    /// A backend for `Onnx` graphs, which can't be loaded from directories.
+    struct BytesBackend;
+
+    impl BackendInner for BytesBackend {
+        fn encoding(&self) -> GraphEncoding {
+            GraphEncoding::Onnx
+        }
+
+        fn load(&mut self, _: &[&[u8]], _: ExecutionTarget) -> Result<Graph, BackendError> {
+            unimplemented!()
+        }
+
+        fn as_dir_loadable(&mut self) -> Option<&mut dyn BackendFromDir> {
+            None
+        }
+    }
+
+    struct TestGraph;
+
+    impl BackendGraph for TestGraph {
+        fn init_execution_context(&self) -> Result<ExecutionContext, BackendError> {
+            unimplemented!()
+        }
+    }
+
+    fn test_backends() -> Vec<Backend> {
+        vec![Backend::from(DirBackend), Backend::from(BytesBackend)]
+    }
+
+    /// A registry which checks graph directories on each fetch, and a
+    /// directory in which to create them.
+    fn registry() -> (LazyRegistry, tempfile::TempDir) {
+        let mut registry = LazyRegistry::new();
+        registry.reload_interval(Duration::ZERO);
+        registry.backends = test_backends;
+        (registry, tempfile::tempdir().unwrap())
+    }
+
+    fn add(registry: &LazyRegistry, dir: &Path, name: &str, model: &str) -> anyhow::Result<()> {
+        let path = dir.join(name);
+        fs::create_dir_all(&path)?;
+        fs::write(path.join("model"), model)?;
+        registry.add(GraphEncoding::Openvino, &path)
+    }
+
+    fn fetch(registry: &LazyRegistry, name: &str) -> Graph {
+        registry.fetch(name).unwrap().unwrap()
+    }
+
+    fn loads() -> usize {
+        LOADS.with(|loads| loads.get())
+    }
+
+    fn is_loaded(registry: &LazyRegistry, name: &str) -> bool {
+        let model = registry.models.lock().unwrap()[name].clone();
+        let loaded = model.state.lock().unwrap().loaded.is_some();
+        loaded
+    }
+
+    #[test]
+    fn loads_on_first_fetch() -> anyhow::Result<()> {
+        let (registry, dir) = registry();
+        add(&registry, dir.path(), "a", "model a")?;
+        assert_eq!(loads(), 0);
+        assert!(!is_loaded(&registry, "a"));
+
+        let graph = fetch(&registry, "a");
+        assert_eq!(loads(), 1);
+        assert!(Arc::ptr_eq(&graph.0, &fetch(&registry, "a").0));
+        assert_eq!(loads(), 1);
+        assert!(registry.fetch("b")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn clones_share_graphs() -> anyhow::Result<()> {
+        let (registry, dir) = registry();
+        let clone = registry.clone();
+        add(&registry, dir.path(), "a", "model a")?;
+
+        let graph = fetch(&clone, "a");
+        assert!(Arc::ptr_eq(&graph.0, &fetch(&registry, "a").0));
+        assert_eq!(loads(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() -> anyhow::Result<()> {
+        let (mut registry, dir) = registry();
+        registry.memory_budget(20);
+        for name in ["a", "b", "c"] {
+            add(&registry, dir.path(), name, "0123456789")?;
+        }
+
+        fetch(&registry, "a");
+        fetch(&registry, "b");
+        fetch(&registry, "a");
+        assert_eq!(loads(), 2);
+
+        // Loading `c` exceeds the budget, so `b`, used least recently, goes.
+        fetch(&registry, "c");
+        assert_eq!(loads(), 3);
+        assert!(is_loaded(&registry, "a"));
+        assert!(!is_loaded(&registry, "b"));
+        assert!(is_loaded(&registry, "c"));
+
+        fetch(&registry, "b");
+        assert_eq!(loads(), 4);
+        assert!(!is_loaded(&registry, "a"));
+        Ok(())
+    }
+
+    #[test]
+    fn reloads_changed_graphs() -> anyhow::Result<()> {
+        let (registry, dir) = registry();
+        add(&registry, dir.path(), "a", "model a")?;
+        let old = fetch(&registry, "a");
+
+        fs::write(dir.path().join("a/model"), "model a, version 2")?;
+        let new = fetch(&registry, "a");
+        assert_eq!(loads(), 2);
+        assert!(!Arc::ptr_eq(&old.0, &new.0));
+        assert!(Arc::ptr_eq(&new.0, &fetch(&registry, "a").0));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_old_graph_when_reload_fails() -> anyhow::Result<()> {
+        let (registry, dir) = registry();
+        add(&registry, dir.path(), "a", "model a")?;
+        let old = fetch(&registry, "a");
+
+        fs::write(dir.path().join("a/model"), "bad")?;
+        assert!(Arc::ptr_eq(&old.0, &fetch(&registry, "a").0));
+        assert_eq!(loads(), 1);
+
+        fs::write(dir.path().join("a/model"), "model a, fixed")?;
+        assert!(!Arc::ptr_eq(&old.0, &fetch(&registry, "a").0));
+        assert_eq!(loads(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn first_load_failure_is_an_error() -> anyhow::Result<()> {
+        let (registry, dir) = registry();
+        add(&registry, dir.path(), "a", "bad")?;
+        assert!(registry.fetch("a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn add_requires_dir_loadable_backend() -> anyhow::Result<()> {
+        let (registry, dir) = registry();
+        let path = dir.path().join("a");
+        fs::create_dir(&path)?;
+
+        for encoding in [GraphEncoding::Onnx, GraphEncoding::Tensorflow] {
+            assert!(registry.add(encoding, &path).is_err());
+        }
+        assert!(registry.fetch("a")?.is_none());
+        Ok(())
+    }
+}
@@ -4,14 +4,26 @@
 //! by name. This API does not mandate how a graph is loaded or how it must be
 //! stored--it could be stored remotely and rematerialized when needed, e.g. A
 //! naive in-memory implementation, [`InMemoryRegistry`] is provided for use
-//! with the Wasmtime CLI.
+//! with the Wasmtime CLI, as well as [`LazyRegistry`], which loads graphs from
+//! disk on first use and shares them between stores.
 
 mod in_memory;
+mod lazy;
 
 use crate::Graph;
 pub use in_memory::InMemoryRegistry;
+pub use lazy::LazyRegistry;
 
 pub trait GraphRegistry: Send + Sync {
     fn get(&self, name: &str) -> Option<&Graph>;
     fn get_mut(&mut self, name: &str) -> Option<&mut Graph>;
+
+    /// Retrieve a copy of the graph named `name`, if any, materializing it if
+    /// needed; fails if the graph exists but can't be materialized.
+    ///
+    /// This is how graphs are loaded by name. By default it clones the graph
+    /// returned by [`GraphRegistry::get`].
+    fn fetch(&self, name: &str) -> anyhow::Result<Option<Graph>> {
+        Ok(self.get(name).cloned())
+    }
 }
@@ -31,9 +31,17 @@ pub struct WasiNnCtx {
 
 impl WasiNnCtx {
     /// Make a new context from the default state.
+    ///
+    /// If several `backends` load the same encoding, the first one is used.
     pub fn new(backends: impl IntoIterator<Item = Backend>, registry: Registry) -> Self {
-        let backends = backends.into_iter().map(|b| (b.encoding(), b)).collect();
-        Self { backends, registry }
+        let mut by_encoding = HashMap::new();
+        for backend in backends {
+            by_encoding.entry(backend.encoding()).or_insert(backend);
+        }
+        Self {
+            backends: by_encoding,
+            registry,
+        }
     }
 }
 
@@ -196,16 +204,21 @@ impl generated::graph::Host for WasiNnView<'_> {
         use core::result::Result::*;
         tracing::debug!("load by name {name:?}");
         let registry = &self.ctx.registry;
-        if let Some(graph) = registry.get(&name) {
-            let graph = graph.clone();
-            let graph = self.table.push(graph)?;
-            Ok(Ok(graph))
-        } else {
-            bail!(
-                self,
-                ErrorCode::NotFound,
-                anyhow!("failed to find graph with name: {name}")
-            );
+        match registry.fetch(&name) {
+            Ok(Some(graph)) => {
+                let graph = self.table.push(graph)?;
+                Ok(Ok(graph))
+            }
+            Ok(None) => {
+                bail!(
+                    self,
+                    ErrorCode::NotFound,
+                    anyhow!("failed to find graph with name: {name}")
+                );
+            }
+            Err(e) => {
+                bail!(self, ErrorCode::RuntimeError, e);
+            }
         }
     }
 }
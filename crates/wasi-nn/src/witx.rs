@@ -39,10 +39,15 @@ pub struct WasiNnCtx {
 
 impl WasiNnCtx {
     /// Make a new context from the default state.
+    ///
+    /// If several `backends` load the same encoding, the first one is used.
     pub fn new(backends: impl IntoIterator<Item = Backend>, registry: Registry) -> Self {
-        let backends = backends.into_iter().map(|b| (b.encoding(), b)).collect();
+        let mut by_encoding = HashMap::new();
+        for backend in backends {
+            by_encoding.entry(backend.encoding()).or_insert(backend);
+        }
         Self {
-            backends,
+            backends: by_encoding,
             registry,
             graphs: Table::default(),
             executions: Table::default(),
@@ -159,8 +164,12 @@ impl generated::wasi_ephemeral_nn::WasiEphemeralNn for WasiNnCtx {
         name: wiggle::GuestPtr<str>,
     ) -> Result<generated::types::Graph> {
         let name = memory.as_str(name)?.unwrap();
-        if let Some(graph) = self.registry.get_mut(&name) {
-            let graph_id = self.graphs.insert(graph.clone().into());
+        let graph = self
+            .registry
+            .fetch(&name)
+            .map_err(BackendError::BackendAccess)?;
+        if let Some(graph) = graph {
+            let graph_id = self.graphs.insert(graph);
             Ok(graph_id.into())
         } else {
             return Err(UsageError::NotFound(name.to_string()).into());
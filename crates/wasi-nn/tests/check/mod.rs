@@ -11,7 +11,11 @@ use std::{
     sync::Mutex,
 };
 
-#[cfg(any(feature = "onnx", all(feature = "winml", target_os = "windows")))]
+#[cfg(any(
+    feature = "onnx",
+    feature = "tract",
+    all(feature = "winml", target_os = "windows")
+))]
 pub mod onnx;
 #[cfg(feature = "openvino")]
 pub mod openvino;
@@ -129,15 +129,14 @@ fn nn_witx_image_classification_openvino_named() -> Result<()> {
     exec::witx::run(NN_WITX_IMAGE_CLASSIFICATION_OPENVINO_NAMED, backend, true)
 }
 
-#[cfg(feature = "onnx")]
+#[cfg(any(feature = "onnx", feature = "tract"))]
 fn nn_witx_image_classification_onnx() -> Result<()> {
     check::onnx::are_artifacts_available()?;
-    let backend = Backend::from(backend::onnx::OnnxBackend::default());
-    exec::witx::run(NN_WITX_IMAGE_CLASSIFICATION_ONNX, backend, false)
+    exec::witx::run(NN_WITX_IMAGE_CLASSIFICATION_ONNX, onnx_backend(), false)
 }
-#[cfg(not(feature = "onnx"))]
+#[cfg(not(any(feature = "onnx", feature = "tract")))]
 fn nn_witx_image_classification_onnx() -> Result<()> {
-    anyhow::bail!("this test requires the `onnx` feature")
+    anyhow::bail!("this test requires the `onnx` or `tract` feature")
 }
 
 #[cfg(all(feature = "winml", target_os = "windows"))]
@@ -185,15 +184,28 @@ fn nn_wit_image_classification_openvino_named() -> Result<()> {
     )
 }
 
-#[cfg(feature = "onnx")]
+#[cfg(any(feature = "onnx", feature = "tract"))]
 fn nn_wit_image_classification_onnx() -> Result<()> {
     check::onnx::are_artifacts_available()?;
-    let backend = Backend::from(backend::onnx::OnnxBackend::default());
-    exec::wit::run(NN_WIT_IMAGE_CLASSIFICATION_ONNX_COMPONENT, backend, false)
+    exec::wit::run(
+        NN_WIT_IMAGE_CLASSIFICATION_ONNX_COMPONENT,
+        onnx_backend(),
+        false,
+    )
 }
-#[cfg(not(feature = "onnx"))]
+#[cfg(not(any(feature = "onnx", feature = "tract")))]
 fn nn_wit_image_classification_onnx() -> Result<()> {
-    anyhow::bail!("this test requires the `onnx` feature")
+    anyhow::bail!("this test requires the `onnx` or `tract` feature")
+}
+
+/// The ONNX tests run on ONNX Runtime when enabled, otherwise on the pure-Rust
+/// tract backend, which needs no installation.
+#[cfg(any(feature = "onnx", feature = "tract"))]
+fn onnx_backend() -> Backend {
+    #[cfg(feature = "onnx")]
+    return Backend::from(backend::onnx::OnnxBackend::default());
+    #[cfg(not(feature = "onnx"))]
+    return Backend::from(backend::tract::TractBackend::default());
 }
 
 #[cfg(feature = "pytorch")]
@@ -281,8 +293,10 @@ impl IgnoreCheck {
         } else {
             Run
         }
-        #[cfg(not(feature = "onnx"))]
-        Ignore("requires the `onnx` feature".into())
+        #[cfg(all(feature = "tract", not(feature = "onnx")))]
+        return Run;
+        #[cfg(not(any(feature = "onnx", feature = "tract")))]
+        Ignore("requires the `onnx` or `tract` feature".into())
     }
 
     fn for_pytorch() -> Self {
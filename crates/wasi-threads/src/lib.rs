@@ -2,10 +2,11 @@
 //!
 //! [`wasi-threads`]: https://github.com/WebAssembly/wasi-threads
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use std::io;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::atomic::{AtomicI32, Ordering};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, Weak};
 use std::thread;
 use wasmtime::{Caller, ExternType, InstancePre, Linker, Module, SharedMemory, Store};
 
@@ -16,13 +17,133 @@ const WASI_ENTRY_POINT: &str = "wasi_thread_start";
 pub struct WasiThreadsCtx<T> {
     instance_pre: Arc<InstancePre<T>>,
     tid: AtomicI32,
+    threads: Arc<Threads>,
+    max_threads: Option<usize>,
+    pool: Option<Arc<Pool>>,
+    green: Option<GreenThreads>,
+    report_traps: bool,
 }
 
 impl<T: Clone + Send + 'static> WasiThreadsCtx<T> {
     pub fn new(module: Module, linker: Arc<Linker<T>>) -> Result<Self> {
         let instance_pre = Arc::new(linker.instantiate_pre(&module)?);
         let tid = AtomicI32::new(0);
-        Ok(Self { instance_pre, tid })
+        Ok(Self {
+            instance_pre,
+            tid,
+            threads: Arc::default(),
+            max_threads: None,
+            pool: None,
+            green: None,
+            report_traps: false,
+        })
+    }
+
+    /// Limit the number of spawned threads which can run at the same time,
+    /// not counting the thread which runs the module's entry point.
+    ///
+    /// Spawning a thread beyond the limit fails, which the guest sees as a
+    /// negative result of `thread-spawn`. By default the number of threads is
+    /// only limited by the host.
+    pub fn max_threads(&mut self, max: usize) -> &mut Self {
+        self.max_threads = Some(max);
+        self
+    }
+
+    /// Keep up to `idle_threads` OS threads around once the thread they ran
+    /// finished, to run the next spawned threads instead of creating a new OS
+    /// thread for each of them.
+    ///
+    /// By default each spawned thread runs on a new OS thread.
+    pub fn thread_pool(&mut self, idle_threads: usize) -> &mut Self {
+        self.pool = Some(Arc::new(Pool {
+            idle: Mutex::new(Vec::new()),
+            max_idle: idle_threads,
+        }));
+        self
+    }
+
+    /// Run spawned threads as green threads, multiplexed on `host_threads` OS
+    /// threads, instead of running each of them on an OS thread of its own.
+    ///
+    /// Each spawned thread still runs in a store of its own, but through
+    /// [`call_async`](wasmtime::TypedFunc::call_async) on a fiber, which
+    /// requires the engine to have [`Config::async_support`] enabled. The
+    /// engine should also have [`Config::async_atomic_wait`] enabled so that
+    /// a thread blocked in `memory.atomic.wait32/64` yields its OS thread to
+    /// the other threads until it's notified; otherwise a waiting thread
+    /// blocks one of the `host_threads`, and once all of them are blocked no
+    /// thread is left to notify them. Spawned threads run on a Tokio runtime,
+    /// so setting [`Config::async_atomic_wait_timer`] to `tokio::time::sleep`
+    /// makes waits with a timeout yield as well.
+    ///
+    /// This takes precedence over [`WasiThreadsCtx::thread_pool`].
+    ///
+    /// [`Config::async_support`]: wasmtime::Config::async_support
+    /// [`Config::async_atomic_wait`]: wasmtime::Config::async_atomic_wait
+    /// [`Config::async_atomic_wait_timer`]: wasmtime::Config::async_atomic_wait_timer
+    pub fn green_threads(&mut self, host_threads: usize) -> Result<&mut Self> {
+        if !self.instance_pre.module().engine().is_async() {
+            bail!("green threads require an engine with async support enabled");
+        }
+        if host_threads == 0 {
+            bail!("green threads require at least one host thread");
+        }
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(host_threads)
+            .thread_name("wasi-green-threads")
+            .enable_all()
+            .build()?;
+        self.green = Some(GreenThreads(Some(runtime)));
+        Ok(self)
+    }
+
+    /// Report traps of spawned threads, and calls to `proc_exit` in them, to
+    /// the embedder through [`WasiThreadsCtx::join`] and
+    /// [`WasiThreadsCtx::take_trap`] instead of exiting the process.
+    ///
+    /// By specification a trap in any thread ends the execution of all of
+    /// them, which by default is done with [`std::process::exit`] as the
+    /// `wasmtime` CLI expects. When traps are reported, the other threads
+    /// keep running, and it's up to the embedder to stop them, for example by
+    /// dropping the store of the main thread and interrupting the others with
+    /// epochs.
+    ///
+    /// The reported error of a call to `proc_exit` can be downcast to
+    /// [`wasmtime_wasi::I32Exit`] or `wasi_common::I32Exit`, depending on the
+    /// WASI implementation.
+    pub fn report_traps(&mut self, enable: bool) -> &mut Self {
+        self.report_traps = enable;
+        self
+    }
+
+    /// The number of spawned threads which are still running.
+    pub fn running_threads(&self) -> usize {
+        self.threads.lock().running
+    }
+
+    /// Wait until all spawned threads finished, or one of them trapped.
+    ///
+    /// Returns the first trap which wasn't taken yet, if traps are reported,
+    /// see [`WasiThreadsCtx::report_traps`].
+    pub fn join(&self) -> Result<()> {
+        let mut state = self
+            .threads
+            .finished
+            .wait_while(self.threads.lock(), |state| {
+                state.running > 0 && state.trap.is_none()
+            })
+            .unwrap();
+        match state.trap.take() {
+            Some(trap) => Err(trap),
+            None => Ok(()),
+        }
+    }
+
+    /// Take the first trap of a spawned thread which wasn't taken yet, if
+    /// traps are reported, see [`WasiThreadsCtx::report_traps`].
+    pub fn take_trap(&self) -> Option<anyhow::Error> {
+        self.threads.lock().trap.take()
     }
 
     pub fn spawn(&self, host: T, thread_start_arg: i32) -> Result<i32> {
@@ -46,6 +167,11 @@ impl<T: Clone + Send + 'static> WasiThreadsCtx<T> {
             return Ok(-1);
         }
 
+        let Some(running) = self.threads.start(self.max_threads) else {
+            log::error!("reached the maximum number of running threads");
+            return Ok(-1);
+        };
+
         let wasi_thread_id = self.next_thread_id();
         if wasi_thread_id.is_none() {
             log::error!("ran out of valid thread IDs");
@@ -53,61 +179,45 @@ impl<T: Clone + Send + 'static> WasiThreadsCtx<T> {
         }
         let wasi_thread_id = wasi_thread_id.unwrap();
 
-        // Start a Rust thread running a new instance of the current module.
-        let builder = thread::Builder::new().name(format!("wasi-thread-{wasi_thread_id}"));
-        builder.spawn(move || {
+        let report_traps = self.report_traps;
+
+        // Start a green thread, scheduled on the host threads alongside the
+        // others, running a new instance of the current module.
+        if let Some(green) = &self.green {
+            let runtime = green.runtime();
+            let thread = runtime.spawn(async move {
+                run_thread_async(&instance_pre, host, wasi_thread_id, thread_start_arg).await
+            });
+            // Panics are caught by the runtime and reported when joining the
+            // task; cancellation only happens when the runtime shuts down.
+            runtime.spawn(async move {
+                let result = match thread.await {
+                    Ok(result) => Ok(result),
+                    Err(e) if e.is_panic() => Err(e.into_panic()),
+                    Err(_) => return,
+                };
+                exited(running, report_traps, wasi_thread_id, result);
+            });
+            return Ok(wasi_thread_id);
+        }
+
+        let job: Job = Box::new(move || {
             // Catch any panic failures in host code; e.g., if a WASI module
             // were to crash, we want all threads to exit, not just this one.
             let result = catch_unwind(AssertUnwindSafe(|| {
-                // Each new instance is created in its own store.
-                let mut store = Store::new(&instance_pre.module().engine(), host);
-
-                let instance = if instance_pre.module().engine().is_async() {
-                    wasmtime_wasi::runtime::in_tokio(instance_pre.instantiate_async(&mut store))
-                } else {
-                    instance_pre.instantiate(&mut store)
-                }
-                .unwrap();
-
-                let thread_entry_point = instance
-                    .get_typed_func::<(i32, i32), ()>(&mut store, WASI_ENTRY_POINT)
-                    .unwrap();
-
-                // Start the thread's entry point. Any traps or calls to
-                // `proc_exit`, by specification, should end execution for all
-                // threads. This code uses `process::exit` to do so, which is
-                // what the user expects from the CLI but probably not in a
-                // Wasmtime embedding.
-                log::trace!(
-                    "spawned thread id = {}; calling start function `{}` with: {}",
-                    wasi_thread_id,
-                    WASI_ENTRY_POINT,
-                    thread_start_arg
-                );
-                let res = if instance_pre.module().engine().is_async() {
-                    wasmtime_wasi::runtime::in_tokio(
-                        thread_entry_point
-                            .call_async(&mut store, (wasi_thread_id, thread_start_arg)),
-                    )
-                } else {
-                    thread_entry_point.call(&mut store, (wasi_thread_id, thread_start_arg))
-                };
-                match res {
-                    Ok(_) => log::trace!("exiting thread id = {} normally", wasi_thread_id),
-                    Err(e) => {
-                        log::trace!("exiting thread id = {} due to error", wasi_thread_id);
-                        let e = wasi_common::maybe_exit_on_error(e);
-                        eprintln!("Error: {e:?}");
-                        std::process::exit(1);
-                    }
-                }
+                run_thread(&instance_pre, host, wasi_thread_id, thread_start_arg)
             }));
+            exited(running, report_traps, wasi_thread_id, result);
+        });
 
-            if let Err(e) = result {
-                eprintln!("wasi-thread-{wasi_thread_id} panicked: {e:?}");
-                std::process::exit(1);
+        // Start a Rust thread running a new instance of the current module.
+        match &self.pool {
+            Some(pool) => pool.run(job)?,
+            None => {
+                let builder = thread::Builder::new().name(format!("wasi-thread-{wasi_thread_id}"));
+                builder.spawn(job)?;
             }
-        })?;
+        }
 
         Ok(wasi_thread_id)
     }
@@ -130,6 +240,218 @@ impl<T: Clone + Send + 'static> WasiThreadsCtx<T> {
     }
 }
 
+/// Run the thread `wasi_thread_id` in a new instance of the module.
+fn run_thread<T: Clone + Send + 'static>(
+    instance_pre: &InstancePre<T>,
+    host: T,
+    wasi_thread_id: i32,
+    thread_start_arg: i32,
+) -> Result<()> {
+    if instance_pre.module().engine().is_async() {
+        return wasmtime_wasi::runtime::in_tokio(run_thread_async(
+            instance_pre,
+            host,
+            wasi_thread_id,
+            thread_start_arg,
+        ));
+    }
+
+    // Each new instance is created in its own store.
+    let mut store = Store::new(&instance_pre.module().engine(), host);
+    let instance = instance_pre.instantiate(&mut store)?;
+    let thread_entry_point =
+        instance.get_typed_func::<(i32, i32), ()>(&mut store, WASI_ENTRY_POINT)?;
+
+    // Start the thread's entry point. Any traps or calls to `proc_exit`, by
+    // specification, should end execution for all threads. Unless traps are
+    // reported, the caller uses `process::exit` to do so, which is what the
+    // user expects from the CLI but probably not in a Wasmtime embedding.
+    log::trace!(
+        "spawned thread id = {}; calling start function `{}` with: {}",
+        wasi_thread_id,
+        WASI_ENTRY_POINT,
+        thread_start_arg
+    );
+    thread_entry_point.call(&mut store, (wasi_thread_id, thread_start_arg))
+}
+
+/// Same as [`run_thread`], but for engines with async support.
+async fn run_thread_async<T: Clone + Send + 'static>(
+    instance_pre: &InstancePre<T>,
+    host: T,
+    wasi_thread_id: i32,
+    thread_start_arg: i32,
+) -> Result<()> {
+    let mut store = Store::new(&instance_pre.module().engine(), host);
+    let instance = instance_pre.instantiate_async(&mut store).await?;
+    let thread_entry_point =
+        instance.get_typed_func::<(i32, i32), ()>(&mut store, WASI_ENTRY_POINT)?;
+
+    log::trace!(
+        "spawned thread id = {}; calling start function `{}` with: {}",
+        wasi_thread_id,
+        WASI_ENTRY_POINT,
+        thread_start_arg
+    );
+    thread_entry_point
+        .call_async(&mut store, (wasi_thread_id, thread_start_arg))
+        .await
+}
+
+/// Handle the end of the thread `wasi_thread_id`, given the result of its
+/// entry point or the payload of its panic.
+fn exited(
+    running: Running,
+    report_traps: bool,
+    wasi_thread_id: i32,
+    result: thread::Result<Result<()>>,
+) {
+    match result {
+        Ok(Ok(())) => log::trace!("exiting thread id = {} normally", wasi_thread_id),
+        Ok(Err(e)) => {
+            log::trace!("exiting thread id = {} due to error", wasi_thread_id);
+            if report_traps {
+                running.trap(e);
+            } else {
+                let e = wasi_common::maybe_exit_on_error(e);
+                eprintln!("Error: {e:?}");
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            if report_traps {
+                running.trap(anyhow!("wasi-thread-{wasi_thread_id} panicked"));
+            } else {
+                eprintln!("wasi-thread-{wasi_thread_id} panicked: {e:?}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// The spawned threads of a [`WasiThreadsCtx`].
+#[derive(Default)]
+struct Threads {
+    state: Mutex<ThreadsState>,
+    /// Notified when a thread finishes.
+    finished: Condvar,
+}
+
+#[derive(Default)]
+struct ThreadsState {
+    running: usize,
+    /// The first trap which wasn't taken yet, if traps are reported.
+    trap: Option<anyhow::Error>,
+}
+
+impl Threads {
+    fn lock(&self) -> MutexGuard<'_, ThreadsState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Count a new running thread, unless `max` threads are already running.
+    fn start(self: &Arc<Self>, max: Option<usize>) -> Option<Running> {
+        let mut state = self.lock();
+        if max.is_some_and(|max| state.running >= max) {
+            return None;
+        }
+        state.running += 1;
+        Some(Running(self.clone()))
+    }
+}
+
+/// A running thread, which is counted until this is dropped.
+struct Running(Arc<Threads>);
+
+impl Running {
+    fn trap(&self, trap: anyhow::Error) {
+        let mut state = self.0.lock();
+        if state.trap.is_none() {
+            state.trap = Some(trap);
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.lock().running -= 1;
+        self.0.finished.notify_all();
+    }
+}
+
+/// The host threads which green threads are multiplexed on.
+struct GreenThreads(Option<tokio::runtime::Runtime>);
+
+impl GreenThreads {
+    fn runtime(&self) -> &tokio::runtime::Runtime {
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl Drop for GreenThreads {
+    fn drop(&mut self) {
+        // The last reference to the context may well be dropped by one of
+        // the green threads, where the runtime can't be shut down blocking.
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// OS threads which wait for a thread to run once the one they ran finished.
+struct Pool {
+    /// The senders of jobs to the idle OS threads.
+    idle: Mutex<Vec<mpsc::Sender<Job>>>,
+    max_idle: usize,
+}
+
+impl Pool {
+    /// Run `job` on an idle OS thread, or on a new one if none is idle.
+    fn run(self: &Arc<Self>, mut job: Job) -> io::Result<()> {
+        loop {
+            let Some(worker) = self.idle.lock().unwrap().pop() else {
+                break;
+            };
+            match worker.send(job) {
+                Ok(()) => return Ok(()),
+                Err(mpsc::SendError(unsent)) => job = unsent,
+            }
+        }
+
+        // Workers don't keep the pool alive, so that idle workers exit once
+        // the pool is dropped.
+        let pool = Arc::downgrade(self);
+        thread::Builder::new()
+            .name("wasi-thread-worker".to_string())
+            .spawn(move || worker(pool, job))?;
+        Ok(())
+    }
+}
+
+fn worker(pool: Weak<Pool>, mut job: Job) {
+    loop {
+        job();
+
+        let (sender, receiver) = mpsc::channel();
+        {
+            let Some(pool) = pool.upgrade() else {
+                return;
+            };
+            let mut idle = pool.idle.lock().unwrap();
+            if idle.len() >= pool.max_idle {
+                return;
+            }
+            idle.push(sender);
+        }
+        job = match receiver.recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+    }
+}
+
 /// Manually add the WASI `thread_spawn` function to the linker.
 ///
 /// It is unclear what namespace the `wasi-threads` proposal should live under:
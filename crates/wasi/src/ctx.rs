@@ -5,8 +5,14 @@ use crate::{
     },
     filesystem::{Dir, OpenMode},
     network::{SocketAddrCheck, SocketAddrUse},
-    pipe, random, stdio,
+    pipe,
+    quota::{FsLimits, FsUsage, QuotaDir},
+    random,
+    replay::Trace,
+    stdio,
     stdio::{StdinStream, StdoutStream},
+    vfs::WasiDir,
+    vnet::VirtualNetwork,
     DirPerms, FilePerms,
 };
 use anyhow::Result;
@@ -52,6 +58,9 @@ pub struct WasiCtxBuilder {
     monotonic_clock: Box<dyn HostMonotonicClock + Send>,
     allowed_network_uses: AllowedNetworkUses,
     allow_blocking_current_thread: bool,
+    fs_limits: FsLimits,
+    trace: Option<Trace>,
+    virtual_network: Option<VirtualNetwork>,
     built: bool,
 }
 
@@ -101,6 +110,9 @@ impl WasiCtxBuilder {
             monotonic_clock: monotonic_clock(),
             allowed_network_uses: AllowedNetworkUses::default(),
             allow_blocking_current_thread: false,
+            fs_limits: FsLimits::default(),
+            trace: None,
+            virtual_network: None,
             built: false,
         }
     }
@@ -205,6 +217,49 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Configures limits on the filesystem resources that the guest can
+    /// consume through the preopened directories of this context.
+    ///
+    /// By default nothing is limited. Whether limited or not, the resources
+    /// consumed are counted and can be read back with [`WasiCtx::fs_usage`].
+    ///
+    /// ```
+    /// use wasmtime_wasi::{FsLimits, WasiCtxBuilder};
+    ///
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// wasi.fs_limits(FsLimits {
+    ///     max_bytes_written: Some(64 << 20),
+    ///     max_files_created: Some(1000),
+    ///     max_open_descriptors: Some(100),
+    /// });
+    /// ```
+    pub fn fs_limits(&mut self, limits: FsLimits) -> &mut Self {
+        self.fs_limits = limits;
+        self
+    }
+
+    /// Records the nondeterministic results of the guest's WASI calls to
+    /// `trace`, or replays them from it.
+    ///
+    /// This applies to the clocks, random generators, stdin and preopened
+    /// directories configured on this builder, whenever they're configured,
+    /// and to the sockets created by the guest. See [`Trace`] for what's
+    /// recorded and what replaying requires.
+    ///
+    /// ```no_run
+    /// use wasmtime_wasi::{Trace, WasiCtxBuilder};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// wasi.trace(Trace::create("run.trace")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn trace(&mut self, trace: Trace) -> &mut Self {
+        self.trace = Some(trace);
+        self
+    }
+
     /// Appends multiple environment variables at once for this builder.
     ///
     /// All environment variables are appended to the list of environment
@@ -331,6 +386,41 @@ impl WasiCtxBuilder {
         file_perms: FilePerms,
     ) -> Result<&mut Self> {
         let dir = cap_std::fs::Dir::open_ambient_dir(host_path.as_ref(), ambient_authority())?;
+        Ok(self.preopened_virtual_dir(dir, guest_path, dir_perms, file_perms))
+    }
+
+    /// Configures a "preopened directory" backed by `dir` to be available to
+    /// WebAssembly.
+    ///
+    /// This is like [`WasiCtxBuilder::preopened_dir`], except that the
+    /// contents of the directory are provided by any implementation of
+    /// [`WasiDir`], such as a [`MemoryDir`](crate::vfs::MemoryDir) or an
+    /// [`OverlayDir`](crate::vfs::OverlayDir). `dir_perms` and `file_perms`
+    /// are enforced in the same way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::{WasiCtxBuilder, DirPerms, FilePerms};
+    /// use wasmtime_wasi::vfs::MemoryDir;
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> std::io::Result<()> {
+    /// let data = MemoryDir::new();
+    /// data.insert_file("config/settings.json", "{}")?;
+    ///
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// wasi.preopened_virtual_dir(data, "/data", DirPerms::READ, FilePerms::READ);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_virtual_dir(
+        &mut self,
+        dir: impl WasiDir,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> &mut Self {
         let mut open_mode = OpenMode::empty();
         if dir_perms.contains(DirPerms::READ) {
             open_mode |= OpenMode::READ;
@@ -339,8 +429,8 @@ impl WasiCtxBuilder {
             open_mode |= OpenMode::WRITE;
         }
         self.preopens.push((
-            Dir::new(
-                dir,
+            Dir::from_wasi_dir(
+                Arc::new(dir),
                 dir_perms,
                 file_perms,
                 open_mode,
@@ -348,7 +438,7 @@ impl WasiCtxBuilder {
             ),
             guest_path.as_ref().to_owned(),
         ));
-        Ok(self)
+        self
     }
 
     /// Set the generator for the `wasi:random/random` number generator to the
@@ -452,6 +542,21 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Use the in-process network `net` instead of the host's network for
+    /// TCP and UDP sockets and `wasi:sockets/ip-name-lookup`.
+    ///
+    /// No host sockets are created when this is set. Which addresses the
+    /// guest can use is still configured with
+    /// [`WasiCtxBuilder::socket_addr_check`] or
+    /// [`WasiCtxBuilder::inherit_network`], and name lookups must still be
+    /// enabled with [`WasiCtxBuilder::allow_ip_name_lookup`].
+    ///
+    /// See the [`vnet`](crate::vnet) module for how the network behaves.
+    pub fn virtual_network(&mut self, net: VirtualNetwork) -> &mut Self {
+        self.virtual_network = Some(net);
+        self
+    }
+
     /// Uses the configured context so far to construct the final [`WasiCtx`].
     ///
     /// Note that each `WasiCtxBuilder` can only be used to "build" once, and
@@ -466,24 +571,48 @@ impl WasiCtxBuilder {
         assert!(!self.built);
 
         let Self {
-            stdin,
+            mut stdin,
             stdout,
             stderr,
             env,
             args,
             preopens,
             socket_addr_check,
-            random,
-            insecure_random,
-            insecure_random_seed,
-            wall_clock,
-            monotonic_clock,
+            mut random,
+            mut insecure_random,
+            mut insecure_random_seed,
+            mut wall_clock,
+            mut monotonic_clock,
             allowed_network_uses,
             allow_blocking_current_thread,
+            fs_limits,
+            trace,
+            virtual_network,
             built: _,
         } = mem::replace(self, Self::new());
         self.built = true;
 
+        if let Some(trace) = &trace {
+            stdin = Box::new(trace.stdin(stdin));
+            random = Box::new(trace.random(random));
+            insecure_random = Box::new(trace.insecure_random(insecure_random));
+            insecure_random_seed = trace.insecure_random_seed(insecure_random_seed);
+            wall_clock = Box::new(trace.wall_clock(wall_clock));
+            monotonic_clock = Box::new(trace.monotonic_clock(monotonic_clock));
+        }
+
+        let fs_usage = FsUsage::new(fs_limits);
+        let preopens = preopens
+            .into_iter()
+            .map(|(mut dir, path)| {
+                if let Some(trace) = &trace {
+                    dir.dir = Arc::new(trace.dir(dir.dir));
+                }
+                dir.dir = Arc::new(QuotaDir::new(dir.dir, fs_usage.clone()));
+                (dir, path)
+            })
+            .collect();
+
         WasiCtx {
             stdin,
             stdout,
@@ -499,6 +628,9 @@ impl WasiCtxBuilder {
             monotonic_clock,
             allowed_network_uses,
             allow_blocking_current_thread,
+            fs_usage,
+            trace,
+            virtual_network,
         }
     }
 
@@ -584,6 +716,9 @@ pub struct WasiCtx {
     pub(crate) socket_addr_check: SocketAddrCheck,
     pub(crate) allowed_network_uses: AllowedNetworkUses,
     pub(crate) allow_blocking_current_thread: bool,
+    pub(crate) fs_usage: FsUsage,
+    pub(crate) trace: Option<Trace>,
+    pub(crate) virtual_network: Option<VirtualNetwork>,
 }
 
 impl WasiCtx {
@@ -591,6 +726,22 @@ impl WasiCtx {
     pub fn builder() -> WasiCtxBuilder {
         WasiCtxBuilder::new()
     }
+
+    /// The filesystem resources consumed by the guest so far, and the limits
+    /// configured with [`WasiCtxBuilder::fs_limits`].
+    ///
+    /// The returned handle keeps being updated as the guest runs.
+    pub fn fs_usage(&self) -> &FsUsage {
+        &self.fs_usage
+    }
+
+    /// Fails if the trace being replayed, if any, has diverged from the guest.
+    pub(crate) fn check_trace(&self) -> anyhow::Result<()> {
+        match &self.trace {
+            Some(trace) => trace.check(),
+            None => Ok(()),
+        }
+    }
 }
 
 pub struct AllowedNetworkUses {
@@ -1,5 +1,6 @@
 use crate::bindings::filesystem::types;
 use crate::runtime::{spawn_blocking, AbortOnDropJoinHandle};
+use crate::vfs::{WasiDir, WasiFile};
 use crate::{
     HostInputStream, HostOutputStream, StreamError, StreamResult, Subscribe, TrappableError,
 };
@@ -21,6 +22,9 @@ impl From<wasmtime::component::ResourceTableError> for FsError {
 
 impl From<io::Error> for FsError {
     fn from(error: io::Error) -> Self {
+        if crate::replay::is_replay_error(&error) {
+            return Self::trap(error);
+        }
         types::ErrorCode::from(error).into()
     }
 }
@@ -78,14 +82,15 @@ bitflags::bitflags! {
 
 #[derive(Clone)]
 pub struct File {
-    /// The operating system File this struct is mediating access to.
+    /// The file this struct is mediating access to, which is an operating
+    /// system file unless it was opened from a virtual directory.
     ///
     /// Wrapped in an Arc because the same underlying file is used for
     /// implementing the stream types. A copy is also needed for
     /// [`spawn_blocking`].
     ///
     /// [`spawn_blocking`]: Self::spawn_blocking
-    pub file: Arc<cap_std::fs::File>,
+    pub file: Arc<dyn WasiFile>,
     /// Permissions to enforce on access to the file. These permissions are
     /// specified by a user of the `crate::WasiCtxBuilder`, and are
     /// enforced prior to any enforced by the underlying operating system.
@@ -105,9 +110,25 @@ impl File {
         perms: FilePerms,
         open_mode: OpenMode,
         allow_blocking_current_thread: bool,
+    ) -> Self {
+        Self::from_wasi_file(
+            Arc::new(file),
+            perms,
+            open_mode,
+            allow_blocking_current_thread,
+        )
+    }
+
+    /// Like [`File::new`], but for any [`WasiFile`], such as a file in a
+    /// virtual directory.
+    pub fn from_wasi_file(
+        file: Arc<dyn WasiFile>,
+        perms: FilePerms,
+        open_mode: OpenMode,
+        allow_blocking_current_thread: bool,
     ) -> Self {
         Self {
-            file: Arc::new(file),
+            file,
             perms,
             open_mode,
             allow_blocking_current_thread,
@@ -130,7 +151,7 @@ impl File {
     /// - [Implement opt-in for enabling WASI to block the current thread](https://github.com/bytecodealliance/wasmtime/pull/8190)
     pub(crate) async fn run_blocking<F, R>(&self, body: F) -> R
     where
-        F: FnOnce(&cap_std::fs::File) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFile) -> R + Send + 'static,
         R: Send + 'static,
     {
         match self.as_blocking_file() {
@@ -141,19 +162,19 @@ impl File {
 
     pub(crate) fn spawn_blocking<F, R>(&self, body: F) -> AbortOnDropJoinHandle<R>
     where
-        F: FnOnce(&cap_std::fs::File) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFile) -> R + Send + 'static,
         R: Send + 'static,
     {
         let f = self.file.clone();
-        spawn_blocking(move || body(&f))
+        spawn_blocking(move || body(&*f))
     }
 
     /// Returns `Some` when the current thread is allowed to block in filesystem
     /// operations, and otherwise returns `None` to indicate that
     /// `spawn_blocking` must be used.
-    pub(crate) fn as_blocking_file(&self) -> Option<&cap_std::fs::File> {
+    pub(crate) fn as_blocking_file(&self) -> Option<&dyn WasiFile> {
         if self.allow_blocking_current_thread {
-            Some(&self.file)
+            Some(&*self.file)
         } else {
             None
         }
@@ -179,13 +200,14 @@ bitflags::bitflags! {
 
 #[derive(Clone)]
 pub struct Dir {
-    /// The operating system file descriptor this struct is mediating access
-    /// to.
+    /// The directory this struct is mediating access to, which is an
+    /// operating system directory unless it was preopened with
+    /// `crate::WasiCtxBuilder::preopened_virtual_dir`.
     ///
     /// Wrapped in an Arc because a copy is needed for [`spawn_blocking`].
     ///
     /// [`spawn_blocking`]: Self::spawn_blocking
-    pub dir: Arc<cap_std::fs::Dir>,
+    pub dir: Arc<dyn WasiDir>,
     /// Permissions to enforce on access to this directory. These permissions
     /// are specified by a user of the `crate::WasiCtxBuilder`, and
     /// are enforced prior to any enforced by the underlying operating system.
@@ -211,9 +233,27 @@ impl Dir {
         file_perms: FilePerms,
         open_mode: OpenMode,
         allow_blocking_current_thread: bool,
+    ) -> Self {
+        Self::from_wasi_dir(
+            Arc::new(dir),
+            perms,
+            file_perms,
+            open_mode,
+            allow_blocking_current_thread,
+        )
+    }
+
+    /// Like [`Dir::new`], but for any [`WasiDir`], such as a virtual
+    /// directory.
+    pub fn from_wasi_dir(
+        dir: Arc<dyn WasiDir>,
+        perms: DirPerms,
+        file_perms: FilePerms,
+        open_mode: OpenMode,
+        allow_blocking_current_thread: bool,
     ) -> Self {
         Dir {
-            dir: Arc::new(dir),
+            dir,
             perms,
             file_perms,
             open_mode,
@@ -237,14 +277,14 @@ impl Dir {
     /// - [Implement opt-in for enabling WASI to block the current thread](https://github.com/bytecodealliance/wasmtime/pull/8190)
     pub(crate) async fn run_blocking<F, R>(&self, body: F) -> R
     where
-        F: FnOnce(&cap_std::fs::Dir) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiDir) -> R + Send + 'static,
         R: Send + 'static,
     {
         if self.allow_blocking_current_thread {
-            body(&self.dir)
+            body(&*self.dir)
         } else {
             let d = self.dir.clone();
-            spawn_blocking(move || body(&d)).await
+            spawn_blocking(move || body(&*d)).await
         }
     }
 }
@@ -270,9 +310,7 @@ impl FileInputStream {
         }
     }
 
-    fn blocking_read(file: &cap_std::fs::File, offset: u64, size: usize) -> ReadState {
-        use system_interface::fs::FileIoExt;
-
+    fn blocking_read(file: &dyn WasiFile, offset: u64, size: usize) -> ReadState {
         let mut buf = BytesMut::zeroed(size);
         loop {
             match file.read_at(&mut buf, offset) {
@@ -326,6 +364,9 @@ impl HostInputStream for FileInputStream {
             }
             ReadState::Waiting(_) => Ok(Bytes::new()),
             ReadState::Error(_) => match mem::replace(&mut self.state, ReadState::Closed) {
+                ReadState::Error(e) if crate::replay::is_replay_error(&e) => {
+                    Err(StreamError::Trap(e.into()))
+                }
                 ReadState::Error(e) => Err(StreamError::LastOperationFailed(e.into())),
                 _ => unreachable!(),
             },
@@ -426,12 +467,10 @@ impl FileOutputStream {
     }
 
     fn blocking_write(
-        file: &cap_std::fs::File,
+        file: &dyn WasiFile,
         mut buf: Bytes,
         mode: FileOutputMode,
     ) -> io::Result<usize> {
-        use system_interface::fs::FileIoExt;
-
         match mode {
             FileOutputMode::Position(mut p) => {
                 let mut total = 0;
@@ -30,6 +30,7 @@ where
 {
     fn now(&mut self) -> anyhow::Result<Datetime> {
         let now = self.ctx().wall_clock.now();
+        self.ctx().check_trace()?;
         Ok(Datetime {
             seconds: now.as_secs(),
             nanoseconds: now.subsec_nanos(),
@@ -38,6 +39,7 @@ where
 
     fn resolution(&mut self) -> anyhow::Result<Datetime> {
         let res = self.ctx().wall_clock.resolution();
+        self.ctx().check_trace()?;
         Ok(Datetime {
             seconds: res.as_secs(),
             nanoseconds: res.subsec_nanos(),
@@ -69,15 +71,20 @@ where
     T: WasiView,
 {
     fn now(&mut self) -> anyhow::Result<Instant> {
-        Ok(self.ctx().monotonic_clock.now())
+        let now = self.ctx().monotonic_clock.now();
+        self.ctx().check_trace()?;
+        Ok(now)
     }
 
     fn resolution(&mut self) -> anyhow::Result<Instant> {
-        Ok(self.ctx().monotonic_clock.resolution())
+        let res = self.ctx().monotonic_clock.resolution();
+        self.ctx().check_trace()?;
+        Ok(res)
     }
 
     fn subscribe_instant(&mut self, when: Instant) -> anyhow::Result<Resource<Pollable>> {
         let clock_now = self.ctx().monotonic_clock.now();
+        self.ctx().check_trace()?;
         let duration = if when > clock_now {
             Duration::from_nanos(when - clock_now)
         } else {
@@ -7,14 +7,18 @@ where
     T: WasiView,
 {
     fn get_random_bytes(&mut self, len: u64) -> anyhow::Result<Vec<u8>> {
-        Ok((&mut self.ctx().random)
+        let bytes = (&mut self.ctx().random)
             .sample_iter(Standard)
             .take(len as usize)
-            .collect())
+            .collect();
+        self.ctx().check_trace()?;
+        Ok(bytes)
     }
 
     fn get_random_u64(&mut self) -> anyhow::Result<u64> {
-        Ok(self.ctx().random.sample(Standard))
+        let value = self.ctx().random.sample(Standard);
+        self.ctx().check_trace()?;
+        Ok(value)
     }
 }
 
@@ -23,14 +27,18 @@ where
     T: WasiView,
 {
     fn get_insecure_random_bytes(&mut self, len: u64) -> anyhow::Result<Vec<u8>> {
-        Ok((&mut self.ctx().insecure_random)
+        let bytes = (&mut self.ctx().insecure_random)
             .sample_iter(Standard)
             .take(len as usize)
-            .collect())
+            .collect();
+        self.ctx().check_trace()?;
+        Ok(bytes)
     }
 
     fn get_insecure_random_u64(&mut self) -> anyhow::Result<u64> {
-        Ok(self.ctx().insecure_random.sample(Standard))
+        let value = self.ctx().insecure_random.sample(Standard);
+        self.ctx().check_trace()?;
+        Ok(value)
     }
 }
 
@@ -40,6 +48,7 @@ where
 {
     fn insecure_seed(&mut self) -> anyhow::Result<(u64, u64)> {
         let seed: u128 = self.ctx().insecure_random_seed;
+        self.ctx().check_trace()?;
         Ok((seed as u64, (seed >> 64) as u64))
     }
 }
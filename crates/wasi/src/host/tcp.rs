@@ -77,6 +77,10 @@ where
         let socket = table.get_mut(&this)?;
 
         let (input, output) = socket.finish_connect()?;
+        let input = match &self.ctx().trace {
+            Some(trace) => trace.tcp_stream(input),
+            None => input,
+        };
 
         let input_stream = self.table().push_child(input, &this)?;
         let output_stream = self.table().push_child(output, &this)?;
@@ -111,6 +115,10 @@ where
         let socket = table.get_mut(&this)?;
 
         let (tcp_socket, input, output) = socket.accept()?;
+        let input = match &self.ctx().trace {
+            Some(trace) => trace.tcp_stream(input),
+            None => input,
+        };
 
         let tcp_socket = self.table().push(tcp_socket)?;
         let input_stream = self.table().push_child(input, &tcp_socket)?;
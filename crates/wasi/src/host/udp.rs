@@ -5,13 +5,12 @@ use crate::{
         sockets::network::{ErrorCode, IpAddressFamily, IpSocketAddress, Network},
         sockets::udp,
     },
-    udp::{IncomingDatagramStream, OutgoingDatagramStream, SendState, UdpState},
+    udp::{IncomingDatagramStream, OutgoingDatagramStream, SendState, Socket, UdpState},
     Subscribe,
 };
 use crate::{IoView, Pollable, SocketError, SocketResult, WasiImpl, WasiView};
 use anyhow::anyhow;
 use async_trait::async_trait;
-use io_lifetimes::AsSocketlike;
 use rustix::io::Errno;
 use std::net::SocketAddr;
 use tokio::io::Interest;
@@ -58,18 +57,23 @@ where
         {
             check.check(local_address, SocketAddrUse::UdpBind).await?;
 
-            // Perform the OS bind call.
-            util::udp_bind(socket.udp_socket(), &local_address).map_err(|error| match error {
-                // From https://pubs.opengroup.org/onlinepubs/9699919799/functions/bind.html:
-                // > [EAFNOSUPPORT] The specified address is not a valid address for the address family of the specified socket
-                //
-                // The most common reasons for this error should have already
-                // been handled by our own validation slightly higher up in this
-                // function. This error mapping is here just in case there is
-                // an edge case we didn't catch.
-                Errno::AFNOSUPPORT => ErrorCode::InvalidArgument,
-                _ => ErrorCode::from(error),
-            })?;
+            match &*socket.inner {
+                // Perform the OS bind call.
+                Socket::Host(udp_socket) => {
+                    util::udp_bind(udp_socket, &local_address).map_err(|error| match error {
+                        // From https://pubs.opengroup.org/onlinepubs/9699919799/functions/bind.html:
+                        // > [EAFNOSUPPORT] The specified address is not a valid address for the address family of the specified socket
+                        //
+                        // The most common reasons for this error should have already
+                        // been handled by our own validation slightly higher up in this
+                        // function. This error mapping is here just in case there is
+                        // an edge case we didn't catch.
+                        Errno::AFNOSUPPORT => ErrorCode::InvalidArgument,
+                        _ => ErrorCode::from(error),
+                    })?
+                }
+                Socket::Virtual(udp_socket) => udp_socket.bind(local_address)?,
+            }
         }
 
         let socket = table.get_mut(&this)?;
@@ -125,7 +129,10 @@ where
 
         // Step #1: Disconnect
         if let UdpState::Connected = socket.udp_state {
-            util::udp_disconnect(socket.udp_socket())?;
+            match &*socket.inner {
+                Socket::Host(udp_socket) => util::udp_disconnect(udp_socket)?,
+                Socket::Virtual(udp_socket) => udp_socket.disconnect(),
+            }
             socket.udp_state = UdpState::Bound;
         }
 
@@ -138,24 +145,27 @@ where
             util::validate_address_family(&connect_addr, &socket.family)?;
             check.check(connect_addr, SocketAddrUse::UdpConnect).await?;
 
-            rustix::net::connect(socket.udp_socket(), &connect_addr).map_err(
-                |error| match error {
-                    Errno::AFNOSUPPORT => ErrorCode::InvalidArgument, // See `bind` implementation.
-                    Errno::INPROGRESS => {
-                        tracing::debug!(
-                            "UDP connect returned EINPROGRESS, which should never happen"
-                        );
-                        ErrorCode::Unknown
-                    }
-                    _ => ErrorCode::from(error),
-                },
-            )?;
+            match &*socket.inner {
+                Socket::Host(udp_socket) => rustix::net::connect(udp_socket, &connect_addr)
+                    .map_err(|error| match error {
+                        Errno::AFNOSUPPORT => ErrorCode::InvalidArgument, // See `bind` implementation.
+                        Errno::INPROGRESS => {
+                            tracing::debug!(
+                                "UDP connect returned EINPROGRESS, which should never happen"
+                            );
+                            ErrorCode::Unknown
+                        }
+                        _ => ErrorCode::from(error),
+                    })?,
+                Socket::Virtual(udp_socket) => udp_socket.connect(connect_addr)?,
+            }
             socket.udp_state = UdpState::Connected;
         }
 
         let incoming_stream = IncomingDatagramStream {
             inner: socket.inner.clone(),
             remote_address,
+            trace: self.ctx().trace.clone(),
         };
         let outgoing_stream = OutgoingDatagramStream {
             inner: socket.inner.clone(),
@@ -181,10 +191,7 @@ where
             _ => {}
         }
 
-        let addr = socket
-            .udp_socket()
-            .as_socketlike_view::<std::net::UdpSocket>()
-            .local_addr()?;
+        let addr = socket.inner.local_addr()?;
         Ok(addr.into())
     }
 
@@ -197,10 +204,7 @@ where
             _ => return Err(ErrorCode::InvalidState.into()),
         }
 
-        let addr = socket
-            .udp_socket()
-            .as_socketlike_view::<std::net::UdpSocket>()
-            .peer_addr()?;
+        let addr = socket.inner.peer_addr()?;
         Ok(addr.into())
     }
 
@@ -222,8 +226,8 @@ where
         let socket = table.get(&this)?;
 
         let ttl = match socket.family {
-            SocketAddressFamily::Ipv4 => util::get_ip_ttl(socket.udp_socket())?,
-            SocketAddressFamily::Ipv6 => util::get_ipv6_unicast_hops(socket.udp_socket())?,
+            SocketAddressFamily::Ipv4 => util::get_ip_ttl(socket.udp_socket()?)?,
+            SocketAddressFamily::Ipv6 => util::get_ipv6_unicast_hops(socket.udp_socket()?)?,
         };
 
         Ok(ttl)
@@ -238,8 +242,8 @@ where
         let socket = table.get(&this)?;
 
         match socket.family {
-            SocketAddressFamily::Ipv4 => util::set_ip_ttl(socket.udp_socket(), value)?,
-            SocketAddressFamily::Ipv6 => util::set_ipv6_unicast_hops(socket.udp_socket(), value)?,
+            SocketAddressFamily::Ipv4 => util::set_ip_ttl(socket.udp_socket()?, value)?,
+            SocketAddressFamily::Ipv6 => util::set_ipv6_unicast_hops(socket.udp_socket()?, value)?,
         }
 
         Ok(())
@@ -249,7 +253,7 @@ where
         let table = self.table();
         let socket = table.get(&this)?;
 
-        let value = util::get_socket_recv_buffer_size(socket.udp_socket())?;
+        let value = util::get_socket_recv_buffer_size(socket.udp_socket()?)?;
         Ok(value as u64)
     }
 
@@ -262,7 +266,7 @@ where
         let socket = table.get(&this)?;
         let value = value.try_into().unwrap_or(usize::MAX);
 
-        util::set_socket_recv_buffer_size(socket.udp_socket(), value)?;
+        util::set_socket_recv_buffer_size(socket.udp_socket()?, value)?;
         Ok(())
     }
 
@@ -270,7 +274,7 @@ where
         let table = self.table();
         let socket = table.get(&this)?;
 
-        let value = util::get_socket_send_buffer_size(socket.udp_socket())?;
+        let value = util::get_socket_send_buffer_size(socket.udp_socket()?)?;
         Ok(value as u64)
     }
 
@@ -283,7 +287,7 @@ where
         let socket = table.get(&this)?;
         let value = value.try_into().unwrap_or(usize::MAX);
 
-        util::set_socket_send_buffer_size(socket.udp_socket(), value)?;
+        util::set_socket_send_buffer_size(socket.udp_socket()?, value)?;
         Ok(())
     }
 
@@ -316,9 +320,16 @@ where
         fn recv_one(
             stream: &IncomingDatagramStream,
         ) -> SocketResult<Option<udp::IncomingDatagram>> {
-            let mut buf = [0; MAX_UDP_DATAGRAM_SIZE];
-            let (size, received_addr) = stream.inner.try_recv_from(&mut buf)?;
-            debug_assert!(size <= buf.len());
+            let receive = || -> std::io::Result<_> {
+                let mut buf = [0; MAX_UDP_DATAGRAM_SIZE];
+                let (size, received_addr) = stream.inner.try_recv_from(&mut buf)?;
+                debug_assert!(size <= buf.len());
+                Ok((buf[..size].to_vec(), received_addr))
+            };
+            let (data, received_addr) = match &stream.trace {
+                Some(trace) => trace.udp_receive(receive)?,
+                None => receive()?,
+            };
 
             match stream.remote_address {
                 Some(connected_addr) if connected_addr != received_addr => {
@@ -329,7 +340,7 @@ where
             }
 
             Ok(Some(udp::IncomingDatagram {
-                data: buf[..size].into(),
+                data,
                 remote_address: received_addr.into(),
             }))
         }
@@ -389,6 +400,10 @@ where
 #[async_trait]
 impl Subscribe for IncomingDatagramStream {
     async fn ready(&mut self) {
+        if matches!(&self.trace, Some(trace) if trace.is_replaying()) {
+            // Replayed datagrams are received immediately.
+            return;
+        }
         // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
         self.inner
             .ready(Interest::READABLE)
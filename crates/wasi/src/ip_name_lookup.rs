@@ -3,6 +3,7 @@ use crate::bindings::sockets::network::{ErrorCode, IpAddress, Network};
 use crate::host::network::util;
 use crate::poll::{subscribe, Pollable, Subscribe};
 use crate::runtime::{spawn_blocking, AbortOnDropJoinHandle};
+use crate::vnet::VirtualNetwork;
 use crate::{IoView, SocketError, WasiImpl, WasiView};
 use anyhow::Result;
 use std::mem;
@@ -36,8 +37,13 @@ where
             return Err(ErrorCode::PermanentResolverFailure.into());
         }
 
-        let task = spawn_blocking(move || blocking_resolve(&host));
-        let resource = self.table().push(ResolveAddressStream::Waiting(task))?;
+        let stream = match &self.ctx().virtual_network {
+            Some(net) => ResolveAddressStream::Done(
+                virtual_resolve(net, &host).map(|addresses| addresses.into_iter()),
+            ),
+            None => ResolveAddressStream::Waiting(spawn_blocking(move || blocking_resolve(&host))),
+        };
+        let resource = self.table().push(stream)?;
         Ok(resource)
     }
 }
@@ -128,3 +134,20 @@ fn blocking_resolve(host: &url::Host) -> Result<Vec<IpAddress>, SocketError> {
         }
     }
 }
+
+/// Resolve `host` with the hosts of a virtual network, which doesn't block.
+fn virtual_resolve(net: &VirtualNetwork, host: &url::Host) -> Result<Vec<IpAddress>, SocketError> {
+    match host {
+        url::Host::Domain(domain) => {
+            let addresses = net
+                .lookup_host(domain)
+                .ok_or(ErrorCode::NameUnresolvable)?
+                .iter()
+                .map(|ip| util::to_canonical(ip).into())
+                .collect();
+
+            Ok(addresses)
+        }
+        _ => blocking_resolve(host),
+    }
+}
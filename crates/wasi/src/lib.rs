@@ -199,13 +199,17 @@ mod poll;
 pub mod preview0;
 #[cfg(feature = "preview1")]
 pub mod preview1;
+mod quota;
 mod random;
+mod replay;
 pub mod runtime;
 mod stdio;
 mod stream;
 mod tcp;
 mod udp;
+pub mod vfs;
 mod view;
+pub mod vnet;
 mod write_stream;
 
 pub use self::clocks::{HostMonotonicClock, HostWallClock};
@@ -214,10 +218,12 @@ pub use self::error::{I32Exit, TrappableError};
 pub use self::filesystem::{DirPerms, FileInputStream, FilePerms, FsError, FsResult};
 pub use self::network::{Network, SocketAddrUse, SocketError, SocketResult};
 pub use self::poll::{subscribe, ClosureFuture, MakeFuture, Pollable, PollableFuture, Subscribe};
+pub use self::quota::{FsLimits, FsUsage};
 pub use self::random::{thread_rng, Deterministic};
+pub use self::replay::Trace;
 pub use self::stdio::{
-    stderr, stdin, stdout, AsyncStdinStream, AsyncStdoutStream, IsATTY, OutputFile, Stderr, Stdin,
-    StdinStream, Stdout, StdoutStream,
+    stderr, stdin, stdout, AsyncStdinStream, AsyncStdoutStream, CaptureSink, CaptureStream,
+    CapturedLine, IsATTY, OutputFile, Stderr, Stdin, StdinStream, Stdout, StdoutStream,
 };
 pub use self::stream::{
     HostInputStream, HostOutputStream, InputStream, OutputStream, StreamError, StreamResult,
@@ -76,6 +76,9 @@ impl From<wasmtime::component::ResourceTableError> for SocketError {
 
 impl From<std::io::Error> for SocketError {
     fn from(error: std::io::Error) -> Self {
+        if crate::replay::is_replay_error(&error) {
+            return Self::trap(error);
+        }
         ErrorCode::from(error).into()
     }
 }
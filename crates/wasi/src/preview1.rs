@@ -72,6 +72,7 @@ use crate::bindings::{
     filesystem::{preopens::Host as _, types as filesystem},
     io::streams,
 };
+use crate::vfs::WasiFile;
 use crate::{
     FsError, IoImpl, IoView, IsATTY, ResourceTable, StreamError, StreamResult, WasiCtx, WasiImpl,
     WasiView,
@@ -83,7 +84,6 @@ use std::ops::{Deref, DerefMut};
 use std::slice;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use system_interface::fs::FileIoExt;
 use wasmtime::component::Resource;
 use wiggle::tracing::instrument;
 use wiggle::{GuestError, GuestMemory, GuestPtr, GuestType};
@@ -603,7 +603,7 @@ impl WasiP1Ctx {
                 let f = self.table().get(&fd)?.file()?;
                 let buf = first_non_empty_ciovec(memory, ciovs)?;
 
-                let do_write = move |f: &cap_std::fs::File, buf: &[u8]| match (append, write) {
+                let do_write = move |f: &dyn WasiFile, buf: &[u8]| match (append, write) {
                     // Note that this is implementing Linux semantics of
                     // `pwrite` where the offset is ignored if the file was
                     // opened in append mode.
@@ -869,6 +869,7 @@ impl From<StreamError> for types::Error {
         match err {
             StreamError::Closed => types::Errno::Io.into(),
             StreamError::LastOperationFailed(e) => match e.downcast::<std::io::Error>() {
+                Ok(err) if crate::replay::is_replay_error(&err) => types::Error::trap(err.into()),
                 Ok(err) => filesystem::ErrorCode::from(err).into(),
                 Err(e) => {
                     tracing::debug!("dropping error {e:?}");
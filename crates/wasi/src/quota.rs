@@ -0,0 +1,389 @@
+use crate::bindings::filesystem::types::{Advice, DescriptorFlags, ErrorCode};
+use crate::vfs::{error, DirEntry, Metadata, OpenOptions, Opened, TimeSpec, WasiDir, WasiFile};
+use std::any::Any;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Limits on the filesystem resources that a guest can consume through a
+/// [`WasiCtx`](crate::WasiCtx), configured with
+/// [`WasiCtxBuilder::fs_limits`](crate::WasiCtxBuilder::fs_limits).
+///
+/// Limits which are `None` aren't enforced. All limits apply to the whole
+/// lifetime of the context, across all of its preopened directories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FsLimits {
+    /// The maximum number of bytes which can be written to files.
+    ///
+    /// Growing a file with `set-size` counts as writing the bytes it's grown
+    /// by, and so does writing past the end of a file: the gap between the
+    /// old end and the write counts as written along with the data. Writes
+    /// which would exceed the limit fail with
+    /// [`ErrorCode::InsufficientSpace`], without writing anything.
+    pub max_bytes_written: Option<u64>,
+    /// The maximum number of files, directories and links which can be
+    /// created.
+    ///
+    /// Creations which would exceed the limit fail with
+    /// [`ErrorCode::Quota`].
+    pub max_files_created: Option<u64>,
+    /// The maximum number of files and directories which can be open at the
+    /// same time, not counting preopened directories.
+    ///
+    /// Opening more fails with [`ErrorCode::Quota`]. A file stays open until
+    /// its descriptor and all of the streams created from it are dropped.
+    pub max_open_descriptors: Option<u64>,
+}
+
+/// The filesystem resources consumed by the guest through a
+/// [`WasiCtx`](crate::WasiCtx), returned by
+/// [`WasiCtx::fs_usage`](crate::WasiCtx::fs_usage).
+///
+/// This is a handle to counters which are shared with the context and keep
+/// being updated while the guest runs, so it can be cloned and kept after the
+/// context is moved into a [`Store`](wasmtime::Store), for example to bill
+/// the guest once it finished.
+#[derive(Clone, Default)]
+pub struct FsUsage(Arc<Counters>);
+
+#[derive(Default)]
+struct Counters {
+    limits: FsLimits,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    files_created: AtomicU64,
+    open_descriptors: AtomicU64,
+}
+
+impl FsUsage {
+    pub(crate) fn new(limits: FsLimits) -> FsUsage {
+        FsUsage(Arc::new(Counters {
+            limits,
+            ..Counters::default()
+        }))
+    }
+
+    /// The limits which are enforced on the usage counted here.
+    pub fn limits(&self) -> FsLimits {
+        self.0.limits
+    }
+
+    /// The number of bytes read from files.
+    pub fn bytes_read(&self) -> u64 {
+        self.0.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes written to files.
+    pub fn bytes_written(&self) -> u64 {
+        self.0.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// The number of files, directories and links created.
+    pub fn files_created(&self) -> u64 {
+        self.0.files_created.load(Ordering::Relaxed)
+    }
+
+    /// The number of files and directories currently open, not counting
+    /// preopened directories.
+    pub fn open_descriptors(&self) -> u64 {
+        self.0.open_descriptors.load(Ordering::Relaxed)
+    }
+
+    fn record_read(&self, len: usize) {
+        self.0.bytes_read.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Reserve `len` bytes to be written, which are counted as written until
+    /// the unused part of them is given back with [`FsUsage::unreserve_write`].
+    fn reserve_write(&self, len: u64) -> Result<(), ErrorCode> {
+        reserve(
+            &self.0.bytes_written,
+            len,
+            self.0.limits.max_bytes_written,
+            ErrorCode::InsufficientSpace,
+        )
+    }
+
+    fn unreserve_write(&self, len: u64) {
+        self.0.bytes_written.fetch_sub(len, Ordering::Relaxed);
+    }
+
+    /// Write `len` bytes with `write`, which returns how many of them it
+    /// wrote, counting `charge(n)` bytes as written when `n` were written.
+    ///
+    /// `charge` must not decrease as `n` grows.
+    fn write(
+        &self,
+        len: usize,
+        charge: impl Fn(usize) -> u64,
+        write: impl FnOnce() -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let reserved = charge(len);
+        self.reserve_write(reserved).map_err(error)?;
+        match write() {
+            Ok(n) => {
+                self.unreserve_write(reserved - charge(n.min(len)));
+                Ok(n)
+            }
+            Err(e) => {
+                self.unreserve_write(reserved);
+                Err(e)
+            }
+        }
+    }
+
+    /// Create something with `create`, counting it as a created file if it
+    /// succeeds.
+    fn create<T>(&self, create: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        reserve(
+            &self.0.files_created,
+            1,
+            self.0.limits.max_files_created,
+            ErrorCode::Quota,
+        )
+        .map_err(error)?;
+        let result = create();
+        if result.is_err() {
+            self.0.files_created.fetch_sub(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Count a newly opened file or directory, until the returned value is
+    /// dropped.
+    fn open_descriptor(&self) -> io::Result<OpenDescriptor> {
+        reserve(
+            &self.0.open_descriptors,
+            1,
+            self.0.limits.max_open_descriptors,
+            ErrorCode::Quota,
+        )
+        .map_err(error)?;
+        Ok(OpenDescriptor(self.clone()))
+    }
+}
+
+/// An open file or directory, counted by [`FsUsage::open_descriptors`] until
+/// this is dropped.
+struct OpenDescriptor(FsUsage);
+
+impl Drop for OpenDescriptor {
+    fn drop(&mut self) {
+        (self.0).0.open_descriptors.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Add `amount` to `counter`, unless that would exceed `max`.
+fn reserve(
+    counter: &AtomicU64,
+    amount: u64,
+    max: Option<u64>,
+    exceeded: ErrorCode,
+) -> Result<(), ErrorCode> {
+    counter
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+            let new = current.checked_add(amount)?;
+            match max {
+                Some(max) if new > max => None,
+                _ => Some(new),
+            }
+        })
+        .map(drop)
+        .map_err(|_| exceeded)
+}
+
+/// A directory whose use, and that of everything opened from it, is counted
+/// and limited by an [`FsUsage`].
+///
+/// Every preopened directory is wrapped in one of these.
+pub(crate) struct QuotaDir {
+    dir: Arc<dyn WasiDir>,
+    usage: FsUsage,
+    /// Preopened directories aren't counted as open.
+    _open: Option<OpenDescriptor>,
+}
+
+impl QuotaDir {
+    pub(crate) fn new(dir: Arc<dyn WasiDir>, usage: FsUsage) -> QuotaDir {
+        QuotaDir {
+            dir,
+            usage,
+            _open: None,
+        }
+    }
+
+    /// Get the directory wrapped by `dir`, so that it can be passed to the
+    /// methods of the directory wrapped by `self`.
+    fn inner(dir: &dyn WasiDir) -> &dyn WasiDir {
+        match dir.as_any().downcast_ref::<QuotaDir>() {
+            Some(dir) => &*dir.dir,
+            None => dir,
+        }
+    }
+}
+
+impl WasiDir for QuotaDir {
+    fn open_at(&self, path: &str, options: &OpenOptions) -> io::Result<Opened> {
+        let open = self.usage.open_descriptor()?;
+        let creates = options.create_new
+            || (options.create && self.dir.metadata_at(path, options.follow_symlinks).is_err());
+        let opened = if creates {
+            self.usage.create(|| self.dir.open_at(path, options))?
+        } else {
+            self.dir.open_at(path, options)?
+        };
+        Ok(match opened {
+            Opened::Dir(dir) => Opened::Dir(Arc::new(QuotaDir {
+                dir,
+                usage: self.usage.clone(),
+                _open: Some(open),
+            })),
+            Opened::File(file) => Opened::File(Arc::new(QuotaFile {
+                file,
+                usage: self.usage.clone(),
+                _open: open,
+            })),
+        })
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.usage.create(|| self.dir.create_dir_at(path))
+    }
+
+    fn read_dir(&self) -> io::Result<Vec<io::Result<DirEntry>>> {
+        self.dir.read_dir()
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.dir.metadata()
+    }
+
+    fn metadata_at(&self, path: &str, follow_symlinks: bool) -> io::Result<Metadata> {
+        self.dir.metadata_at(path, follow_symlinks)
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.dir.set_times(atime, mtime)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        atime: Option<TimeSpec>,
+        mtime: Option<TimeSpec>,
+        follow_symlinks: bool,
+    ) -> io::Result<()> {
+        self.dir.set_times_at(path, atime, mtime, follow_symlinks)
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let new_dir = Self::inner(new_dir);
+        self.usage
+            .create(|| self.dir.hard_link_at(old_path, new_dir, new_path))
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        self.dir.rename_at(old_path, Self::inner(new_dir), new_path)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        self.usage.create(|| self.dir.symlink_at(target, path))
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<String> {
+        self.dir.read_link_at(path)
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.dir.remove_dir_at(path)
+    }
+
+    fn unlink_file_at(&self, path: &str) -> io::Result<()> {
+        self.dir.unlink_file_at(path)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.dir.sync()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.dir.sync_data()
+    }
+
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        self.dir.sync_flags()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file opened from a [`QuotaDir`].
+struct QuotaFile {
+    file: Arc<dyn WasiFile>,
+    usage: FsUsage,
+    _open: OpenDescriptor,
+}
+
+impl WasiFile for QuotaFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let n = self.file.read_at(buf, offset)?;
+        self.usage.record_read(n);
+        Ok(n)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        // Writing past the end of the file also grows it by the gap before
+        // `offset`, which counts as written like it does for `set_len`.
+        let old_len = self.file.metadata()?.len;
+        let charge = |n: usize| {
+            let end = offset.saturating_add(n as u64);
+            (n as u64).max(end.saturating_sub(old_len))
+        };
+        self.usage
+            .write(buf.len(), charge, || self.file.write_at(buf, offset))
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.usage
+            .write(buf.len(), |n| n as u64, || self.file.append(buf))
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        let grown = size.saturating_sub(self.file.metadata()?.len);
+        self.usage.reserve_write(grown).map_err(error)?;
+        self.file.set_len(size).inspect_err(|_| {
+            self.usage.unreserve_write(grown);
+        })
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.file.set_times(atime, mtime)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        self.file.sync_flags()
+    }
+
+    fn advise(&self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        self.file.advise(offset, len, advice)
+    }
+}
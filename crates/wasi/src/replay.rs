@@ -0,0 +1,951 @@
+use crate::bindings::filesystem::types::{Advice, DescriptorFlags, ErrorCode};
+use crate::clocks::{HostMonotonicClock, HostWallClock};
+use crate::stdio::StdinStream;
+use crate::stream::{HostInputStream, InputStream, StreamError, StreamResult};
+use crate::vfs::{self, DirEntry, Metadata, OpenOptions, Opened, TimeSpec, WasiDir, WasiFile};
+use crate::Subscribe;
+use bytes::Bytes;
+use cap_rand::RngCore;
+use std::any::Any;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The first bytes of every trace file, followed by a little-endian `u32`
+/// version.
+const MAGIC: &[u8; 12] = b"wasi-replay\0";
+const VERSION: u32 = 1;
+
+/// A trace of the nondeterministic results of the WASI calls made by a guest,
+/// which is either being recorded or replayed.
+///
+/// A trace is configured with [`WasiCtxBuilder::trace`]. When recording, the
+/// result of every call whose outcome depends on the host rather than on the
+/// guest is appended to the trace:
+///
+/// * the wall and monotonic clocks,
+/// * the secure and insecure random generators, and the insecure seed,
+/// * reads from stdin,
+/// * reads from files opened through preopened directories,
+/// * data received from TCP and UDP sockets.
+///
+/// When replaying, these calls return the recorded results instead, in the
+/// order they were recorded, so that a guest which makes the same calls sees
+/// exactly the same results as the recorded run.
+///
+/// Replaying still needs the environment of the recorded run for everything
+/// which isn't recorded: files read by the guest must exist to be opened,
+/// although their contents come from the trace, and sockets must be able to
+/// connect, although the data received comes from the trace. Readiness of
+/// pollables other than replayed streams isn't recorded either.
+///
+/// The trace is a compact binary format written with [`Trace::record`] and
+/// read back with [`Trace::replay`]; its layout is an implementation detail
+/// which may change between versions of this crate.
+///
+/// # Errors
+///
+/// If a replayed guest diverges from the recorded run, i.e. it makes a call
+/// other than the next recorded one, or makes more calls than were recorded,
+/// or if the trace is malformed, the WASI call traps, since there's no result
+/// it could return which would keep the replay faithful. Every later call
+/// which would be replayed traps as well.
+///
+/// [`WasiCtxBuilder::trace`]: crate::WasiCtxBuilder::trace
+#[derive(Clone)]
+pub struct Trace(Arc<Mutex<State>>);
+
+enum State {
+    Record {
+        writer: Box<dyn Write + Send>,
+        /// The first error writing to `writer`, reported by [`Trace::flush`].
+        error: Option<io::Error>,
+    },
+    Replay {
+        reader: Box<dyn Read + Send>,
+        /// Why the replay failed, after which no more events are replayed.
+        failed: Option<ReplayError>,
+    },
+}
+
+/// The error of a WASI call which can't be replayed, because the guest
+/// diverged from the recorded run or the trace is malformed.
+#[derive(Clone, Debug)]
+pub(crate) struct ReplayError(String);
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Whether `err` is a [`ReplayError`] returned by a traced file or socket,
+/// which traps rather than being returned to the guest.
+pub(crate) fn is_replay_error(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|e| e.downcast_ref::<ReplayError>().is_some())
+}
+
+fn diverged(message: String) -> ReplayError {
+    ReplayError(format!("WASI replay diverged: {message}"))
+}
+
+fn malformed() -> ReplayError {
+    ReplayError("malformed WASI trace".to_string())
+}
+
+/// The kinds of events in a trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Event {
+    WallClockNow,
+    WallClockResolution,
+    MonotonicClockNow,
+    MonotonicClockResolution,
+    Random,
+    InsecureRandom,
+    InsecureRandomSeed,
+    Stdin,
+    StdinIsatty,
+    FileRead,
+    TcpRead,
+    UdpReceive,
+}
+
+const EVENTS: [Event; 12] = [
+    Event::WallClockNow,
+    Event::WallClockResolution,
+    Event::MonotonicClockNow,
+    Event::MonotonicClockResolution,
+    Event::Random,
+    Event::InsecureRandom,
+    Event::InsecureRandomSeed,
+    Event::Stdin,
+    Event::StdinIsatty,
+    Event::FileRead,
+    Event::TcpRead,
+    Event::UdpReceive,
+];
+
+impl Trace {
+    /// Start recording a trace to `writer`.
+    ///
+    /// Writes are buffered by the caller's writer, if at all; use
+    /// [`Trace::create`] to record to a file with buffering.
+    pub fn record(mut writer: impl Write + Send + 'static) -> io::Result<Trace> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(Trace(Arc::new(Mutex::new(State::Record {
+            writer: Box::new(writer),
+            error: None,
+        }))))
+    }
+
+    /// Start recording a trace to the file at `path`, which is created or
+    /// truncated.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Trace> {
+        Trace::record(BufWriter::new(File::create(path)?))
+    }
+
+    /// Start replaying the trace read from `reader`.
+    pub fn replay(mut reader: impl Read + Send + 'static) -> io::Result<Trace> {
+        let mut header = [0; MAGIC.len() + 4];
+        reader.read_exact(&mut header)?;
+        if header[..MAGIC.len()] != MAGIC[..] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a WASI trace",
+            ));
+        }
+        let version = u32::from_le_bytes(header[MAGIC.len()..].try_into().unwrap());
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported WASI trace version {version}"),
+            ));
+        }
+        Ok(Trace(Arc::new(Mutex::new(State::Replay {
+            reader: Box::new(reader),
+            failed: None,
+        }))))
+    }
+
+    /// Start replaying the trace in the file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Trace> {
+        Trace::replay(BufReader::new(File::open(path)?))
+    }
+
+    /// Whether this trace is being replayed rather than recorded.
+    pub fn is_replaying(&self) -> bool {
+        matches!(*self.0.lock().unwrap(), State::Replay { .. })
+    }
+
+    /// Flush the recorded trace to its writer.
+    ///
+    /// Errors writing the trace don't interrupt the guest, so this returns
+    /// the first error which happened while recording, if any. Does nothing
+    /// when replaying.
+    pub fn flush(&self) -> io::Result<()> {
+        match &mut *self.0.lock().unwrap() {
+            State::Record { writer, error } => match error.take() {
+                Some(e) => Err(e),
+                None => writer.flush(),
+            },
+            State::Replay { .. } => Ok(()),
+        }
+    }
+
+    /// Fails if the replay has failed, so that calls whose results can't
+    /// fail, like reading a clock, trap once they've diverged.
+    pub(crate) fn check(&self) -> anyhow::Result<()> {
+        match &*self.0.lock().unwrap() {
+            State::Replay {
+                failed: Some(e), ..
+            } => Err(e.clone().into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the result of an `event`: when recording it's computed with `live`
+    /// and recorded, and when replaying it's the next recorded result.
+    fn event<T: Record>(&self, event: Event, live: impl FnOnce() -> T) -> Result<T, ReplayError> {
+        if self.is_replaying() {
+            self.replay_event(event)
+        } else {
+            Ok(self.record_event(event, live))
+        }
+    }
+
+    /// Like [`Trace::event`], for calls which can't fail: if the result can't
+    /// be replayed it's computed with `live` instead, and the failure is
+    /// reported by [`Trace::check`].
+    fn event_or_live<T: Record>(&self, event: Event, live: impl FnOnce() -> T) -> T {
+        if self.is_replaying() {
+            self.replay_event(event).unwrap_or_else(|_| live())
+        } else {
+            self.record_event(event, live)
+        }
+    }
+
+    fn record_event<T: Record>(&self, event: Event, live: impl FnOnce() -> T) -> T {
+        // The lock isn't held while computing the result, which may block on
+        // I/O, so results are recorded in the order they're returned.
+        let value = live();
+        if let State::Record { writer, error } = &mut *self.0.lock().unwrap() {
+            if error.is_none() {
+                let mut payload = Vec::new();
+                value.encode(&mut payload);
+                if let Err(e) = write_event(writer, event, &payload) {
+                    *error = Some(e);
+                }
+            }
+        }
+        value
+    }
+
+    fn replay_event<T: Record>(&self, event: Event) -> Result<T, ReplayError> {
+        let mut state = self.0.lock().unwrap();
+        let State::Replay { reader, failed } = &mut *state else {
+            unreachable!()
+        };
+        if let Some(e) = failed {
+            return Err(e.clone());
+        }
+        let result = read_event(reader).and_then(|recorded| match recorded {
+            None => Err(diverged(format!(
+                "the trace ended, but the guest made a {event:?} call"
+            ))),
+            Some((recorded, _)) if recorded != event => Err(diverged(format!(
+                "the trace has a {recorded:?} call next, but the guest made a {event:?} call"
+            ))),
+            Some((_, payload)) => {
+                let mut payload = &payload[..];
+                let value = T::decode(&mut payload)?;
+                if !payload.is_empty() {
+                    return Err(malformed());
+                }
+                Ok(value)
+            }
+        });
+        if let Err(e) = &result {
+            *failed = Some(e.clone());
+        }
+        result
+    }
+
+    /// Fail the replay because the guest diverged from the recorded run in a
+    /// way which is only noticed once an event was replayed.
+    fn diverge(&self, message: String) -> ReplayError {
+        let e = diverged(message);
+        if let State::Replay { failed, .. } = &mut *self.0.lock().unwrap() {
+            failed.get_or_insert_with(|| e.clone());
+        }
+        e
+    }
+
+    /// Record or replay the reads from `stream`, which is a TCP socket's input
+    /// stream.
+    pub(crate) fn tcp_stream(&self, stream: InputStream) -> InputStream {
+        Box::new(TraceInputStream {
+            stream,
+            trace: self.clone(),
+            event: Event::TcpRead,
+        })
+    }
+
+    /// Record or replay a datagram received by `receive`.
+    pub(crate) fn udp_receive(
+        &self,
+        receive: impl FnOnce() -> io::Result<(Vec<u8>, SocketAddr)>,
+    ) -> io::Result<(Vec<u8>, SocketAddr)> {
+        self.event(Event::UdpReceive, receive)
+            .unwrap_or_else(|e| Err(io::Error::other(e)))
+    }
+
+    pub(crate) fn wall_clock(&self, clock: Box<dyn HostWallClock + Send>) -> TraceWallClock {
+        TraceWallClock {
+            clock,
+            trace: self.clone(),
+        }
+    }
+
+    pub(crate) fn monotonic_clock(
+        &self,
+        clock: Box<dyn HostMonotonicClock + Send>,
+    ) -> TraceMonotonicClock {
+        TraceMonotonicClock {
+            clock,
+            trace: self.clone(),
+        }
+    }
+
+    pub(crate) fn random(&self, rng: Box<dyn RngCore + Send>) -> TraceRng {
+        TraceRng {
+            rng,
+            trace: self.clone(),
+            event: Event::Random,
+        }
+    }
+
+    pub(crate) fn insecure_random(&self, rng: Box<dyn RngCore + Send>) -> TraceRng {
+        TraceRng {
+            rng,
+            trace: self.clone(),
+            event: Event::InsecureRandom,
+        }
+    }
+
+    pub(crate) fn insecure_random_seed(&self, seed: u128) -> u128 {
+        self.event_or_live(Event::InsecureRandomSeed, || seed)
+    }
+
+    pub(crate) fn stdin(&self, stdin: Box<dyn StdinStream>) -> TraceStdin {
+        TraceStdin {
+            stdin,
+            trace: self.clone(),
+        }
+    }
+
+    pub(crate) fn dir(&self, dir: Arc<dyn WasiDir>) -> TraceDir {
+        TraceDir {
+            dir,
+            trace: self.clone(),
+        }
+    }
+}
+
+fn write_event(writer: &mut dyn Write, event: Event, payload: &[u8]) -> io::Result<()> {
+    let index = EVENTS.iter().position(|e| *e == event).unwrap() as u8;
+    let len = u32::try_from(payload.len()).map_err(io::Error::other)?;
+    writer.write_all(&[index])?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+fn read_event(reader: &mut dyn Read) -> Result<Option<(Event, Vec<u8>)>, ReplayError> {
+    let read_error = |e: io::Error| ReplayError(format!("failed to read the WASI trace: {e}"));
+    let mut index = [0];
+    if reader.read(&mut index).map_err(read_error)? == 0 {
+        return Ok(None);
+    }
+    let event = *EVENTS.get(usize::from(index[0])).ok_or_else(malformed)?;
+    let mut len = [0; 4];
+    reader.read_exact(&mut len).map_err(read_error)?;
+    let len = u32::from_le_bytes(len);
+    // The length isn't trusted to preallocate the payload, as a malformed
+    // trace may claim far more than the rest of it holds.
+    let mut payload = Vec::new();
+    Read::take(&mut *reader, u64::from(len))
+        .read_to_end(&mut payload)
+        .map_err(read_error)?;
+    if payload.len() != len as usize {
+        return Err(malformed());
+    }
+    Ok(Some((event, payload)))
+}
+
+/// A value which can be recorded in a trace.
+trait Record: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], ReplayError> {
+    if input.len() < len {
+        return Err(malformed());
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+macro_rules! record_int {
+    ($($ty:ty)*) => ($(
+        impl Record for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+                let bytes = take(input, std::mem::size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )*)
+}
+
+record_int!(u8 u32 i32 u64 u128);
+
+impl Record for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u8::from(*self).encode(out)
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        Ok(u8::decode(input)? != 0)
+    }
+}
+
+impl Record for Duration {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_secs().encode(out);
+        self.subsec_nanos().encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        let secs = u64::decode(input)?;
+        let nanos = u32::decode(input)?;
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl Record for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        let len = u32::decode(input)? as usize;
+        Ok(take(input, len)?.to_vec())
+    }
+}
+
+impl Record for Bytes {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        Ok(Vec::decode(input)?.into())
+    }
+}
+
+impl Record for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        String::from_utf8(Vec::decode(input)?).map_err(|_| malformed())
+    }
+}
+
+impl Record for SocketAddr {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.to_string().encode(out)
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        String::decode(input)?.parse().map_err(|_| malformed())
+    }
+}
+
+impl<A: Record, B: Record> Record for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        let a = A::decode(input)?;
+        let b = B::decode(input)?;
+        Ok((a, b))
+    }
+}
+
+impl<T: Record, E: Record> Record for Result<T, E> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Ok(value) => {
+                out.push(0);
+                value.encode(out);
+            }
+            Err(e) => {
+                out.push(1);
+                e.encode(out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        Ok(match u8::decode(input)? {
+            0 => Ok(T::decode(input)?),
+            1 => Err(E::decode(input)?),
+            _ => return Err(malformed()),
+        })
+    }
+}
+
+/// Stream errors are replayed with the message of the recorded error, since
+/// the error itself can't be recorded.
+impl Record for StreamError {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            StreamError::Closed => out.push(0),
+            StreamError::LastOperationFailed(e) => {
+                out.push(1);
+                format!("{e:?}").encode(out);
+            }
+            StreamError::Trap(e) => {
+                out.push(2);
+                format!("{e:?}").encode(out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        Ok(match u8::decode(input)? {
+            0 => StreamError::Closed,
+            1 => StreamError::LastOperationFailed(anyhow::anyhow!(String::decode(input)?)),
+            2 => StreamError::Trap(anyhow::anyhow!(String::decode(input)?)),
+            _ => return Err(malformed()),
+        })
+    }
+}
+
+/// I/O errors are replayed as the same OS error, filesystem error code, or
+/// error kind, whichever the recorded error had.
+impl Record for io::Error {
+    fn encode(&self, out: &mut Vec<u8>) {
+        if let Some(errno) = self.raw_os_error() {
+            out.push(0);
+            errno.encode(out);
+        } else if let Some(code) = vfs::error_code(self) {
+            out.push(1);
+            (ERROR_CODES.iter().position(|c| *c == code).unwrap() as u8).encode(out);
+        } else {
+            out.push(2);
+            let kind = self.kind();
+            let kind = ERROR_KINDS.iter().position(|k| *k == kind);
+            (kind.unwrap_or(ERROR_KINDS.len()) as u8).encode(out);
+            self.to_string().encode(out);
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ReplayError> {
+        Ok(match u8::decode(input)? {
+            0 => io::Error::from_raw_os_error(i32::decode(input)?),
+            1 => {
+                let code = ERROR_CODES.get(usize::from(u8::decode(input)?));
+                vfs::error(*code.ok_or_else(malformed)?)
+            }
+            2 => {
+                let kind = ERROR_KINDS.get(usize::from(u8::decode(input)?));
+                let kind = kind.copied().unwrap_or(io::ErrorKind::Other);
+                io::Error::new(kind, String::decode(input)?)
+            }
+            _ => return Err(malformed()),
+        })
+    }
+}
+
+const ERROR_CODES: [ErrorCode; 37] = [
+    ErrorCode::Access,
+    ErrorCode::WouldBlock,
+    ErrorCode::Already,
+    ErrorCode::BadDescriptor,
+    ErrorCode::Busy,
+    ErrorCode::Deadlock,
+    ErrorCode::Quota,
+    ErrorCode::Exist,
+    ErrorCode::FileTooLarge,
+    ErrorCode::IllegalByteSequence,
+    ErrorCode::InProgress,
+    ErrorCode::Interrupted,
+    ErrorCode::Invalid,
+    ErrorCode::Io,
+    ErrorCode::IsDirectory,
+    ErrorCode::Loop,
+    ErrorCode::TooManyLinks,
+    ErrorCode::MessageSize,
+    ErrorCode::NameTooLong,
+    ErrorCode::NoDevice,
+    ErrorCode::NoEntry,
+    ErrorCode::NoLock,
+    ErrorCode::InsufficientMemory,
+    ErrorCode::InsufficientSpace,
+    ErrorCode::NotDirectory,
+    ErrorCode::NotEmpty,
+    ErrorCode::NotRecoverable,
+    ErrorCode::Unsupported,
+    ErrorCode::NoTty,
+    ErrorCode::NoSuchDevice,
+    ErrorCode::Overflow,
+    ErrorCode::NotPermitted,
+    ErrorCode::Pipe,
+    ErrorCode::ReadOnly,
+    ErrorCode::InvalidSeek,
+    ErrorCode::TextFileBusy,
+    ErrorCode::CrossDevice,
+];
+
+/// The error kinds which are replayed as such; others are replayed as
+/// [`io::ErrorKind::Other`].
+const ERROR_KINDS: [io::ErrorKind; 19] = [
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::AlreadyExists,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::NotConnected,
+    io::ErrorKind::AddrInUse,
+    io::ErrorKind::AddrNotAvailable,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::WriteZero,
+    io::ErrorKind::Unsupported,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::OutOfMemory,
+];
+
+pub(crate) struct TraceWallClock {
+    clock: Box<dyn HostWallClock + Send>,
+    trace: Trace,
+}
+
+impl HostWallClock for TraceWallClock {
+    fn resolution(&self) -> Duration {
+        self.trace
+            .event_or_live(Event::WallClockResolution, || self.clock.resolution())
+    }
+
+    fn now(&self) -> Duration {
+        self.trace
+            .event_or_live(Event::WallClockNow, || self.clock.now())
+    }
+}
+
+pub(crate) struct TraceMonotonicClock {
+    clock: Box<dyn HostMonotonicClock + Send>,
+    trace: Trace,
+}
+
+impl HostMonotonicClock for TraceMonotonicClock {
+    fn resolution(&self) -> u64 {
+        self.trace
+            .event_or_live(Event::MonotonicClockResolution, || self.clock.resolution())
+    }
+
+    fn now(&self) -> u64 {
+        self.trace
+            .event_or_live(Event::MonotonicClockNow, || self.clock.now())
+    }
+}
+
+pub(crate) struct TraceRng {
+    rng: Box<dyn RngCore + Send>,
+    trace: Trace,
+    event: Event,
+}
+
+impl RngCore for TraceRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, buf: &mut [u8]) {
+        let live = |rng: &mut Box<dyn RngCore + Send>| {
+            let mut bytes = vec![0; buf.len()];
+            rng.fill_bytes(&mut bytes);
+            bytes
+        };
+        let mut bytes: Vec<u8> = self.trace.event_or_live(self.event, || live(&mut self.rng));
+        if bytes.len() != buf.len() {
+            self.trace.diverge(format!(
+                "the trace has {} random bytes next, but the guest requested {}",
+                bytes.len(),
+                buf.len()
+            ));
+            bytes = live(&mut self.rng);
+        }
+        buf.copy_from_slice(&bytes);
+    }
+
+    fn try_fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), cap_rand::Error> {
+        self.fill_bytes(buf);
+        Ok(())
+    }
+}
+
+pub(crate) struct TraceStdin {
+    stdin: Box<dyn StdinStream>,
+    trace: Trace,
+}
+
+impl StdinStream for TraceStdin {
+    fn stream(&self) -> Box<dyn HostInputStream> {
+        Box::new(TraceInputStream {
+            stream: self.stdin.stream(),
+            trace: self.trace.clone(),
+            event: Event::Stdin,
+        })
+    }
+
+    fn isatty(&self) -> bool {
+        self.trace
+            .event_or_live(Event::StdinIsatty, || self.stdin.isatty())
+    }
+}
+
+/// An input stream whose reads are recorded or replayed.
+///
+/// When replaying, the wrapped stream isn't read from, and this stream is
+/// always ready since its reads return immediately.
+struct TraceInputStream {
+    stream: InputStream,
+    trace: Trace,
+    event: Event,
+}
+
+#[async_trait::async_trait]
+impl HostInputStream for TraceInputStream {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        let bytes = self
+            .trace
+            .event(self.event, || self.stream.read(size))
+            .map_err(|e| StreamError::Trap(e.into()))??;
+        if bytes.len() > size {
+            let e = self.trace.diverge(format!(
+                "the trace has a read of {} bytes next, but the guest requested at most {size}",
+                bytes.len()
+            ));
+            return Err(StreamError::Trap(e.into()));
+        }
+        Ok(bytes)
+    }
+
+    async fn cancel(&mut self) {
+        self.stream.cancel().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for TraceInputStream {
+    async fn ready(&mut self) {
+        if !self.trace.is_replaying() {
+            self.stream.ready().await
+        }
+    }
+}
+
+/// A directory whose files' reads, and those of everything opened from it,
+/// are recorded or replayed.
+///
+/// Every preopened directory is wrapped in one of these when a trace is
+/// configured.
+pub(crate) struct TraceDir {
+    dir: Arc<dyn WasiDir>,
+    trace: Trace,
+}
+
+impl TraceDir {
+    /// Get the directory wrapped by `dir`, so that it can be passed to the
+    /// methods of the directory wrapped by `self`.
+    fn inner(dir: &dyn WasiDir) -> &dyn WasiDir {
+        match dir.as_any().downcast_ref::<TraceDir>() {
+            Some(dir) => &*dir.dir,
+            None => dir,
+        }
+    }
+}
+
+impl WasiDir for TraceDir {
+    fn open_at(&self, path: &str, options: &OpenOptions) -> io::Result<Opened> {
+        Ok(match self.dir.open_at(path, options)? {
+            Opened::Dir(dir) => Opened::Dir(Arc::new(self.trace.dir(dir))),
+            Opened::File(file) => Opened::File(Arc::new(TraceFile {
+                file,
+                trace: self.trace.clone(),
+            })),
+        })
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.dir.create_dir_at(path)
+    }
+
+    fn read_dir(&self) -> io::Result<Vec<io::Result<DirEntry>>> {
+        self.dir.read_dir()
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.dir.metadata()
+    }
+
+    fn metadata_at(&self, path: &str, follow_symlinks: bool) -> io::Result<Metadata> {
+        self.dir.metadata_at(path, follow_symlinks)
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.dir.set_times(atime, mtime)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        atime: Option<TimeSpec>,
+        mtime: Option<TimeSpec>,
+        follow_symlinks: bool,
+    ) -> io::Result<()> {
+        self.dir.set_times_at(path, atime, mtime, follow_symlinks)
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        self.dir
+            .hard_link_at(old_path, Self::inner(new_dir), new_path)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        self.dir.rename_at(old_path, Self::inner(new_dir), new_path)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        self.dir.symlink_at(target, path)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<String> {
+        self.dir.read_link_at(path)
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.dir.remove_dir_at(path)
+    }
+
+    fn unlink_file_at(&self, path: &str) -> io::Result<()> {
+        self.dir.unlink_file_at(path)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.dir.sync()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.dir.sync_data()
+    }
+
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        self.dir.sync_flags()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file opened from a [`TraceDir`].
+struct TraceFile {
+    file: Arc<dyn WasiFile>,
+    trace: Trace,
+}
+
+impl WasiFile for TraceFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let bytes: Vec<u8> = self
+            .trace
+            .event(Event::FileRead, || {
+                let n = self.file.read_at(buf, offset)?;
+                Ok(buf[..n].to_vec())
+            })
+            .map_err(io::Error::other)??;
+        if bytes.len() > buf.len() {
+            return Err(io::Error::other(self.trace.diverge(format!(
+                "the trace has a file read of {} bytes next, but the guest requested at most {}",
+                bytes.len(),
+                buf.len()
+            ))));
+        }
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Ok(bytes.len())
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.file.write_at(buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.file.append(buf)
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        self.file.set_len(size)
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.file.set_times(atime, mtime)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        self.file.sync_flags()
+    }
+
+    fn advise(&self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        self.file.advise(offset, len, advice)
+    }
+}
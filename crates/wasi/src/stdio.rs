@@ -139,7 +139,13 @@ impl Subscribe for AsyncStdinStream {
 mod worker_thread_stdin;
 pub use self::worker_thread_stdin::{stdin, Stdin};
 
+mod capture;
+pub use self::capture::{CaptureSink, CaptureStream, CapturedLine};
+
 /// Similar to [`StdinStream`], except for output.
+///
+/// Built-in implementations are provided for [`Stdout`], [`Stderr`],
+/// [`OutputFile`], [`CaptureStream`] and the output pipes of [`pipe`].
 pub trait StdoutStream: Send {
     /// Returns a fresh new stream which can write to this output stream.
     ///
@@ -468,7 +474,9 @@ where
     T: WasiView,
 {
     fn get_terminal_stdin(&mut self) -> anyhow::Result<Option<Resource<TerminalInput>>> {
-        if self.ctx().stdin.isatty() {
+        let isatty = self.ctx().stdin.isatty();
+        self.ctx().check_trace()?;
+        if isatty {
             let fd = self.table().push(TerminalInput)?;
             Ok(Some(fd))
         } else {
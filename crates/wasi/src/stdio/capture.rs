@@ -0,0 +1,406 @@
+//! Output streams which capture a guest's stdout or stderr line by line.
+
+use crate::{HostOutputStream, StdoutStream, StreamError, StreamResult, Subscribe};
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Where the lines captured by a [`CaptureStream`] go.
+pub enum CaptureSink {
+    /// Keep the most recent lines in memory, up to `max_bytes` in total; see
+    /// [`CaptureStream::lines`].
+    ///
+    /// Each line counts as its text plus the size of a [`CapturedLine`], so
+    /// that empty lines can't be retained without bound either.
+    Memory { max_bytes: usize },
+    /// Write each line to `writer`, prefixed with its timestamp if
+    /// `timestamps` is set.
+    Text {
+        writer: Box<dyn Write + Send>,
+        timestamps: bool,
+    },
+    /// Write each line to the given writer as a JSON object on its own line,
+    /// e.g. `{"timestamp":"2024-01-01T00:00:00.000Z","stream":"stdout","line":"hello"}`.
+    JsonLines(Box<dyn Write + Send>),
+    /// Emit each line as a `tracing` event with the given level and the
+    /// `wasmtime_wasi::stdio` target. These events can in turn be routed to
+    /// the `log` ecosystem with `tracing`'s `log` feature.
+    Tracing(tracing::Level),
+}
+
+/// A line of output captured by a [`CaptureStream`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedLine {
+    /// When the first byte of this line was written.
+    pub timestamp: SystemTime,
+    /// The line without its terminating newline. Invalid UTF-8 is replaced
+    /// with `U+FFFD`.
+    pub line: String,
+    /// How many bytes at the end of the line were dropped because the line
+    /// was longer than the [maximum length](CaptureStream::max_line_len).
+    pub truncated: usize,
+}
+
+impl CapturedLine {
+    /// The memory this line counts as in a [`CaptureSink::Memory`].
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<CapturedLine>() + self.line.len()
+    }
+}
+
+/// A [`StdoutStream`] which splits the output of a guest into lines and sends
+/// each line, with the time it was written, to a [`CaptureSink`].
+///
+/// Memory use is bounded: lines longer than the
+/// [maximum length](CaptureStream::max_line_len) are truncated and marked as
+/// such, and [`CaptureSink::Memory`] only retains the most recent lines.
+///
+/// Clones of a capture stream share the same state, so the same stream can be
+/// passed to [`WasiCtxBuilder::stdout`](crate::WasiCtxBuilder::stdout) while a
+/// clone is kept to retrieve the captured lines. A final line without a
+/// trailing newline is captured by [`CaptureStream::finish`], or once the last
+/// clone is dropped.
+#[derive(Clone)]
+pub struct CaptureStream {
+    state: Arc<Mutex<CaptureState>>,
+}
+
+struct CaptureState {
+    name: String,
+    sink: CaptureSink,
+    max_line_len: usize,
+    /// The start of the line being written, up to `max_line_len` bytes.
+    partial: Vec<u8>,
+    /// When the first byte of the line being written was received.
+    started: Option<SystemTime>,
+    /// The number of bytes of the line being written that were dropped.
+    truncated: usize,
+    /// The lines retained by [`CaptureSink::Memory`], and their total size.
+    lines: VecDeque<CapturedLine>,
+    buffered: usize,
+    /// The number of lines [`CaptureSink::Memory`] no longer retains.
+    dropped_lines: u64,
+}
+
+impl CaptureStream {
+    /// Capture the output stream called `name`, e.g. `"stdout"`, into `sink`.
+    pub fn new(name: &str, sink: CaptureSink) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(CaptureState {
+                name: name.to_string(),
+                sink,
+                max_line_len: 64 * 1024,
+                partial: Vec::new(),
+                started: None,
+                truncated: 0,
+                lines: VecDeque::new(),
+                buffered: 0,
+                dropped_lines: 0,
+            })),
+        }
+    }
+
+    /// Truncate lines to `len` bytes; defaults to 64 KiB.
+    pub fn max_line_len(self, len: usize) -> Self {
+        self.state.lock().unwrap().max_line_len = len;
+        self
+    }
+
+    /// Returns the lines retained by a [`CaptureSink::Memory`] stream, oldest
+    /// first, or nothing for other sinks.
+    pub fn lines(&self) -> Vec<CapturedLine> {
+        self.state.lock().unwrap().lines.iter().cloned().collect()
+    }
+
+    /// Returns the number of lines which were captured by a
+    /// [`CaptureSink::Memory`] stream but are no longer retained as they no
+    /// longer fit in its memory.
+    pub fn dropped_lines(&self) -> u64 {
+        self.state.lock().unwrap().dropped_lines
+    }
+
+    /// Capture the final line if it wasn't terminated by a newline, and flush
+    /// the sink.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.started.is_some() {
+            state.end_line()?;
+        }
+        state.flush()
+    }
+}
+
+impl CaptureState {
+    fn write(&mut self, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            if self.started.is_none() {
+                self.started = Some(SystemTime::now());
+            }
+            let (line, rest, end) = match bytes.iter().position(|b| *b == b'\n') {
+                Some(i) => (&bytes[..i], &bytes[i + 1..], true),
+                None => (bytes, &[][..], false),
+            };
+            let keep = line
+                .len()
+                .min(self.max_line_len.saturating_sub(self.partial.len()));
+            self.partial.extend_from_slice(&line[..keep]);
+            self.truncated += line.len() - keep;
+            if end {
+                self.end_line()?;
+            }
+            bytes = rest;
+        }
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        if self.partial.last() == Some(&b'\r') && self.truncated == 0 {
+            self.partial.pop();
+        }
+        let line = CapturedLine {
+            timestamp: self.started.take().unwrap_or_else(SystemTime::now),
+            line: String::from_utf8_lossy(&self.partial).into_owned(),
+            truncated: std::mem::take(&mut self.truncated),
+        };
+        self.partial.clear();
+
+        match &mut self.sink {
+            CaptureSink::Memory { max_bytes } => {
+                self.buffered += line.memory_size();
+                self.lines.push_back(line);
+                while self.buffered > *max_bytes {
+                    let Some(oldest) = self.lines.pop_front() else {
+                        break;
+                    };
+                    self.buffered -= oldest.memory_size();
+                    self.dropped_lines += 1;
+                }
+                Ok(())
+            }
+            CaptureSink::Text { writer, timestamps } => {
+                let mut out = String::new();
+                if *timestamps {
+                    out.push_str(&format_timestamp(line.timestamp));
+                    out.push(' ');
+                }
+                out.push_str(&line.line);
+                if line.truncated > 0 {
+                    let _ = write!(out, " [{} bytes truncated]", line.truncated);
+                }
+                out.push('\n');
+                writer.write_all(out.as_bytes())
+            }
+            CaptureSink::JsonLines(writer) => {
+                let mut out = format!(
+                    "{{\"timestamp\":\"{}\",\"stream\":{},\"line\":{}",
+                    format_timestamp(line.timestamp),
+                    json_string(&self.name),
+                    json_string(&line.line),
+                );
+                if line.truncated > 0 {
+                    let _ = write!(out, ",\"truncated\":{}", line.truncated);
+                }
+                out.push_str("}\n");
+                writer.write_all(out.as_bytes())
+            }
+            CaptureSink::Tracing(level) => {
+                macro_rules! event {
+                    ($level:expr) => {
+                        tracing::event!(
+                            target: "wasmtime_wasi::stdio",
+                            $level,
+                            stream = %self.name,
+                            truncated = line.truncated,
+                            "{}",
+                            line.line
+                        )
+                    };
+                }
+                match *level {
+                    tracing::Level::TRACE => event!(tracing::Level::TRACE),
+                    tracing::Level::DEBUG => event!(tracing::Level::DEBUG),
+                    tracing::Level::INFO => event!(tracing::Level::INFO),
+                    tracing::Level::WARN => event!(tracing::Level::WARN),
+                    tracing::Level::ERROR => event!(tracing::Level::ERROR),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            CaptureSink::Text { writer, .. } | CaptureSink::JsonLines(writer) => writer.flush(),
+            CaptureSink::Memory { .. } | CaptureSink::Tracing(_) => Ok(()),
+        }
+    }
+}
+
+impl Drop for CaptureState {
+    fn drop(&mut self) {
+        if self.started.is_some() {
+            let _ = self.end_line();
+        }
+        let _ = self.flush();
+    }
+}
+
+impl StdoutStream for CaptureStream {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+}
+
+impl HostOutputStream for CaptureStream {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .write(&bytes)
+            .map_err(|e| StreamError::LastOperationFailed(anyhow::anyhow!(e)))
+    }
+
+    // Only the sink is flushed: a line is captured once it's complete, even if
+    // the guest flushes its output in the middle of it.
+    fn flush(&mut self) -> StreamResult<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .flush()
+            .map_err(|e| StreamError::LastOperationFailed(anyhow::anyhow!(e)))
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(1024 * 1024)
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for CaptureStream {
+    async fn ready(&mut self) {}
+}
+
+/// Format `time` as an RFC 3339 timestamp in UTC with millisecond precision.
+fn format_timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+/// Quote and escape `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    serde_json::to_string(s).expect("strings always serialize")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// A writer whose output can be inspected once it's moved into a sink.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn write(stream: &CaptureStream, bytes: &'static [u8]) {
+        let mut view = stream.stream();
+        view.write(Bytes::from_static(bytes)).unwrap();
+    }
+
+    #[test]
+    fn memory_lines_are_split_and_bounded() {
+        // Room for 12 bytes of text in three lines.
+        let max_bytes = 12 + 3 * std::mem::size_of::<CapturedLine>();
+        let capture = CaptureStream::new("stdout", CaptureSink::Memory { max_bytes });
+        write(&capture, b"one\ntw");
+        write(&capture, b"o\r\nthree\nfour");
+        assert_eq!(capture.dropped_lines(), 0);
+        let lines = capture.lines();
+        let text = lines.iter().map(|l| l.line.as_str()).collect::<Vec<_>>();
+        assert_eq!(text, ["one", "two", "three"]);
+
+        // "four" only shows up once finished, and then pushes out "one".
+        capture.finish().unwrap();
+        let lines = capture.lines();
+        let text = lines.iter().map(|l| l.line.as_str()).collect::<Vec<_>>();
+        assert_eq!(text, ["two", "three", "four"]);
+        assert_eq!(capture.dropped_lines(), 1);
+    }
+
+    #[test]
+    fn memory_empty_lines_are_bounded() {
+        let max_bytes = 64 * std::mem::size_of::<CapturedLine>();
+        let capture = CaptureStream::new("stdout", CaptureSink::Memory { max_bytes });
+        for _ in 0..1000 {
+            write(&capture, b"\n\n\n\n\n\n\n\n\n\n");
+        }
+        assert_eq!(capture.lines().len(), 64);
+        assert_eq!(capture.dropped_lines(), 10_000 - 64);
+    }
+
+    #[test]
+    fn long_lines_are_truncated() {
+        let buf = SharedBuf::default();
+        let capture = CaptureStream::new(
+            "stderr",
+            CaptureSink::Text {
+                writer: Box::new(buf.clone()),
+                timestamps: false,
+            },
+        )
+        .max_line_len(4);
+        write(&capture, b"abcdefgh\nij");
+        write(&capture, b"klmn\n");
+        assert_eq!(
+            buf.contents(),
+            "abcd [4 bytes truncated]\nijkl [2 bytes truncated]\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let buf = SharedBuf::default();
+        let capture = CaptureStream::new("stdout", CaptureSink::JsonLines(Box::new(buf.clone())))
+            .max_line_len(10);
+        // The final line is captured once the stream is dropped.
+        write(&capture, b"say \"hi\"\t\x01!!");
+        drop(capture);
+        let contents = buf.contents();
+        let (timestamp, rest) = contents
+            .strip_prefix("{\"timestamp\":\"")
+            .unwrap()
+            .split_once('"')
+            .unwrap();
+        assert_eq!(timestamp.len(), "2024-01-01T00:00:00.000Z".len());
+        assert_eq!(
+            rest,
+            ",\"stream\":\"stdout\",\"line\":\"say \\\"hi\\\"\\t\\u0001\",\"truncated\":2}\n"
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(951_827_696_789);
+        assert_eq!(format_timestamp(time), "2000-02-29T12:34:56.789Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
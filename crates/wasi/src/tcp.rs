@@ -2,6 +2,7 @@ use crate::bindings::sockets::tcp::ErrorCode;
 use crate::host::network;
 use crate::network::SocketAddressFamily;
 use crate::runtime::{with_ambient_tokio_runtime, AbortOnDropJoinHandle};
+use crate::vnet::{self, VirtualNetwork};
 use crate::{
     HostInputStream, HostOutputStream, InputStream, OutputStream, SocketError, SocketResult,
     StreamError, Subscribe,
@@ -18,7 +19,7 @@ use std::mem;
 use std::net::{Shutdown, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
-use std::task::Poll;
+use std::task::{Context, Poll};
 use tokio::sync::Mutex;
 
 /// Value taken from rust std library.
@@ -30,33 +31,33 @@ const DEFAULT_BACKLOG: u32 = 128;
 /// activities of binding, listening, accepting, and connecting.
 enum TcpState {
     /// The initial state for a newly-created socket.
-    Default(tokio::net::TcpSocket),
+    Default(Socket),
 
     /// Binding started via `start_bind`.
-    BindStarted(tokio::net::TcpSocket),
+    BindStarted(Socket),
 
     /// Binding finished via `finish_bind`. The socket has an address but
     /// is not yet listening for connections.
-    Bound(tokio::net::TcpSocket),
+    Bound(Socket),
 
     /// Listening started via `listen_start`.
-    ListenStarted(tokio::net::TcpSocket),
+    ListenStarted(Socket),
 
     /// The socket is now listening and waiting for an incoming connection.
     Listening {
-        listener: tokio::net::TcpListener,
-        pending_accept: Option<io::Result<tokio::net::TcpStream>>,
+        listener: Listener,
+        pending_accept: Option<io::Result<Stream>>,
     },
 
     /// An outgoing connection is started via `start_connect`.
-    Connecting(Pin<Box<dyn Future<Output = io::Result<tokio::net::TcpStream>> + Send>>),
+    Connecting(Pin<Box<dyn Future<Output = io::Result<Stream>> + Send>>),
 
     /// An outgoing connection is ready to be established.
-    ConnectReady(io::Result<tokio::net::TcpStream>),
+    ConnectReady(io::Result<Stream>),
 
     /// An outgoing connection has been established.
     Connected {
-        stream: Arc<tokio::net::TcpStream>,
+        stream: Arc<Stream>,
 
         // WASI is single threaded, so in practice these Mutexes should never be contended:
         reader: Arc<Mutex<TcpReader>>,
@@ -85,6 +86,93 @@ impl std::fmt::Debug for TcpState {
     }
 }
 
+/// A socket which isn't listening or connected yet, either of the host or of a
+/// [`VirtualNetwork`].
+enum Socket {
+    Host(tokio::net::TcpSocket),
+    Virtual(vnet::TcpSocket),
+}
+
+enum Listener {
+    Host(tokio::net::TcpListener),
+    Virtual(vnet::TcpListener),
+}
+
+impl Listener {
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Stream>> {
+        match self {
+            Listener::Host(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| Stream::Host(stream)),
+            Listener::Virtual(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| Stream::Virtual(stream)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Stream {
+    Host(tokio::net::TcpStream),
+    Virtual(vnet::TcpStream),
+}
+
+impl Stream {
+    fn try_read_buf(&self, buf: &mut bytes::BytesMut) -> io::Result<usize> {
+        match self {
+            Stream::Host(stream) => stream.try_read_buf(buf),
+            Stream::Virtual(stream) => {
+                let len = buf.len();
+                buf.resize(buf.capacity(), 0);
+                let result = stream.try_read(&mut buf[len..]);
+                buf.truncate(len + *result.as_ref().unwrap_or(&0));
+                result
+            }
+        }
+    }
+
+    async fn readable(&self) -> io::Result<()> {
+        match self {
+            Stream::Host(stream) => stream.readable().await,
+            Stream::Virtual(stream) => {
+                stream.readable().await;
+                Ok(())
+            }
+        }
+    }
+
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Host(stream) => stream.try_write(buf),
+            Stream::Virtual(stream) => stream.try_write(buf),
+        }
+    }
+
+    async fn writable(&self) -> io::Result<()> {
+        match self {
+            Stream::Host(stream) => stream.writable().await,
+            Stream::Virtual(stream) => {
+                stream.writable().await;
+                Ok(())
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Stream::Host(stream) => stream.local_addr(),
+            Stream::Virtual(stream) => Ok(stream.local_addr()),
+        }
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Stream::Host(stream) => stream.peer_addr(),
+            Stream::Virtual(stream) => Ok(stream.peer_addr()),
+        }
+    }
+}
+
 /// A host TCP socket, plus associated bookkeeping.
 pub struct TcpSocket {
     /// The current state in the bind/listen/accept/connect progression.
@@ -124,10 +212,20 @@ impl TcpSocket {
                 }
             };
 
-            Self::from_state(TcpState::Default(socket), family)
+            Self::from_state(TcpState::Default(Socket::Host(socket)), family)
         })
     }
 
+    /// Create a new socket in the given family on a virtual network.
+    pub(crate) fn new_virtual(net: &VirtualNetwork, family: AddressFamily) -> io::Result<Self> {
+        let family = match family {
+            AddressFamily::Ipv4 => SocketAddressFamily::Ipv4,
+            AddressFamily::Ipv6 => SocketAddressFamily::Ipv6,
+        };
+        let socket = vnet::TcpSocket::new(net.clone());
+        Self::from_state(TcpState::Default(Socket::Virtual(socket)), family)
+    }
+
     /// Create a `TcpSocket` from an existing socket.
     fn from_state(state: TcpState, family: SocketAddressFamily) -> io::Result<Self> {
         Ok(Self {
@@ -149,15 +247,25 @@ impl TcpSocket {
         use crate::bindings::sockets::network::ErrorCode;
 
         match &self.tcp_state {
-            TcpState::Default(socket) | TcpState::Bound(socket) => {
+            TcpState::Default(Socket::Host(socket)) | TcpState::Bound(Socket::Host(socket)) => {
                 Ok(socket.as_socketlike_view::<std::net::TcpStream>())
             }
-            TcpState::Connected { stream, .. } => {
-                Ok(stream.as_socketlike_view::<std::net::TcpStream>())
-            }
-            TcpState::Listening { listener, .. } => {
-                Ok(listener.as_socketlike_view::<std::net::TcpStream>())
-            }
+            TcpState::Connected { stream, .. } => match &**stream {
+                Stream::Host(stream) => Ok(stream.as_socketlike_view::<std::net::TcpStream>()),
+                Stream::Virtual(_) => Err(ErrorCode::NotSupported.into()),
+            },
+            TcpState::Listening {
+                listener: Listener::Host(listener),
+                ..
+            } => Ok(listener.as_socketlike_view::<std::net::TcpStream>()),
+
+            // Virtual sockets don't support socket options.
+            TcpState::Default(Socket::Virtual(_))
+            | TcpState::Bound(Socket::Virtual(_))
+            | TcpState::Listening {
+                listener: Listener::Virtual(_),
+                ..
+            } => Err(ErrorCode::NotSupported.into()),
 
             TcpState::BindStarted(..)
             | TcpState::ListenStarted(..)
@@ -170,7 +278,7 @@ impl TcpSocket {
 
 impl TcpSocket {
     pub fn start_bind(&mut self, local_address: SocketAddr) -> io::Result<()> {
-        let tokio_socket = match &self.tcp_state {
+        let socket = match &mut self.tcp_state {
             TcpState::Default(socket) => socket,
             TcpState::BindStarted(..) => return Err(Errno::ALREADY.into()),
             _ => return Err(Errno::ISCONN.into()),
@@ -179,47 +287,50 @@ impl TcpSocket {
         network::util::validate_unicast(&local_address)?;
         network::util::validate_address_family(&local_address, &self.family)?;
 
-        {
-            // Automatically bypass the TIME_WAIT state when the user is trying
-            // to bind to a specific port:
-            let reuse_addr = local_address.port() > 0;
-
-            // Unconditionally (re)set SO_REUSEADDR, even when the value is false.
-            // This ensures we're not accidentally affected by any socket option
-            // state left behind by a previous failed call to this method (start_bind).
-            network::util::set_tcp_reuseaddr(&tokio_socket, reuse_addr)?;
-
-            // Perform the OS bind call.
-            tokio_socket.bind(local_address).map_err(|error| {
-                match Errno::from_io_error(&error) {
-                    // From https://pubs.opengroup.org/onlinepubs/9699919799/functions/bind.html:
-                    // > [EAFNOSUPPORT] The specified address is not a valid address for the address family of the specified socket
-                    //
-                    // The most common reasons for this error should have already
-                    // been handled by our own validation slightly higher up in this
-                    // function. This error mapping is here just in case there is
-                    // an edge case we didn't catch.
-                    Some(Errno::AFNOSUPPORT) =>  io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "The specified address is not a valid address for the address family of the specified socket",
-                    ),
-
-                    // See: https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-bind#:~:text=WSAENOBUFS
-                    // Windows returns WSAENOBUFS when the ephemeral ports have been exhausted.
-                    #[cfg(windows)]
-                    Some(Errno::NOBUFS) => io::Error::new(io::ErrorKind::AddrInUse, "no more free local ports"),
-
-                    _ => error,
-                }
-            })?;
+        match socket {
+            Socket::Host(tokio_socket) => {
+                // Automatically bypass the TIME_WAIT state when the user is trying
+                // to bind to a specific port:
+                let reuse_addr = local_address.port() > 0;
+
+                // Unconditionally (re)set SO_REUSEADDR, even when the value is false.
+                // This ensures we're not accidentally affected by any socket option
+                // state left behind by a previous failed call to this method (start_bind).
+                network::util::set_tcp_reuseaddr(&*tokio_socket, reuse_addr)?;
+
+                // Perform the OS bind call.
+                tokio_socket.bind(local_address).map_err(|error| {
+                    match Errno::from_io_error(&error) {
+                        // From https://pubs.opengroup.org/onlinepubs/9699919799/functions/bind.html:
+                        // > [EAFNOSUPPORT] The specified address is not a valid address for the address family of the specified socket
+                        //
+                        // The most common reasons for this error should have already
+                        // been handled by our own validation slightly higher up in this
+                        // function. This error mapping is here just in case there is
+                        // an edge case we didn't catch.
+                        Some(Errno::AFNOSUPPORT) =>  io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "The specified address is not a valid address for the address family of the specified socket",
+                        ),
+
+                        // See: https://learn.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-bind#:~:text=WSAENOBUFS
+                        // Windows returns WSAENOBUFS when the ephemeral ports have been exhausted.
+                        #[cfg(windows)]
+                        Some(Errno::NOBUFS) => io::Error::new(io::ErrorKind::AddrInUse, "no more free local ports"),
+
+                        _ => error,
+                    }
+                })?;
+            }
+            Socket::Virtual(socket) => socket.bind(local_address)?,
+        }
 
-            self.tcp_state = match std::mem::replace(&mut self.tcp_state, TcpState::Closed) {
-                TcpState::Default(socket) => TcpState::BindStarted(socket),
-                _ => unreachable!(),
-            };
+        self.tcp_state = match std::mem::replace(&mut self.tcp_state, TcpState::Closed) {
+            TcpState::Default(socket) => TcpState::BindStarted(socket),
+            _ => unreachable!(),
+        };
 
-            Ok(())
-        }
+        Ok(())
     }
 
     pub fn finish_bind(&mut self) -> SocketResult<()> {
@@ -251,15 +362,22 @@ impl TcpSocket {
         network::util::validate_remote_address(&remote_address)?;
         network::util::validate_address_family(&remote_address, &self.family)?;
 
-        let (TcpState::Default(tokio_socket) | TcpState::Bound(tokio_socket)) =
+        let (TcpState::Default(socket) | TcpState::Bound(socket)) =
             std::mem::replace(&mut self.tcp_state, TcpState::Closed)
         else {
             unreachable!();
         };
 
-        let future = tokio_socket.connect(remote_address);
-
-        self.tcp_state = TcpState::Connecting(Box::pin(future));
+        self.tcp_state = match socket {
+            Socket::Host(tokio_socket) => {
+                let future = tokio_socket.connect(remote_address);
+                TcpState::Connecting(Box::pin(async move { future.await.map(Stream::Host) }))
+            }
+            // Connecting to a virtual listener completes immediately.
+            Socket::Virtual(socket) => {
+                TcpState::ConnectReady(socket.connect(remote_address).map(Stream::Virtual))
+            }
+        };
         Ok(())
     }
 
@@ -268,7 +386,7 @@ impl TcpSocket {
         let result = match previous_state {
             TcpState::ConnectReady(result) => result,
             TcpState::Connecting(mut future) => {
-                let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+                let mut cx = Context::from_waker(futures::task::noop_waker_ref());
                 match with_ambient_tokio_runtime(|| future.as_mut().poll(&mut cx)) {
                     Poll::Ready(result) => result,
                     Poll::Pending => {
@@ -322,15 +440,24 @@ impl TcpSocket {
     }
 
     pub fn finish_listen(&mut self) -> SocketResult<()> {
-        let tokio_socket = match std::mem::replace(&mut self.tcp_state, TcpState::Closed) {
-            TcpState::ListenStarted(tokio_socket) => tokio_socket,
+        let socket = match std::mem::replace(&mut self.tcp_state, TcpState::Closed) {
+            TcpState::ListenStarted(socket) => socket,
             previous_state => {
                 self.tcp_state = previous_state;
                 return Err(ErrorCode::NotInProgress.into());
             }
         };
 
-        match with_ambient_tokio_runtime(|| tokio_socket.listen(self.listen_backlog_size)) {
+        let result = match socket {
+            Socket::Host(tokio_socket) => {
+                with_ambient_tokio_runtime(|| tokio_socket.listen(self.listen_backlog_size))
+                    .map(Listener::Host)
+            }
+            Socket::Virtual(socket) => socket
+                .listen(self.listen_backlog_size as usize)
+                .map(Listener::Virtual),
+        };
+        match result {
             Ok(listener) => {
                 self.tcp_state = TcpState::Listening {
                     listener,
@@ -372,10 +499,8 @@ impl TcpSocket {
         let result = match pending_accept.take() {
             Some(result) => result,
             None => {
-                let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
-                match with_ambient_tokio_runtime(|| listener.poll_accept(&mut cx))
-                    .map_ok(|(stream, _)| stream)
-                {
+                let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+                match with_ambient_tokio_runtime(|| listener.poll_accept(&mut cx)) {
                     Poll::Ready(result) => result,
                     Poll::Pending => Err(Errno::WOULDBLOCK.into()),
                 }
@@ -417,26 +542,26 @@ impl TcpSocket {
         })?;
 
         #[cfg(target_os = "macos")]
-        {
+        if let Stream::Host(client) = &client {
             // Manually inherit socket options from listener. We only have to
             // do this on platforms that don't already do this automatically
             // and only if a specific value was explicitly set on the listener.
 
             if let Some(size) = self.receive_buffer_size {
-                _ = network::util::set_socket_recv_buffer_size(&client, size); // Ignore potential error.
+                _ = network::util::set_socket_recv_buffer_size(client, size); // Ignore potential error.
             }
 
             if let Some(size) = self.send_buffer_size {
-                _ = network::util::set_socket_send_buffer_size(&client, size); // Ignore potential error.
+                _ = network::util::set_socket_send_buffer_size(client, size); // Ignore potential error.
             }
 
             // For some reason, IP_TTL is inherited, but IPV6_UNICAST_HOPS isn't.
             if let (SocketAddressFamily::Ipv6, Some(ttl)) = (self.family, self.hop_limit) {
-                _ = network::util::set_ipv6_unicast_hops(&client, ttl); // Ignore potential error.
+                _ = network::util::set_ipv6_unicast_hops(client, ttl); // Ignore potential error.
             }
 
             if let Some(value) = self.keep_alive_idle_time {
-                _ = network::util::set_tcp_keepidle(&client, value); // Ignore potential error.
+                _ = network::util::set_tcp_keepidle(client, value); // Ignore potential error.
             }
         }
 
@@ -460,9 +585,15 @@ impl TcpSocket {
     }
 
     pub fn local_address(&self) -> SocketResult<SocketAddr> {
-        let view = match self.tcp_state {
+        let view = match &self.tcp_state {
             TcpState::Default(..) => return Err(ErrorCode::InvalidState.into()),
             TcpState::BindStarted(..) => return Err(ErrorCode::ConcurrencyConflict.into()),
+            TcpState::Bound(Socket::Virtual(socket)) => return Ok(socket.local_addr()?),
+            TcpState::Listening {
+                listener: Listener::Virtual(listener),
+                ..
+            } => return Ok(listener.local_addr()),
+            TcpState::Connected { stream, .. } => return Ok(stream.local_addr()?),
             _ => self.as_std_view()?,
         };
 
@@ -470,15 +601,13 @@ impl TcpSocket {
     }
 
     pub fn remote_address(&self) -> SocketResult<SocketAddr> {
-        let view = match self.tcp_state {
-            TcpState::Connected { .. } => self.as_std_view()?,
+        match &self.tcp_state {
+            TcpState::Connected { stream, .. } => Ok(stream.peer_addr()?),
             TcpState::Connecting(..) | TcpState::ConnectReady(..) => {
-                return Err(ErrorCode::ConcurrencyConflict.into())
+                Err(ErrorCode::ConcurrencyConflict.into())
             }
-            _ => return Err(ErrorCode::InvalidState.into()),
-        };
-
-        Ok(view.peer_addr()?)
+            _ => Err(ErrorCode::InvalidState.into()),
+        }
     }
 
     pub fn is_listening(&self) -> bool {
@@ -504,13 +633,20 @@ impl TcpSocket {
             TcpState::Default(..) | TcpState::Bound(..) => {
                 // Socket not listening yet. Stash value for first invocation to `listen`.
             }
-            TcpState::Listening { listener, .. } => {
+            TcpState::Listening {
+                listener: Listener::Host(listener),
+                ..
+            } => {
                 // Try to update the backlog by calling `listen` again.
                 // Not all platforms support this. We'll only update our own value if the OS supports changing the backlog size after the fact.
 
                 rustix::net::listen(&listener, value.try_into().unwrap())
                     .map_err(|_| ErrorCode::NotSupported)?;
             }
+            TcpState::Listening {
+                listener: Listener::Virtual(listener),
+                ..
+            } => listener.set_backlog(value as usize),
             _ => return Err(ErrorCode::InvalidState.into()),
         }
         self.listen_backlog_size = value;
@@ -677,10 +813,7 @@ impl Subscribe for TcpSocket {
             } => match pending_accept {
                 Some(_) => {}
                 None => {
-                    let result = futures::future::poll_fn(|cx| {
-                        listener.poll_accept(cx).map_ok(|(stream, _)| stream)
-                    })
-                    .await;
+                    let result = futures::future::poll_fn(|cx| listener.poll_accept(cx)).await;
                     *pending_accept = Some(result);
                 }
             },
@@ -689,12 +822,12 @@ impl Subscribe for TcpSocket {
 }
 
 struct TcpReader {
-    stream: Arc<tokio::net::TcpStream>,
+    stream: Arc<Stream>,
     closed: bool,
 }
 
 impl TcpReader {
-    fn new(stream: Arc<tokio::net::TcpStream>) -> Self {
+    fn new(stream: Arc<Stream>) -> Self {
         Self {
             stream,
             closed: false,
@@ -764,7 +897,7 @@ impl Subscribe for TcpReadStream {
 const SOCKET_READY_SIZE: usize = 1024 * 1024 * 1024;
 
 struct TcpWriter {
-    stream: Arc<tokio::net::TcpStream>,
+    stream: Arc<Stream>,
     state: WriteState,
 }
 
@@ -777,14 +910,14 @@ enum WriteState {
 }
 
 impl TcpWriter {
-    fn new(stream: Arc<tokio::net::TcpStream>) -> Self {
+    fn new(stream: Arc<Stream>) -> Self {
         Self {
             stream,
             state: WriteState::Ready,
         }
     }
 
-    fn try_write_portable(stream: &tokio::net::TcpStream, buf: &[u8]) -> io::Result<usize> {
+    fn try_write_portable(stream: &Stream, buf: &[u8]) -> io::Result<usize> {
         stream.try_write(buf).map_err(|error| {
             match Errno::from_io_error(&error) {
                 // Windows returns `WSAESHUTDOWN` when writing to a shut down socket.
@@ -979,10 +1112,15 @@ impl Subscribe for TcpWriteStream {
     }
 }
 
-fn native_shutdown(stream: &tokio::net::TcpStream, how: Shutdown) {
-    _ = stream
-        .as_socketlike_view::<std::net::TcpStream>()
-        .shutdown(how);
+fn native_shutdown(stream: &Stream, how: Shutdown) {
+    match stream {
+        Stream::Host(stream) => {
+            _ = stream
+                .as_socketlike_view::<std::net::TcpStream>()
+                .shutdown(how);
+        }
+        Stream::Virtual(stream) => stream.shutdown(how),
+    }
 }
 
 fn try_lock_for_stream<T>(mutex: &Mutex<T>) -> Result<tokio::sync::MutexGuard<'_, T>, StreamError> {
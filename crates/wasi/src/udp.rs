@@ -1,12 +1,16 @@
+use crate::bindings::sockets::network::ErrorCode;
 use crate::host::network::util;
 use crate::poll::Subscribe;
 use crate::runtime::with_ambient_tokio_runtime;
+use crate::vnet::{self, VirtualNetwork};
+use crate::{SocketResult, Trace};
 use async_trait::async_trait;
 use cap_net_ext::{AddressFamily, Blocking};
 use io_lifetimes::raw::{FromRawSocketlike, IntoRawSocketlike};
 use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::io::{Interest, Ready};
 
 use super::network::{SocketAddrCheck, SocketAddressFamily};
 
@@ -29,6 +33,72 @@ pub(crate) enum UdpState {
     Connected,
 }
 
+/// The socket underlying a [`UdpSocket`] and its streams, either of the host
+/// or of a [`VirtualNetwork`].
+///
+/// The methods mirror those of [`tokio::net::UdpSocket`].
+pub(crate) enum Socket {
+    Host(tokio::net::UdpSocket),
+    Virtual(vnet::UdpSocket),
+}
+
+impl Socket {
+    /// The host socket, for operations which virtual sockets don't support.
+    pub(crate) fn host_socket(&self) -> SocketResult<&tokio::net::UdpSocket> {
+        match self {
+            Socket::Host(socket) => Ok(socket),
+            Socket::Virtual(_) => Err(ErrorCode::NotSupported.into()),
+        }
+    }
+
+    pub(crate) fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        match self {
+            Socket::Host(socket) => socket.try_recv_from(buf),
+            Socket::Virtual(socket) => socket.try_recv_from(buf),
+        }
+    }
+
+    pub(crate) fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Host(socket) => socket.try_send(buf),
+            Socket::Virtual(socket) => socket.try_send(buf),
+        }
+    }
+
+    pub(crate) fn try_send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        match self {
+            Socket::Host(socket) => socket.try_send_to(buf, target),
+            Socket::Virtual(socket) => socket.try_send_to(buf, target),
+        }
+    }
+
+    pub(crate) async fn ready(&self, interest: Interest) -> io::Result<Ready> {
+        match self {
+            Socket::Host(socket) => socket.ready(interest).await,
+            // Sending on a virtual socket never blocks.
+            Socket::Virtual(_) if interest.is_writable() => Ok(Ready::WRITABLE),
+            Socket::Virtual(socket) => {
+                socket.readable().await;
+                Ok(Ready::READABLE)
+            }
+        }
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Socket::Host(socket) => socket.local_addr(),
+            Socket::Virtual(socket) => socket.local_addr(),
+        }
+    }
+
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Socket::Host(socket) => socket.peer_addr(),
+            Socket::Virtual(socket) => socket.peer_addr(),
+        }
+    }
+}
+
 /// A host UDP socket, plus associated bookkeeping.
 ///
 /// The inner state is wrapped in an Arc because the same underlying socket is
@@ -36,7 +106,7 @@ pub(crate) enum UdpState {
 pub struct UdpSocket {
     /// The part of a `UdpSocket` which is reference-counted so that we
     /// can pass it to async tasks.
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: Arc<Socket>,
 
     /// The current state in the bind/connect progression.
     pub(crate) udp_state: UdpState,
@@ -73,33 +143,50 @@ impl UdpSocket {
         let socket = Self::setup_tokio_udp_socket(fd)?;
 
         Ok(UdpSocket {
-            inner: Arc::new(socket),
+            inner: Arc::new(Socket::Host(socket)),
             udp_state: UdpState::Default,
             family: socket_address_family,
             socket_addr_check: None,
         })
     }
 
+    /// Create a new socket in the given family on a virtual network.
+    pub(crate) fn new_virtual(net: &VirtualNetwork, family: AddressFamily) -> Self {
+        let family = match family {
+            AddressFamily::Ipv4 => SocketAddressFamily::Ipv4,
+            AddressFamily::Ipv6 => SocketAddressFamily::Ipv6,
+        };
+        UdpSocket {
+            inner: Arc::new(Socket::Virtual(vnet::UdpSocket::new(net.clone()))),
+            udp_state: UdpState::Default,
+            family,
+            socket_addr_check: None,
+        }
+    }
+
     fn setup_tokio_udp_socket(fd: rustix::fd::OwnedFd) -> io::Result<tokio::net::UdpSocket> {
         let std_socket =
             unsafe { std::net::UdpSocket::from_raw_socketlike(fd.into_raw_socketlike()) };
         with_ambient_tokio_runtime(|| tokio::net::UdpSocket::try_from(std_socket))
     }
 
-    pub fn udp_socket(&self) -> &tokio::net::UdpSocket {
-        &self.inner
+    pub fn udp_socket(&self) -> SocketResult<&tokio::net::UdpSocket> {
+        self.inner.host_socket()
     }
 }
 
 pub struct IncomingDatagramStream {
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: Arc<Socket>,
 
     /// If this has a value, the stream is "connected".
     pub(crate) remote_address: Option<SocketAddr>,
+
+    /// If this has a value, received datagrams are recorded or replayed.
+    pub(crate) trace: Option<Trace>,
 }
 
 pub struct OutgoingDatagramStream {
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: Arc<Socket>,
 
     /// If this has a value, the stream is "connected".
     pub(crate) remote_address: Option<SocketAddr>,
@@ -0,0 +1,310 @@
+//! Filesystems which can be preopened for guests.
+//!
+//! Every directory and file that a guest can access through
+//! `wasi:filesystem` is backed by an implementation of [`WasiDir`] or
+//! [`WasiFile`]. Host directories preopened with
+//! [`WasiCtxBuilder::preopened_dir`](crate::WasiCtxBuilder::preopened_dir)
+//! are backed by [`cap_std::fs::Dir`], and any other implementation of
+//! [`WasiDir`] can be preopened with
+//! [`WasiCtxBuilder::preopened_virtual_dir`](crate::WasiCtxBuilder::preopened_virtual_dir).
+//! This module provides:
+//!
+//! * [`MemoryDir`], a filesystem held entirely in memory, which can also be
+//!   read from a tar or zip archive with the `vfs-archive` feature.
+//! * [`OverlayDir`], which layers a writable directory over a read-only one,
+//!   leaving the read-only one untouched.
+//!
+//! The [`DirPerms`](crate::DirPerms) and [`FilePerms`](crate::FilePerms)
+//! given when preopening a directory are enforced before any of these
+//! traits' methods are called, so implementations don't need to check them.
+//!
+//! The methods of these traits may block, and are called on a thread where
+//! blocking is allowed.
+
+use crate::bindings::filesystem::types::{Advice, DescriptorFlags, ErrorCode};
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+mod host;
+mod memory;
+mod overlay;
+
+#[cfg(feature = "vfs-archive")]
+mod archive;
+
+pub use self::memory::MemoryDir;
+pub use self::overlay::OverlayDir;
+
+/// A directory which can be preopened for guests.
+///
+/// Paths given to the methods of this trait are relative to the directory,
+/// use `/` as separator and must not refer to anything outside of the
+/// directory; [`ErrorCode::NotPermitted`] should be returned for paths which
+/// do, such as absolute paths or paths with too many `..` components.
+///
+/// Errors are returned as [`io::Error`]s. Errors which don't come from the
+/// operating system can be created with [`error`] to control the
+/// [`ErrorCode`] that the guest sees.
+pub trait WasiDir: Send + Sync + 'static {
+    /// Open the file or directory at `path`.
+    fn open_at(&self, path: &str, options: &OpenOptions) -> io::Result<Opened>;
+
+    /// Create a new directory at `path`.
+    fn create_dir_at(&self, path: &str) -> io::Result<()>;
+
+    /// List the entries of this directory, excluding `.` and `..`.
+    ///
+    /// Each entry may fail individually.
+    fn read_dir(&self) -> io::Result<Vec<io::Result<DirEntry>>>;
+
+    /// Get the metadata of this directory.
+    fn metadata(&self) -> io::Result<Metadata>;
+
+    /// Get the metadata of the file or directory at `path`, or of the
+    /// symbolic link itself if `path` names one and `follow_symlinks` is
+    /// false.
+    fn metadata_at(&self, path: &str, follow_symlinks: bool) -> io::Result<Metadata>;
+
+    /// Set the access and modification times of this directory, leaving
+    /// those which are `None` unchanged.
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()>;
+
+    /// Set the access and modification times of the file or directory at
+    /// `path`, leaving those which are `None` unchanged.
+    fn set_times_at(
+        &self,
+        path: &str,
+        atime: Option<TimeSpec>,
+        mtime: Option<TimeSpec>,
+        follow_symlinks: bool,
+    ) -> io::Result<()>;
+
+    /// Create a hard link at `new_path` within `new_dir` to the file at
+    /// `old_path`.
+    ///
+    /// `new_dir` may be of a different type than `self`, in which case
+    /// [`ErrorCode::CrossDevice`] should be returned.
+    fn hard_link_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str)
+        -> io::Result<()>;
+
+    /// Move the file or directory at `old_path` to `new_path` within
+    /// `new_dir`.
+    ///
+    /// `new_dir` may be of a different type than `self`, in which case
+    /// [`ErrorCode::CrossDevice`] should be returned.
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()>;
+
+    /// Create a symbolic link at `path` whose contents are `target`.
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()>;
+
+    /// Read the contents of the symbolic link at `path`.
+    fn read_link_at(&self, path: &str) -> io::Result<String>;
+
+    /// Remove the empty directory at `path`.
+    fn remove_dir_at(&self, path: &str) -> io::Result<()>;
+
+    /// Remove the file or symbolic link at `path`.
+    fn unlink_file_at(&self, path: &str) -> io::Result<()>;
+
+    /// Flush this directory's contents and metadata to storage.
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Flush this directory's contents to storage.
+    fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The synchronization flags this directory was opened with, from
+    /// [`DescriptorFlags::REQUESTED_WRITE_SYNC`],
+    /// [`DescriptorFlags::DATA_INTEGRITY_SYNC`] and
+    /// [`DescriptorFlags::FILE_INTEGRITY_SYNC`].
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        Ok(DescriptorFlags::empty())
+    }
+
+    /// Get `self` as [`Any`], so that implementations of
+    /// [`WasiDir::hard_link_at`] and [`WasiDir::rename_at`] can tell whether
+    /// the other directory is of the same type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A file which can be opened by guests, from a [`WasiDir`].
+pub trait WasiFile: Send + Sync + 'static {
+    /// Read from the file at `offset` into `buf`, returning the number of
+    /// bytes read, which is zero at the end of the file.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Write `buf` to the file at `offset`, returning the number of bytes
+    /// written.
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+
+    /// Write `buf` to the end of the file, returning the number of bytes
+    /// written.
+    fn append(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Get the metadata of this file.
+    fn metadata(&self) -> io::Result<Metadata>;
+
+    /// Truncate or extend the file to `size` bytes.
+    fn set_len(&self, size: u64) -> io::Result<()>;
+
+    /// Set the access and modification times of this file, leaving those
+    /// which are `None` unchanged.
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()>;
+
+    /// Flush this file's contents and metadata to storage.
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Flush this file's contents to storage.
+    fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The synchronization flags this file was opened with, from
+    /// [`DescriptorFlags::REQUESTED_WRITE_SYNC`],
+    /// [`DescriptorFlags::DATA_INTEGRITY_SYNC`] and
+    /// [`DescriptorFlags::FILE_INTEGRITY_SYNC`].
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        Ok(DescriptorFlags::empty())
+    }
+
+    /// Advise the implementation of how a range of the file will be
+    /// accessed.
+    fn advise(&self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        let _ = (offset, len, advice);
+        Ok(())
+    }
+}
+
+/// How to open a file or directory with [`WasiDir::open_at`].
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    /// Open for reading.
+    pub read: bool,
+    /// Open for writing.
+    pub write: bool,
+    /// Create a file if nothing exists at the path.
+    pub create: bool,
+    /// Create a file, failing with [`ErrorCode::Exist`] if something already
+    /// exists at the path.
+    pub create_new: bool,
+    /// Truncate the file to zero length.
+    pub truncate: bool,
+    /// Follow a symbolic link at the path.
+    pub follow_symlinks: bool,
+}
+
+/// A file or directory opened with [`WasiDir::open_at`].
+pub enum Opened {
+    /// A directory.
+    Dir(Arc<dyn WasiDir>),
+    /// A file.
+    File(Arc<dyn WasiFile>),
+}
+
+/// The type of a file or directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// A directory.
+    Directory,
+    /// A regular file.
+    RegularFile,
+    /// A symbolic link.
+    SymbolicLink,
+    /// A block device.
+    BlockDevice,
+    /// A character device.
+    CharacterDevice,
+    /// Some other kind of file.
+    Unknown,
+}
+
+/// An entry of a directory, listed with [`WasiDir::read_dir`].
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    /// The entry's name.
+    pub name: String,
+    /// The entry's type.
+    pub file_type: FileType,
+}
+
+/// The metadata of a file or directory.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// The type of the file or directory.
+    pub file_type: FileType,
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// The number of hard links to the file.
+    pub nlink: u64,
+    /// The device containing the file. Together with `ino` this identifies
+    /// the file.
+    pub dev: u64,
+    /// The file's inode number, unique within `dev`.
+    pub ino: u64,
+    /// When the file was last accessed.
+    pub accessed: Option<SystemTime>,
+    /// When the file was last modified.
+    pub modified: Option<SystemTime>,
+    /// When the file's status last changed or, on some platforms, when the
+    /// file was created.
+    pub created: Option<SystemTime>,
+}
+
+/// A new access or modification time of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeSpec {
+    /// The current time.
+    Now,
+    /// The given time.
+    Absolute(SystemTime),
+}
+
+/// Create an [`io::Error`] which is reported to guests as `code`.
+pub fn error(code: ErrorCode) -> io::Error {
+    let kind = match code {
+        ErrorCode::NoEntry => io::ErrorKind::NotFound,
+        ErrorCode::Exist => io::ErrorKind::AlreadyExists,
+        ErrorCode::NotPermitted | ErrorCode::Access | ErrorCode::ReadOnly => {
+            io::ErrorKind::PermissionDenied
+        }
+        ErrorCode::Invalid => io::ErrorKind::InvalidInput,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, code)
+}
+
+/// The `ErrorCode` that an error created with [`error`] is reported as.
+pub(crate) fn error_code(err: &io::Error) -> Option<ErrorCode> {
+    err.get_ref()?.downcast_ref::<ErrorCode>().copied()
+}
+
+/// Split `path`, relative to some directory, into its normal components,
+/// resolving `.` and `..` lexically.
+///
+/// Returns [`ErrorCode::NotPermitted`] for absolute paths and paths which
+/// leave the directory.
+pub(crate) fn components(path: &str) -> io::Result<Vec<&str>> {
+    if path.starts_with('/') {
+        return Err(error(ErrorCode::NotPermitted));
+    }
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(error(ErrorCode::NotPermitted));
+                }
+            }
+            component => components.push(component),
+        }
+    }
+    Ok(components)
+}
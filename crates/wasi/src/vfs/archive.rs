@@ -0,0 +1,76 @@
+//! Reading a [`MemoryDir`] from a tar or zip archive.
+
+use super::{error, MemoryDir};
+use crate::bindings::filesystem::types::ErrorCode;
+use std::io::{self, Read, Seek};
+use std::path::{Component, Path};
+
+impl MemoryDir {
+    /// Read a read-only filesystem from the tar archive read from `reader`.
+    ///
+    /// Only the directories and regular files of the archive are read;
+    /// other kinds of entries, such as symbolic links, are skipped. Entries
+    /// whose paths are absolute or contain `..` are rejected.
+    pub fn from_tar(reader: impl Read) -> io::Result<MemoryDir> {
+        let dir = MemoryDir::new();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = archive_path(&entry.path()?)?;
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_dir() {
+                dir.insert_dir(&path)?;
+            } else if entry_type.is_file() {
+                // Sizes in headers aren't trusted to preallocate, as they may
+                // be far larger than the archive itself.
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+                dir.insert_file(&path, contents)?;
+            }
+        }
+        Ok(dir.into_read_only())
+    }
+
+    /// Read a read-only filesystem from the zip archive read from `reader`.
+    ///
+    /// Only the directories and regular files of the archive are read.
+    /// Entries whose paths are absolute or contain `..` are rejected.
+    pub fn from_zip(reader: impl Read + Seek) -> io::Result<MemoryDir> {
+        let dir = MemoryDir::new();
+        let mut archive = zip::ZipArchive::new(reader)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let path = match file.enclosed_name() {
+                Some(path) => archive_path(path)?,
+                None => return Err(error(ErrorCode::NotPermitted)),
+            };
+            if file.is_dir() {
+                dir.insert_dir(&path)?;
+            } else if file.is_file() {
+                let mut contents = Vec::new();
+                file.read_to_end(&mut contents)?;
+                dir.insert_file(&path, contents)?;
+            }
+        }
+        Ok(dir.into_read_only())
+    }
+}
+
+/// Convert the path of an archive entry to a path relative to the root of a
+/// [`MemoryDir`].
+fn archive_path(path: &Path) -> io::Result<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(
+                name.to_str()
+                    .ok_or_else(|| error(ErrorCode::IllegalByteSequence))?,
+            ),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(error(ErrorCode::NotPermitted));
+            }
+        }
+    }
+    Ok(components.join("/"))
+}
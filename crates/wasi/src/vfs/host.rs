@@ -0,0 +1,277 @@
+//! The implementations of [`WasiDir`] and [`WasiFile`] for host directories
+//! and files.
+
+use super::{
+    error, DirEntry, FileType, Metadata, OpenOptions, Opened, TimeSpec, WasiDir, WasiFile,
+};
+use crate::bindings::filesystem::types::{Advice, DescriptorFlags, ErrorCode};
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use system_interface::fs::{FdFlags, FileIoExt, GetSetFdFlags};
+
+impl WasiDir for cap_std::fs::Dir {
+    fn open_at(&self, path: &str, options: &OpenOptions) -> io::Result<Opened> {
+        use cap_fs_ext::{FollowSymlinks, OpenOptionsFollowExt, OpenOptionsMaybeDirExt};
+
+        let mut opts = cap_std::fs::OpenOptions::new();
+        opts.maybe_dir(true)
+            .read(options.read)
+            .write(options.write)
+            .truncate(options.truncate);
+        if options.create_new {
+            opts.create_new(true);
+        } else if options.create {
+            opts.create(true);
+        }
+        opts.follow(if options.follow_symlinks {
+            FollowSymlinks::Yes
+        } else {
+            FollowSymlinks::No
+        });
+
+        let mut opened = self.open_with(path, &opts)?;
+        if opened.metadata()?.is_dir() {
+            Ok(Opened::Dir(Arc::new(cap_std::fs::Dir::from_std_file(
+                opened.into_std(),
+            ))))
+        } else {
+            // FIXME cap-std needs a nonblocking open option so that files reads and writes
+            // are nonblocking. Instead we set it after opening here:
+            let set_fd_flags = opened.new_set_fd_flags(FdFlags::NONBLOCK)?;
+            opened.set_fd_flags(set_fd_flags)?;
+            Ok(Opened::File(Arc::new(opened)))
+        }
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.create_dir(path)
+    }
+
+    fn read_dir(&self) -> io::Result<Vec<io::Result<DirEntry>>> {
+        // Both `entries` and `metadata` perform syscalls, which is why the
+        // metadata of all entries is read here rather than lazily.
+        Ok(self
+            .entries()?
+            .map(|entry| {
+                let entry = entry?;
+                let meta = entry.metadata()?;
+                let name = entry
+                    .file_name()
+                    .into_string()
+                    .map_err(|_| error(ErrorCode::IllegalByteSequence))?;
+                Ok(DirEntry {
+                    name,
+                    file_type: file_type(meta.file_type()),
+                })
+            })
+            .collect())
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        Ok(metadata(self.dir_metadata()?))
+    }
+
+    fn metadata_at(&self, path: &str, follow_symlinks: bool) -> io::Result<Metadata> {
+        let meta = if follow_symlinks {
+            cap_std::fs::Dir::metadata(self, path)?
+        } else {
+            self.symlink_metadata(path)?
+        };
+        Ok(metadata(meta))
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        use fs_set_times::SetTimes;
+        SetTimes::set_times(self, atime.map(set_times_spec), mtime.map(set_times_spec))
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        atime: Option<TimeSpec>,
+        mtime: Option<TimeSpec>,
+        follow_symlinks: bool,
+    ) -> io::Result<()> {
+        use cap_fs_ext::DirExt;
+
+        let atime = atime.map(cap_fs_ext_spec);
+        let mtime = mtime.map(cap_fs_ext_spec);
+        if follow_symlinks {
+            DirExt::set_times(self, path, atime, mtime)
+        } else {
+            self.set_symlink_times(path, atime, mtime)
+        }
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let new_dir = same_type(new_dir)?;
+        self.hard_link(old_path, new_dir, new_path)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let new_dir = same_type(new_dir)?;
+        self.rename(old_path, new_dir, new_path)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        // On windows, Dir.symlink is provided by DirExt
+        #[cfg(windows)]
+        use cap_fs_ext::DirExt;
+
+        self.symlink(target, path)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<String> {
+        self.read_link(path)?
+            .into_os_string()
+            .into_string()
+            .map_err(|_| error(ErrorCode::IllegalByteSequence))
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.remove_dir(path)
+    }
+
+    fn unlink_file_at(&self, path: &str) -> io::Result<()> {
+        use cap_fs_ext::DirExt;
+        self.remove_file_or_symlink(path)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.open(std::path::Component::CurDir)?.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.open(std::path::Component::CurDir)?.sync_data()
+    }
+
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        Ok(sync_flags(self.get_fd_flags()?))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl WasiFile for cap_std::fs::File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        FileIoExt::read_at(self, buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        FileIoExt::write_at(self, buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        FileIoExt::append(self, buf)
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        Ok(metadata(cap_std::fs::File::metadata(self)?))
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        cap_std::fs::File::set_len(self, size)
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        use fs_set_times::SetTimes;
+        SetTimes::set_times(self, atime.map(set_times_spec), mtime.map(set_times_spec))
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        cap_std::fs::File::sync_data(self)
+    }
+
+    fn sync_flags(&self) -> io::Result<DescriptorFlags> {
+        Ok(sync_flags(self.get_fd_flags()?))
+    }
+
+    fn advise(&self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        use system_interface::fs::Advice as A;
+
+        let advice = match advice {
+            Advice::Normal => A::Normal,
+            Advice::Sequential => A::Sequential,
+            Advice::Random => A::Random,
+            Advice::WillNeed => A::WillNeed,
+            Advice::DontNeed => A::DontNeed,
+            Advice::NoReuse => A::NoReuse,
+        };
+        FileIoExt::advise(self, offset, len, advice)
+    }
+}
+
+/// Get `dir` as a host directory, or fail if it's a virtual one.
+fn same_type(dir: &dyn WasiDir) -> io::Result<&cap_std::fs::Dir> {
+    dir.as_any()
+        .downcast_ref()
+        .ok_or_else(|| error(ErrorCode::CrossDevice))
+}
+
+fn sync_flags(flags: FdFlags) -> DescriptorFlags {
+    let mut out = DescriptorFlags::empty();
+    if flags.contains(FdFlags::DSYNC) {
+        out |= DescriptorFlags::REQUESTED_WRITE_SYNC;
+    }
+    if flags.contains(FdFlags::RSYNC) {
+        out |= DescriptorFlags::DATA_INTEGRITY_SYNC;
+    }
+    if flags.contains(FdFlags::SYNC) {
+        out |= DescriptorFlags::FILE_INTEGRITY_SYNC;
+    }
+    out
+}
+
+fn set_times_spec(t: TimeSpec) -> fs_set_times::SystemTimeSpec {
+    match t {
+        TimeSpec::Now => fs_set_times::SystemTimeSpec::SymbolicNow,
+        TimeSpec::Absolute(t) => fs_set_times::SystemTimeSpec::Absolute(t),
+    }
+}
+
+fn cap_fs_ext_spec(t: TimeSpec) -> cap_fs_ext::SystemTimeSpec {
+    cap_fs_ext::SystemTimeSpec::from_std(set_times_spec(t))
+}
+
+fn file_type(ft: cap_std::fs::FileType) -> FileType {
+    use cap_fs_ext::FileTypeExt;
+    if ft.is_dir() {
+        FileType::Directory
+    } else if ft.is_symlink() {
+        FileType::SymbolicLink
+    } else if ft.is_block_device() {
+        FileType::BlockDevice
+    } else if ft.is_char_device() {
+        FileType::CharacterDevice
+    } else if ft.is_file() {
+        FileType::RegularFile
+    } else {
+        FileType::Unknown
+    }
+}
+
+fn metadata(meta: cap_std::fs::Metadata) -> Metadata {
+    use cap_fs_ext::MetadataExt;
+    Metadata {
+        file_type: file_type(meta.file_type()),
+        len: meta.len(),
+        nlink: meta.nlink(),
+        dev: meta.dev(),
+        ino: meta.ino(),
+        accessed: meta.accessed().map(|t| t.into_std()).ok(),
+        modified: meta.modified().map(|t| t.into_std()).ok(),
+        created: meta.created().map(|t| t.into_std()).ok(),
+    }
+}
@@ -0,0 +1,690 @@
+//! A filesystem held entirely in memory.
+
+use super::{
+    components, error, DirEntry, FileType, Metadata, OpenOptions, Opened, TimeSpec, WasiDir,
+    WasiFile,
+};
+use crate::bindings::filesystem::types::ErrorCode;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+/// A directory of a filesystem held entirely in memory.
+///
+/// A new `MemoryDir` is the root of an empty filesystem, which can be
+/// populated by the host with [`MemoryDir::insert_file`] and
+/// [`MemoryDir::insert_dir`] before it is preopened, and by guests which are
+/// allowed to mutate it. Clones of a `MemoryDir` refer to the same
+/// directory, so the host can preopen a clone and inspect the filesystem
+/// after the guest ran.
+///
+/// A filesystem can be made read-only with [`MemoryDir::into_read_only`],
+/// after which guests fail to modify it through any of its directories with
+/// [`ErrorCode::ReadOnly`], regardless of the permissions it was preopened
+/// with. The host can still modify it.
+///
+/// Guests can't grow a file past a maximum size, which is
+/// [`MemoryDir::DEFAULT_MAX_FILE_SIZE`] unless configured with
+/// [`MemoryDir::with_max_file_size`]; writes and truncations beyond it fail
+/// with [`ErrorCode::FileTooLarge`].
+///
+/// Symbolic links are not supported.
+#[derive(Clone)]
+pub struct MemoryDir {
+    fs: Arc<MemoryFs>,
+    ino: u64,
+}
+
+struct MemoryFs {
+    /// The device number of this filesystem, distinct from that of other
+    /// `MemoryFs`s.
+    dev: u64,
+    read_only: AtomicBool,
+    max_file_size: AtomicU64,
+    tree: Mutex<Tree>,
+}
+
+/// The structure of a filesystem, protected by a single lock so that
+/// operations involving several directories, such as renames, are atomic.
+struct Tree {
+    dirs: HashMap<u64, DirNode>,
+    next_ino: u64,
+}
+
+struct DirNode {
+    entries: BTreeMap<String, Entry>,
+    times: Times,
+}
+
+/// An entry of a directory: either a directory, which is looked up in
+/// `Tree::dirs` and ceases to exist once removed, or a file, which lives on
+/// while it is open even after it is removed.
+#[derive(Clone)]
+enum Entry {
+    Dir(u64),
+    File(Arc<FileNode>),
+}
+
+struct FileNode {
+    ino: u64,
+    nlink: AtomicU64,
+    data: RwLock<Vec<u8>>,
+    times: Mutex<Times>,
+}
+
+#[derive(Clone, Copy)]
+struct Times {
+    accessed: SystemTime,
+    modified: SystemTime,
+    changed: SystemTime,
+}
+
+impl Times {
+    fn now() -> Times {
+        let now = SystemTime::now();
+        Times {
+            accessed: now,
+            modified: now,
+            changed: now,
+        }
+    }
+
+    fn set(&mut self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) {
+        let resolve = |t| match t {
+            TimeSpec::Now => SystemTime::now(),
+            TimeSpec::Absolute(t) => t,
+        };
+        if let Some(t) = atime {
+            self.accessed = resolve(t);
+        }
+        if let Some(t) = mtime {
+            self.modified = resolve(t);
+        }
+        self.changed = SystemTime::now();
+    }
+
+    fn modify(&mut self) {
+        let now = SystemTime::now();
+        self.modified = now;
+        self.changed = now;
+    }
+}
+
+/// The next device number to give to a new filesystem.
+static NEXT_DEV: AtomicU64 = AtomicU64::new(1);
+
+/// The inode number of the root directory of every filesystem.
+const ROOT: u64 = 1;
+
+impl Default for MemoryDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryDir {
+    /// The default maximum size of files grown by guests: 1 GiB.
+    pub const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+
+    /// Create the root directory of a new, empty, filesystem.
+    pub fn new() -> MemoryDir {
+        let mut dirs = HashMap::new();
+        dirs.insert(ROOT, DirNode::new());
+        MemoryDir {
+            fs: Arc::new(MemoryFs {
+                dev: NEXT_DEV.fetch_add(1, Ordering::Relaxed),
+                read_only: AtomicBool::new(false),
+                max_file_size: AtomicU64::new(Self::DEFAULT_MAX_FILE_SIZE),
+                tree: Mutex::new(Tree {
+                    dirs,
+                    next_ino: ROOT + 1,
+                }),
+            }),
+            ino: ROOT,
+        }
+    }
+
+    /// Make this directory's filesystem read-only for guests.
+    pub fn into_read_only(self) -> MemoryDir {
+        self.fs.read_only.store(true, Ordering::Relaxed);
+        self
+    }
+
+    /// Limit the size, in bytes, that guests can grow files of this
+    /// directory's filesystem to.
+    ///
+    /// Defaults to [`MemoryDir::DEFAULT_MAX_FILE_SIZE`].
+    pub fn with_max_file_size(self, bytes: u64) -> MemoryDir {
+        self.fs.max_file_size.store(bytes, Ordering::Relaxed);
+        self
+    }
+
+    /// Create a file at `path` with the given `contents`, replacing any
+    /// file already there, and creating any missing parent directories.
+    pub fn insert_file(&self, path: &str, contents: impl Into<Vec<u8>>) -> io::Result<()> {
+        let mut tree = self.fs.tree.lock().unwrap();
+        let (parent, name) = self.create_parents(&mut tree, path)?;
+        let Some(name) = name else {
+            return Err(error(ErrorCode::IsDirectory));
+        };
+        let file = tree.new_file(contents.into());
+        let parent = tree.dir_mut(parent)?;
+        match parent.entries.get(&name) {
+            Some(Entry::Dir(_)) => return Err(error(ErrorCode::IsDirectory)),
+            Some(Entry::File(old)) => {
+                old.nlink.fetch_sub(1, Ordering::Relaxed);
+            }
+            None => {}
+        }
+        parent.entries.insert(name, Entry::File(file));
+        parent.times.modify();
+        Ok(())
+    }
+
+    /// Create a directory at `path`, along with any missing parent
+    /// directories, if it doesn't exist yet.
+    pub fn insert_dir(&self, path: &str) -> io::Result<()> {
+        let mut tree = self.fs.tree.lock().unwrap();
+        let (parent, name) = self.create_parents(&mut tree, path)?;
+        if let Some(name) = name {
+            tree.ensure_dir(parent, &name)?;
+        }
+        Ok(())
+    }
+
+    /// Read the contents of the file at `path`.
+    pub fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let tree = self.fs.tree.lock().unwrap();
+        match tree.lookup(self.ino, path)? {
+            Entry::File(file) => Ok(file.data.read().unwrap().clone()),
+            Entry::Dir(_) => Err(error(ErrorCode::IsDirectory)),
+        }
+    }
+
+    /// Resolve the parent directory of `path`, creating it and its own
+    /// parents if needed, and return it with the final component of `path`,
+    /// if any.
+    fn create_parents(&self, tree: &mut Tree, path: &str) -> io::Result<(u64, Option<String>)> {
+        let mut components = components(path)?;
+        let name = components.pop().map(str::to_string);
+        let mut dir = self.ino;
+        for component in components {
+            dir = tree.ensure_dir(dir, component)?;
+        }
+        Ok((dir, name))
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.fs.read_only.load(Ordering::Relaxed) {
+            Err(error(ErrorCode::ReadOnly))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get `dir` as a directory of the same filesystem as `self`.
+    fn same_fs<'a>(&self, dir: &'a dyn WasiDir) -> io::Result<&'a MemoryDir> {
+        match dir.as_any().downcast_ref::<MemoryDir>() {
+            Some(dir) if Arc::ptr_eq(&self.fs, &dir.fs) => Ok(dir),
+            _ => Err(error(ErrorCode::CrossDevice)),
+        }
+    }
+
+    fn file(&self, node: Arc<FileNode>) -> MemoryFile {
+        MemoryFile {
+            fs: self.fs.clone(),
+            node,
+        }
+    }
+}
+
+impl DirNode {
+    fn new() -> DirNode {
+        DirNode {
+            entries: BTreeMap::new(),
+            times: Times::now(),
+        }
+    }
+}
+
+impl Tree {
+    fn dir(&self, ino: u64) -> io::Result<&DirNode> {
+        // A directory which was removed while open no longer has entries.
+        self.dirs.get(&ino).ok_or_else(|| error(ErrorCode::NoEntry))
+    }
+
+    fn dir_mut(&mut self, ino: u64) -> io::Result<&mut DirNode> {
+        self.dirs
+            .get_mut(&ino)
+            .ok_or_else(|| error(ErrorCode::NoEntry))
+    }
+
+    fn new_ino(&mut self) -> u64 {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        ino
+    }
+
+    fn new_file(&mut self, data: Vec<u8>) -> Arc<FileNode> {
+        Arc::new(FileNode {
+            ino: self.new_ino(),
+            nlink: AtomicU64::new(1),
+            data: RwLock::new(data),
+            times: Mutex::new(Times::now()),
+        })
+    }
+
+    fn create_dir(&mut self, parent: u64, name: &str) -> io::Result<u64> {
+        if self.dir(parent)?.entries.contains_key(name) {
+            return Err(error(ErrorCode::Exist));
+        }
+        let ino = self.new_ino();
+        self.dirs.insert(ino, DirNode::new());
+        let parent = self.dir_mut(parent)?;
+        parent.entries.insert(name.to_string(), Entry::Dir(ino));
+        parent.times.modify();
+        Ok(ino)
+    }
+
+    /// Get the directory `name` within `parent`, creating it if it doesn't
+    /// exist yet.
+    fn ensure_dir(&mut self, parent: u64, name: &str) -> io::Result<u64> {
+        match self.dir(parent)?.entries.get(name) {
+            Some(Entry::Dir(ino)) => Ok(*ino),
+            Some(Entry::File(_)) => Err(error(ErrorCode::NotDirectory)),
+            None => self.create_dir(parent, name),
+        }
+    }
+
+    /// Resolve `path`, relative to the directory `dir`, to its parent
+    /// directory and final component, or to `dir` itself and `None` if the
+    /// path has no components.
+    fn resolve(&self, dir: u64, path: &str) -> io::Result<(u64, Option<String>)> {
+        let mut components = components(path)?;
+        let name = components.pop().map(str::to_string);
+        let mut dir = dir;
+        self.dir(dir)?;
+        for component in components {
+            dir = match self.dir(dir)?.entries.get(component) {
+                Some(Entry::Dir(ino)) => *ino,
+                Some(Entry::File(_)) => return Err(error(ErrorCode::NotDirectory)),
+                None => return Err(error(ErrorCode::NoEntry)),
+            };
+        }
+        Ok((dir, name))
+    }
+
+    fn lookup(&self, dir: u64, path: &str) -> io::Result<Entry> {
+        match self.resolve(dir, path)? {
+            (dir, None) => Ok(Entry::Dir(dir)),
+            (dir, Some(name)) => self
+                .dir(dir)?
+                .entries
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| error(ErrorCode::NoEntry)),
+        }
+    }
+
+    /// Is the directory `ino` the directory `ancestor` or one of its
+    /// descendants?
+    fn is_within(&self, ino: u64, ancestor: u64) -> bool {
+        if ino == ancestor {
+            return true;
+        }
+        let Ok(dir) = self.dir(ancestor) else {
+            return false;
+        };
+        dir.entries.values().any(|entry| match entry {
+            Entry::Dir(child) => self.is_within(ino, *child),
+            Entry::File(_) => false,
+        })
+    }
+
+    fn metadata(&self, dev: u64, entry: &Entry) -> io::Result<Metadata> {
+        match entry {
+            Entry::Dir(ino) => {
+                let dir = self.dir(*ino)?;
+                Ok(Metadata {
+                    file_type: FileType::Directory,
+                    len: 0,
+                    nlink: 1,
+                    dev,
+                    ino: *ino,
+                    accessed: Some(dir.times.accessed),
+                    modified: Some(dir.times.modified),
+                    created: Some(dir.times.changed),
+                })
+            }
+            Entry::File(file) => Ok(file.metadata(dev)),
+        }
+    }
+}
+
+impl FileNode {
+    fn metadata(&self, dev: u64) -> Metadata {
+        let times = *self.times.lock().unwrap();
+        Metadata {
+            file_type: FileType::RegularFile,
+            len: self.data.read().unwrap().len() as u64,
+            nlink: self.nlink.load(Ordering::Relaxed),
+            dev,
+            ino: self.ino,
+            accessed: Some(times.accessed),
+            modified: Some(times.modified),
+            created: Some(times.changed),
+        }
+    }
+}
+
+impl WasiDir for MemoryDir {
+    fn open_at(&self, path: &str, options: &OpenOptions) -> io::Result<Opened> {
+        let create = options.create || options.create_new;
+        if create || options.write || options.truncate {
+            self.check_writable()?;
+        }
+
+        let mut tree = self.fs.tree.lock().unwrap();
+        let (parent, name) = tree.resolve(self.ino, path)?;
+        let existing = match &name {
+            None => Some(Entry::Dir(parent)),
+            Some(name) => tree.dir(parent)?.entries.get(name).cloned(),
+        };
+        match existing {
+            Some(_) if options.create_new => Err(error(ErrorCode::Exist)),
+            Some(Entry::Dir(ino)) => {
+                if options.write || options.truncate {
+                    return Err(error(ErrorCode::IsDirectory));
+                }
+                Ok(Opened::Dir(Arc::new(MemoryDir {
+                    fs: self.fs.clone(),
+                    ino,
+                })))
+            }
+            Some(Entry::File(file)) => {
+                if options.truncate {
+                    file.data.write().unwrap().clear();
+                    file.times.lock().unwrap().modify();
+                }
+                Ok(Opened::File(Arc::new(self.file(file))))
+            }
+            None if create => {
+                let name = name.unwrap();
+                let file = tree.new_file(Vec::new());
+                let parent = tree.dir_mut(parent)?;
+                parent.entries.insert(name, Entry::File(file.clone()));
+                parent.times.modify();
+                Ok(Opened::File(Arc::new(self.file(file))))
+            }
+            None => Err(error(ErrorCode::NoEntry)),
+        }
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.check_writable()?;
+        let mut tree = self.fs.tree.lock().unwrap();
+        match tree.resolve(self.ino, path)? {
+            (_, None) => Err(error(ErrorCode::Exist)),
+            (parent, Some(name)) => tree.create_dir(parent, &name).map(drop),
+        }
+    }
+
+    fn read_dir(&self) -> io::Result<Vec<io::Result<DirEntry>>> {
+        let tree = self.fs.tree.lock().unwrap();
+        Ok(tree
+            .dir(self.ino)?
+            .entries
+            .iter()
+            .map(|(name, entry)| {
+                Ok(DirEntry {
+                    name: name.clone(),
+                    file_type: match entry {
+                        Entry::Dir(_) => FileType::Directory,
+                        Entry::File(_) => FileType::RegularFile,
+                    },
+                })
+            })
+            .collect())
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        let tree = self.fs.tree.lock().unwrap();
+        tree.metadata(self.fs.dev, &Entry::Dir(self.ino))
+    }
+
+    fn metadata_at(&self, path: &str, _follow_symlinks: bool) -> io::Result<Metadata> {
+        let tree = self.fs.tree.lock().unwrap();
+        let entry = tree.lookup(self.ino, path)?;
+        tree.metadata(self.fs.dev, &entry)
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.set_times_at(".", atime, mtime, true)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        atime: Option<TimeSpec>,
+        mtime: Option<TimeSpec>,
+        _follow_symlinks: bool,
+    ) -> io::Result<()> {
+        self.check_writable()?;
+        let mut tree = self.fs.tree.lock().unwrap();
+        match tree.lookup(self.ino, path)? {
+            Entry::Dir(ino) => tree.dir_mut(ino)?.times.set(atime, mtime),
+            Entry::File(file) => file.times.lock().unwrap().set(atime, mtime),
+        }
+        Ok(())
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let new_dir = self.same_fs(new_dir)?;
+        self.check_writable()?;
+        let mut tree = self.fs.tree.lock().unwrap();
+        let file = match tree.lookup(self.ino, old_path)? {
+            Entry::File(file) => file,
+            Entry::Dir(_) => return Err(error(ErrorCode::NotPermitted)),
+        };
+        let (parent, Some(name)) = tree.resolve(new_dir.ino, new_path)? else {
+            return Err(error(ErrorCode::Exist));
+        };
+        let parent = tree.dir_mut(parent)?;
+        if parent.entries.contains_key(&name) {
+            return Err(error(ErrorCode::Exist));
+        }
+        file.nlink.fetch_add(1, Ordering::Relaxed);
+        file.times.lock().unwrap().changed = SystemTime::now();
+        parent.entries.insert(name, Entry::File(file));
+        parent.times.modify();
+        Ok(())
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let new_dir = self.same_fs(new_dir)?;
+        self.check_writable()?;
+        let mut tree = self.fs.tree.lock().unwrap();
+        let (old_parent, Some(old_name)) = tree.resolve(self.ino, old_path)? else {
+            return Err(error(ErrorCode::Busy));
+        };
+        let (new_parent, Some(new_name)) = tree.resolve(new_dir.ino, new_path)? else {
+            return Err(error(ErrorCode::Busy));
+        };
+        let entry = tree
+            .dir(old_parent)?
+            .entries
+            .get(&old_name)
+            .cloned()
+            .ok_or_else(|| error(ErrorCode::NoEntry))?;
+        if old_parent == new_parent && old_name == new_name {
+            return Ok(());
+        }
+
+        match (&entry, tree.dir(new_parent)?.entries.get(&new_name)) {
+            (Entry::Dir(ino), _) if tree.is_within(new_parent, *ino) => {
+                return Err(error(ErrorCode::Invalid));
+            }
+            (Entry::Dir(_), Some(Entry::File(_))) => return Err(error(ErrorCode::NotDirectory)),
+            (Entry::File(_), Some(Entry::Dir(_))) => return Err(error(ErrorCode::IsDirectory)),
+            (Entry::Dir(_), Some(Entry::Dir(target))) => {
+                if !tree.dir(*target)?.entries.is_empty() {
+                    return Err(error(ErrorCode::NotEmpty));
+                }
+                let target = *target;
+                tree.dirs.remove(&target);
+            }
+            (Entry::File(_), Some(Entry::File(target))) => {
+                target.nlink.fetch_sub(1, Ordering::Relaxed);
+            }
+            (_, None) => {}
+        }
+
+        let old = tree.dir_mut(old_parent)?;
+        old.entries.remove(&old_name);
+        old.times.modify();
+        let new = tree.dir_mut(new_parent)?;
+        new.entries.insert(new_name, entry);
+        new.times.modify();
+        Ok(())
+    }
+
+    fn symlink_at(&self, _target: &str, _path: &str) -> io::Result<()> {
+        Err(error(ErrorCode::Unsupported))
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<String> {
+        let tree = self.fs.tree.lock().unwrap();
+        tree.lookup(self.ino, path)?;
+        Err(error(ErrorCode::Invalid))
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.check_writable()?;
+        let mut tree = self.fs.tree.lock().unwrap();
+        let (parent, Some(name)) = tree.resolve(self.ino, path)? else {
+            return Err(error(ErrorCode::Busy));
+        };
+        let ino = match tree.dir(parent)?.entries.get(&name) {
+            Some(Entry::Dir(ino)) => *ino,
+            Some(Entry::File(_)) => return Err(error(ErrorCode::NotDirectory)),
+            None => return Err(error(ErrorCode::NoEntry)),
+        };
+        if !tree.dir(ino)?.entries.is_empty() {
+            return Err(error(ErrorCode::NotEmpty));
+        }
+        tree.dirs.remove(&ino);
+        let parent = tree.dir_mut(parent)?;
+        parent.entries.remove(&name);
+        parent.times.modify();
+        Ok(())
+    }
+
+    fn unlink_file_at(&self, path: &str) -> io::Result<()> {
+        self.check_writable()?;
+        let mut tree = self.fs.tree.lock().unwrap();
+        let (parent, Some(name)) = tree.resolve(self.ino, path)? else {
+            return Err(error(ErrorCode::IsDirectory));
+        };
+        let parent = tree.dir_mut(parent)?;
+        match parent.entries.get(&name) {
+            Some(Entry::File(file)) => {
+                file.nlink.fetch_sub(1, Ordering::Relaxed);
+            }
+            Some(Entry::Dir(_)) => return Err(error(ErrorCode::IsDirectory)),
+            None => return Err(error(ErrorCode::NoEntry)),
+        }
+        parent.entries.remove(&name);
+        parent.times.modify();
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file of a [`MemoryDir`]'s filesystem.
+struct MemoryFile {
+    fs: Arc<MemoryFs>,
+    node: Arc<FileNode>,
+}
+
+impl MemoryFile {
+    fn check_writable(&self) -> io::Result<()> {
+        if self.fs.read_only.load(Ordering::Relaxed) {
+            Err(error(ErrorCode::ReadOnly))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_locked(&self, data: &mut Vec<u8>, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let offset = usize::try_from(offset).map_err(|_| error(ErrorCode::FileTooLarge))?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or_else(|| error(ErrorCode::FileTooLarge))?;
+        if data.len() < end {
+            self.grow(data, end)?;
+        }
+        data[offset..end].copy_from_slice(buf);
+        self.node.times.lock().unwrap().modify();
+        Ok(buf.len())
+    }
+}
+
+impl WasiFile for MemoryFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let data = self.node.data.read().unwrap();
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.check_writable()?;
+        let mut data = self.node.data.write().unwrap();
+        self.write_locked(&mut data, buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.check_writable()?;
+        let mut data = self.node.data.write().unwrap();
+        let offset = data.len() as u64;
+        self.write_locked(&mut data, buf, offset)
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        Ok(self.node.metadata(self.fs.dev))
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        self.check_writable()?;
+        let size = usize::try_from(size).map_err(|_| error(ErrorCode::FileTooLarge))?;
+        let mut data = self.node.data.write().unwrap();
+        if data.len() < size {
+            self.grow(&mut data, size)?;
+        } else {
+            data.truncate(size);
+        }
+        drop(data);
+        self.node.times.lock().unwrap().modify();
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.check_writable()?;
+        self.node.times.lock().unwrap().set(atime, mtime);
+        Ok(())
+    }
+}
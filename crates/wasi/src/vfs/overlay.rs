@@ -0,0 +1,495 @@
+//! A writable directory layered over a read-only one.
+
+use super::{
+    components, error, DirEntry, FileType, Metadata, OpenOptions, Opened, TimeSpec, WasiDir,
+};
+use crate::bindings::filesystem::types::ErrorCode;
+use std::any::Any;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// A directory which layers a writable `upper` directory over a `base`
+/// directory which is never modified.
+///
+/// The contents of both directories are merged, with entries of `upper`
+/// hiding those of `base` at the same path. Files of `base` are copied to
+/// `upper` before they are modified, along with their parent directories,
+/// and the removal of entries of `base` is recorded in memory so that they
+/// stay hidden for as long as the `OverlayDir` lives.
+///
+/// Files of `base` which are open for reading when they are copied to
+/// `upper` keep reading the contents of `base`.
+pub struct OverlayDir {
+    overlay: Arc<Overlay>,
+    /// The components of the path from the root of the overlay to this
+    /// directory.
+    path: Vec<String>,
+}
+
+struct Overlay {
+    base: Arc<dyn WasiDir>,
+    upper: Arc<dyn WasiDir>,
+    /// The paths of entries of `base` which were removed or replaced, and
+    /// whose descendants in `base` are therefore hidden too.
+    whiteouts: Mutex<HashSet<String>>,
+    /// Serializes the operations modifying the overlay, so that concurrent
+    /// copies of the same file to `upper` don't interfere.
+    lock: Mutex<()>,
+}
+
+/// The layer in which an entry was found.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Layer {
+    Upper,
+    Base,
+}
+
+/// The size of the chunks in which files are copied to the upper layer.
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+impl OverlayDir {
+    /// Create a directory merging the contents of `upper` over those of
+    /// `base`, to which all modifications are made.
+    pub fn new(base: Arc<dyn WasiDir>, upper: Arc<dyn WasiDir>) -> OverlayDir {
+        OverlayDir {
+            overlay: Arc::new(Overlay {
+                base,
+                upper,
+                whiteouts: Mutex::new(HashSet::new()),
+                lock: Mutex::new(()),
+            }),
+            path: Vec::new(),
+        }
+    }
+
+    /// Resolve `path`, relative to this directory, to the path relative to
+    /// the root of the overlay, as passed to the layers.
+    fn full(&self, path: &str) -> io::Result<String> {
+        let mut full = self.path.clone();
+        full.extend(components(path)?.into_iter().map(str::to_string));
+        Ok(join(&full))
+    }
+
+    /// Get `dir` as a directory of the same overlay as `self`.
+    fn same_overlay<'a>(&self, dir: &'a dyn WasiDir) -> io::Result<&'a OverlayDir> {
+        match dir.as_any().downcast_ref::<OverlayDir>() {
+            Some(dir) if Arc::ptr_eq(&self.overlay, &dir.overlay) => Ok(dir),
+            _ => Err(error(ErrorCode::CrossDevice)),
+        }
+    }
+
+    fn subdir(&self, full: &str) -> OverlayDir {
+        OverlayDir {
+            overlay: self.overlay.clone(),
+            path: components(full)
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+impl Overlay {
+    /// Is `full`, or one of its parents, a removed entry of `base`?
+    fn whited_out(&self, full: &str) -> bool {
+        let whiteouts = self.whiteouts.lock().unwrap();
+        full.match_indices('/')
+            .map(|(i, _)| &full[..i])
+            .chain([full])
+            .any(|path| whiteouts.contains(path))
+    }
+
+    fn whiteout(&self, full: &str) {
+        self.whiteouts.lock().unwrap().insert(full.to_string());
+    }
+
+    fn layer(&self, layer: Layer) -> &dyn WasiDir {
+        match layer {
+            Layer::Upper => &*self.upper,
+            Layer::Base => &*self.base,
+        }
+    }
+
+    /// Get the metadata of `full` within `layer`, if it exists there and
+    /// isn't hidden.
+    fn find_in(&self, layer: Layer, full: &str, follow: bool) -> io::Result<Option<Metadata>> {
+        if layer == Layer::Base && full != "." && self.whited_out(full) {
+            return Ok(None);
+        }
+        match self.layer(layer).metadata_at(full, follow) {
+            Ok(meta) => Ok(Some(meta)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Find the layer providing `full`, and its metadata there.
+    fn find(&self, full: &str, follow: bool) -> io::Result<Option<(Layer, Metadata)>> {
+        for layer in [Layer::Upper, Layer::Base] {
+            if let Some(meta) = self.find_in(layer, full, follow)? {
+                return Ok(Some((layer, meta)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// List the merged entries of the directory `full`.
+    fn read_dir(&self, full: &str) -> io::Result<BTreeMap<String, DirEntry>> {
+        let mut entries = BTreeMap::new();
+        let mut found = false;
+        for layer in [Layer::Base, Layer::Upper] {
+            match self.find_in(layer, full, true)? {
+                Some(meta) if meta.file_type == FileType::Directory => {}
+                _ => continue,
+            }
+            found = true;
+            let dir = match self.layer(layer).open_at(full, &read_only())? {
+                Opened::Dir(dir) => dir,
+                Opened::File(_) => return Err(error(ErrorCode::NotDirectory)),
+            };
+            for entry in dir.read_dir()? {
+                let entry = entry?;
+                if layer == Layer::Base && self.whited_out(&join_name(full, &entry.name)) {
+                    continue;
+                }
+                entries.insert(entry.name.clone(), entry);
+            }
+        }
+        if !found {
+            return Err(error(ErrorCode::NoEntry));
+        }
+        Ok(entries)
+    }
+
+    /// Make sure that the parent directories of `full` exist in `upper`,
+    /// copying them from `base` if needed.
+    fn copy_up_parents(&self, full: &str) -> io::Result<()> {
+        let mut parent = String::new();
+        let mut components = components(full)?;
+        components.pop();
+        for component in components {
+            if !parent.is_empty() {
+                parent.push('/');
+            }
+            parent.push_str(component);
+            match self.find(&parent, true)? {
+                Some((Layer::Upper, _)) => {}
+                Some((Layer::Base, meta)) if meta.file_type == FileType::Directory => {
+                    self.upper.create_dir_at(&parent)?;
+                }
+                Some(_) => return Err(error(ErrorCode::NotDirectory)),
+                None => return Err(error(ErrorCode::NoEntry)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Make sure that `full`, and everything within it if it's a directory,
+    /// exists in `upper`, copying it from `base` if needed.
+    fn copy_up(&self, full: &str) -> io::Result<()> {
+        if full == "." {
+            return Ok(());
+        }
+        let (layer, meta) = self
+            .find(full, false)?
+            .ok_or_else(|| error(ErrorCode::NoEntry))?;
+        if layer == Layer::Base {
+            self.copy_up_parents(full)?;
+            match meta.file_type {
+                FileType::Directory => self.upper.create_dir_at(full)?,
+                FileType::SymbolicLink => {
+                    let target = self.base.read_link_at(full)?;
+                    self.upper.symlink_at(&target, full)?;
+                }
+                _ => self.copy_file(full)?,
+            }
+        }
+        if meta.file_type == FileType::Directory {
+            for name in self.read_dir(full)?.into_keys() {
+                self.copy_up(&join_name(full, &name))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_file(&self, full: &str) -> io::Result<()> {
+        let Opened::File(from) = self.base.open_at(full, &read_only())? else {
+            return Err(error(ErrorCode::IsDirectory));
+        };
+        let options = OpenOptions {
+            write: true,
+            create_new: true,
+            ..OpenOptions::default()
+        };
+        let Opened::File(to) = self.upper.open_at(full, &options)? else {
+            return Err(error(ErrorCode::IsDirectory));
+        };
+        let mut buf = vec![0; COPY_CHUNK_SIZE];
+        let mut offset = 0;
+        loop {
+            let n = from.read_at(&mut buf, offset)?;
+            if n == 0 {
+                break;
+            }
+            let mut written = 0;
+            while written < n {
+                written += to.write_at(&buf[written..n], offset + written as u64)?;
+            }
+            offset += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Fail with `Exist` if `full` exists, and otherwise prepare `upper` for
+    /// the creation of `full`.
+    fn prepare_create(&self, full: &str) -> io::Result<()> {
+        if full == "." || self.find(full, false)?.is_some() {
+            return Err(error(ErrorCode::Exist));
+        }
+        self.copy_up_parents(full)
+    }
+
+    /// Record the removal of `full` from the overlay, after it was removed
+    /// from `upper` if it existed there.
+    fn removed(&self, full: &str) -> io::Result<()> {
+        if self.find_in(Layer::Base, full, false)?.is_some() {
+            self.whiteout(full);
+        }
+        Ok(())
+    }
+}
+
+impl WasiDir for OverlayDir {
+    fn open_at(&self, path: &str, options: &OpenOptions) -> io::Result<Opened> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        let create = options.create || options.create_new;
+        let modify = options.write || options.truncate;
+
+        let _guard = (create || modify).then(|| overlay.lock.lock().unwrap());
+        match overlay.find(&full, options.follow_symlinks)? {
+            Some(_) if options.create_new => Err(error(ErrorCode::Exist)),
+            Some((_, meta)) if meta.file_type == FileType::Directory => {
+                if modify {
+                    return Err(error(ErrorCode::IsDirectory));
+                }
+                Ok(Opened::Dir(Arc::new(self.subdir(&full))))
+            }
+            Some((Layer::Base, _)) if !modify => overlay.base.open_at(&full, options),
+            Some((Layer::Base, _)) => {
+                overlay.copy_up(&full)?;
+                overlay.upper.open_at(&full, options)
+            }
+            Some((Layer::Upper, _)) => overlay.upper.open_at(&full, options),
+            None if create => {
+                overlay.copy_up_parents(&full)?;
+                overlay.upper.open_at(&full, options)
+            }
+            None => Err(error(ErrorCode::NoEntry)),
+        }
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        let _guard = overlay.lock.lock().unwrap();
+        overlay.prepare_create(&full)?;
+        overlay.upper.create_dir_at(&full)
+    }
+
+    fn read_dir(&self) -> io::Result<Vec<io::Result<DirEntry>>> {
+        let full = join(&self.path);
+        Ok(self
+            .overlay
+            .read_dir(&full)?
+            .into_values()
+            .map(Ok)
+            .collect())
+    }
+
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.metadata_at(".", true)
+    }
+
+    fn metadata_at(&self, path: &str, follow_symlinks: bool) -> io::Result<Metadata> {
+        let full = self.full(path)?;
+        match self.overlay.find(&full, follow_symlinks)? {
+            Some((_, meta)) => Ok(meta),
+            None => Err(error(ErrorCode::NoEntry)),
+        }
+    }
+
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> io::Result<()> {
+        self.set_times_at(".", atime, mtime, true)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        atime: Option<TimeSpec>,
+        mtime: Option<TimeSpec>,
+        follow_symlinks: bool,
+    ) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        let _guard = overlay.lock.lock().unwrap();
+        overlay.copy_up(&full)?;
+        overlay
+            .upper
+            .set_times_at(&full, atime, mtime, follow_symlinks)
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let new_dir = self.same_overlay(new_dir)?;
+        let old_full = self.full(old_path)?;
+        let new_full = new_dir.full(new_path)?;
+        let _guard = overlay.lock.lock().unwrap();
+        overlay.prepare_create(&new_full)?;
+        overlay.copy_up(&old_full)?;
+        overlay
+            .upper
+            .hard_link_at(&old_full, &*overlay.upper, &new_full)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let new_dir = self.same_overlay(new_dir)?;
+        let old_full = self.full(old_path)?;
+        let new_full = new_dir.full(new_path)?;
+        if old_full == "." || new_full == "." {
+            return Err(error(ErrorCode::Busy));
+        }
+        let _guard = overlay.lock.lock().unwrap();
+
+        let (_, old_meta) = overlay
+            .find(&old_full, false)?
+            .ok_or_else(|| error(ErrorCode::NoEntry))?;
+        let old_is_dir = old_meta.file_type == FileType::Directory;
+        if old_is_dir && new_full.starts_with(&format!("{old_full}/")) {
+            return Err(error(ErrorCode::Invalid));
+        }
+        if let Some((layer, new_meta)) = overlay.find(&new_full, false)? {
+            let new_is_dir = new_meta.file_type == FileType::Directory;
+            match (old_is_dir, new_is_dir) {
+                (true, false) => return Err(error(ErrorCode::NotDirectory)),
+                (false, true) => return Err(error(ErrorCode::IsDirectory)),
+                (true, true) => {
+                    if !overlay.read_dir(&new_full)?.is_empty() {
+                        return Err(error(ErrorCode::NotEmpty));
+                    }
+                    // Replace the empty directory with the renamed one in
+                    // `upper`, hiding the one in `base`, if any.
+                    if layer == Layer::Upper {
+                        overlay.upper.remove_dir_at(&new_full)?;
+                    }
+                }
+                (false, false) => {}
+            }
+        }
+
+        overlay.copy_up(&old_full)?;
+        overlay.copy_up_parents(&new_full)?;
+        overlay
+            .upper
+            .rename_at(&old_full, &*overlay.upper, &new_full)?;
+        overlay.removed(&old_full)?;
+        if overlay.find_in(Layer::Base, &new_full, false)?.is_some() {
+            overlay.whiteout(&new_full);
+        }
+        Ok(())
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        let _guard = overlay.lock.lock().unwrap();
+        overlay.prepare_create(&full)?;
+        overlay.upper.symlink_at(target, &full)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<String> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        match overlay.find(&full, false)? {
+            Some((layer, _)) => overlay.layer(layer).read_link_at(&full),
+            None => Err(error(ErrorCode::NoEntry)),
+        }
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        if full == "." {
+            return Err(error(ErrorCode::Busy));
+        }
+        let _guard = overlay.lock.lock().unwrap();
+        let (layer, meta) = overlay
+            .find(&full, false)?
+            .ok_or_else(|| error(ErrorCode::NoEntry))?;
+        if meta.file_type != FileType::Directory {
+            return Err(error(ErrorCode::NotDirectory));
+        }
+        if !overlay.read_dir(&full)?.is_empty() {
+            return Err(error(ErrorCode::NotEmpty));
+        }
+        if layer == Layer::Upper {
+            overlay.upper.remove_dir_at(&full)?;
+        }
+        overlay.removed(&full)
+    }
+
+    fn unlink_file_at(&self, path: &str) -> io::Result<()> {
+        let overlay = &*self.overlay;
+        let full = self.full(path)?;
+        let _guard = overlay.lock.lock().unwrap();
+        let (layer, meta) = overlay
+            .find(&full, false)?
+            .ok_or_else(|| error(ErrorCode::NoEntry))?;
+        if meta.file_type == FileType::Directory {
+            return Err(error(ErrorCode::IsDirectory));
+        }
+        if layer == Layer::Upper {
+            overlay.upper.unlink_file_at(&full)?;
+        }
+        overlay.removed(&full)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn read_only() -> OpenOptions {
+    OpenOptions {
+        read: true,
+        follow_symlinks: true,
+        ..OpenOptions::default()
+    }
+}
+
+/// Join the components of a path relative to the root of the overlay.
+fn join(components: &[String]) -> String {
+    if components.is_empty() {
+        ".".to_string()
+    } else {
+        components.join("/")
+    }
+}
+
+/// Join the path of a directory relative to the root of the overlay with the
+/// name of one of its entries.
+fn join_name(dir: &str, name: &str) -> String {
+    if dir == "." {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
@@ -0,0 +1,789 @@
+//! An in-process virtual network for WASI sockets.
+//!
+//! A [`VirtualNetwork`] replaces the host's network for the sockets and name
+//! lookups of the guests whose context is built with
+//! [`WasiCtxBuilder::virtual_network`](crate::WasiCtxBuilder::virtual_network).
+//! No OS sockets are involved: connections and datagrams are passed in memory
+//! between the guests which share a network, and the host, which can listen,
+//! connect and exchange datagrams on the network as well through
+//! [`VirtualNetwork::listen_tcp`], [`VirtualNetwork::connect_tcp`] and
+//! [`VirtualNetwork::bind_udp`].
+//!
+//! The network behaves like a single machine which every IP address belongs
+//! to: sockets can bind to any address, and connect or send datagrams to any
+//! address that another socket of the network is bound to. Sockets bound to
+//! the unspecified address receive connections and datagrams sent to any
+//! address of their family, and use the loopback address as their source
+//! address. Names are resolved with the hosts added with
+//! [`VirtualNetwork::insert_host`].
+//!
+//! The [`socket_addr_check`](crate::WasiCtxBuilder::socket_addr_check) and
+//! allowed uses of the network configured on the context still apply to
+//! virtual sockets. Socket options such as buffer sizes and keep-alive aren't
+//! supported by virtual sockets.
+//!
+//! ```
+//! use wasmtime_wasi::vnet::VirtualNetwork;
+//! use wasmtime_wasi::WasiCtxBuilder;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let net = VirtualNetwork::new();
+//! net.insert_host("backend.test", ["10.0.0.2".parse().unwrap()]);
+//!
+//! // A guest connecting to `backend.test:8080` is connected to this listener.
+//! let listener = net.listen_tcp("10.0.0.2:8080".parse().unwrap())?;
+//!
+//! let mut wasi = WasiCtxBuilder::new();
+//! wasi.virtual_network(net.clone()).inherit_network().allow_ip_name_lookup(true);
+//!
+//! let (stream, peer) = listener.accept().await?;
+//! # drop((stream, peer));
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::future::poll_fn;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{ready, Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The number of bytes buffered in each direction of a TCP connection before
+/// writes have to wait for the peer to read.
+const TCP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The number of datagrams queued on a UDP socket before further datagrams
+/// sent to it are dropped.
+const UDP_QUEUE_SIZE: usize = 1024;
+
+/// The ports allocated to sockets bound to port 0 are taken from
+/// `FIRST_EPHEMERAL_PORT..=u16::MAX`.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// The listen backlog of listeners created by the host.
+const DEFAULT_BACKLOG: usize = 128;
+
+/// An in-process network of TCP and UDP sockets, see the [module
+/// documentation](self).
+///
+/// This is a handle to the network, which is shared with its clones.
+#[derive(Clone, Default)]
+pub struct VirtualNetwork(Arc<Mutex<Network>>);
+
+#[derive(Default)]
+struct Network {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    bound: HashSet<(Protocol, SocketAddr)>,
+    listeners: HashMap<SocketAddr, Arc<AcceptQueue>>,
+    receivers: HashMap<SocketAddr, Arc<DatagramQueue>>,
+    /// The offset from [`FIRST_EPHEMERAL_PORT`] of the next port to try to
+    /// allocate.
+    next_port: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl VirtualNetwork {
+    /// Create a new network, without any sockets or hosts.
+    pub fn new() -> VirtualNetwork {
+        VirtualNetwork::default()
+    }
+
+    /// Make `name` resolve to `addrs` in name lookups.
+    ///
+    /// Names are matched case-insensitively; internationalized names must be
+    /// given in their punycode form. Inserting a name again replaces its
+    /// addresses.
+    pub fn insert_host(&self, name: &str, addrs: impl IntoIterator<Item = IpAddr>) {
+        self.lock()
+            .hosts
+            .insert(name.to_ascii_lowercase(), addrs.into_iter().collect());
+    }
+
+    /// The addresses that `name` resolves to, if it was inserted with
+    /// [`VirtualNetwork::insert_host`].
+    pub(crate) fn lookup_host(&self, name: &str) -> Option<Vec<IpAddr>> {
+        self.lock().hosts.get(&name.to_ascii_lowercase()).cloned()
+    }
+
+    /// Listen for TCP connections on `addr`.
+    ///
+    /// If the port of `addr` is 0, a free port is allocated; it can be read
+    /// back with [`TcpListener::local_addr`].
+    pub fn listen_tcp(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let mut socket = TcpSocket::new(self.clone());
+        socket.bind(addr)?;
+        socket.listen(DEFAULT_BACKLOG)
+    }
+
+    /// Connect to the TCP listener at `addr`.
+    ///
+    /// Fails with [`io::ErrorKind::ConnectionRefused`] if nothing listens on
+    /// `addr`, or if its backlog is full.
+    pub fn connect_tcp(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        TcpSocket::new(self.clone()).connect(addr)
+    }
+
+    /// Bind a UDP socket to `addr`.
+    ///
+    /// If the port of `addr` is 0, a free port is allocated; it can be read
+    /// back with [`UdpSocket::local_addr`].
+    pub fn bind_udp(&self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::new(self.clone());
+        socket.bind(addr)?;
+        Ok(socket)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Network> {
+        self.0.lock().unwrap()
+    }
+}
+
+impl Network {
+    /// Reserve `addr` for a socket, allocating a port if its port is 0.
+    fn bind(&mut self, protocol: Protocol, addr: SocketAddr) -> io::Result<SocketAddr> {
+        let addr = normalize(addr);
+        if addr.port() != 0 {
+            if self.in_use(protocol, addr) {
+                return Err(io::ErrorKind::AddrInUse.into());
+            }
+            self.bound.insert((protocol, addr));
+            return Ok(addr);
+        }
+
+        let ports = u16::MAX - FIRST_EPHEMERAL_PORT + 1;
+        for _ in 0..ports {
+            let port = FIRST_EPHEMERAL_PORT + self.next_port;
+            self.next_port = (self.next_port + 1) % ports;
+            let addr = SocketAddr::new(addr.ip(), port);
+            if !self.in_use(protocol, addr) {
+                self.bound.insert((protocol, addr));
+                return Ok(addr);
+            }
+        }
+        Err(io::ErrorKind::AddrInUse.into())
+    }
+
+    /// Whether binding `addr` would conflict with a bound socket.
+    fn in_use(&self, protocol: Protocol, addr: SocketAddr) -> bool {
+        self.bound.iter().any(|(p, bound)| {
+            *p == protocol
+                && bound.port() == addr.port()
+                && bound.is_ipv4() == addr.is_ipv4()
+                && (bound.ip() == addr.ip()
+                    || bound.ip().is_unspecified()
+                    || addr.ip().is_unspecified())
+        })
+    }
+
+    /// Find the entry of `sockets` for the socket which receives what's sent
+    /// to `addr`.
+    fn find<T: Clone>(sockets: &HashMap<SocketAddr, T>, addr: SocketAddr) -> Option<T> {
+        let addr = normalize(addr);
+        sockets
+            .get(&addr)
+            .or_else(|| sockets.get(&SocketAddr::new(unspecified(addr.ip()), addr.port())))
+            .cloned()
+    }
+}
+
+/// Remove the IPv6 flow info and scope id of `addr`, which aren't used to
+/// match addresses.
+fn normalize(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip(), addr.port())
+}
+
+fn unspecified(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    }
+}
+
+/// The address that a socket bound to `addr` sends from.
+fn source_addr(addr: SocketAddr) -> SocketAddr {
+    if !addr.ip().is_unspecified() {
+        return addr;
+    }
+    let ip: IpAddr = match addr.ip() {
+        IpAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+        IpAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+    };
+    SocketAddr::new(ip, addr.port())
+}
+
+/// An address reserved by a socket, which is released when this is dropped.
+struct Binding {
+    net: VirtualNetwork,
+    protocol: Protocol,
+    addr: SocketAddr,
+}
+
+impl Drop for Binding {
+    fn drop(&mut self) {
+        let mut net = self.net.lock();
+        net.bound.remove(&(self.protocol, self.addr));
+        match self.protocol {
+            Protocol::Tcp => drop(net.listeners.remove(&self.addr)),
+            Protocol::Udp => drop(net.receivers.remove(&self.addr)),
+        }
+    }
+}
+
+/// Wakers of the tasks waiting for a change of some state.
+#[derive(Default)]
+struct Wakers(Vec<Waker>);
+
+impl Wakers {
+    fn register(&mut self, waker: &Waker) {
+        if !self.0.iter().any(|w| w.will_wake(waker)) {
+            self.0.push(waker.clone());
+        }
+    }
+
+    fn wake(&mut self) {
+        for waker in self.0.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// One direction of a TCP connection.
+#[derive(Default)]
+struct Pipe(Mutex<PipeState>);
+
+#[derive(Default)]
+struct PipeState {
+    data: VecDeque<u8>,
+    /// The writing end was shut down or dropped: reads return the remaining
+    /// data and then the end of the stream.
+    write_closed: bool,
+    /// The reading end was shut down or dropped: writes fail.
+    read_closed: bool,
+    readers: Wakers,
+    writers: Wakers,
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.0.lock().unwrap()
+    }
+
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.data.is_empty() {
+            if state.write_closed || state.read_closed {
+                return Ok(0);
+            }
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(state.data.len());
+        for (dst, src) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *dst = src;
+        }
+        state.writers.wake();
+        Ok(n)
+    }
+
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock();
+        if !state.data.is_empty() || state.write_closed || state.read_closed {
+            return Poll::Ready(());
+        }
+        state.readers.register(cx.waker());
+        Poll::Pending
+    }
+
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.write_closed || state.read_closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let n = buf.len().min(TCP_BUFFER_SIZE - state.data.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        state.data.extend(&buf[..n]);
+        state.readers.wake();
+        Ok(n)
+    }
+
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock();
+        if state.data.len() < TCP_BUFFER_SIZE || state.write_closed || state.read_closed {
+            return Poll::Ready(());
+        }
+        state.writers.register(cx.waker());
+        Poll::Pending
+    }
+
+    fn close_write(&self) {
+        let mut state = self.lock();
+        state.write_closed = true;
+        state.readers.wake();
+        state.writers.wake();
+    }
+
+    fn close_read(&self) {
+        let mut state = self.lock();
+        state.read_closed = true;
+        state.data.clear();
+        state.readers.wake();
+        state.writers.wake();
+    }
+}
+
+/// A TCP connection of a [`VirtualNetwork`].
+///
+/// Both ends of the connection are shut down when this is dropped.
+pub struct TcpStream {
+    read: Arc<Pipe>,
+    write: Arc<Pipe>,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    /// The local address of the client side of a connection.
+    _binding: Option<Binding>,
+}
+
+impl TcpStream {
+    /// Create the client and server ends of a connection from `client` to
+    /// `server`.
+    fn pair(client: SocketAddr, server: SocketAddr) -> (TcpStream, TcpStream) {
+        let up = Arc::new(Pipe::default());
+        let down = Arc::new(Pipe::default());
+        let client_end = TcpStream {
+            read: down.clone(),
+            write: up.clone(),
+            local_addr: client,
+            peer_addr: server,
+            _binding: None,
+        };
+        let server_end = TcpStream {
+            read: up,
+            write: down,
+            local_addr: server,
+            peer_addr: client,
+            _binding: None,
+        };
+        (client_end, server_end)
+    }
+
+    /// The local address of this end of the connection.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The address of the other end of the connection.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Read the data available, without waiting.
+    ///
+    /// Returns 0 at the end of the stream, and fails with
+    /// [`io::ErrorKind::WouldBlock`] if no data is available yet.
+    pub fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read.try_read(buf)
+    }
+
+    /// Write as much of `buf` as can be buffered, without waiting.
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`] if the buffer is full, and
+    /// with [`io::ErrorKind::BrokenPipe`] if either end was shut down.
+    pub fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.write.try_write(buf)
+    }
+
+    /// Wait until [`TcpStream::try_read`] can return without blocking.
+    pub async fn readable(&self) {
+        poll_fn(|cx| self.read.poll_read_ready(cx)).await
+    }
+
+    /// Wait until [`TcpStream::try_write`] can return without blocking.
+    pub async fn writable(&self) {
+        poll_fn(|cx| self.write.poll_write_ready(cx)).await
+    }
+
+    /// Shut down the reading or writing half, or both halves, of this end of
+    /// the connection.
+    pub fn shutdown(&self, how: Shutdown) {
+        if let Shutdown::Read | Shutdown::Both = how {
+            self.read.close_read();
+        }
+        if let Shutdown::Write | Shutdown::Both = how {
+            self.write.close_write();
+        }
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        self.shutdown(Shutdown::Both);
+    }
+}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpStream")
+            .field("local_addr", &self.local_addr)
+            .field("peer_addr", &self.peer_addr)
+            .finish()
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            match self.try_read(buf.initialize_unfilled()) {
+                Ok(n) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.read.poll_read_ready(cx))
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            match self.try_write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.write.poll_write_ready(cx))
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.shutdown(Shutdown::Write);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The connections waiting to be accepted by a listener.
+struct AcceptQueue(Mutex<AcceptState>);
+
+struct AcceptState {
+    pending: VecDeque<TcpStream>,
+    backlog: usize,
+    acceptors: Wakers,
+}
+
+impl AcceptQueue {
+    fn lock(&self) -> MutexGuard<'_, AcceptState> {
+        self.0.lock().unwrap()
+    }
+}
+
+/// A TCP listener of a [`VirtualNetwork`].
+///
+/// The listener stops listening when this is dropped, and the connections
+/// which weren't accepted yet are closed.
+pub struct TcpListener {
+    queue: Arc<AcceptQueue>,
+    binding: Binding,
+}
+
+impl TcpListener {
+    /// The address this listener listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.binding.addr
+    }
+
+    /// Accept a connection, returning it along with the address of the
+    /// peer.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Poll for a connection to accept.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+        let mut state = self.queue.lock();
+        match state.pending.pop_front() {
+            Some(stream) => {
+                let peer = stream.peer_addr;
+                Poll::Ready(Ok((stream, peer)))
+            }
+            None => {
+                state.acceptors.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Set the number of connections which can wait to be accepted, beyond
+    /// which connecting to this listener is refused.
+    pub fn set_backlog(&self, backlog: usize) {
+        self.queue.lock().backlog = backlog;
+    }
+}
+
+/// A TCP socket of a guest before it listens or connects.
+pub(crate) struct TcpSocket {
+    net: VirtualNetwork,
+    binding: Option<Binding>,
+}
+
+impl TcpSocket {
+    pub(crate) fn new(net: VirtualNetwork) -> TcpSocket {
+        TcpSocket { net, binding: None }
+    }
+
+    pub(crate) fn bind(&mut self, addr: SocketAddr) -> io::Result<()> {
+        if self.binding.is_some() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let addr = self.net.lock().bind(Protocol::Tcp, addr)?;
+        self.binding = Some(Binding {
+            net: self.net.clone(),
+            protocol: Protocol::Tcp,
+            addr,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.binding {
+            Some(binding) => Ok(binding.addr),
+            None => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    pub(crate) fn listen(self, backlog: usize) -> io::Result<TcpListener> {
+        let Some(binding) = self.binding else {
+            return Err(io::ErrorKind::InvalidInput.into());
+        };
+        let queue = Arc::new(AcceptQueue(Mutex::new(AcceptState {
+            pending: VecDeque::new(),
+            backlog,
+            acceptors: Wakers::default(),
+        })));
+        self.net
+            .lock()
+            .listeners
+            .insert(binding.addr, queue.clone());
+        Ok(TcpListener { queue, binding })
+    }
+
+    pub(crate) fn connect(mut self, addr: SocketAddr) -> io::Result<TcpStream> {
+        if self.binding.is_none() {
+            self.bind(SocketAddr::new(unspecified(addr.ip()), 0))?;
+        }
+        let binding = self.binding.take().unwrap();
+
+        let queue = {
+            let net = self.net.lock();
+            Network::find(&net.listeners, addr).ok_or(io::ErrorKind::ConnectionRefused)?
+        };
+        let mut queue = queue.lock();
+        if queue.pending.len() >= queue.backlog {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        }
+        let (mut client, server) = TcpStream::pair(source_addr(binding.addr), normalize(addr));
+        client._binding = Some(binding);
+        queue.pending.push_back(server);
+        queue.acceptors.wake();
+        Ok(client)
+    }
+}
+
+/// The datagrams received by a UDP socket, along with their source address.
+struct DatagramQueue(Mutex<DatagramState>);
+
+#[derive(Default)]
+struct DatagramState {
+    datagrams: VecDeque<(Vec<u8>, SocketAddr)>,
+    receivers: Wakers,
+}
+
+impl DatagramQueue {
+    fn lock(&self) -> MutexGuard<'_, DatagramState> {
+        self.0.lock().unwrap()
+    }
+}
+
+/// A UDP socket of a [`VirtualNetwork`].
+///
+/// Like on a real network, datagrams sent to an address no socket is bound
+/// to, or to a socket whose queue of received datagrams is full, are dropped.
+pub struct UdpSocket {
+    net: VirtualNetwork,
+    queue: Arc<DatagramQueue>,
+    state: Mutex<UdpState>,
+}
+
+#[derive(Default)]
+struct UdpState {
+    binding: Option<Binding>,
+    peer: Option<SocketAddr>,
+}
+
+impl UdpSocket {
+    pub(crate) fn new(net: VirtualNetwork) -> UdpSocket {
+        UdpSocket {
+            net,
+            queue: Arc::new(DatagramQueue(Mutex::default())),
+            state: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, UdpState> {
+        self.state.lock().unwrap()
+    }
+
+    pub(crate) fn bind(&self, addr: SocketAddr) -> io::Result<()> {
+        let mut state = self.lock();
+        if state.binding.is_some() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        self.bind_locked(&mut state, addr)?;
+        Ok(())
+    }
+
+    /// Bind to `addr`, returning the address bound to.
+    fn bind_locked(&self, state: &mut UdpState, addr: SocketAddr) -> io::Result<SocketAddr> {
+        let addr = {
+            let mut net = self.net.lock();
+            let addr = net.bind(Protocol::Udp, addr)?;
+            net.receivers.insert(addr, self.queue.clone());
+            addr
+        };
+        state.binding = Some(Binding {
+            net: self.net.clone(),
+            protocol: Protocol::Udp,
+            addr,
+        });
+        Ok(addr)
+    }
+
+    /// The address this socket sends from to `addr`, binding it to a free
+    /// port first if it isn't bound yet.
+    fn source_addr(&self, state: &mut UdpState, addr: SocketAddr) -> io::Result<SocketAddr> {
+        let bound = match &state.binding {
+            Some(binding) => binding.addr,
+            None => self.bind_locked(state, SocketAddr::new(unspecified(addr.ip()), 0))?,
+        };
+        Ok(source_addr(bound))
+    }
+
+    /// Only send to, and receive from, `addr`.
+    pub(crate) fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        let mut state = self.lock();
+        self.source_addr(&mut state, addr)?;
+        state.peer = Some(normalize(addr));
+        Ok(())
+    }
+
+    pub(crate) fn disconnect(&self) {
+        self.lock().peer = None;
+    }
+
+    /// The address this socket is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let state = self.lock();
+        match (&state.binding, state.peer) {
+            (Some(binding), Some(_)) => Ok(source_addr(binding.addr)),
+            (Some(binding), None) => Ok(binding.addr),
+            (None, _) => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.lock()
+            .peer
+            .ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+
+    /// Receive a datagram, without waiting, returning its size and source
+    /// address.
+    ///
+    /// Datagrams larger than `buf` are truncated. Fails with
+    /// [`io::ErrorKind::WouldBlock`] if no datagram was received.
+    pub fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let Some((data, source)) = self.queue.lock().datagrams.pop_front() else {
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok((n, source))
+    }
+
+    /// Wait until a datagram was received.
+    pub async fn readable(&self) {
+        poll_fn(|cx| {
+            let mut queue = self.queue.lock();
+            if queue.datagrams.is_empty() {
+                queue.receivers.register(cx.waker());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+
+    /// Receive a datagram, returning its size and source address.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            self.readable().await;
+            match self.try_recv_from(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a datagram to `addr`.
+    ///
+    /// Sending never blocks, so this is the same as
+    /// [`UdpSocket::send_to`].
+    pub fn try_send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let source = self.source_addr(&mut self.lock(), addr)?;
+        let receiver = Network::find(&self.net.lock().receivers, addr);
+        if let Some(receiver) = receiver {
+            let mut receiver = receiver.lock();
+            if receiver.datagrams.len() < UDP_QUEUE_SIZE {
+                receiver.datagrams.push_back((buf.to_vec(), source));
+                receiver.receivers.wake();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Send a datagram to the address this socket is connected to.
+    pub(crate) fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        let peer = self.peer_addr()?;
+        self.try_send_to(buf, peer)
+    }
+
+    /// Send a datagram to `addr`.
+    pub async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.try_send_to(buf, addr)
+    }
+}
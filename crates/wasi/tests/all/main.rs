@@ -90,4 +90,8 @@ macro_rules! assert_test_exists {
 mod api;
 mod async_;
 mod preview1;
+mod quota;
+mod replay;
 mod sync;
+mod vfs;
+mod vnet;
@@ -0,0 +1,91 @@
+use super::vfs::run;
+use anyhow::Result;
+use test_programs_artifacts::PREVIEW2_FILE_READ_WRITE_COMPONENT;
+use wasmtime_wasi::vfs::MemoryDir;
+use wasmtime_wasi::{DirPerms, FilePerms, FsLimits, FsUsage, WasiCtxBuilder};
+
+async fn run_file_read_write(limits: FsLimits) -> Result<(MemoryDir, FsUsage, Result<()>)> {
+    let dir = MemoryDir::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir.clone(), "/", DirPerms::all(), FilePerms::all())
+        .fs_limits(limits)
+        .build();
+    let usage = wasi.fs_usage().clone();
+    let result = run(PREVIEW2_FILE_READ_WRITE_COMPONENT, wasi).await;
+    Ok((dir, usage, result))
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn fs_usage_is_counted() -> Result<()> {
+    let (_dir, usage, result) = run_file_read_write(FsLimits::default()).await?;
+    result?;
+
+    // The gap before the data, written at offset 5, counts as written too.
+    assert_eq!(
+        usage.bytes_written(),
+        "\0\0\0\0\0Hello, World!".len() as u64
+    );
+    assert!(usage.bytes_read() >= "\0\0\0\0\0Hello, World!".len() as u64);
+    assert_eq!(usage.files_created(), 1);
+    assert_eq!(usage.open_descriptors(), 0);
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn fs_limits_bytes_written() -> Result<()> {
+    let limits = FsLimits {
+        max_bytes_written: Some(15),
+        ..FsLimits::default()
+    };
+    let (dir, usage, result) = run_file_read_write(limits).await?;
+    assert!(result.is_err());
+
+    // The write which would have exceeded the limit didn't happen at all.
+    assert_eq!(usage.bytes_written(), "\0\0\0\0\0Hello, ".len() as u64);
+    assert_eq!(dir.read_file("test.txt")?, b"\0\0\0\0\0Hello, ");
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn fs_limits_bytes_written_sparse() -> Result<()> {
+    // "Hello, " alone fits in the limit, but not along with the five bytes
+    // before offset 5 which writing it grows the empty file by.
+    let limits = FsLimits {
+        max_bytes_written: Some(10),
+        ..FsLimits::default()
+    };
+    let (dir, usage, result) = run_file_read_write(limits).await?;
+    assert!(result.is_err());
+
+    assert_eq!(usage.bytes_written(), 0);
+    assert_eq!(dir.read_file("test.txt")?, b"");
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn fs_limits_files_created() -> Result<()> {
+    let limits = FsLimits {
+        max_files_created: Some(0),
+        ..FsLimits::default()
+    };
+    let (dir, usage, result) = run_file_read_write(limits).await?;
+    assert!(result.is_err());
+
+    assert_eq!(usage.files_created(), 0);
+    assert!(dir.read_file("test.txt").is_err());
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn fs_limits_open_descriptors() -> Result<()> {
+    let limits = FsLimits {
+        max_open_descriptors: Some(0),
+        ..FsLimits::default()
+    };
+    let (dir, usage, result) = run_file_read_write(limits).await?;
+    assert!(result.is_err());
+
+    assert_eq!(usage.open_descriptors(), 0);
+    assert!(dir.read_file("test.txt").is_err());
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use crate::vfs::run;
+use anyhow::Result;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use test_programs_artifacts::{CLI_FILE_READ_COMPONENT, CLI_STDIN_COMPONENT};
+use wasmtime_wasi::pipe::MemoryInputPipe;
+use wasmtime_wasi::vfs::MemoryDir;
+use wasmtime_wasi::{DirPerms, FilePerms, Trace, WasiCtxBuilder};
+
+/// A trace writer whose contents can be read back once recorded.
+#[derive(Clone, Default)]
+struct Recording(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Recording {
+    fn replay(&self) -> Result<Trace> {
+        let recorded = self.0.lock().unwrap().clone();
+        Ok(Trace::replay(io::Cursor::new(recorded))?)
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn replay_stdin() -> Result<()> {
+    let recording = Recording::default();
+    let wasi = WasiCtxBuilder::new()
+        .stdin(MemoryInputPipe::new("So rested he by the Tumtum tree"))
+        .trace(Trace::record(recording.clone())?)
+        .build();
+    run(CLI_STDIN_COMPONENT, wasi).await?;
+
+    // The guest asserts what it reads from stdin, which is now closed.
+    let wasi = WasiCtxBuilder::new().trace(recording.replay()?).build();
+    run(CLI_STDIN_COMPONENT, wasi).await?;
+
+    // Without the trace the guest fails.
+    let wasi = WasiCtxBuilder::new().build();
+    assert!(run(CLI_STDIN_COMPONENT, wasi).await.is_err());
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn replay_file_reads() -> Result<()> {
+    let dir = MemoryDir::new();
+    dir.insert_file("bar.txt", "And stood awhile in thought")?;
+    let recording = Recording::default();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir, "/", DirPerms::READ, FilePerms::READ)
+        .trace(Trace::record(recording.clone())?)
+        .build();
+    run(CLI_FILE_READ_COMPONENT, wasi).await?;
+
+    // The file must still exist, but its contents come from the trace.
+    let dir = MemoryDir::new();
+    dir.insert_file("bar.txt", "x".repeat(27))?;
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir.clone(), "/", DirPerms::READ, FilePerms::READ)
+        .trace(recording.replay()?)
+        .build();
+    run(CLI_FILE_READ_COMPONENT, wasi).await?;
+
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir, "/", DirPerms::READ, FilePerms::READ)
+        .build();
+    assert!(run(CLI_FILE_READ_COMPONENT, wasi).await.is_err());
+    Ok(())
+}
+
+#[test]
+fn replay_rejects_other_files() {
+    assert!(Trace::replay(io::Cursor::new(b"not a trace at all")).is_err());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn replay_divergence_traps() -> Result<()> {
+    let recording = Recording::default();
+    let wasi = WasiCtxBuilder::new()
+        .stdin(MemoryInputPipe::new("So rested he by the Tumtum tree"))
+        .trace(Trace::record(recording.clone())?)
+        .build();
+    run(CLI_STDIN_COMPONENT, wasi).await?;
+
+    // A different program makes different calls than were recorded.
+    let dir = MemoryDir::new();
+    dir.insert_file("bar.txt", "And stood awhile in thought")?;
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir, "/", DirPerms::READ, FilePerms::READ)
+        .trace(recording.replay()?)
+        .build();
+    let err = run(CLI_FILE_READ_COMPONENT, wasi).await.unwrap_err();
+    assert!(
+        format!("{err:?}").contains("WASI replay diverged"),
+        "{err:?}"
+    );
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn replay_malformed_trace_traps() -> Result<()> {
+    let recording = Recording::default();
+    Trace::record(recording.clone())?;
+    // An event claiming a payload far larger than the trace.
+    recording
+        .0
+        .lock()
+        .unwrap()
+        .extend([0, 0xff, 0xff, 0xff, 0xff]);
+    let wasi = WasiCtxBuilder::new().trace(recording.replay()?).build();
+    let err = run(CLI_STDIN_COMPONENT, wasi).await.unwrap_err();
+    assert!(
+        format!("{err:?}").contains("malformed WASI trace"),
+        "{err:?}"
+    );
+    Ok(())
+}
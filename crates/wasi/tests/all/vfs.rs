@@ -0,0 +1,246 @@
+use anyhow::{bail, Result};
+use std::io::{self, Write};
+use std::sync::Arc;
+use test_programs_artifacts::API_READ_ONLY_COMPONENT;
+use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime::Store;
+use wasmtime_wasi::bindings::filesystem::types::ErrorCode;
+use wasmtime_wasi::bindings::Command;
+use wasmtime_wasi::vfs::{MemoryDir, OpenOptions, Opened, OverlayDir, WasiDir};
+use wasmtime_wasi::{
+    add_to_linker_async, DirPerms, FilePerms, IoView, WasiCtx, WasiCtxBuilder, WasiView,
+};
+
+struct CommandCtx {
+    table: ResourceTable,
+    wasi: WasiCtx,
+}
+
+impl IoView for CommandCtx {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+}
+impl WasiView for CommandCtx {
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+pub(super) async fn run(path: &str, wasi: WasiCtx) -> Result<()> {
+    let engine = test_programs_artifacts::engine(|config| {
+        config.async_support(true);
+    });
+    let mut linker = Linker::new(&engine);
+    add_to_linker_async(&mut linker)?;
+
+    let table = ResourceTable::new();
+    let mut store = Store::new(&engine, CommandCtx { table, wasi });
+    let component = Component::from_file(&engine, path)?;
+    let command = Command::instantiate_async(&mut store, &component, &linker).await?;
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
+}
+
+fn read(dir: &dyn WasiDir, path: &str) -> io::Result<Vec<u8>> {
+    let options = OpenOptions {
+        read: true,
+        follow_symlinks: true,
+        ..OpenOptions::default()
+    };
+    let Opened::File(file) = dir.open_at(path, &options)? else {
+        return Err(io::Error::other("not a file"));
+    };
+    let mut contents = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        match file.read_at(&mut buf, contents.len() as u64)? {
+            0 => return Ok(contents),
+            n => contents.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+fn write(dir: &dyn WasiDir, path: &str, contents: &[u8]) -> io::Result<()> {
+    let options = OpenOptions {
+        write: true,
+        create: true,
+        truncate: true,
+        follow_symlinks: true,
+        ..OpenOptions::default()
+    };
+    let Opened::File(file) = dir.open_at(path, &options)? else {
+        return Err(io::Error::other("not a file"));
+    };
+    file.write_at(contents, 0)?;
+    Ok(())
+}
+
+fn names(dir: &dyn WasiDir) -> io::Result<Vec<String>> {
+    dir.read_dir()?
+        .into_iter()
+        .map(|entry| Ok(entry?.name))
+        .collect()
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vfs_memory_dir_read_only_perms() -> Result<()> {
+    let dir = MemoryDir::new();
+    dir.insert_file("bar.txt", "And stood awhile in thought")?;
+    dir.insert_dir("sub")?;
+
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir.clone(), "/", DirPerms::READ, FilePerms::READ)
+        .build();
+    run(API_READ_ONLY_COMPONENT, wasi).await?;
+
+    assert_eq!(dir.read_file("bar.txt")?, b"And stood awhile in thought");
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vfs_overlay_dir_read_only_perms() -> Result<()> {
+    let base = tempfile::tempdir()?;
+    std::fs::File::create(base.path().join("bar.txt"))?
+        .write_all(b"And stood awhile in thought")?;
+    std::fs::create_dir(base.path().join("sub"))?;
+    let base = cap_std::fs::Dir::open_ambient_dir(base.path(), cap_std::ambient_authority())?;
+
+    let upper = MemoryDir::new();
+    let overlay = OverlayDir::new(Arc::new(base), Arc::new(upper.clone()));
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(overlay, "/", DirPerms::READ, FilePerms::READ)
+        .build();
+    run(API_READ_ONLY_COMPONENT, wasi).await?;
+
+    assert!(names(&upper)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn vfs_memory_dir_into_read_only() -> Result<()> {
+    let dir = MemoryDir::new();
+    dir.insert_file("a.txt", "a")?;
+    let dir = dir.into_read_only();
+
+    for result in [
+        write(&dir, "a.txt", b"b"),
+        write(&dir, "b.txt", b"b"),
+        dir.create_dir_at("d"),
+        dir.unlink_file_at("a.txt"),
+    ] {
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            other => bail!("expected a permission error, got {other:?}"),
+        }
+    }
+    assert_eq!(read(&dir, "a.txt")?, b"a");
+    Ok(())
+}
+
+#[test]
+fn vfs_memory_dir_limits_file_size() -> Result<()> {
+    let dir = MemoryDir::new().with_max_file_size(16);
+    let options = OpenOptions {
+        write: true,
+        create: true,
+        follow_symlinks: true,
+        ..OpenOptions::default()
+    };
+    let Opened::File(file) = dir.open_at("a.txt", &options)? else {
+        bail!("not a file");
+    };
+
+    for result in [
+        file.write_at(b"x", 1 << 40),
+        file.write_at(b"x", u64::MAX),
+        file.write_at(&[0; 17], 0),
+        file.set_len(1 << 40).map(|()| 0),
+    ] {
+        match result {
+            Err(e) if is_file_too_large(&e) => {}
+            other => bail!("expected a file-too-large error, got {other:?}"),
+        }
+    }
+    assert_eq!(file.write_at(b"x", 15)?, 1);
+    assert_eq!(dir.read_file("a.txt")?.len(), 16);
+    Ok(())
+}
+
+fn is_file_too_large(e: &io::Error) -> bool {
+    matches!(
+        e.get_ref().and_then(|e| e.downcast_ref::<ErrorCode>()),
+        Some(ErrorCode::FileTooLarge)
+    )
+}
+
+#[test]
+fn vfs_memory_dir_rejects_escaping_paths() -> Result<()> {
+    let dir = MemoryDir::new();
+    dir.insert_file("d/a.txt", "a")?;
+
+    assert_eq!(read(&dir, "d/../d/./a.txt")?, b"a");
+    for path in ["../a.txt", "/d/a.txt", "d/../../a.txt"] {
+        match read(&dir, path) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            other => bail!("expected {path} to be rejected, got {other:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn vfs_overlay_dir_copies_up_and_hides_removed_entries() -> Result<()> {
+    let base = MemoryDir::new();
+    base.insert_file("a.txt", "base a")?;
+    base.insert_file("d/b.txt", "base b")?;
+    base.insert_file("d/c.txt", "base c")?;
+    let base = base.into_read_only();
+    let upper = MemoryDir::new();
+    let overlay = OverlayDir::new(Arc::new(base.clone()), Arc::new(upper.clone()));
+
+    // Reads see the base.
+    assert_eq!(read(&overlay, "d/b.txt")?, b"base b");
+    assert_eq!(names(&overlay)?, ["a.txt", "d"]);
+
+    // Writes go to the upper directory, copying parents.
+    write(&overlay, "d/b.txt", b"upper b")?;
+    assert_eq!(read(&overlay, "d/b.txt")?, b"upper b");
+    assert_eq!(upper.read_file("d/b.txt")?, b"upper b");
+    assert_eq!(base.read_file("d/b.txt")?, b"base b");
+    assert_eq!(names(&overlay)?, ["a.txt", "d"]);
+    let Opened::Dir(d) = overlay.open_at("d", &OpenOptions::default())? else {
+        bail!("expected a directory");
+    };
+    assert_eq!(names(&*d)?, ["b.txt", "c.txt"]);
+
+    // Removed entries of the base are hidden, and can be created again.
+    overlay.unlink_file_at("a.txt")?;
+    assert_eq!(
+        overlay.metadata_at("a.txt", true).unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+    assert_eq!(names(&overlay)?, ["d"]);
+    write(&overlay, "a.txt", b"new a")?;
+    assert_eq!(read(&overlay, "a.txt")?, b"new a");
+    assert_eq!(base.read_file("a.txt")?, b"base a");
+
+    // Renamed directories are copied up as a whole.
+    overlay.rename_at("d", &overlay, "e")?;
+    assert_eq!(names(&overlay)?, ["a.txt", "e"]);
+    assert_eq!(read(&overlay, "e/b.txt")?, b"upper b");
+    assert_eq!(read(&overlay, "e/c.txt")?, b"base c");
+    assert!(overlay.metadata_at("d/c.txt", true).is_err());
+
+    // Directories are only removed once empty in the merged view.
+    assert!(overlay.remove_dir_at("e").is_err());
+    overlay.unlink_file_at("e/b.txt")?;
+    overlay.unlink_file_at("e/c.txt")?;
+    overlay.remove_dir_at("e")?;
+    assert_eq!(names(&overlay)?, ["a.txt"]);
+    assert_eq!(names(&base)?, ["a.txt", "d"]);
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use crate::vfs::run;
+use anyhow::Result;
+use std::io;
+use std::net::{Shutdown, SocketAddr};
+use test_programs_artifacts::{
+    PREVIEW2_TCP_SAMPLE_APPLICATION_COMPONENT, PREVIEW2_UDP_SAMPLE_APPLICATION_COMPONENT,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use wasmtime_wasi::vnet::VirtualNetwork;
+use wasmtime_wasi::WasiCtxBuilder;
+
+fn addr(addr: &str) -> SocketAddr {
+    addr.parse().unwrap()
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vnet_tcp_sample_application() -> Result<()> {
+    let net = VirtualNetwork::new();
+    let wasi = WasiCtxBuilder::new()
+        .virtual_network(net.clone())
+        .inherit_network()
+        .build();
+    run(PREVIEW2_TCP_SAMPLE_APPLICATION_COMPONENT, wasi).await?;
+
+    // The guest's listeners are gone once it's done.
+    let err = net.connect_tcp(addr("127.0.0.1:49152")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vnet_udp_sample_application() -> Result<()> {
+    let wasi = WasiCtxBuilder::new()
+        .virtual_network(VirtualNetwork::new())
+        .inherit_network()
+        .build();
+    run(PREVIEW2_UDP_SAMPLE_APPLICATION_COMPONENT, wasi).await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vnet_socket_addr_check_applies() -> Result<()> {
+    let wasi = WasiCtxBuilder::new()
+        .virtual_network(VirtualNetwork::new())
+        .build();
+    assert!(run(PREVIEW2_TCP_SAMPLE_APPLICATION_COMPONENT, wasi)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn vnet_tcp() -> Result<()> {
+    let net = VirtualNetwork::new();
+    let listener = net.listen_tcp(addr("0.0.0.0:80"))?;
+    assert_eq!(listener.local_addr(), addr("0.0.0.0:80"));
+
+    // Listening on the unspecified address accepts any address of the family.
+    let mut client = net.connect_tcp(addr("10.1.2.3:80"))?;
+    let (mut server, peer) = listener.accept().await?;
+    assert_eq!(client.peer_addr(), addr("10.1.2.3:80"));
+    assert_eq!(server.local_addr(), addr("10.1.2.3:80"));
+    assert_eq!(client.local_addr(), peer);
+    assert!(peer.ip().is_loopback());
+
+    client.write_all(b"ping").await?;
+    client.shutdown(Shutdown::Write);
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).await?;
+    assert_eq!(received, b"ping");
+
+    server.write_all(b"pong").await?;
+    drop(server);
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).await?;
+    assert_eq!(received, b"pong");
+
+    let err = net.connect_tcp(addr("[::1]:80")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    let err = net.listen_tcp(addr("127.0.0.1:80")).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+    drop(listener);
+    let err = net.connect_tcp(addr("10.1.2.3:80")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    Ok(())
+}
+
+#[tokio::test]
+async fn vnet_tcp_backpressure() -> Result<()> {
+    let net = VirtualNetwork::new();
+    let listener = net.listen_tcp(addr("127.0.0.1:0"))?;
+    let mut client = net.connect_tcp(listener.local_addr())?;
+    let (mut server, _) = listener.accept().await?;
+
+    let data = vec![7; 1 << 20];
+    let writer = tokio::spawn(async move {
+        client.write_all(&data).await?;
+        client.shutdown(Shutdown::Write);
+        io::Result::Ok(())
+    });
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).await?;
+    writer.await??;
+    assert_eq!(received, vec![7; 1 << 20]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn vnet_udp() -> Result<()> {
+    let net = VirtualNetwork::new();
+    let server = net.bind_udp(addr("[::]:53"))?;
+    let client = net.bind_udp(addr("[::1]:0"))?;
+
+    client.send_to(b"query", addr("[fd00::1]:53")).await?;
+    let mut buf = [0; 3];
+    let (n, source) = server.recv_from(&mut buf).await?;
+    assert_eq!(&buf[..n], b"que");
+    assert_eq!(source, client.local_addr()?);
+
+    // Datagrams to addresses nothing is bound to are dropped.
+    server.send_to(b"lost", addr("[::1]:9")).await?;
+    server.send_to(b"answer", source).await?;
+    let mut buf = [0; 16];
+    let (n, source) = client.recv_from(&mut buf).await?;
+    assert_eq!(&buf[..n], b"answer");
+    assert_eq!(source, addr("[::1]:53"));
+
+    let err = client.try_recv_from(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    Ok(())
+}
@@ -5,6 +5,8 @@ use alloc::sync::Arc;
 use bitflags::Flags;
 use core::fmt;
 use core::str::FromStr;
+#[cfg(all(feature = "async", feature = "threads"))]
+use core::{future::Future, pin::Pin, time::Duration};
 use serde_derive::{Deserialize, Serialize};
 #[cfg(any(feature = "cache", feature = "cranelift", feature = "winch"))]
 use std::path::Path;
@@ -130,6 +132,8 @@ pub struct Config {
     target: Option<target_lexicon::Triple>,
     #[cfg(feature = "gc")]
     collector: Collector,
+    #[cfg(feature = "gc")]
+    pub(crate) gc_heap_sizing: GcHeapSizing,
     profiling_strategy: ProfilingStrategy,
     tunables: ConfigTunables,
 
@@ -164,6 +168,10 @@ pub struct Config {
     pub(crate) async_stack_size: usize,
     #[cfg(feature = "async")]
     pub(crate) async_stack_zeroing: bool,
+    #[cfg(all(feature = "async", feature = "threads"))]
+    pub(crate) async_atomic_wait: bool,
+    #[cfg(all(feature = "async", feature = "threads"))]
+    pub(crate) async_atomic_wait_timer: Option<Arc<AsyncAtomicWaitTimer>>,
     #[cfg(feature = "async")]
     pub(crate) stack_creator: Option<Arc<dyn RuntimeFiberStackCreator>>,
     pub(crate) async_support: bool,
@@ -177,6 +185,11 @@ pub struct Config {
     pub(crate) detect_host_feature: Option<fn(&str) -> Option<bool>>,
 }
 
+/// The timer configured with [`Config::async_atomic_wait_timer`].
+#[cfg(all(feature = "async", feature = "threads"))]
+pub(crate) type AsyncAtomicWaitTimer =
+    dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync;
+
 /// User-provided configuration for the compiler.
 #[cfg(any(feature = "cranelift", feature = "winch"))]
 #[derive(Debug, Clone)]
@@ -246,6 +259,8 @@ impl Config {
             target: None,
             #[cfg(feature = "gc")]
             collector: Collector::default(),
+            #[cfg(feature = "gc")]
+            gc_heap_sizing: GcHeapSizing::default(),
             #[cfg(feature = "cache")]
             cache_config: CacheConfig::new_cache_disabled(),
             profiling_strategy: ProfilingStrategy::None,
@@ -272,6 +287,10 @@ impl Config {
             async_stack_size: 2 << 20,
             #[cfg(feature = "async")]
             async_stack_zeroing: false,
+            #[cfg(all(feature = "async", feature = "threads"))]
+            async_atomic_wait: false,
+            #[cfg(all(feature = "async", feature = "threads"))]
+            async_atomic_wait_timer: None,
             #[cfg(feature = "async")]
             stack_creator: None,
             async_support: false,
@@ -799,6 +818,53 @@ impl Config {
         self
     }
 
+    /// Configures whether `memory.atomic.wait32` and `memory.atomic.wait64`
+    /// suspend the current future instead of blocking the current thread.
+    ///
+    /// By default a wasm thread waiting on a shared memory parks the OS thread
+    /// it's running on until it's notified or times out. When this option is
+    /// enabled, and wasm is executing within [`call_async`], the waiting wasm
+    /// instead yields back to the executor polling the future, which is woken
+    /// up again by a `memory.atomic.notify` on the same address (or once the
+    /// timeout elapses). This makes it possible to multiplex many waiting wasm
+    /// threads, each in its own [`Store`](crate::Store), on a small number of
+    /// host threads.
+    ///
+    /// Waits in stores which aren't being driven through [`call_async`] keep
+    /// blocking the current thread, as do waits with a timeout unless a timer
+    /// is configured with [`Config::async_atomic_wait_timer`]. This option
+    /// requires [`Config::async_support`] to be enabled to have any effect.
+    ///
+    /// This option defaults to `false`.
+    ///
+    /// [`call_async`]: crate::TypedFunc::call_async
+    #[cfg(all(feature = "async", feature = "threads"))]
+    pub fn async_atomic_wait(&mut self, enable: bool) -> &mut Self {
+        self.async_atomic_wait = enable;
+        self
+    }
+
+    /// Configures the timer used to time out `memory.atomic.wait32` and
+    /// `memory.atomic.wait64` when they suspend the current future, as
+    /// configured with [`Config::async_atomic_wait`].
+    ///
+    /// The `sleep` function returns a future which resolves once the given
+    /// duration has elapsed, and is called once for every wait with a timeout.
+    /// It's polled by the executor polling the waiting wasm, so it's typically
+    /// that executor's own timer, such as `tokio::time::sleep`. The future is
+    /// dropped as soon as the wait completes.
+    ///
+    /// By default there's no timer, and waits with a timeout block the current
+    /// thread even when [`Config::async_atomic_wait`] is enabled.
+    #[cfg(all(feature = "async", feature = "threads"))]
+    pub fn async_atomic_wait_timer(
+        &mut self,
+        sleep: impl Fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.async_atomic_wait_timer = Some(Arc::new(sleep));
+        self
+    }
+
     fn wasm_feature(&mut self, flag: WasmFeatures, enable: bool) -> &mut Self {
         self.enabled_features.set(flag, enable);
         self.disabled_features.set(flag, !enable);
@@ -1180,6 +1246,75 @@ impl Config {
         self
     }
 
+    /// Configures the initial capacity, in bytes, of each store's GC heap.
+    ///
+    /// A store's GC heap is created lazily, the first time that something in
+    /// the store allocates a GC object.
+    ///
+    /// The default value for this is 512KiB.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_initial_size(&mut self, bytes: usize) -> &mut Self {
+        self.gc_heap_sizing.initial_size = bytes;
+        self
+    }
+
+    /// Configures the maximum capacity, in bytes, that each store's GC heap may
+    /// grow to.
+    ///
+    /// This much virtual memory is reserved up front for each GC heap, so that
+    /// growing a heap never needs to move it, but only the heap's current
+    /// capacity is ever used for allocation. Each growth of a GC heap is
+    /// additionally subject to the store's
+    /// [`ResourceLimiter::gc_heap_growing`](crate::ResourceLimiter::gc_heap_growing)
+    /// hook, if any.
+    ///
+    /// Only the deferred reference-counting collector supports growing its
+    /// heap; the null collector's heap always keeps its initial capacity.
+    ///
+    /// The default value for this is the same as the
+    /// [initial size](Config::gc_heap_initial_size), meaning that GC heaps do
+    /// not grow by default.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_maximum_size(&mut self, bytes: usize) -> &mut Self {
+        self.gc_heap_sizing.maximum_size = Some(bytes);
+        self
+    }
+
+    /// Configures the factor by which a GC heap's capacity is multiplied when
+    /// it grows.
+    ///
+    /// Regardless of this factor, a heap always grows by at least enough to
+    /// satisfy the allocation that triggered the growth, up to the
+    /// [maximum size](Config::gc_heap_maximum_size). After a collection, a
+    /// heap is grown whenever it would otherwise be more than `1 / factor`
+    /// full once the pending allocation is satisfied.
+    ///
+    /// This must be a finite value that is at least `1.0`. The default value
+    /// for this is `2.0`.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_growth_factor(&mut self, factor: f64) -> &mut Self {
+        self.gc_heap_sizing.growth_factor = factor;
+        self
+    }
+
+    /// Configures the GC heap capacity, in bytes, below which a heap grows
+    /// without first collecting garbage.
+    ///
+    /// When an allocation fails in a GC heap whose capacity is smaller than
+    /// this threshold, the heap is grown immediately, which avoids repeatedly
+    /// collecting small heaps that are still warming up. Once a heap's
+    /// capacity reaches this threshold, a failed allocation always performs a
+    /// collection first and only grows the heap if that didn't free enough
+    /// space.
+    ///
+    /// The default value for this is `0`, meaning that a collection is always
+    /// attempted before growing.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_gc_before_grow_threshold(&mut self, bytes: usize) -> &mut Self {
+        self.gc_heap_sizing.gc_before_grow_threshold = bytes;
+        self
+    }
+
     /// Creates a default profiler based on the profiling strategy chosen.
     ///
     /// Profiler creation calls the type's default initializer where the purpose is
@@ -2191,6 +2326,16 @@ impl Config {
         if self.max_wasm_stack == 0 {
             bail!("max_wasm_stack size cannot be zero");
         }
+        #[cfg(feature = "gc")]
+        {
+            let sizing = &self.gc_heap_sizing;
+            if sizing.maximum_size() < sizing.initial_size {
+                bail!("gc_heap_maximum_size cannot be less than gc_heap_initial_size");
+            }
+            if !(sizing.growth_factor.is_finite() && sizing.growth_factor >= 1.0) {
+                bail!("gc_heap_growth_factor must be a finite number that is at least 1.0");
+            }
+        }
         #[cfg(not(feature = "wmemcheck"))]
         if self.wmemcheck {
             bail!("wmemcheck (memory checker) was requested but is not enabled in this build");
@@ -2308,14 +2453,16 @@ impl Config {
             Ok(Some(match self.collector.try_not_auto()? {
                 #[cfg(feature = "gc-drc")]
                 Collector::DeferredReferenceCounting => {
-                    Arc::new(crate::runtime::vm::DrcCollector::default()) as Arc<dyn GcRuntime>
+                    Arc::new(crate::runtime::vm::DrcCollector::new(&self.gc_heap_sizing))
+                        as Arc<dyn GcRuntime>
                 }
                 #[cfg(not(feature = "gc-drc"))]
                 Collector::DeferredReferenceCounting => unreachable!(),
 
                 #[cfg(feature = "gc-null")]
                 Collector::Null => {
-                    Arc::new(crate::runtime::vm::NullCollector::default()) as Arc<dyn GcRuntime>
+                    Arc::new(crate::runtime::vm::NullCollector::new(&self.gc_heap_sizing))
+                        as Arc<dyn GcRuntime>
                 }
                 #[cfg(not(feature = "gc-null"))]
                 Collector::Null => unreachable!(),
@@ -2826,6 +2973,46 @@ impl Collector {
     }
 }
 
+/// The sizing policy for stores' GC heaps.
+///
+/// See [`Config::gc_heap_initial_size`] and friends.
+#[cfg(feature = "gc")]
+#[derive(Clone, Debug)]
+pub(crate) struct GcHeapSizing {
+    pub initial_size: usize,
+    pub maximum_size: Option<usize>,
+    pub growth_factor: f64,
+    pub gc_before_grow_threshold: usize,
+}
+
+#[cfg(feature = "gc")]
+impl GcHeapSizing {
+    /// The default initial GC heap size: 512KiB.
+    #[cfg(not(miri))]
+    const DEFAULT_INITIAL_SIZE: usize = 1 << 19;
+
+    /// The default initial GC heap size for miri: 64KiB.
+    #[cfg(miri)]
+    const DEFAULT_INITIAL_SIZE: usize = 1 << 16;
+
+    /// The maximum size that GC heaps may grow to.
+    pub fn maximum_size(&self) -> usize {
+        self.maximum_size.unwrap_or(self.initial_size)
+    }
+}
+
+#[cfg(feature = "gc")]
+impl Default for GcHeapSizing {
+    fn default() -> Self {
+        GcHeapSizing {
+            initial_size: Self::DEFAULT_INITIAL_SIZE,
+            maximum_size: None,
+            growth_factor: 2.0,
+            gc_before_grow_threshold: 0,
+        }
+    }
+}
+
 /// Possible optimization levels for the Cranelift codegen backend.
 #[non_exhaustive]
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
@@ -726,6 +726,16 @@ impl Engine {
         self.inner.epoch.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Returns metrics about the resources in use from this engine's pooling
+    /// instance allocator, or `None` if this engine doesn't use the pooling
+    /// allocator.
+    ///
+    /// See [`InstanceAllocationStrategy::Pooling`](crate::InstanceAllocationStrategy::Pooling).
+    #[cfg(feature = "pooling-allocator")]
+    pub fn pooling_allocator_metrics(&self) -> Option<crate::PoolingAllocatorMetrics> {
+        crate::PoolingAllocatorMetrics::new(self)
+    }
+
     /// Returns a [`std::hash::Hash`] that can be used to check precompiled WebAssembly compatibility.
     ///
     /// The outputs of [`Engine::precompile_module`] and [`Engine::precompile_component`]
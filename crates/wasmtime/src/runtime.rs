@@ -92,6 +92,11 @@ pub(crate) use uninhabited::*;
 #[cfg(feature = "pooling-allocator")]
 pub use vm::PoolConcurrencyLimitError;
 
+#[cfg(feature = "pooling-allocator")]
+mod pooling_metrics;
+#[cfg(feature = "pooling-allocator")]
+pub use pooling_metrics::PoolingAllocatorMetrics;
+
 #[cfg(feature = "profiling")]
 mod profiling;
 #[cfg(feature = "profiling")]
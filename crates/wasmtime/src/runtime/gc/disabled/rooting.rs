@@ -227,3 +227,50 @@ impl<T: GcRef> RootedGcRefImpl<T> for ManuallyRooted<T> {
         match self.inner {}
     }
 }
+
+/// This type has been disabled because the `gc` cargo feature was not enabled
+/// at compile time.
+pub struct WeakRooted<T: GcRef> {
+    pub(crate) inner: Uninhabited,
+    _phantom: marker::PhantomData<T>,
+}
+
+impl<T: GcRef> Clone for WeakRooted<T> {
+    fn clone(&self) -> Self {
+        match self.inner {}
+    }
+}
+
+impl<T: GcRef> Copy for WeakRooted<T> {}
+
+impl<T: GcRef> Debug for WeakRooted<T> {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inner {}
+    }
+}
+
+impl<T: GcRef> WeakRooted<T> {
+    pub fn new(_store: impl AsContextMut, root: &impl RootedGcRef<T>) -> Result<Self> {
+        root.assert_unreachable()
+    }
+
+    pub fn with_finalization_token(
+        _store: impl AsContextMut,
+        root: &impl RootedGcRef<T>,
+        _token: u64,
+    ) -> Result<Self> {
+        root.assert_unreachable()
+    }
+
+    pub fn upgrade(&self, _store: impl AsContextMut) -> Option<Rooted<T>> {
+        match self.inner {}
+    }
+
+    pub fn is_reclaimed(&self, _store: impl AsContextMut) -> bool {
+        match self.inner {}
+    }
+
+    pub fn unroot(self, _store: impl AsContextMut) {
+        match self.inner {}
+    }
+}
@@ -5,14 +5,18 @@ mod anyref;
 mod arrayref;
 mod eqref;
 mod externref;
+mod heap_snapshot;
 mod i31;
 mod rooting;
+mod stats;
 mod structref;
 
 pub use anyref::*;
 pub use arrayref::*;
 pub use eqref::*;
 pub use externref::*;
+pub use heap_snapshot::*;
 pub use i31::*;
 pub use rooting::*;
+pub use stats::*;
 pub use structref::*;
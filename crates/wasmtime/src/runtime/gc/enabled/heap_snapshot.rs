@@ -0,0 +1,439 @@
+//! Snapshots of the objects in a store's GC heap.
+
+use crate::hash_map::HashMap;
+use crate::prelude::*;
+use crate::runtime::vm::VMGcRef;
+use crate::store::StoreOpaque;
+use core::fmt::Write;
+use wasmtime_environ::{VMGcKind, WasmCompositeInnerType, WasmStorageType};
+
+/// Where a GC root that is part of a [`GcHeapSnapshot`] comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GcRootKind {
+    /// A GC reference held in a live Wasm stack frame.
+    WasmStack,
+    /// A GC reference held in a Wasm global or table.
+    GlobalsAndTables,
+    /// A GC reference held by the host through a [`Rooted`][crate::Rooted] or
+    /// [`ManuallyRooted`][crate::ManuallyRooted].
+    Host,
+}
+
+impl GcRootKind {
+    fn name(&self) -> &'static str {
+        match self {
+            GcRootKind::WasmStack => "(Wasm stack roots)",
+            GcRootKind::GlobalsAndTables => "(Wasm global and table roots)",
+            GcRootKind::Host => "(host roots)",
+        }
+    }
+}
+
+/// The kind of a GC object in a [`GcHeapSnapshot`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GcObjectKind {
+    /// A Wasm `struct`.
+    Struct,
+    /// A Wasm `array`.
+    Array,
+    /// An `externref` whose host data lives outside of the GC heap.
+    ExternRef,
+    /// Any other kind of `anyref` object.
+    AnyRef,
+}
+
+/// A single object within a [`GcHeapSnapshot`].
+#[derive(Clone, Debug)]
+pub struct GcHeapSnapshotObject {
+    id: u32,
+    kind: GcObjectKind,
+    type_name: String,
+    size: usize,
+    edges: Vec<(GcHeapSnapshotEdge, usize)>,
+}
+
+/// The name of a reference from one object to another in a
+/// [`GcHeapSnapshot`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GcHeapSnapshotEdge {
+    /// The reference is stored in the struct field with the given index.
+    Field(u32),
+    /// The reference is stored in the array element with the given index.
+    Element(u32),
+}
+
+impl GcHeapSnapshotObject {
+    /// A unique identifier for this object.
+    ///
+    /// This is derived from the object's location in the GC heap, so it will
+    /// identify the same object across multiple snapshots of the same store as
+    /// long as the object is not reclaimed in between.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// What kind of object this is.
+    pub fn kind(&self) -> GcObjectKind {
+        self.kind
+    }
+
+    /// A textual representation of this object's type, such as `(struct (field
+    /// i32))`.
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// The size of this object in the GC heap, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The objects that this object references.
+    ///
+    /// Each reference is returned along with the index of the referenced
+    /// object in [`GcHeapSnapshot::objects`].
+    pub fn edges(&self) -> &[(GcHeapSnapshotEdge, usize)] {
+        &self.edges
+    }
+}
+
+/// A snapshot of the live objects inside a store's GC heap.
+///
+/// Snapshots are created with [`Store::gc_heap_snapshot`][crate::Store::gc_heap_snapshot]
+/// and contain every GC object reachable from the store's roots, along with
+/// each object's type, size, and outgoing references. Unreachable objects that
+/// have not been collected yet are not included.
+///
+/// A snapshot can be inspected programmatically or serialized into the
+/// `.heapsnapshot` format used by V8 with
+/// [`GcHeapSnapshot::to_v8_heap_snapshot`], which can be loaded by existing
+/// tools such as the "Memory" tab of Chrome's developer tools.
+#[derive(Clone, Debug, Default)]
+pub struct GcHeapSnapshot {
+    objects: Vec<GcHeapSnapshotObject>,
+    roots: Vec<(GcRootKind, usize)>,
+}
+
+impl GcHeapSnapshot {
+    pub(crate) fn new(store: &mut StoreOpaque) -> Result<GcHeapSnapshot> {
+        let mut snapshot = GcHeapSnapshot::default();
+
+        // If the GC heap hasn't been initialized, there is nothing to snapshot.
+        if store.gc_store().is_err() {
+            return Ok(snapshot);
+        }
+
+        let roots = store.gc_roots_by_kind();
+        let engine = store.engine().clone();
+        let gc_store = store.unwrap_gc_store_mut();
+
+        let mut index_of = HashMap::<u32, usize>::new();
+        let mut worklist = Vec::new();
+
+        for (kind, gc_ref) in roots {
+            if gc_ref.is_i31() {
+                continue;
+            }
+            let index = snapshot.intern(&mut index_of, &mut worklist, &gc_ref);
+            snapshot.roots.push((kind, index));
+        }
+
+        while let Some(gc_ref) = worklist.pop() {
+            let header = gc_store.header(&gc_ref);
+            let kind = header.kind();
+            let ty_index = header.ty();
+            let size = gc_store.gc_heap.object_size(&gc_ref);
+
+            let registry = engine.signatures();
+            let (kind, type_name, edges) = match ty_index.and_then(|i| registry.borrow(i)) {
+                Some(ty) => {
+                    let layout = registry
+                        .layout(ty_index.unwrap())
+                        .expect("GC types should have a GC layout");
+                    let mut edges = Vec::new();
+                    match &ty.composite_type.inner {
+                        WasmCompositeInnerType::Struct(s) => {
+                            let layout = layout.unwrap_struct();
+                            let data = gc_store.gc_heap.gc_object_data(&gc_ref);
+                            for (i, (field, offset)) in
+                                s.fields.iter().zip(layout.fields.iter()).enumerate()
+                            {
+                                if is_gc_ref(&field.element_type) {
+                                    let raw = data.read_u32(*offset);
+                                    edges.push((GcHeapSnapshotEdge::Field(i as u32), raw));
+                                }
+                            }
+                            (GcObjectKind::Struct, ty.composite_type.to_string(), edges)
+                        }
+                        WasmCompositeInnerType::Array(a) => {
+                            let layout = layout.unwrap_array();
+                            if is_gc_ref(&a.0.element_type) {
+                                let len =
+                                    gc_store.gc_heap.array_len(gc_ref.as_arrayref_unchecked());
+                                let data = gc_store.gc_heap.gc_object_data(&gc_ref);
+                                for i in 0..len {
+                                    let raw = data.read_u32(layout.elem_offset(i));
+                                    edges.push((GcHeapSnapshotEdge::Element(i), raw));
+                                }
+                            }
+                            (GcObjectKind::Array, ty.composite_type.to_string(), edges)
+                        }
+                        WasmCompositeInnerType::Func(_) | WasmCompositeInnerType::Cont(_) => {
+                            unreachable!("only structs and arrays are allocated in the GC heap")
+                        }
+                    }
+                }
+                None if kind.matches(VMGcKind::ExternRef) => {
+                    (GcObjectKind::ExternRef, "externref".to_string(), Vec::new())
+                }
+                None => (GcObjectKind::AnyRef, "anyref".to_string(), Vec::new()),
+            };
+
+            let edges = edges
+                .into_iter()
+                .filter_map(|(edge, raw)| {
+                    let target = VMGcRef::from_raw_u32(raw)?;
+                    if target.is_i31() {
+                        return None;
+                    }
+                    Some((edge, snapshot.intern(&mut index_of, &mut worklist, &target)))
+                })
+                .collect();
+
+            let object = &mut snapshot.objects[index_of[&gc_ref.as_raw_u32()]];
+            object.kind = kind;
+            object.type_name = type_name;
+            object.size = size;
+            object.edges = edges;
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Get the index of the given object, adding it to this snapshot and to
+    /// the `worklist` of objects to visit if we haven't seen it before.
+    fn intern(
+        &mut self,
+        index_of: &mut HashMap<u32, usize>,
+        worklist: &mut Vec<VMGcRef>,
+        gc_ref: &VMGcRef,
+    ) -> usize {
+        let raw = gc_ref.as_raw_u32();
+        *index_of.entry(raw).or_insert_with(|| {
+            self.objects.push(GcHeapSnapshotObject {
+                id: raw,
+                kind: GcObjectKind::AnyRef,
+                type_name: String::new(),
+                size: 0,
+                edges: Vec::new(),
+            });
+            worklist.push(gc_ref.unchecked_copy());
+            self.objects.len() - 1
+        })
+    }
+
+    /// All of the objects in this snapshot.
+    pub fn objects(&self) -> &[GcHeapSnapshotObject] {
+        &self.objects
+    }
+
+    /// The GC roots of this snapshot.
+    ///
+    /// Each root is returned along with the index of the rooted object in
+    /// [`GcHeapSnapshot::objects`]. An object may be rooted multiple times.
+    pub fn roots(&self) -> &[(GcRootKind, usize)] {
+        &self.roots
+    }
+
+    /// The total size of all objects in this snapshot, in bytes.
+    pub fn total_size(&self) -> usize {
+        self.objects.iter().map(|o| o.size).sum()
+    }
+
+    /// Serialize this snapshot in V8's `.heapsnapshot` JSON format.
+    pub fn to_v8_heap_snapshot(&self) -> String {
+        // The V8 format is a flat encoding of the graph: every node is a
+        // sequence of `NODE_FIELDS.len()` numbers in the `nodes` array, every
+        // edge is three numbers in the `edges` array, and all names are
+        // indices into the `strings` array. Edges belong to nodes in order,
+        // based on each node's `edge_count`, and refer to their target node by
+        // its offset in the `nodes` array.
+        //
+        // The first node is the synthetic root of the graph. It references
+        // one synthetic node per kind of GC root, which in turn reference the
+        // actual rooted objects.
+        const NODE_FIELDS: usize = 7;
+        const NODE_HIDDEN: u32 = 0;
+        const NODE_ARRAY: u32 = 1;
+        const NODE_OBJECT: u32 = 3;
+        const NODE_NATIVE: u32 = 8;
+        const NODE_SYNTHETIC: u32 = 9;
+        const EDGE_ELEMENT: u32 = 1;
+        const EDGE_PROPERTY: u32 = 2;
+
+        let mut strings = Vec::new();
+        let mut string_ids = HashMap::<String, usize>::new();
+        let mut string_id = |s: String| {
+            *string_ids.entry(s.clone()).or_insert_with(|| {
+                strings.push(s);
+                strings.len() - 1
+            })
+        };
+
+        let root_kinds = [
+            GcRootKind::WasmStack,
+            GcRootKind::GlobalsAndTables,
+            GcRootKind::Host,
+        ];
+        let first_object = 1 + root_kinds.len();
+
+        // Synthetic nodes get small even ids while objects' ids are derived
+        // from their (aligned, non-zero) heap index and made odd, so the two
+        // never collide.
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        nodes.extend([
+            NODE_SYNTHETIC,
+            string_id(String::new()) as u32,
+            0,
+            0,
+            root_kinds.len() as u32,
+            0,
+            0,
+        ]);
+        for (i, kind) in root_kinds.iter().enumerate() {
+            edges.extend([EDGE_ELEMENT, i as u32 + 1, ((1 + i) * NODE_FIELDS) as u32]);
+            let roots = self.roots.iter().filter(|(k, _)| k == kind);
+            nodes.extend([
+                NODE_SYNTHETIC,
+                string_id(kind.name().to_string()) as u32,
+                2 * (i as u32 + 1),
+                0,
+                roots.clone().count() as u32,
+                0,
+                0,
+            ]);
+            for (j, (_, object)) in roots.enumerate() {
+                edges.extend([
+                    EDGE_ELEMENT,
+                    j as u32,
+                    ((first_object + object) * NODE_FIELDS) as u32,
+                ]);
+            }
+        }
+        for object in self.objects.iter() {
+            let ty = match object.kind {
+                GcObjectKind::Struct => NODE_OBJECT,
+                GcObjectKind::Array => NODE_ARRAY,
+                GcObjectKind::ExternRef => NODE_NATIVE,
+                GcObjectKind::AnyRef => NODE_HIDDEN,
+            };
+            nodes.extend([
+                ty,
+                string_id(object.type_name.clone()) as u32,
+                object.id | 1,
+                object.size as u32,
+                object.edges.len() as u32,
+                0,
+                0,
+            ]);
+            for (edge, target) in object.edges.iter() {
+                let (ty, name) = match edge {
+                    GcHeapSnapshotEdge::Field(i) => {
+                        (EDGE_PROPERTY, string_id(format!("field {i}")) as u32)
+                    }
+                    GcHeapSnapshotEdge::Element(i) => (EDGE_ELEMENT, *i),
+                };
+                edges.extend([ty, name, ((first_object + target) * NODE_FIELDS) as u32]);
+            }
+        }
+
+        let mut json = String::new();
+        json.push_str(concat!(
+            r#"{"snapshot":{"meta":{"#,
+            r#""node_fields":["type","name","id","self_size","edge_count","trace_node_id","detachedness"],"#,
+            r#""node_types":[["hidden","array","string","object","code","closure","regexp","number","native","synthetic","concatenated string","sliced string","symbol","bigint","object shape"],"string","number","number","number","number","number"],"#,
+            r#""edge_fields":["type","name_or_index","to_node"],"#,
+            r#""edge_types":[["context","element","property","internal","hidden","shortcut","weak"],"string_or_number","node"],"#,
+            r#""trace_function_info_fields":["function_id","name","script_name","script_id","line","column"],"#,
+            r#""trace_node_fields":["id","function_info_index","count","size","children"],"#,
+            r#""sample_fields":["timestamp_us","last_assigned_id"],"#,
+            r#""location_fields":["object_index","script_id","line","column"]},"#,
+        ));
+        write!(
+            json,
+            r#""node_count":{},"edge_count":{},"trace_function_count":0}},"#,
+            nodes.len() / NODE_FIELDS,
+            edges.len() / 3,
+        )
+        .unwrap();
+        write_json_numbers(&mut json, "nodes", &nodes);
+        json.push(',');
+        write_json_numbers(&mut json, "edges", &edges);
+        json.push_str(
+            r#","trace_function_infos":[],"trace_tree":[],"samples":[],"locations":[],"strings":["#,
+        );
+        for (i, s) in strings.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_string(&mut json, s);
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Is a field of the given storage type represented as a `VMGcRef`?
+fn is_gc_ref(ty: &WasmStorageType) -> bool {
+    match ty {
+        WasmStorageType::Val(v) => v.is_vmgcref_type(),
+        WasmStorageType::I8 | WasmStorageType::I16 => false,
+    }
+}
+
+fn write_json_numbers(json: &mut String, name: &str, numbers: &[u32]) {
+    write!(json, r#""{name}":["#).unwrap();
+    for (i, n) in numbers.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(json, "{n}").unwrap();
+    }
+    json.push(']');
+}
+
+fn write_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_snapshot_is_valid_json_shape() {
+        let json = GcHeapSnapshot::default().to_v8_heap_snapshot();
+        assert!(json.starts_with(r#"{"snapshot":{"meta":"#));
+        assert!(json.contains(r#""node_count":4,"edge_count":3,"#));
+        assert!(json.ends_with(
+            r#""strings":["","(Wasm stack roots)","(Wasm global and table roots)","(host roots)"]}"#
+        ));
+    }
+
+    #[test]
+    fn json_string_escaping() {
+        let mut json = String::new();
+        write_json_string(&mut json, "a\"b\\c\n");
+        assert_eq!(json, r#""a\"b\\c\u000a""#);
+    }
+}
@@ -102,6 +102,7 @@
 //! can. However, if you really must, consider also using an `AutoAssertNoGc`
 //! across the block of code that is manipulating raw GC references.
 
+use crate::hash_map::HashMap;
 use crate::runtime::vm::{GcRootsList, GcStore, VMGcRef};
 use crate::vm::VMStore;
 use crate::{prelude::*, ValRaw};
@@ -369,6 +370,32 @@ pub(crate) struct RootSet {
     /// Generation counter for entries to prevent ABA bugs with `RootScope` and
     /// `Rooted<T>`.
     lifo_generation: u32,
+
+    /// Weak references to GC objects, for use with `WeakRooted<T>`.
+    ///
+    /// These are *not* traced as GC roots. Entries are removed once their
+    /// referent is reclaimed, or when the `WeakRooted<T>` is unrooted.
+    weak_refs: Slab<WeakRef>,
+
+    /// The `weak_refs` entries for each weak referent, so that clearing the
+    /// weak references to reclaimed objects doesn't need to scan all of
+    /// `weak_refs`.
+    weak_refs_by_target: HashMap<VMGcRef, Vec<SlabId>>,
+
+    /// Generation counter for weak references, to prevent ABA bugs when a
+    /// `weak_refs` entry is reused after its referent was reclaimed.
+    weak_generation: u32,
+
+    /// The finalization tokens of weak referents that have been reclaimed, but
+    /// which have not been passed to the store's finalization callback yet.
+    pending_finalizations: Vec<u64>,
+}
+
+#[derive(Debug)]
+struct WeakRef {
+    generation: u32,
+    gc_ref: VMGcRef,
+    finalization_token: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -467,6 +494,94 @@ impl RootSet {
             index,
         }
     }
+
+    /// Add a weak reference to `gc_ref`.
+    ///
+    /// Callers are responsible for having the GC heap watch `gc_ref` for
+    /// reclamation, so that `process_reclaimed_weak_refs` can clear this weak
+    /// reference when its referent is deallocated.
+    fn push_weak_ref(&mut self, gc_ref: VMGcRef, finalization_token: Option<u64>) -> (SlabId, u32) {
+        self.weak_generation = self.weak_generation.wrapping_add(1);
+        let generation = self.weak_generation;
+        let target = gc_ref.unchecked_copy();
+        let id = self.weak_refs.alloc(WeakRef {
+            generation,
+            gc_ref,
+            finalization_token,
+        });
+        self.weak_refs_by_target.entry(target).or_default().push(id);
+        (id, generation)
+    }
+
+    /// Remove a weak reference, if it has not already been removed.
+    ///
+    /// Returns the referent if this was its last weak reference, in which case
+    /// callers are responsible for having the GC heap stop watching it.
+    fn remove_weak_ref(&mut self, id: SlabId, generation: u32) -> Option<VMGcRef> {
+        self.get_weak_ref(id, generation)?;
+        let weak_ref = self.weak_refs.dealloc(id);
+        let ids = self
+            .weak_refs_by_target
+            .get_mut(&weak_ref.gc_ref)
+            .expect("weak referents are indexed");
+        ids.retain(|i| *i != id);
+        if !ids.is_empty() {
+            return None;
+        }
+        self.weak_refs_by_target.remove(&weak_ref.gc_ref);
+        Some(weak_ref.gc_ref)
+    }
+
+    /// Get the referent of a weak reference, if it has not been reclaimed.
+    ///
+    /// Callers must call `process_reclaimed_weak_refs` first, otherwise this
+    /// may return a reference to an object that has since been deallocated.
+    fn get_weak_ref(&self, id: SlabId, generation: u32) -> Option<&VMGcRef> {
+        self.weak_refs
+            .get(id)
+            .filter(|w| w.generation == generation)
+            .map(|w| &w.gc_ref)
+    }
+
+    /// Clear every weak reference whose referent has been reclaimed by the GC
+    /// heap, queueing up finalization tokens as necessary.
+    ///
+    /// This must be called before reading weak references, and before
+    /// creating new ones, so that a weak reference is never confused for a
+    /// reference to a new object that reuses its reclaimed referent's heap
+    /// index.
+    pub(crate) fn process_reclaimed_weak_refs(&mut self, gc_store: Option<&mut GcStore>) {
+        let Some(gc_store) = gc_store else {
+            return;
+        };
+
+        let mut reclaimed = Vec::new();
+        gc_store.take_reclaimed_weak_targets(&mut reclaimed);
+        if reclaimed.is_empty() {
+            return;
+        }
+        log::trace!(
+            "Clearing weak references to {} reclaimed objects",
+            reclaimed.len()
+        );
+
+        for gc_ref in reclaimed {
+            let Some(ids) = self.weak_refs_by_target.remove(&gc_ref) else {
+                continue;
+            };
+            for id in ids {
+                let weak_ref = self.weak_refs.dealloc(id);
+                self.pending_finalizations
+                    .extend(weak_ref.finalization_token);
+            }
+        }
+    }
+
+    /// Take the finalization tokens of reclaimed weak referents that have not
+    /// yet been passed to the store's finalization callback.
+    pub(crate) fn take_pending_finalizations(&mut self) -> Vec<u64> {
+        mem::take(&mut self.pending_finalizations)
+    }
 }
 
 /// A scoped, rooted reference to a garbage-collected `T`.
@@ -1832,6 +1947,246 @@ impl<T: GcRef> RootedGcRefImpl<T> for ManuallyRooted<T> {
     }
 }
 
+/// A weak reference to a garbage-collected `T`.
+///
+/// Unlike [`Rooted<T>`][crate::Rooted] and
+/// [`ManuallyRooted<T>`][crate::ManuallyRooted], a `WeakRooted<T>` does not
+/// keep its referent alive: once Wasm and the host have dropped every strong
+/// reference to the object, the collector is free to reclaim it. Use
+/// [`WeakRooted::upgrade`] to get a strong, rooted reference to the object if
+/// it is still alive.
+///
+/// A `WeakRooted<T>` may optionally be created with a *finalization token*,
+/// via [`WeakRooted::with_finalization_token`]. After the referent is
+/// reclaimed, the token is passed to the callback registered with
+/// [`Store::gc_finalization_callback`][crate::Store::gc_finalization_callback]
+/// at the end of the next [`Store::gc`][crate::Store::gc]. This lets hosts
+/// learn when Wasm has dropped its last reference to an
+/// [`ExternRef`][crate::ExternRef] or [`AnyRef`][crate::AnyRef] that they
+/// passed in.
+///
+/// Note that when and whether an object is reclaimed depends on the collector
+/// in use. For example, the null collector never reclaims anything, and the
+/// deferred reference-counting collector does not reclaim cycles.
+///
+/// `WeakRooted<T>` handles are `Copy`. The store's bookkeeping for a weak
+/// reference is released when its referent is reclaimed, when it is
+/// [unrooted][WeakRooted::unroot], or when the store is dropped. Hosts that
+/// create many weak references to long-lived objects should unroot them once
+/// they're no longer needed, since otherwise they are kept around for as long
+/// as their referents, which is forever under collectors that never reclaim
+/// anything.
+///
+/// # Example
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn _foo() -> Result<()> {
+/// let mut config = Config::new();
+/// config.wasm_gc(true);
+/// config.collector(Collector::DeferredReferenceCounting);
+/// let engine = Engine::new(&config)?;
+/// let mut store = Store::new(&engine, ());
+///
+/// let weak = {
+///     let mut scope = RootScope::new(&mut store);
+///     let x = ExternRef::new(&mut scope, 1234)?;
+///     let weak = WeakRooted::new(&mut scope, &x)?;
+///
+///     // While `x` is rooted, the weak reference can be upgraded.
+///     assert!(weak.upgrade(&mut scope).is_some());
+///
+///     weak
+/// };
+///
+/// // Now that `x` is no longer rooted, it is reclaimed, and the weak reference
+/// // can no longer be upgraded.
+/// store.gc();
+/// assert!(weak.upgrade(&mut store).is_none());
+/// # Ok(())
+/// # }
+/// ```
+pub struct WeakRooted<T: GcRef> {
+    store_id: StoreId,
+    generation: u32,
+    id: SlabId,
+    _phantom: marker::PhantomData<T>,
+}
+
+impl<T: GcRef> Clone for WeakRooted<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: GcRef> Copy for WeakRooted<T> {}
+
+impl<T: GcRef> Debug for WeakRooted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = format!("WeakRooted<{}>", any::type_name::<T>());
+        f.debug_struct(&name)
+            .field("store_id", &self.store_id)
+            .field("generation", &self.generation)
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<T: GcRef> WeakRooted<T> {
+    /// Create a new weak reference to the object that `root` refers to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `root` has been unrooted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `root` is not associated with the given `store`.
+    pub fn new(mut store: impl AsContextMut, root: &impl RootedGcRef<T>) -> Result<Self> {
+        Self::_new(store.as_context_mut().0, root, None)
+    }
+
+    /// Create a new weak reference to the object that `root` refers to, and
+    /// register `token` to be passed to the store's finalization callback
+    /// after that object is reclaimed.
+    ///
+    /// See
+    /// [`Store::gc_finalization_callback`][crate::Store::gc_finalization_callback]
+    /// for more details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `root` has been unrooted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `root` is not associated with the given `store`.
+    pub fn with_finalization_token(
+        mut store: impl AsContextMut,
+        root: &impl RootedGcRef<T>,
+        token: u64,
+    ) -> Result<Self> {
+        Self::_new(store.as_context_mut().0, root, Some(token))
+    }
+
+    pub(crate) fn _new(
+        store: &mut StoreOpaque,
+        root: &impl RootedGcRef<T>,
+        finalization_token: Option<u64>,
+    ) -> Result<Self> {
+        // Clear out stale weak references before watching a new object, since
+        // it may reuse the heap index of a reclaimed object.
+        store.process_reclaimed_weak_refs();
+
+        let mut store = AutoAssertNoGc::new(store);
+        let gc_ref = root.try_gc_ref(&store)?.unchecked_copy();
+        if !gc_ref.is_i31() {
+            store.unwrap_gc_store_mut().watch_weak_target(&gc_ref);
+        }
+
+        let (id, generation) = store
+            .gc_roots_mut()
+            .push_weak_ref(gc_ref, finalization_token);
+        Ok(WeakRooted {
+            store_id: store.id(),
+            generation,
+            id,
+            _phantom: marker::PhantomData,
+        })
+    }
+
+    /// Get a rooted, strong reference to this weak reference's referent, if it
+    /// has not been reclaimed yet.
+    ///
+    /// The resulting `Rooted<T>` keeps the object alive for as long as it
+    /// remains rooted, as usual.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this weak reference is not associated with the given `store`.
+    pub fn upgrade(&self, mut store: impl AsContextMut) -> Option<Rooted<T>> {
+        self._upgrade(store.as_context_mut().0)
+    }
+
+    pub(crate) fn _upgrade(&self, store: &mut StoreOpaque) -> Option<Rooted<T>> {
+        assert!(
+            self.comes_from_same_store(store),
+            "object used with wrong store"
+        );
+        store.process_reclaimed_weak_refs();
+
+        let mut store = AutoAssertNoGc::new(store);
+        let gc_ref = store
+            .gc_roots()
+            .get_weak_ref(self.id, self.generation)?
+            .unchecked_copy();
+        let gc_ref = if gc_ref.is_i31() {
+            gc_ref
+        } else {
+            store.unwrap_gc_store_mut().clone_gc_ref(&gc_ref)
+        };
+        Some(Rooted::new(&mut store, gc_ref))
+    }
+
+    /// Release the store's bookkeeping for this weak reference.
+    ///
+    /// This does not affect the referent. Afterwards, this weak reference and
+    /// all of its copies behave as if the referent was reclaimed: they can no
+    /// longer be upgraded, and the referent's finalization token, if any, is
+    /// never passed to the store's finalization callback on its behalf.
+    ///
+    /// Unrooting a weak reference that has already been unrooted, or whose
+    /// referent has been reclaimed, does nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this weak reference is not associated with the given `store`.
+    pub fn unroot(self, mut store: impl AsContextMut) {
+        self._unroot(store.as_context_mut().0)
+    }
+
+    pub(crate) fn _unroot(self, store: &mut StoreOpaque) {
+        assert!(
+            self.comes_from_same_store(store),
+            "object used with wrong store"
+        );
+        // Clear out stale weak references first, so that the referent's
+        // finalization is still reported if it was already reclaimed.
+        store.process_reclaimed_weak_refs();
+
+        let mut store = AutoAssertNoGc::new(store);
+        if let Some(gc_ref) = store
+            .gc_roots_mut()
+            .remove_weak_ref(self.id, self.generation)
+        {
+            store.unwrap_gc_store_mut().unwatch_weak_target(&gc_ref);
+        }
+    }
+
+    /// Has this weak reference's referent been reclaimed?
+    ///
+    /// # Panics
+    ///
+    /// Panics if this weak reference is not associated with the given `store`.
+    pub fn is_reclaimed(&self, mut store: impl AsContextMut) -> bool {
+        let store = store.as_context_mut().0;
+        assert!(
+            self.comes_from_same_store(store),
+            "object used with wrong store"
+        );
+        store.process_reclaimed_weak_refs();
+        store
+            .gc_roots()
+            .get_weak_ref(self.id, self.generation)
+            .is_none()
+    }
+
+    #[inline]
+    pub(crate) fn comes_from_same_store(&self, store: &StoreOpaque) -> bool {
+        self.store_id == store.id()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ExternRef;
@@ -1844,5 +2199,6 @@ mod tests {
         // unintentionally.
         assert_eq!(std::mem::size_of::<Rooted<ExternRef>>(), 16);
         assert_eq!(std::mem::size_of::<ManuallyRooted<ExternRef>>(), 16);
+        assert_eq!(std::mem::size_of::<WeakRooted<ExternRef>>(), 16);
     }
 }
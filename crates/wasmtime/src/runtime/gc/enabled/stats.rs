@@ -0,0 +1,58 @@
+//! Statistics about a store's GC heap.
+
+use core::time::Duration;
+
+/// Allocation and collection statistics for a [`Store`][crate::Store]'s GC
+/// heap.
+///
+/// Obtained via [`Store::gc_stats`][crate::Store::gc_stats] and friends. Taking
+/// a snapshot of these statistics is cheap, so it is fine to do so frequently,
+/// for example from inside a call hook via
+/// [`StoreContextMut::gc_stats`][crate::StoreContextMut::gc_stats].
+///
+/// If the store has not allocated its GC heap yet, then all statistics are
+/// zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GcStats {
+    /// The total number of bytes allocated in the GC heap over the store's
+    /// lifetime.
+    pub bytes_allocated: u64,
+
+    /// The total number of objects allocated in the GC heap over the store's
+    /// lifetime.
+    ///
+    /// The null collector allocates inline in compiled Wasm code and does not
+    /// count objects, so this is always zero with that collector.
+    pub objects_allocated: u64,
+
+    /// The number of bytes currently occupied by allocated objects.
+    pub live_bytes: usize,
+
+    /// The number of objects currently allocated in the GC heap.
+    ///
+    /// Objects that are unreachable but not yet collected are included in
+    /// this count. Always zero with the null collector.
+    pub live_objects: usize,
+
+    /// The current capacity of the GC heap, in bytes.
+    pub heap_capacity: usize,
+
+    /// The number of times the GC heap's capacity has grown.
+    pub heap_growths: u64,
+
+    /// The number of garbage collections performed.
+    pub collections: u64,
+
+    /// The total time spent performing garbage collections.
+    ///
+    /// This is only measured when the `std` Cargo feature is enabled.
+    pub collection_time: Duration,
+
+    /// The number of entries in the deferred reference-counting collector's
+    /// over-approximated table of GC references held by Wasm stack frames.
+    ///
+    /// A large table means that Wasm is passing many references around
+    /// between collections. Always zero for other collectors.
+    pub activations_table_len: usize,
+}
@@ -125,6 +125,42 @@ pub trait ResourceLimiter {
         Ok(())
     }
 
+    /// Notifies the resource limiter that a store's GC heap has been requested
+    /// to grow.
+    ///
+    /// * `current` is the current capacity of the GC heap in bytes.
+    /// * `desired` is the desired capacity of the GC heap in bytes.
+    /// * `maximum` is the maximum capacity of the GC heap in bytes, as
+    ///   configured by
+    ///   [`Config::gc_heap_maximum_size`](crate::Config::gc_heap_maximum_size).
+    ///
+    /// GC heaps grow when an allocation fails, according to the sizing policy
+    /// configured with
+    /// [`Config::gc_heap_growth_factor`](crate::Config::gc_heap_growth_factor)
+    /// and
+    /// [`Config::gc_heap_gc_before_grow_threshold`](crate::Config::gc_heap_gc_before_grow_threshold).
+    /// Unlike linear memories, this is never requested by WebAssembly
+    /// explicitly, and `desired` never exceeds `maximum`.
+    ///
+    /// ## Return Value
+    ///
+    /// If `Ok(true)` is returned from this function then the GC heap grows.
+    ///
+    /// If `Ok(false)` is returned then the GC heap keeps its current capacity.
+    /// If a garbage collection doesn't free up enough space, then the
+    /// allocation that requested the growth fails, which traps in WebAssembly
+    /// or returns a [`GcHeapOutOfMemory`](crate::GcHeapOutOfMemory) error to
+    /// the embedder.
+    ///
+    /// If `Err(e)` is returned then the allocation that requested the growth
+    /// will behave as if a trap has been raised.
+    ///
+    /// The default implementation always allows the GC heap to grow.
+    fn gc_heap_growing(&mut self, current: usize, desired: usize, maximum: usize) -> Result<bool> {
+        let _ = (current, desired, maximum);
+        Ok(true)
+    }
+
     /// The maximum number of instances that can be created for a `Store`.
     ///
     /// Module instantiation will fail if this limit is exceeded.
@@ -201,6 +237,17 @@ pub trait ResourceLimiterAsync {
         Ok(())
     }
 
+    /// Asynchronous version of [`ResourceLimiter::gc_heap_growing`]
+    async fn gc_heap_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: usize,
+    ) -> Result<bool> {
+        let _ = (current, desired, maximum);
+        Ok(true)
+    }
+
     /// Identical to [`ResourceLimiter::instances`]`
     fn instances(&self) -> usize {
         DEFAULT_INSTANCE_LIMIT
@@ -254,6 +301,18 @@ impl StoreLimitsBuilder {
         self
     }
 
+    /// The maximum number of bytes a store's GC heap can grow to.
+    ///
+    /// Growing a GC heap beyond this limit will fail. Note that this does not
+    /// limit a GC heap's initial size.
+    ///
+    /// By default, GC heaps are only limited by
+    /// [`Config::gc_heap_maximum_size`](crate::Config::gc_heap_maximum_size).
+    pub fn gc_heap_size(mut self, limit: usize) -> Self {
+        self.0.gc_heap_size = Some(limit);
+        self
+    }
+
     /// The maximum number of instances that can be created for a [`Store`](crate::Store).
     ///
     /// Module instantiation will fail if this limit is exceeded.
@@ -316,6 +375,7 @@ impl StoreLimitsBuilder {
 pub struct StoreLimits {
     memory_size: Option<usize>,
     table_elements: Option<usize>,
+    gc_heap_size: Option<usize>,
     instances: usize,
     tables: usize,
     memories: usize,
@@ -327,6 +387,7 @@ impl Default for StoreLimits {
         Self {
             memory_size: None,
             table_elements: None,
+            gc_heap_size: None,
             instances: DEFAULT_INSTANCE_LIMIT,
             tables: DEFAULT_TABLE_LIMIT,
             memories: DEFAULT_MEMORY_LIMIT,
@@ -394,6 +455,23 @@ impl ResourceLimiter for StoreLimits {
         }
     }
 
+    fn gc_heap_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: usize,
+    ) -> Result<bool> {
+        let allow = match self.gc_heap_size {
+            Some(limit) if desired > limit => false,
+            _ => true,
+        };
+        if !allow && self.trap_on_grow_failure {
+            bail!("forcing trap when growing GC heap to {desired} bytes")
+        } else {
+            Ok(allow)
+        }
+    }
+
     fn instances(&self) -> usize {
         self.instances
     }
@@ -0,0 +1,62 @@
+use crate::runtime::vm::PoolingInstanceAllocator;
+use crate::Engine;
+
+/// A snapshot of the resources in use from an [`Engine`]'s pooling instance
+/// allocator.
+///
+/// Created with [`Engine::pooling_allocator_metrics`]. Each method reads the
+/// current value when called, so values read at different times may be
+/// inconsistent with each other while instances are being allocated or
+/// deallocated concurrently.
+#[derive(Clone)]
+pub struct PoolingAllocatorMetrics {
+    engine: Engine,
+}
+
+impl PoolingAllocatorMetrics {
+    pub(crate) fn new(engine: &Engine) -> Option<Self> {
+        engine.allocator().as_pooling()?;
+        Some(Self {
+            engine: engine.clone(),
+        })
+    }
+
+    fn allocator(&self) -> &PoolingInstanceAllocator {
+        self.engine.allocator().as_pooling().unwrap()
+    }
+
+    /// The number of core module instances allocated right now, out of
+    /// [`PoolingAllocationConfig::total_core_instances`](crate::PoolingAllocationConfig::total_core_instances).
+    pub fn core_instances(&self) -> u64 {
+        self.allocator().live_core_instances()
+    }
+
+    /// The number of component instances allocated right now, out of
+    /// [`PoolingAllocationConfig::total_component_instances`](crate::PoolingAllocationConfig::total_component_instances).
+    pub fn component_instances(&self) -> u64 {
+        self.allocator().live_component_instances()
+    }
+
+    /// The number of linear memories allocated right now, out of
+    /// [`PoolingAllocationConfig::total_memories`](crate::PoolingAllocationConfig::total_memories).
+    pub fn memories(&self) -> usize {
+        self.allocator().live_memories()
+    }
+
+    /// The number of tables allocated right now, out of
+    /// [`PoolingAllocationConfig::total_tables`](crate::PoolingAllocationConfig::total_tables).
+    pub fn tables(&self) -> usize {
+        self.allocator().live_tables()
+    }
+}
+
+impl core::fmt::Debug for PoolingAllocatorMetrics {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PoolingAllocatorMetrics")
+            .field("core_instances", &self.core_instances())
+            .field("component_instances", &self.component_instances())
+            .field("memories", &self.memories())
+            .field("tables", &self.tables())
+            .finish()
+    }
+}
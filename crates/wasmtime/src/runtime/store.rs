@@ -226,6 +226,8 @@ pub struct StoreInner<T> {
     call_hook: Option<CallHookInner<T>>,
     epoch_deadline_behavior:
         Option<Box<dyn FnMut(StoreContextMut<T>) -> Result<UpdateDeadline> + Send + Sync>>,
+    #[cfg(feature = "gc")]
+    gc_finalization_callback: Option<Box<dyn FnMut(StoreContextMut<'_, T>, u64) + Send + Sync>>,
     // for comments about `ManuallyDrop`, see `Store::into_data`
     data: ManuallyDrop<T>,
 }
@@ -610,6 +612,8 @@ impl<T> Store<T> {
             limiter: None,
             call_hook: None,
             epoch_deadline_behavior: None,
+            #[cfg(feature = "gc")]
+            gc_finalization_callback: None,
             data: ManuallyDrop::new(data),
         });
 
@@ -889,7 +893,8 @@ impl<T> Store<T> {
     /// This method is only available when the `gc` Cargo feature is enabled.
     #[cfg(feature = "gc")]
     pub fn gc(&mut self) {
-        self.inner.gc()
+        self.inner.gc();
+        self.inner.run_gc_finalizers();
     }
 
     /// Perform garbage collection asynchronously.
@@ -905,6 +910,53 @@ impl<T> Store<T> {
         T: Send,
     {
         self.inner.gc_async().await;
+        self.inner.run_gc_finalizers();
+    }
+
+    /// Configure a callback that is invoked with the finalization token of each
+    /// [`WeakRooted`](crate::WeakRooted) referent that has been reclaimed.
+    ///
+    /// Weak references created with
+    /// [`WeakRooted::with_finalization_token`](crate::WeakRooted::with_finalization_token)
+    /// carry a host-chosen token. Once their referent has been reclaimed, the
+    /// token is passed to this callback at the end of the next call to
+    /// [`Store::gc`] or [`Store::gc_async`]. This includes objects reclaimed by
+    /// collections that were triggered automatically while Wasm was running,
+    /// but the callback itself is never invoked while Wasm is on the stack.
+    ///
+    /// Tokens of objects reclaimed while no callback is configured are
+    /// discarded at the next explicit collection.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_finalization_callback(
+        &mut self,
+        callback: impl FnMut(StoreContextMut<'_, T>, u64) + Send + Sync + 'static,
+    ) {
+        self.inner.gc_finalization_callback = Some(Box::new(callback));
+    }
+
+    /// Take a snapshot of the live objects in this store's GC heap.
+    ///
+    /// The snapshot contains every GC object that is reachable from this
+    /// store's roots, which can be useful when debugging excessive memory use
+    /// in GC-using guests. See [`GcHeapSnapshot`](crate::GcHeapSnapshot) for
+    /// more details.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_snapshot(&mut self) -> Result<crate::GcHeapSnapshot> {
+        self.inner.gc_heap_snapshot()
+    }
+
+    /// Get allocation and collection statistics for this store's GC heap.
+    ///
+    /// See [`GcStats`](crate::GcStats) for more details.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        self.inner.gc_stats()
     }
 
     /// Returns the amount fuel in this [`Store`]. When fuel is enabled, it must
@@ -1119,6 +1171,16 @@ impl<'a, T> StoreContext<'a, T> {
     pub fn get_fuel(&self) -> Result<u64> {
         self.0.get_fuel()
     }
+
+    /// Get allocation and collection statistics for this store's GC heap.
+    ///
+    /// Same as [`Store::gc_stats`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        self.0.gc_stats()
+    }
 }
 
 impl<'a, T> StoreContextMut<'a, T> {
@@ -1148,7 +1210,8 @@ impl<'a, T> StoreContextMut<'a, T> {
     /// This method is only available when the `gc` Cargo feature is enabled.
     #[cfg(feature = "gc")]
     pub fn gc(&mut self) {
-        self.0.gc()
+        self.0.gc();
+        self.0.run_gc_finalizers();
     }
 
     /// Perform garbage collection of `ExternRef`s.
@@ -1162,6 +1225,27 @@ impl<'a, T> StoreContextMut<'a, T> {
         T: Send,
     {
         self.0.gc_async().await;
+        self.0.run_gc_finalizers();
+    }
+
+    /// Take a snapshot of the live objects in this store's GC heap.
+    ///
+    /// Same as [`Store::gc_heap_snapshot`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_snapshot(&mut self) -> Result<crate::GcHeapSnapshot> {
+        self.0.gc_heap_snapshot()
+    }
+
+    /// Get allocation and collection statistics for this store's GC heap.
+    ///
+    /// Same as [`Store::gc_stats`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        self.0.gc_stats()
     }
 
     /// Returns remaining fuel in this store.
@@ -1678,6 +1762,8 @@ impl StoreOpaque {
         roots.clear();
         self.gc_roots_list = roots;
 
+        self.process_reclaimed_weak_refs();
+
         log::trace!("============ End GC ===========");
     }
 
@@ -1732,6 +1818,8 @@ impl StoreOpaque {
         roots.clear();
         self.gc_roots_list = roots;
 
+        self.process_reclaimed_weak_refs();
+
         log::trace!("============ End Async GC ===========");
     }
 
@@ -1763,6 +1851,104 @@ impl StoreOpaque {
         log::trace!("End trace GC roots")
     }
 
+    /// Clear any weak references whose referents have been reclaimed.
+    #[cfg(feature = "gc")]
+    pub(crate) fn process_reclaimed_weak_refs(&mut self) {
+        self.gc_roots
+            .process_reclaimed_weak_refs(self.gc_store.as_mut());
+    }
+
+    #[cfg(feature = "gc")]
+    pub(crate) fn gc_heap_snapshot(&mut self) -> Result<crate::GcHeapSnapshot> {
+        crate::GcHeapSnapshot::new(self)
+    }
+
+    #[cfg(feature = "gc")]
+    pub(crate) fn gc_stats(&self) -> crate::GcStats {
+        let Some(gc_store) = &self.gc_store else {
+            return crate::GcStats::default();
+        };
+        let heap = gc_store.stats();
+        crate::GcStats {
+            bytes_allocated: heap.bytes_allocated,
+            objects_allocated: heap.objects_allocated,
+            live_bytes: heap.live_bytes,
+            live_objects: heap.live_objects,
+            heap_capacity: heap.capacity,
+            heap_growths: heap.growths,
+            collections: gc_store.collections,
+            collection_time: gc_store.collection_time,
+            activations_table_len: heap.over_approximated_stack_roots,
+        }
+    }
+
+    /// Compute the capacity that the GC heap should grow to so that an
+    /// allocation of `bytes_needed` bytes can succeed, according to the
+    /// engine's GC heap sizing policy.
+    ///
+    /// Returns `None` if the heap should not, or cannot, grow.
+    #[cfg(feature = "gc")]
+    fn gc_heap_growth_target(&self, bytes_needed: u64, after_gc: bool) -> Option<usize> {
+        // Growth is always in multiples of this many bytes.
+        const GROWTH_GRANULARITY: usize = 64 * 1024;
+
+        let sizing = &self.engine().config().gc_heap_sizing;
+        let gc_heap = &self.gc_store.as_ref()?.gc_heap;
+        let current = gc_heap.capacity();
+        let maximum = gc_heap.maximum_capacity();
+        if current >= maximum {
+            return None;
+        }
+        let bytes_needed = usize::try_from(bytes_needed).ok()?;
+
+        // After a collection, only grow if the heap would still be more than
+        // `1 / growth_factor` full once this allocation is satisfied.
+        if after_gc {
+            let occupied = gc_heap.stats().live_bytes.saturating_add(bytes_needed);
+            if scale_by_factor(occupied, sizing.growth_factor) <= current {
+                return None;
+            }
+        }
+
+        let desired = core::cmp::max(
+            scale_by_factor(current, sizing.growth_factor),
+            current.saturating_add(bytes_needed),
+        );
+        let desired = desired
+            .checked_next_multiple_of(GROWTH_GRANULARITY)
+            .unwrap_or(usize::MAX);
+        let desired = core::cmp::min(desired, maximum);
+        if desired > current {
+            Some(desired)
+        } else {
+            None
+        }
+    }
+
+    /// Collect all of this store's GC roots, along with where each one of them
+    /// comes from, without performing a collection.
+    #[cfg(feature = "gc")]
+    pub(crate) fn gc_roots_by_kind(&mut self) -> Vec<(crate::GcRootKind, VMGcRef)> {
+        let mut roots = core::mem::take(&mut self.gc_roots_list);
+        let mut result = Vec::new();
+
+        let tracers: [(crate::GcRootKind, fn(&mut Self, &mut GcRootsList)); 3] = [
+            (crate::GcRootKind::WasmStack, Self::trace_wasm_stack_roots),
+            (crate::GcRootKind::GlobalsAndTables, Self::trace_vmctx_roots),
+            (crate::GcRootKind::Host, Self::trace_user_roots),
+        ];
+        for (kind, trace) in tracers {
+            trace(self, &mut roots);
+            for root in unsafe { roots.iter() } {
+                result.push((kind, root.get()));
+            }
+            roots.clear();
+        }
+
+        self.gc_roots_list = roots;
+        result
+    }
+
     #[cfg(feature = "gc")]
     fn trace_wasm_stack_roots(&mut self, gc_roots_list: &mut GcRootsList) {
         use crate::runtime::vm::SendSyncPtr;
@@ -2747,7 +2933,25 @@ unsafe impl<T> crate::runtime::vm::VMStore for StoreInner<T> {
     }
 
     #[cfg(feature = "gc")]
-    fn maybe_async_gc(&mut self, root: Option<VMGcRef>) -> Result<Option<VMGcRef>> {
+    fn maybe_async_gc(
+        &mut self,
+        root: Option<VMGcRef>,
+        bytes_needed: Option<u64>,
+    ) -> Result<Option<VMGcRef>> {
+        // Small heaps are grown without collecting first, if configured.
+        if let Some(bytes_needed) = bytes_needed {
+            let threshold = self
+                .engine()
+                .config()
+                .gc_heap_sizing
+                .gc_before_grow_threshold;
+            if self.unwrap_gc_store().gc_heap.capacity() < threshold
+                && self.maybe_grow_gc_heap(bytes_needed, false)?
+            {
+                return Ok(root);
+            }
+        }
+
         let mut scope = RootScope::new(self);
         let store = scope.as_context_mut().0;
         let store_id = store.id();
@@ -2766,6 +2970,11 @@ unsafe impl<T> crate::runtime::vm::VMStore for StoreInner<T> {
             (**store).gc();
         }
 
+        // If the collection didn't leave enough free space, grow the heap.
+        if let Some(bytes_needed) = bytes_needed {
+            store.maybe_grow_gc_heap(bytes_needed, true)?;
+        }
+
         let root = match root {
             None => None,
             Some(r) => {
@@ -2781,7 +2990,11 @@ unsafe impl<T> crate::runtime::vm::VMStore for StoreInner<T> {
     }
 
     #[cfg(not(feature = "gc"))]
-    fn maybe_async_gc(&mut self, root: Option<VMGcRef>) -> Result<Option<VMGcRef>> {
+    fn maybe_async_gc(
+        &mut self,
+        root: Option<VMGcRef>,
+        _bytes_needed: Option<u64>,
+    ) -> Result<Option<VMGcRef>> {
         Ok(root)
     }
 
@@ -2792,6 +3005,88 @@ unsafe impl<T> crate::runtime::vm::VMStore for StoreInner<T> {
 }
 
 impl<T> StoreInner<T> {
+    /// Try to grow the GC heap so that an allocation of `bytes_needed` bytes
+    /// can succeed, according to the engine's GC heap sizing policy and this
+    /// store's resource limiter.
+    ///
+    /// When `after_gc` is true, the heap is only grown if it is still too full
+    /// after a collection.
+    ///
+    /// Returns whether the heap was grown.
+    #[cfg(feature = "gc")]
+    fn maybe_grow_gc_heap(&mut self, bytes_needed: u64, after_gc: bool) -> Result<bool> {
+        let Some(desired) = self.gc_heap_growth_target(bytes_needed, after_gc) else {
+            return Ok(false);
+        };
+        let gc_heap = &self.unwrap_gc_store().gc_heap;
+        let current = gc_heap.capacity();
+        let maximum = gc_heap.maximum_capacity();
+
+        if !self.gc_heap_growing(current, desired, maximum)? {
+            log::trace!("resource limiter rejected growing GC heap to {desired:#x} bytes");
+            return Ok(false);
+        }
+
+        self.unwrap_gc_store_mut().gc_heap.grow(desired);
+        Ok(true)
+    }
+
+    /// Pass the finalization tokens of reclaimed weak referents to this store's
+    /// finalization callback, if any.
+    #[cfg(feature = "gc")]
+    fn run_gc_finalizers(&mut self) {
+        let tokens = self.gc_roots_mut().take_pending_finalizations();
+        if tokens.is_empty() {
+            return;
+        }
+
+        // Temporarily take the callback to avoid mutably borrowing multiple
+        // times.
+        let Some(mut callback) = self.gc_finalization_callback.take() else {
+            return;
+        };
+        for token in tokens {
+            callback((&mut *self).as_context_mut(), token);
+        }
+
+        // Put back the callback, unless it was replaced while running.
+        if self.gc_finalization_callback.is_none() {
+            self.gc_finalization_callback = Some(callback);
+        }
+    }
+
+    #[cfg(feature = "gc")]
+    fn gc_heap_growing(&mut self, current: usize, desired: usize, maximum: usize) -> Result<bool> {
+        // Need to borrow async_cx before the mut borrow of the limiter.
+        // self.async_cx() panicks when used with a non-async store, so
+        // wrap this in an option.
+        #[cfg(feature = "async")]
+        let async_cx = if self.async_support()
+            && matches!(self.limiter, Some(ResourceLimiterInner::Async(_)))
+        {
+            Some(self.async_cx().unwrap())
+        } else {
+            None
+        };
+
+        match self.limiter {
+            Some(ResourceLimiterInner::Sync(ref mut limiter)) => {
+                limiter(&mut self.data).gc_heap_growing(current, desired, maximum)
+            }
+            #[cfg(feature = "async")]
+            Some(ResourceLimiterInner::Async(ref mut limiter)) => unsafe {
+                async_cx
+                    .expect("ResourceLimiterAsync requires async Store")
+                    .block_on(
+                        limiter(&mut self.data)
+                            .gc_heap_growing(current, desired, maximum)
+                            .as_mut(),
+                    )?
+            },
+            None => Ok(true),
+        }
+    }
+
     pub(crate) fn set_epoch_deadline(&mut self, delta: u64) {
         // Set a new deadline based on the "epoch deadline delta".
         //
@@ -2923,6 +3218,15 @@ impl<T: Copy> Drop for Reset<T> {
     }
 }
 
+/// Multiply `value` by `factor`, saturating at `usize::MAX`.
+#[cfg(feature = "gc")]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn scale_by_factor(value: usize, factor: f64) -> usize {
+    debug_assert!(factor.is_finite() && factor >= 1.0);
+    // NB: float-to-int `as` casts saturate, which is what we want here.
+    (value as f64 * factor) as usize
+}
+
 #[cfg(test)]
 mod tests {
     use super::{get_fuel, refuel, set_fuel};
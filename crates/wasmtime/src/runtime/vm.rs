@@ -194,11 +194,20 @@ pub unsafe trait VMStore {
     /// Optionally given a GC reference that is rooted for the collection, and
     /// then whose updated GC reference is returned.
     ///
+    /// If this GC is being triggered because an allocation of `bytes_needed`
+    /// bytes failed, then the GC heap may additionally, or instead, be grown
+    /// according to the engine's GC heap sizing policy and the store's
+    /// resource limiter.
+    ///
     /// Cooperative, async-yielding (if configured) is completely transparent.
     ///
     /// If the async GC was cancelled, returns an error. This should be raised
     /// as a trap to clean up Wasm execution.
-    fn maybe_async_gc(&mut self, root: Option<VMGcRef>) -> Result<Option<VMGcRef>>;
+    fn maybe_async_gc(
+        &mut self,
+        root: Option<VMGcRef>,
+        bytes_needed: Option<u64>,
+    ) -> Result<Option<VMGcRef>>;
 
     /// Metadata required for resources for the component model.
     #[cfg(feature = "component-model")]
@@ -25,6 +25,7 @@ use crate::runtime::vm::GcHeapAllocationIndex;
 use core::alloc::Layout;
 use core::any::Any;
 use core::mem::MaybeUninit;
+use core::time::Duration;
 use wasmtime_environ::{GcArrayLayout, GcStructLayout, VMGcKind, VMSharedTypeIndex};
 
 /// GC-related data that is one-to-one with a `wasmtime::Store`.
@@ -47,6 +48,15 @@ pub struct GcStore {
 
     /// The function-references table for this GC heap.
     pub func_ref_table: FuncRefTable,
+
+    /// The number of collections performed on this GC heap.
+    pub collections: u64,
+
+    /// The total time spent performing collections on this GC heap.
+    ///
+    /// This is only measured when the `std` feature is enabled, and is always
+    /// zero otherwise.
+    pub collection_time: Duration,
 }
 
 impl GcStore {
@@ -59,20 +69,58 @@ impl GcStore {
             gc_heap,
             host_data_table,
             func_ref_table,
+            collections: 0,
+            collection_time: Duration::ZERO,
         }
     }
 
     /// Perform garbage collection within this heap.
     pub fn gc(&mut self, roots: GcRootsIter<'_>) {
+        let start = CollectionTimer::start();
         let mut collection = self.gc_heap.gc(roots, &mut self.host_data_table);
         collection.collect();
+        self.collections += 1;
+        self.collection_time += start.elapsed();
     }
 
     /// Asynchronously perform garbage collection within this heap.
+    ///
+    /// Note that the measured collection time includes any time spent
+    /// suspended between collection increments.
     #[cfg(feature = "async")]
     pub async fn gc_async(&mut self, roots: GcRootsIter<'_>) {
+        let start = CollectionTimer::start();
         let collection = self.gc_heap.gc(roots, &mut self.host_data_table);
         collect_async(collection).await;
+        self.collections += 1;
+        self.collection_time += start.elapsed();
+    }
+
+    /// Get this heap's allocation statistics.
+    pub fn stats(&self) -> GcHeapStats {
+        self.gc_heap.stats()
+    }
+
+    /// Watch the given object for reclamation, because it is the target of a
+    /// weak reference.
+    pub fn watch_weak_target(&mut self, gc_ref: &VMGcRef) {
+        if !gc_ref.is_i31() {
+            self.gc_heap.watch_weak_target(gc_ref);
+        }
+    }
+
+    /// Stop watching the given object for reclamation, because it is no longer
+    /// the target of any weak reference.
+    pub fn unwatch_weak_target(&mut self, gc_ref: &VMGcRef) {
+        if !gc_ref.is_i31() {
+            self.gc_heap.unwatch_weak_target(gc_ref);
+        }
+    }
+
+    /// Take the watched weak targets that have been reclaimed since the last
+    /// call to this method.
+    pub fn take_reclaimed_weak_targets(&mut self, reclaimed: &mut Vec<VMGcRef>) {
+        self.gc_heap.take_reclaimed_weak_targets(reclaimed);
     }
 
     /// Get the kind of the given GC reference.
@@ -255,3 +303,25 @@ impl GcStore {
         self.gc_heap.array_len(arrayref)
     }
 }
+
+/// Measures how long a collection takes, when we have a clock to do so.
+struct CollectionTimer {
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl CollectionTimer {
+    fn start() -> Self {
+        CollectionTimer {
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(feature = "std")]
+        return self.start.elapsed();
+        #[cfg(not(feature = "std"))]
+        return Duration::ZERO;
+    }
+}
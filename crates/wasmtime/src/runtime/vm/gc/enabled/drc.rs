@@ -47,15 +47,15 @@ use crate::hash_set::HashSet;
 use crate::prelude::*;
 use crate::runtime::vm::{
     mmap::AlignedLength, ExternRefHostDataId, ExternRefHostDataTable, GarbageCollection, GcHeap,
-    GcHeapObject, GcProgress, GcRootsIter, GcRuntime, Mmap, TypedGcRef, VMExternRef, VMGcHeader,
-    VMGcRef,
+    GcHeapObject, GcHeapStats, GcProgress, GcRootsIter, GcRuntime, Mmap, TypedGcRef, VMExternRef,
+    VMGcHeader, VMGcRef,
 };
 use core::ops::{Deref, DerefMut, Range};
 use core::{
     alloc::Layout,
     any::Any,
     cell::UnsafeCell,
-    mem,
+    cmp, mem,
     num::NonZeroUsize,
     ptr::{self, NonNull},
 };
@@ -69,9 +69,22 @@ use wasmtime_environ::{GcArrayLayout, GcStructLayout, GcTypeLayouts, VMGcKind, V
 ///
 /// This is not a moving collector; it doesn't have a nursery or do any
 /// compaction.
-#[derive(Default)]
 pub struct DrcCollector {
     layouts: DrcTypeLayouts,
+    initial_capacity: usize,
+    maximum_capacity: usize,
+}
+
+impl DrcCollector {
+    /// Create a new DRC collector whose heaps are sized according to the given
+    /// policy.
+    pub fn new(sizing: &crate::config::GcHeapSizing) -> Self {
+        DrcCollector {
+            layouts: DrcTypeLayouts::default(),
+            initial_capacity: sizing.initial_size,
+            maximum_capacity: sizing.maximum_size(),
+        }
+    }
 }
 
 unsafe impl GcRuntime for DrcCollector {
@@ -80,7 +93,7 @@ unsafe impl GcRuntime for DrcCollector {
     }
 
     fn new_gc_heap(&self) -> Result<Box<dyn GcHeap>> {
-        let heap = DrcHeap::new()?;
+        let heap = DrcHeap::with_capacity(self.initial_capacity, self.maximum_capacity)?;
         Ok(Box::new(heap) as _)
     }
 }
@@ -91,25 +104,39 @@ struct DrcHeap {
     // NB: this box shouldn't be strictly necessary, but it makes upholding the
     // safety invariants of the `vmctx_gc_heap_data` more obviously correct.
     activations_table: Box<VMGcRefActivationsTable>,
+    /// The memory reserved for this heap, which is large enough for the heap's
+    /// maximum capacity.
     heap: Mmap<AlignedLength>,
+    /// The free list for the portion of `heap` that is currently available for
+    /// allocation, which is the heap's current capacity.
     free_list: FreeList,
+    initial_capacity: usize,
+    stats: GcHeapStats,
+    /// Objects that are the targets of weak references, and which we must
+    /// report when they are deallocated.
+    weak_targets: HashSet<VMGcRef>,
+    /// Weak targets that have been deallocated but not yet reported.
+    reclaimed_weak_targets: Vec<VMGcRef>,
 }
 
 impl DrcHeap {
-    /// Construct a new, default DRC heap.
-    fn new() -> Result<Self> {
-        Self::with_capacity(super::DEFAULT_GC_HEAP_CAPACITY)
-    }
-
-    /// Create a new DRC heap with the given capacity.
-    fn with_capacity(capacity: usize) -> Result<Self> {
-        let heap = Mmap::with_at_least(capacity)?;
-        let free_list = FreeList::new(heap.len());
+    /// Construct a new DRC heap whose free list initially covers
+    /// `initial_capacity` bytes, reserving enough memory up front for the heap
+    /// to grow to `maximum_capacity` bytes without moving.
+    fn with_capacity(initial_capacity: usize, maximum_capacity: usize) -> Result<Self> {
+        debug_assert!(initial_capacity <= maximum_capacity);
+        let heap = Mmap::with_at_least(maximum_capacity)?;
+        let initial_capacity = cmp::min(initial_capacity, heap.len());
+        let free_list = FreeList::new(initial_capacity);
         Ok(Self {
             no_gc_count: 0,
             activations_table: Box::new(VMGcRefActivationsTable::default()),
             heap,
             free_list,
+            initial_capacity,
+            stats: GcHeapStats::default(),
+            weak_targets: HashSet::new(),
+            reclaimed_weak_targets: Vec::new(),
         })
     }
 
@@ -119,6 +146,11 @@ impl DrcHeap {
         let layout = FreeList::layout(size);
         self.free_list
             .dealloc(gc_ref.as_heap_index().unwrap(), layout);
+        self.stats.live_bytes -= size;
+        self.stats.live_objects -= 1;
+        if !self.weak_targets.is_empty() && self.weak_targets.remove(&gc_ref) {
+            self.reclaimed_weak_targets.push(gc_ref);
+        }
     }
 
     fn object_range(&self, gc_ref: &VMGcRef) -> Range<usize> {
@@ -553,6 +585,14 @@ unsafe impl GcHeap for DrcHeap {
             return Err(crate::Trap::AllocationTooLarge.into());
         }
 
+        // If the allocation is too large for the heap's current capacity, but
+        // not for its maximum capacity, then let our caller grow the heap and
+        // try again.
+        if layout.size() > self.free_list.max_size() && self.free_list.capacity() < self.heap.len()
+        {
+            return Ok(None);
+        }
+
         let gc_ref = match self.free_list.alloc(layout)? {
             None => return Ok(None),
             Some(index) => VMGcRef::from_heap_index(index).unwrap(),
@@ -566,6 +606,12 @@ unsafe impl GcHeap for DrcHeap {
             ref_count: UnsafeCell::new(1),
         };
         log::trace!("increment {gc_ref:#p} ref count -> 1");
+
+        self.stats.bytes_allocated += u64::from(size);
+        self.stats.objects_allocated += 1;
+        self.stats.live_bytes += layout.size();
+        self.stats.live_objects += 1;
+
         Ok(Some(gc_ref))
     }
 
@@ -679,11 +725,60 @@ unsafe impl GcHeap for DrcHeap {
             activations_table,
             free_list,
             heap: _,
+            initial_capacity,
+            stats,
+            weak_targets,
+            reclaimed_weak_targets,
         } = self;
 
         *no_gc_count = 0;
-        free_list.reset();
+        // Shrink back down to the initial capacity, so that the next store to
+        // use this heap starts from scratch.
+        *free_list = FreeList::new(*initial_capacity);
         activations_table.reset();
+        *stats = GcHeapStats::default();
+        weak_targets.clear();
+        reclaimed_weak_targets.clear();
+    }
+
+    fn capacity(&self) -> usize {
+        self.free_list.capacity()
+    }
+
+    fn maximum_capacity(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn grow(&mut self, new_capacity: usize) {
+        assert!(new_capacity > self.capacity());
+        assert!(new_capacity <= self.maximum_capacity());
+        log::trace!(
+            "growing DRC heap capacity from {:#x} to {new_capacity:#x}",
+            self.capacity()
+        );
+        self.free_list.grow(new_capacity);
+        self.stats.growths += 1;
+    }
+
+    fn stats(&self) -> GcHeapStats {
+        GcHeapStats {
+            capacity: self.capacity(),
+            over_approximated_stack_roots: self.activations_table.len(),
+            ..self.stats
+        }
+    }
+
+    fn watch_weak_target(&mut self, gc_ref: &VMGcRef) {
+        debug_assert!(!gc_ref.is_i31());
+        self.weak_targets.insert(gc_ref.unchecked_copy());
+    }
+
+    fn unwatch_weak_target(&mut self, gc_ref: &VMGcRef) {
+        self.weak_targets.remove(gc_ref);
+    }
+
+    fn take_reclaimed_weak_targets(&mut self, reclaimed: &mut Vec<VMGcRef>) {
+        reclaimed.append(&mut self.reclaimed_weak_targets);
     }
 
     fn heap_slice(&self) -> &[UnsafeCell<u8>] {
@@ -933,6 +1028,12 @@ impl VMGcRefActivationsTable {
         self.alloc.chunk.len().saturating_sub(slots_unused)
     }
 
+    /// The number of entries in this table, including duplicates in the bump
+    /// chunk that have not been deduplicated by a GC yet.
+    fn len(&self) -> usize {
+        self.over_approximated_stack_roots.len() + self.num_filled_in_bump_chunk()
+    }
+
     fn elements(&self, mut f: impl FnMut(&VMGcRef)) {
         for elem in self.over_approximated_stack_roots.iter() {
             f(elem);
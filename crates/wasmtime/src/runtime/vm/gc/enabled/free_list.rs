@@ -38,7 +38,13 @@ impl FreeList {
         free_list
     }
 
-    fn max_size(&self) -> usize {
+    /// The total capacity of the range of memory this free list manages.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The largest allocation size that this free list could ever satisfy.
+    pub fn max_size(&self) -> usize {
         let cap = cmp::min(self.capacity, usize::try_from(u32::MAX).unwrap());
         round_usize_down_to_pow2(cap.saturating_sub(ALIGN_USIZE), ALIGN_USIZE)
     }
@@ -257,8 +263,52 @@ impl FreeList {
         }
     }
 
-    /// Reset this free list, making the whole range available for allocation.
-    pub fn reset(&mut self) {
+    /// Grow this free list to manage `new_capacity` bytes, making the new
+    /// space at the end of the range available for allocation.
+    pub fn grow(&mut self, new_capacity: usize) {
+        assert!(new_capacity >= self.capacity);
+        let old_end = self.usable_end();
+        self.capacity = new_capacity;
+        let new_end = self.usable_end();
+        debug_assert!(new_end >= old_end);
+
+        let len = new_end - old_end;
+        if len == 0 {
+            return;
+        }
+
+        // Either extend the last free block, if it is (close enough to)
+        // contiguous with the new space, or add a new block for it.
+        let last_block = self
+            .free_block_index_to_len
+            .last_key_value()
+            .map(|(idx, len)| (*idx, *len));
+        match last_block {
+            Some((last_index, last_len))
+                if blocks_are_contiguous(last_index, last_len, old_end) =>
+            {
+                let merged_block_len = new_end - last_index;
+                debug_assert_eq!(merged_block_len % ALIGN_U32, 0);
+                *self.free_block_index_to_len.get_mut(&last_index).unwrap() = merged_block_len;
+            }
+            _ if len >= MIN_BLOCK_SIZE => {
+                self.free_block_index_to_len.insert(old_end, len);
+            }
+            _ => {}
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_integrity();
+    }
+
+    /// The end of the allocatable range within this free list's capacity.
+    fn usable_end(&self) -> u32 {
+        let (start, len) = self.entire_range();
+        start + len
+    }
+
+    /// Get the start index and length of the entire allocatable range.
+    fn entire_range(&self) -> (u32, u32) {
         let end = u32::try_from(self.capacity).unwrap_or_else(|_| {
             assert!(self.capacity > usize::try_from(u32::MAX).unwrap());
             u32::MAX
@@ -270,6 +320,12 @@ impl FreeList {
         let start = ALIGN_U32;
 
         let len = round_u32_down_to_pow2(end.saturating_sub(start), ALIGN_U32);
+        (start, len)
+    }
+
+    /// Reset this free list, making the whole range available for allocation.
+    pub fn reset(&mut self) {
+        let (start, len) = self.entire_range();
 
         let entire_range = if len >= MIN_BLOCK_SIZE {
             Some((start, len))
@@ -773,4 +829,39 @@ mod tests {
             )
             .is_err());
     }
+
+    #[test]
+    fn grow() {
+        let min = usize::try_from(MIN_BLOCK_SIZE).unwrap();
+        let layout = Layout::from_size_align(min, ALIGN_USIZE).unwrap();
+
+        // Free list with room for exactly 2 min-sized blocks.
+        let mut free_list = FreeList::new(ALIGN_USIZE + min * 2);
+        let a = free_list.alloc(layout).unwrap().unwrap();
+        free_list.alloc(layout).unwrap().unwrap();
+        assert!(free_list.alloc(layout).unwrap().is_none());
+
+        // Growing while full adds a new block for the new space.
+        free_list.grow(ALIGN_USIZE + min * 4);
+        assert_eq!(free_list.capacity(), ALIGN_USIZE + min * 4);
+        assert_eq!(free_list.free_block_index_to_len.len(), 1);
+        free_list.alloc(layout).unwrap().unwrap();
+
+        // Growing when the last block is free extends that block.
+        free_list.grow(ALIGN_USIZE + min * 6);
+        assert_eq!(free_list.free_block_index_to_len.len(), 1);
+        assert_eq!(
+            free_list
+                .free_block_index_to_len
+                .values()
+                .copied()
+                .sum::<u32>(),
+            MIN_BLOCK_SIZE * 3
+        );
+
+        // Deallocating a block that is not adjacent to the free space at the
+        // end adds a separate free block.
+        free_list.dealloc(a, layout);
+        assert_eq!(free_list.free_block_index_to_len.len(), 2);
+    }
 }
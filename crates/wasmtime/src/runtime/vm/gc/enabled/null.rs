@@ -9,8 +9,8 @@ use crate::{
     prelude::*,
     vm::{
         mmap::AlignedLength, ExternRefHostDataId, ExternRefHostDataTable, GarbageCollection,
-        GcHeap, GcHeapObject, GcProgress, GcRootsIter, Mmap, SendSyncUnsafeCell, TypedGcRef,
-        VMGcHeader, VMGcRef,
+        GcHeap, GcHeapObject, GcHeapStats, GcProgress, GcRootsIter, Mmap, SendSyncUnsafeCell,
+        TypedGcRef, VMGcHeader, VMGcRef,
     },
     GcHeapOutOfMemory,
 };
@@ -26,9 +26,23 @@ use wasmtime_environ::{
 };
 
 /// The null collector.
-#[derive(Default)]
 pub struct NullCollector {
     layouts: NullTypeLayouts,
+    capacity: usize,
+}
+
+impl NullCollector {
+    /// Create a new null collector whose heaps are sized according to the
+    /// given policy.
+    ///
+    /// The null collector's heaps never grow, since its bump allocation is
+    /// inlined into compiled Wasm code, so only the initial size is used.
+    pub fn new(sizing: &crate::config::GcHeapSizing) -> Self {
+        NullCollector {
+            layouts: NullTypeLayouts::default(),
+            capacity: sizing.initial_size,
+        }
+    }
 }
 
 unsafe impl GcRuntime for NullCollector {
@@ -37,7 +51,7 @@ unsafe impl GcRuntime for NullCollector {
     }
 
     fn new_gc_heap(&self) -> Result<Box<dyn GcHeap>> {
-        let heap = NullHeap::new()?;
+        let heap = NullHeap::with_capacity(self.capacity)?;
         Ok(Box::new(heap) as _)
     }
 }
@@ -115,12 +129,7 @@ fn oom() -> Error {
 }
 
 impl NullHeap {
-    /// Construct a new, default heap for the null collector.
-    fn new() -> Result<Self> {
-        Self::with_capacity(super::DEFAULT_GC_HEAP_CAPACITY)
-    }
-
-    /// Create a new DRC heap with the given capacity.
+    /// Create a new null heap with the given capacity.
     fn with_capacity(capacity: usize) -> Result<Self> {
         let heap = Mmap::with_at_least(capacity)?;
         Ok(Self {
@@ -324,6 +333,39 @@ unsafe impl GcHeap for NullHeap {
         *next.get_mut() = NonZeroU32::new(1).unwrap();
         *no_gc_count = 0;
     }
+
+    fn capacity(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn maximum_capacity(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn grow(&mut self, _new_capacity: usize) {
+        unreachable!("the null collector's heap never grows")
+    }
+
+    fn stats(&self) -> GcHeapStats {
+        // Objects are bump-allocated inline by compiled Wasm code, so we don't
+        // know how many there are, only how far the bump pointer has moved.
+        // Nothing is ever freed, so everything allocated is still live.
+        let used = usize::try_from(unsafe { (*self.next.get()).get() } - 1).unwrap();
+        GcHeapStats {
+            bytes_allocated: u64::try_from(used).unwrap(),
+            live_bytes: used,
+            capacity: self.heap.len(),
+            ..GcHeapStats::default()
+        }
+    }
+
+    fn watch_weak_target(&mut self, _gc_ref: &VMGcRef) {
+        // Nothing is ever reclaimed, so weak targets stay alive forever.
+    }
+
+    fn unwatch_weak_target(&mut self, _gc_ref: &VMGcRef) {}
+
+    fn take_reclaimed_weak_targets(&mut self, _reclaimed: &mut Vec<VMGcRef>) {}
 }
 
 struct NullCollection {}
@@ -375,6 +375,66 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
     #[cfg(feature = "pooling-allocator")]
     fn reset(&mut self);
 
+    ////////////////////////////////////////////////////////////////////////////
+    // Heap Sizing Methods
+
+    /// Get this heap's current capacity, in bytes.
+    ///
+    /// This is the portion of the heap that is available for allocation. It
+    /// may be smaller than the `heap_slice`, which covers all of the memory
+    /// that is reserved for this heap to grow into.
+    fn capacity(&self) -> usize;
+
+    /// Get the maximum capacity, in bytes, that this heap can grow to.
+    ///
+    /// Heaps that do not support growing return their current capacity.
+    fn maximum_capacity(&self) -> usize;
+
+    /// Grow this heap's capacity to `new_capacity` bytes.
+    ///
+    /// Callers must ensure that `self.capacity() < new_capacity <=
+    /// self.maximum_capacity()`.
+    ///
+    /// Growing must not move the heap or otherwise change its `heap_slice`,
+    /// since pointers to and bounds of the heap slice are cached in
+    /// `VMContext`s and compiled Wasm code.
+    fn grow(&mut self, new_capacity: usize);
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Statistics Methods
+
+    /// Get allocation statistics for this heap.
+    ///
+    /// This should be cheap to call, as it may be called from call hooks and
+    /// other hot-ish paths.
+    fn stats(&self) -> GcHeapStats;
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Weak Reference Methods
+
+    /// Start watching the given object for reclamation, because it is the
+    /// target of a weak reference.
+    ///
+    /// Once a watched object is deallocated, it must be reported (exactly once)
+    /// by the next call to `take_reclaimed_weak_targets`, and it is no longer
+    /// watched. This must happen at the moment of deallocation, before the
+    /// object's heap index can be reused for a new allocation.
+    ///
+    /// Registering an object that is already being watched is a no-op.
+    ///
+    /// Collectors that never reclaim objects may implement this as a no-op.
+    fn watch_weak_target(&mut self, gc_ref: &VMGcRef);
+
+    /// Stop watching the given object for reclamation, because it is no longer
+    /// the target of any weak reference.
+    ///
+    /// Unregistering an object that is not being watched is a no-op.
+    fn unwatch_weak_target(&mut self, gc_ref: &VMGcRef);
+
+    /// Move every watched object that has been deallocated since the last call
+    /// to this method into `reclaimed`.
+    fn take_reclaimed_weak_targets(&mut self, reclaimed: &mut Vec<VMGcRef>);
+
     ////////////////////////////////////////////////////////////////////////////
     // Accessors for the raw bytes of the GC heap
 
@@ -501,6 +561,35 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
     }
 }
 
+/// Allocation statistics reported by a `GcHeap`.
+///
+/// Collection counts and timings are tracked by `GcStore`, not by the heap
+/// itself, since they are independent of the collector implementation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcHeapStats {
+    /// The total number of bytes allocated in this heap over its lifetime.
+    pub bytes_allocated: u64,
+
+    /// The total number of objects allocated in this heap over its lifetime.
+    pub objects_allocated: u64,
+
+    /// The number of bytes currently occupied by allocated objects.
+    pub live_bytes: usize,
+
+    /// The number of objects that are currently allocated.
+    pub live_objects: usize,
+
+    /// The current capacity of this heap, in bytes.
+    pub capacity: usize,
+
+    /// The number of times this heap's capacity has grown.
+    pub growths: u64,
+
+    /// The number of entries in the collector's over-approximated set of
+    /// on-stack GC roots, for collectors that keep one.
+    pub over_approximated_stack_roots: usize,
+}
+
 /// A list of GC roots.
 ///
 /// This is effectively a builder for a `GcRootsIter` that will be given to a GC
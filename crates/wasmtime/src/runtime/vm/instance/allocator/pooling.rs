@@ -486,7 +486,33 @@ impl PoolingInstanceAllocator {
     }
 }
 
+impl PoolingInstanceAllocator {
+    /// The number of core module instances allocated right now.
+    pub(crate) fn live_core_instances(&self) -> u64 {
+        self.live_core_instances.load(Ordering::Acquire)
+    }
+
+    /// The number of component instances allocated right now.
+    pub(crate) fn live_component_instances(&self) -> u64 {
+        self.live_component_instances.load(Ordering::Acquire)
+    }
+
+    /// The number of linear memories allocated right now.
+    pub(crate) fn live_memories(&self) -> usize {
+        self.memories.num_used_slots()
+    }
+
+    /// The number of tables allocated right now.
+    pub(crate) fn live_tables(&self) -> usize {
+        self.tables.num_used_slots()
+    }
+}
+
 unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
+    fn as_pooling(&self) -> Option<&PoolingInstanceAllocator> {
+        Some(self)
+    }
+
     #[cfg(feature = "component-model")]
     fn validate_component_impl<'a>(
         &self,
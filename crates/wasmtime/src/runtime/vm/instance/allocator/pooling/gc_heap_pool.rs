@@ -78,6 +78,10 @@ impl GcHeapPool {
     /// Deallocate a previously-allocated GC heap.
     pub fn deallocate(&self, allocation_index: GcHeapAllocationIndex, mut heap: Box<dyn GcHeap>) {
         debug_assert_ne!(allocation_index, GcHeapAllocationIndex::default());
+
+        // NB: This also shrinks the heap back down to its initial capacity, in
+        // case the previous store grew it, so that each store's heap growth is
+        // subject to its own resource limiter.
         heap.reset();
 
         // NB: Replace the heap before freeing the index. If we did it in the
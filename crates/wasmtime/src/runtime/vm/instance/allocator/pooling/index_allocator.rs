@@ -36,6 +36,11 @@ impl SimpleIndexAllocator {
         self.0.is_empty()
     }
 
+    /// How many slots are in use right now?
+    pub fn num_used_slots(&self) -> usize {
+        self.0.num_used_slots()
+    }
+
     pub fn alloc(&self) -> Option<SlotId> {
         self.0.alloc(None)
     }
@@ -185,6 +190,16 @@ impl ModuleAffinityIndexAllocator {
             .any(|s| matches!(s, SlotState::Used(_)))
     }
 
+    /// How many slots are in use right now?
+    pub fn num_used_slots(&self) -> usize {
+        let inner = self.0.lock().unwrap();
+        inner
+            .slot_state
+            .iter()
+            .filter(|s| matches!(s, SlotState::Used(_)))
+            .count()
+    }
+
     /// Allocate a new index from this allocator optionally using `id` as an
     /// affinity request if the allocation strategy supports it.
     ///
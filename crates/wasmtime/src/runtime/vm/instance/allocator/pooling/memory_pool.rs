@@ -313,6 +313,14 @@ impl MemoryPool {
         self.stripes.iter().all(|s| s.allocator.is_empty())
     }
 
+    /// How many memories are allocated right now?
+    pub fn num_used_slots(&self) -> usize {
+        self.stripes
+            .iter()
+            .map(|s| s.allocator.num_used_slots())
+            .sum()
+    }
+
     /// Allocate a single memory for the given instance allocation request.
     pub fn allocate(
         &self,
@@ -57,10 +57,14 @@
 use super::continuation::imp::VMContRef;
 use super::continuation::VMContObj;
 use crate::prelude::*;
+#[cfg(all(feature = "threads", feature = "async"))]
+use crate::runtime::vm::parking_spot::Sleep;
 use crate::runtime::vm::table::{Table, TableElementType};
 use crate::runtime::vm::vmcontext::VMFuncRef;
 use crate::runtime::vm::{HostResultHasUnwindSentinel, Instance, TrapReason, VMGcRef, VMStore};
 use core::convert::Infallible;
+#[cfg(all(feature = "threads", feature = "async"))]
+use core::pin::Pin;
 use core::ptr::NonNull;
 #[cfg(feature = "threads")]
 use core::time::Duration;
@@ -530,7 +534,7 @@ unsafe fn gc(store: &mut dyn VMStore, _instance: &mut Instance, gc_ref: u32) ->
         gc_store.expose_gc_ref_to_wasm(gc_ref);
     }
 
-    match store.maybe_async_gc(gc_ref)? {
+    match store.maybe_async_gc(gc_ref, None)? {
         None => Ok(0),
         Some(r) => {
             let raw = r.as_raw_u32();
@@ -585,8 +589,9 @@ unsafe fn gc_alloc_raw(
     {
         Some(r) => r,
         None => {
-            // If the allocation failed, do a GC to hopefully clean up space.
-            store.maybe_async_gc(None)?;
+            // If the allocation failed, do a GC to hopefully clean up space,
+            // growing the heap if necessary.
+            store.maybe_async_gc(None, Some(u64::try_from(layout.size()).unwrap()))?;
 
             // And then try again.
             store
@@ -712,7 +717,8 @@ unsafe fn array_new_data(
         None => {
             // Collect garbage to hopefully free up space, then try the
             // allocation again.
-            store.maybe_async_gc(None)?;
+            let bytes_needed = array_layout.layout(len).size();
+            store.maybe_async_gc(None, Some(u64::try_from(bytes_needed).unwrap()))?;
             store
                 .store_opaque_mut()
                 .unwrap_gc_store_mut()
@@ -891,7 +897,9 @@ unsafe fn array_new_elem(
             Err(e) if e.is::<GcHeapOutOfMemory<()>>() => {
                 // Collect garbage to hopefully free up space, then try the
                 // allocation again.
-                store.maybe_async_gc(None)?;
+                let len = u32::try_from(vals.len()).unwrap();
+                let bytes_needed = pre.layout().layout(len).size();
+                store.maybe_async_gc(None, Some(u64::try_from(bytes_needed).unwrap()))?;
                 ArrayRef::_new_fixed(store, &pre, &vals)?
             }
             Err(e) => return Err(e),
@@ -1106,35 +1114,71 @@ fn memory_atomic_notify(
 // Implementation of `memory.atomic.wait32` for locally defined memories.
 #[cfg(feature = "threads")]
 fn memory_atomic_wait32(
-    _store: &mut dyn VMStore,
+    #[cfg_attr(not(feature = "async"), allow(unused_variables))] store: &mut dyn VMStore,
     instance: &mut Instance,
     memory_index: u32,
     addr_index: u64,
     expected: u32,
     timeout: u64,
-) -> Result<u32, Trap> {
+) -> Result<u32, TrapReason> {
     let timeout = (timeout as i64 >= 0).then(|| Duration::from_nanos(timeout));
-    let memory = MemoryIndex::from_u32(memory_index);
-    Ok(instance
-        .get_runtime_memory(memory)
-        .atomic_wait32(addr_index, expected, timeout)? as u32)
+    let memory = instance.get_runtime_memory(MemoryIndex::from_u32(memory_index));
+    #[cfg(feature = "async")]
+    if let (Some((cx, timeout)), Some(shared)) =
+        (async_wait_cx(store, timeout), memory.as_shared_memory())
+    {
+        let mut wait = shared.atomic_wait32_async(addr_index, expected, timeout)?;
+        // SAFETY: the wait future only borrows the shared memory, which is
+        // kept alive by `instance` for the duration of this libcall.
+        return Ok(unsafe { cx.block_on(Pin::new(&mut wait))? } as u32);
+    }
+    Ok(memory.atomic_wait32(addr_index, expected, timeout)? as u32)
 }
 
 // Implementation of `memory.atomic.wait64` for locally defined memories.
 #[cfg(feature = "threads")]
 fn memory_atomic_wait64(
-    _store: &mut dyn VMStore,
+    #[cfg_attr(not(feature = "async"), allow(unused_variables))] store: &mut dyn VMStore,
     instance: &mut Instance,
     memory_index: u32,
     addr_index: u64,
     expected: u64,
     timeout: u64,
-) -> Result<u32, Trap> {
+) -> Result<u32, TrapReason> {
     let timeout = (timeout as i64 >= 0).then(|| Duration::from_nanos(timeout));
-    let memory = MemoryIndex::from_u32(memory_index);
-    Ok(instance
-        .get_runtime_memory(memory)
-        .atomic_wait64(addr_index, expected, timeout)? as u32)
+    let memory = instance.get_runtime_memory(MemoryIndex::from_u32(memory_index));
+    #[cfg(feature = "async")]
+    if let (Some((cx, timeout)), Some(shared)) =
+        (async_wait_cx(store, timeout), memory.as_shared_memory())
+    {
+        let mut wait = shared.atomic_wait64_async(addr_index, expected, timeout)?;
+        // SAFETY: the wait future only borrows the shared memory, which is
+        // kept alive by `instance` for the duration of this libcall.
+        return Ok(unsafe { cx.block_on(Pin::new(&mut wait))? } as u32);
+    }
+    Ok(memory.atomic_wait64(addr_index, expected, timeout)? as u32)
+}
+
+// Returns the context to suspend the current fiber with when the store is
+// configured to do so for `memory.atomic.wait*`, rather than blocking the
+// thread, along with the embedder's timer for the `timeout` if there is one.
+#[cfg(all(feature = "threads", feature = "async"))]
+fn async_wait_cx(
+    store: &mut dyn VMStore,
+    timeout: Option<Duration>,
+) -> Option<(crate::store::AsyncCx, Option<Sleep>)> {
+    let store = store.store_opaque();
+    let config = store.engine().config();
+    if !store.async_support() || !config.async_atomic_wait {
+        return None;
+    }
+    // A wait which may time out can only be suspended if there's a timer to
+    // resume it.
+    let timeout = match timeout {
+        Some(timeout) => Some(config.async_atomic_wait_timer.as_ref()?(timeout)),
+        None => None,
+    };
+    Some((store.async_cx()?, timeout))
 }
 
 // Hook for when an instance runs out of fuel.
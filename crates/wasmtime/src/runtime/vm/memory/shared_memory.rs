@@ -1,5 +1,7 @@
 use crate::prelude::*;
 use crate::runtime::vm::memory::{validate_atomic_addr, LocalMemory, MmapMemory};
+#[cfg(feature = "async")]
+use crate::runtime::vm::parking_spot::{AsyncWait, Sleep};
 use crate::runtime::vm::parking_spot::{ParkingSpot, Waiter};
 use crate::runtime::vm::vmcontext::VMMemoryDefinition;
 use crate::runtime::vm::{Memory, VMStore, WaitResult};
@@ -160,6 +162,48 @@ impl SharedMemory {
         })
     }
 
+    /// Same as `atomic_wait32`, but returns a future which suspends the
+    /// current task, rather than blocking the current thread, until notified
+    /// or until the `timeout` future resolves.
+    #[cfg(feature = "async")]
+    pub fn atomic_wait32_async(
+        &self,
+        addr_index: u64,
+        expected: u32,
+        timeout: Option<Sleep>,
+    ) -> Result<AsyncWait<'_>, Trap> {
+        let addr = validate_atomic_addr(&self.0.def.0, addr_index, 4, 4)?;
+        log::trace!(
+            "memory.atomic.wait32(addr={addr_index:#x}, expected={expected}, timeout={}) (async)",
+            timeout.is_some()
+        );
+
+        // SAFETY: `addr_index` was validated by `validate_atomic_addr` above.
+        let atomic = unsafe { AtomicU32::from_ptr(addr.cast()) };
+        Ok(self.0.spot.wait32_async(atomic, expected, timeout))
+    }
+
+    /// Same as `atomic_wait64`, but returns a future which suspends the
+    /// current task, rather than blocking the current thread, until notified
+    /// or until the `timeout` future resolves.
+    #[cfg(feature = "async")]
+    pub fn atomic_wait64_async(
+        &self,
+        addr_index: u64,
+        expected: u64,
+        timeout: Option<Sleep>,
+    ) -> Result<AsyncWait<'_>, Trap> {
+        let addr = validate_atomic_addr(&self.0.def.0, addr_index, 8, 8)?;
+        log::trace!(
+            "memory.atomic.wait64(addr={addr_index:#x}, expected={expected}, timeout={}) (async)",
+            timeout.is_some()
+        );
+
+        // SAFETY: `addr_index` was validated by `validate_atomic_addr` above.
+        let atomic = unsafe { AtomicU64::from_ptr(addr.cast()) };
+        Ok(self.0.spot.wait64_async(atomic, expected, timeout))
+    }
+
     pub(crate) fn page_size(&self) -> u64 {
         self.0.ty.page_size()
     }
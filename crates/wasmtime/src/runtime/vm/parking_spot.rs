@@ -8,15 +8,22 @@
 //!   on a queue keyed by some address.
 //! - *Unparking* refers to dequeuing a thread from a queue keyed by some address
 //!   and resuming it.
+//!
+//! Waiting can additionally be done asynchronously, in which case the waiter
+//! enqueued is a task's `Waker` rather than a thread, and unparking wakes the
+//! task instead.
 
 #![deny(missing_docs)]
 
 use crate::prelude::*;
 use crate::runtime::vm::{SendSyncPtr, WaitResult};
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::ptr::NonNull;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering::SeqCst};
 use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
 use std::thread::{self, Thread};
 use std::time::{Duration, Instant};
 
@@ -40,7 +47,7 @@ pub struct Waiter {
 struct WaiterInner {
     // NB: this field may be read concurrently, but is only written under the
     // lock of a `ParkingSpot`.
-    thread: Thread,
+    wake: Wake,
 
     // NB: these fields are only modified/read under the lock of a
     // `ParkingSpot`.
@@ -49,6 +56,42 @@ struct WaiterInner {
     prev: Option<SendSyncPtr<WaiterInner>>,
 }
 
+/// How a waiter is resumed once it's notified.
+enum Wake {
+    /// The waiter is a thread blocked in `ParkingSpot::wait`.
+    Thread(Thread),
+    /// The waiter is a task polling an `AsyncWait` future.
+    Task(Waker),
+}
+
+impl Wake {
+    fn wake(&self) {
+        match self {
+            Wake::Thread(thread) => thread.unpark(),
+            Wake::Task(waker) => waker.wake_by_ref(),
+        }
+    }
+}
+
+/// A future which resolves once an `AsyncWait` times out, provided by the
+/// embedder's executor.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A future, created by `ParkingSpot::wait32_async` or
+/// `ParkingSpot::wait64_async`, which resolves once the waiter is notified or
+/// its timeout elapses.
+pub struct AsyncWait<'a> {
+    spot: &'a ParkingSpot,
+    key: u64,
+    // The check performed on the first poll, `None` once the waiter has been
+    // enqueued.
+    validate: Option<Box<dyn FnOnce() -> bool + Send + 'a>>,
+    // Dropped as soon as the wait completes, which cancels the timer.
+    timeout: Option<Sleep>,
+    waiter: Waiter,
+    queued: bool,
+}
+
 impl ParkingSpot {
     /// Atomically validates if `atomic == expected` and, if so, blocks the
     /// current thread.
@@ -101,6 +144,43 @@ impl ParkingSpot {
         )
     }
 
+    /// Same as `wait32`, but instead of blocking the current thread this
+    /// returns a future which suspends the current task until notified.
+    ///
+    /// The check of `atomic == expected` happens when the future is first
+    /// polled, atomically with enqueuing the task's `Waker`. Dropping the
+    /// future before it completes removes the waiter from the queue.
+    ///
+    /// The wait times out once the `timeout` future, if any, resolves.
+    pub fn wait32_async<'a>(
+        &'a self,
+        atomic: &'a AtomicU32,
+        expected: u32,
+        timeout: Option<Sleep>,
+    ) -> AsyncWait<'a> {
+        AsyncWait::new(
+            self,
+            atomic.as_ptr() as u64,
+            Box::new(move || atomic.load(SeqCst) == expected),
+            timeout,
+        )
+    }
+
+    /// Same as `wait32_async`, but for 64-bit values.
+    pub fn wait64_async<'a>(
+        &'a self,
+        atomic: &'a AtomicU64,
+        expected: u64,
+        timeout: Option<Sleep>,
+    ) -> AsyncWait<'a> {
+        AsyncWait::new(
+            self,
+            atomic.as_ptr() as u64,
+            Box::new(move || atomic.load(SeqCst) == expected),
+            timeout,
+        )
+    }
+
     fn wait(
         &self,
         key: u64,
@@ -126,7 +206,7 @@ impl ParkingSpot {
                 next: None,
                 prev: None,
                 notified: false,
-                thread: thread::current(),
+                wake: Wake::Thread(thread::current()),
             })
         });
         assert!(waiter.next.is_none());
@@ -135,7 +215,7 @@ impl ParkingSpot {
         // Clear the `notified` flag if it was previously notified and
         // configure the thread to wakeup as our own.
         waiter.notified = false;
-        waiter.thread = thread::current();
+        waiter.wake = Wake::Thread(thread::current());
 
         let ptr = SendSyncPtr::new(NonNull::from(&mut **waiter));
         let spot = inner.entry(key).or_insert_with(Spot::default);
@@ -207,7 +287,7 @@ impl ParkingSpot {
                 let head = head.as_mut();
                 assert!(head.next.is_none());
                 head.notified = true;
-                head.thread.unpark();
+                head.wake.wake();
                 unparked += 1;
                 if unparked == n {
                     break;
@@ -236,6 +316,111 @@ impl Waiter {
     }
 }
 
+impl<'a> AsyncWait<'a> {
+    fn new(
+        spot: &'a ParkingSpot,
+        key: u64,
+        validate: Box<dyn FnOnce() -> bool + Send + 'a>,
+        timeout: Option<Sleep>,
+    ) -> AsyncWait<'a> {
+        AsyncWait {
+            spot,
+            key,
+            validate: Some(validate),
+            timeout,
+            waiter: Waiter::new(),
+            queued: false,
+        }
+    }
+}
+
+impl Future for AsyncWait<'_> {
+    type Output = WaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WaitResult> {
+        let this = &mut *self;
+        let mut inner = this
+            .spot
+            .inner
+            .lock()
+            .expect("failed to lock inner parking table");
+
+        // On the first poll perform the same validate-and-enqueue as `wait`
+        // does, except that the task's waker is what gets woken up.
+        if let Some(validate) = this.validate.take() {
+            if !validate() {
+                this.timeout = None;
+                return Poll::Ready(WaitResult::Mismatch);
+            }
+            let waiter = this.waiter.inner.insert(Box::new(WaiterInner {
+                next: None,
+                prev: None,
+                notified: false,
+                wake: Wake::Task(cx.waker().clone()),
+            }));
+            let ptr = SendSyncPtr::new(NonNull::from(&mut **waiter));
+            unsafe {
+                inner
+                    .entry(this.key)
+                    .or_insert_with(Spot::default)
+                    .push(ptr);
+            }
+            this.queued = true;
+        }
+
+        let Some(waiter) = this.waiter.inner.as_mut() else {
+            panic!("`AsyncWait` polled after completion");
+        };
+        if waiter.notified {
+            this.queued = false;
+            this.timeout = None;
+            return Poll::Ready(WaitResult::Ok);
+        }
+        if let Some(timeout) = &mut this.timeout {
+            if timeout.as_mut().poll(cx).is_ready() {
+                let ptr = SendSyncPtr::new(NonNull::from(&mut **waiter));
+                unsafe {
+                    inner.get_mut(&this.key).unwrap().remove(ptr);
+                }
+                this.queued = false;
+                this.timeout = None;
+                return Poll::Ready(WaitResult::TimedOut);
+            }
+        }
+
+        // The task may have moved since the last poll, so make sure that it's
+        // the most recent waker which is woken by `notify`.
+        if !matches!(&waiter.wake, Wake::Task(w) if w.will_wake(cx.waker())) {
+            waiter.wake = Wake::Task(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for AsyncWait<'_> {
+    fn drop(&mut self) {
+        if !self.queued {
+            return;
+        }
+
+        // A future dropped while waiting is still in the queue unless it was
+        // notified in the meantime, in which case the notification is lost
+        // just like it would be for a thread that stops waiting.
+        let mut inner = self
+            .spot
+            .inner
+            .lock()
+            .expect("failed to lock inner parking table");
+        let waiter = self.waiter.inner.as_mut().unwrap();
+        if !waiter.notified {
+            let ptr = SendSyncPtr::new(NonNull::from(&mut **waiter));
+            unsafe {
+                inner.get_mut(&self.key).unwrap().remove(ptr);
+            }
+        }
+    }
+}
+
 impl Spot {
     /// Adds `waiter` to the queue at the end.
     ///
@@ -306,6 +491,8 @@ impl Spot {
 mod tests {
     use super::{ParkingSpot, Waiter};
     use crate::prelude::*;
+    use crate::runtime::vm::WaitResult;
+    use std::future::Future;
     use std::sync::atomic::{AtomicU64, Ordering};
     use std::thread;
     use std::time::{Duration, Instant};
@@ -620,4 +807,92 @@ mod tests {
             }
         });
     }
+
+    fn thread_waker() -> std::task::Waker {
+        struct ThreadWaker(thread::Thread);
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        std::sync::Arc::new(ThreadWaker(thread::current())).into()
+    }
+
+    // A timer which sleeps on a thread of its own, in place of an executor's.
+    fn sleep(duration: Duration) -> super::Sleep {
+        let (tx, rx) = std::sync::mpsc::channel::<std::task::Waker>();
+        let deadline = Instant::now() + duration;
+        thread::spawn(move || {
+            thread::sleep(duration);
+            for waker in rx {
+                waker.wake();
+            }
+        });
+        Box::pin(std::future::poll_fn(move |cx| {
+            if Instant::now() >= deadline {
+                return std::task::Poll::Ready(());
+            }
+            let _ = tx.send(cx.waker().clone());
+            std::task::Poll::Pending
+        }))
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = thread_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(ret) = future.as_mut().poll(&mut cx) {
+                break ret;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn async_wait_notify() {
+        let parking_spot = ParkingSpot::default();
+        let atomic = AtomicU64::new(0);
+
+        let result = block_on(parking_spot.wait64_async(&atomic, 1, None));
+        assert_eq!(result, WaitResult::Mismatch);
+
+        thread::scope(|s| {
+            let waiter = s.spawn(|| loop {
+                let cur = atomic.load(Ordering::SeqCst);
+                if cur == 1 {
+                    break;
+                }
+                block_on(parking_spot.wait64_async(&atomic, cur, None));
+            });
+            atomic.store(1, Ordering::SeqCst);
+            while !waiter.is_finished() {
+                parking_spot.notify(&atomic, 1);
+                thread::yield_now();
+            }
+        });
+    }
+
+    #[test]
+    fn async_wait_timeout_and_drop() {
+        let parking_spot = ParkingSpot::default();
+        let atomic = AtomicU64::new(0);
+
+        let result =
+            block_on(parking_spot.wait64_async(&atomic, 0, Some(sleep(Duration::from_millis(1)))));
+        assert_eq!(result, WaitResult::TimedOut);
+        assert_eq!(parking_spot.notify(&atomic, 1), 0);
+
+        // A future dropped while enqueued no longer counts as a waiter.
+        let mut future = Box::pin(parking_spot.wait64_async(&atomic, 0, None));
+        let waker = thread_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(parking_spot.notify(&atomic, 1), 1);
+        assert!(future.as_mut().poll(&mut cx).is_ready());
+        let mut future = Box::pin(parking_spot.wait64_async(&atomic, 0, None));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        drop(future);
+        assert_eq!(parking_spot.notify(&atomic, 1), 0);
+    }
 }
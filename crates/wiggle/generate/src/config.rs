@@ -59,9 +59,17 @@ impl Parse for ConfigField {
             Ok(ConfigField::Error(input.parse()?))
         } else if lookahead.peek(Token![async]) {
             input.parse::<Token![async]>()?;
+            let offload_with = if input.peek(syn::token::Bracket) {
+                let content;
+                let _ = bracketed!(content in input);
+                Some(content.parse()?)
+            } else {
+                None
+            };
             input.parse::<Token![:]>()?;
             Ok(ConfigField::Async(AsyncConf {
                 block_with: None,
+                offload_with,
                 functions: input.parse()?,
             }))
         } else if lookahead.peek(kw::block_on) {
@@ -76,6 +84,7 @@ impl Parse for ConfigField {
             input.parse::<Token![:]>()?;
             Ok(ConfigField::Async(AsyncConf {
                 block_with: Some(block_with),
+                offload_with: None,
                 functions: input.parse()?,
             }))
         } else if lookahead.peek(kw::wasmtime) {
@@ -389,6 +398,7 @@ impl std::fmt::Debug for UserErrorConfField {
 /// Modules and funcs that have async signatures
 pub struct AsyncConf {
     block_with: Option<TokenStream>,
+    offload_with: Option<TokenStream>,
     functions: AsyncFunctions,
 }
 
@@ -398,14 +408,16 @@ pub enum Asyncness {
     Sync,
     /// Wiggle function is asynchronous, but wasmtime Func is synchronous
     Blocking { block_with: TokenStream },
-    /// Wiggle function and wasmtime Func are asynchronous.
-    Async,
+    /// Wiggle function and wasmtime Func are asynchronous. The future of each
+    /// host call is passed through `offload_with`, if any, before it's
+    /// returned to wasmtime.
+    Async { offload_with: Option<TokenStream> },
 }
 
 impl Asyncness {
     pub fn is_async(&self) -> bool {
         match self {
-            Self::Async => true,
+            Self::Async { .. } => true,
             _ => false,
         }
     }
@@ -440,7 +452,9 @@ impl AsyncConf {
             Some(block_with) => Asyncness::Blocking {
                 block_with: block_with.clone(),
             },
-            None => Asyncness::Async,
+            None => Asyncness::Async {
+                offload_with: self.offload_with.clone(),
+            },
         };
         match &self.functions {
             AsyncFunctions::Some(fs) => {
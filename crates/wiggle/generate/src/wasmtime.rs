@@ -131,14 +131,20 @@ fn generate_func(
     };
 
     match asyncness {
-        Asyncness::Async => {
+        Asyncness::Async { offload_with } => {
             let arg_decls = quote! { ( #(#arg_names,)* ) : ( #(#arg_tys,)* ) };
+            let future = match offload_with {
+                Some(offload_with) => {
+                    quote!(#offload_with(#module_str, #field_str, async move { #body }))
+                }
+                None => quote!(async move { #body }),
+            };
             quote! {
                 linker.func_wrap_async(
                     #module_str,
                     #field_str,
                     move |mut caller: wiggle::wasmtime_crate::Caller<'_, T>, #arg_decls| {
-                        Box::new(async move { #body })
+                        Box::new(#future)
                     },
                 )?;
             }
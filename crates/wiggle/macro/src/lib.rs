@@ -43,6 +43,14 @@ use syn::parse_macro_input;
 ///       `errno => trappable AnErrorType`.
 /// * Optional: `async` takes a set of witx modules and functions which are
 ///   made Rust `async` functions in the module trait.
+///     * With the syntax `async[path::to::offload]: ...`, the future of each
+///       of these host calls is passed to `path::to::offload` when added to a
+///       `wasmtime::Linker`, along with the witx module and function names,
+///       and it returns the future handed to Wasmtime. This is a hook to run
+///       blocking host calls off of an async executor, e.g. with
+///       `tokio::task::block_in_place`. It must accept `(&'static str,
+///       &'static str, impl Future + Send)` and return a `Future + Send` with
+///       the same output.
 ///
 /// ## Example
 ///
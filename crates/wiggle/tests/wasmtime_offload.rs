@@ -0,0 +1,95 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wasmtime::{Config, Engine, Linker, Module, Store, Val};
+use wiggle::GuestMemory;
+
+wiggle::from_witx!({
+    witx: ["$CARGO_MANIFEST_DIR/tests/atoms.witx"],
+    async[crate::offload]: *,
+});
+
+static OFFLOADED: AtomicUsize = AtomicUsize::new(0);
+
+// Count the host calls passed through here, and poll them as blocking code.
+fn offload<F: Future + Send>(
+    module: &'static str,
+    func: &'static str,
+    future: F,
+) -> impl Future<Output = F::Output> + Send {
+    assert_eq!((module, func), ("atoms", "double_int_return_float"));
+    OFFLOADED.fetch_add(1, Ordering::SeqCst);
+    let mut future = Box::pin(future);
+    std::future::poll_fn(move |cx| tokio::task::block_in_place(|| future.as_mut().poll(cx)))
+}
+
+pub struct Ctx;
+impl wiggle::GuestErrorType for types::Errno {
+    fn success() -> Self {
+        types::Errno::Ok
+    }
+}
+
+#[wiggle::async_trait]
+impl atoms::Atoms for Ctx {
+    async fn int_float_args(
+        &mut self,
+        _: &mut GuestMemory<'_>,
+        _an_int: u32,
+        _an_float: f32,
+    ) -> Result<(), types::Errno> {
+        Ok(())
+    }
+    async fn double_int_return_float(
+        &mut self,
+        _: &mut GuestMemory<'_>,
+        an_int: u32,
+    ) -> Result<types::AliasToFloat, types::Errno> {
+        // Still pending for a bit, to make sure offloaded futures are woken.
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        Ok((an_int as f32) * 2.0)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_offloaded_host_func() {
+    let engine = Engine::new(Config::new().async_support(true)).unwrap();
+    let mut store = Store::new(&engine, Ctx);
+    let mut linker = Linker::new(&engine);
+    atoms::add_to_linker(&mut linker, |cx| cx).unwrap();
+    let shim_mod = Module::new(
+        &engine,
+        r#"
+        (module
+            (import "atoms" "double_int_return_float" (func $double_int_return_float (param i32 i32) (result i32)))
+            (memory 1)
+            (export "memory" (memory 0))
+            (func (export "double_int_return_float_shim") (param i32 i32) (result i32)
+                local.get 0
+                local.get 1
+                call $double_int_return_float
+            )
+        )
+        "#,
+    )
+    .unwrap();
+    let shim_inst = linker
+        .instantiate_async(&mut store, &shim_mod)
+        .await
+        .unwrap();
+
+    let before = OFFLOADED.load(Ordering::SeqCst);
+    let mut results = [Val::I32(0)];
+    shim_inst
+        .get_func(&mut store, "double_int_return_float_shim")
+        .unwrap()
+        .call_async(&mut store, &[21i32.into(), 0i32.into()], &mut results)
+        .await
+        .unwrap();
+    assert_eq!(results[0].unwrap_i32(), types::Errno::Ok as i32);
+    assert_eq!(OFFLOADED.load(Ordering::SeqCst), before + 1);
+
+    let mem = shim_inst.get_memory(&mut store, "memory").unwrap();
+    let mut result_bytes = [0; 4];
+    mem.read(&store, 0, &mut result_bytes).unwrap();
+    assert_eq!(f32::from_le_bytes(result_bytes), 42.0);
+}
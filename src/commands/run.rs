@@ -24,13 +24,13 @@ use wasmtime_wasi_nn::wit::WasiNnView;
 use wasmtime_wasi_threads::WasiThreadsCtx;
 
 #[cfg(feature = "wasi-config")]
-use wasmtime_wasi_config::{WasiConfig, WasiConfigVariables};
+use wasmtime_wasi_config::{ConfigProvider, WasiConfig};
 #[cfg(feature = "wasi-http")]
 use wasmtime_wasi_http::{
     WasiHttpCtx, DEFAULT_OUTGOING_BODY_BUFFER_CHUNKS, DEFAULT_OUTGOING_BODY_CHUNK_SIZE,
 };
 #[cfg(feature = "wasi-keyvalue")]
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder};
+use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx};
 
 fn parse_preloads(s: &str) -> Result<(String, PathBuf)> {
     let parts: Vec<&str> = s.splitn(2, '=').collect();
@@ -69,6 +69,36 @@ pub struct RunCommand {
     #[arg(long)]
     pub argv0: Option<String>,
 
+    /// Print statistics about GC heap allocations and collections to stderr
+    /// once the program finishes running.
+    #[arg(long)]
+    pub gc_stats: bool,
+
+    /// Record the nondeterministic results of the WASI calls made by the
+    /// program, such as clocks, randomness, stdin, file reads and socket data,
+    /// to this file.
+    ///
+    /// The recorded run can be reproduced later with `--replay`.
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay a run recorded with `--record`, returning the results of WASI
+    /// calls from this file instead of from the host.
+    ///
+    /// The program must be run with the same arguments, environment and
+    /// preopened directories as when it was recorded.
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Write each line of the program's stdout and stderr as a JSON object,
+    /// with the line's timestamp and stream, to the host's stdout and stderr
+    /// respectively.
+    ///
+    /// Lines longer than 64 KiB are truncated, which is recorded in the
+    /// `truncated` field of the object.
+    #[arg(long)]
+    pub log_stdout_json: bool,
+
     /// The WebAssembly module to run and arguments to pass to it.
     ///
     /// Arguments passed to the wasm module will be configured as WASI CLI
@@ -91,6 +121,11 @@ impl RunCommand {
 
         let mut config = self.run.common.config(None)?;
         config.async_support(true);
+        #[cfg(feature = "wasi-threads")]
+        if self.run.common.wasi.green_threads.is_some() {
+            config.async_atomic_wait(true);
+            config.async_atomic_wait_timer(|timeout| Box::pin(tokio::time::sleep(timeout)));
+        }
 
         if self.run.common.wasm.timeout.is_some() {
             config.epoch_interruption(true);
@@ -235,8 +270,10 @@ impl RunCommand {
             Err(anyhow::Error::from(wasmtime::Trap::Interrupt))
                 .with_context(|| format!("timed out after {elapsed}"))
         }) {
-            Ok(()) => (),
+            Ok(()) => self.report_after_run(&mut store),
             Err(e) => {
+                self.report_after_run(&mut store);
+
                 // Exit the process if Wasmtime understands the error;
                 // otherwise, fall back on Rust's default error printing/return
                 // code.
@@ -265,6 +302,29 @@ impl RunCommand {
         Ok(())
     }
 
+    /// Writes out the reports and output which were requested to be produced
+    /// once the program finishes running, whether or not it succeeded.
+    fn report_after_run(&self, store: &mut Store<Host>) {
+        if let Some(path) = &self.run.common.debug.gc_heap_snapshot {
+            if let Err(e) = write_gc_heap_snapshot(store, path) {
+                eprintln!("warning: failed to write GC heap snapshot: {e:#}");
+            }
+        }
+        if self.gc_stats {
+            print_gc_stats(store);
+        }
+        if let Some(trace) = &store.data().wasi_trace {
+            if let Err(e) = trace.flush() {
+                eprintln!("warning: failed to write WASI trace: {e}");
+            }
+        }
+        for capture in &store.data().stdio_captures {
+            if let Err(e) = capture.finish() {
+                eprintln!("warning: failed to write captured output: {e}");
+            }
+        }
+    }
+
     fn compute_argv(&self) -> Result<Vec<String>> {
         let mut result = Vec::new();
 
@@ -720,19 +780,14 @@ impl RunCommand {
                         bail!("Cannot enable wasi-config for core wasm modules");
                     }
                     CliLinker::Component(linker) => {
-                        let vars = WasiConfigVariables::from_iter(
-                            self.run
-                                .common
-                                .wasi
-                                .config_var
-                                .iter()
-                                .map(|v| (v.key.clone(), v.value.clone())),
-                        );
-
-                        wasmtime_wasi_config::add_to_linker(linker, |h| {
-                            WasiConfig::new(Arc::get_mut(h.wasi_config.as_mut().unwrap()).unwrap())
+                        // The config file is only read once, as commands
+                        // usually don't run for long.
+                        let (provider, _file) = self.run.wasi_config_provider()?;
+
+                        wasmtime_wasi_config::add_to_linker_async(linker, |h| {
+                            WasiConfig::new(&**h.wasi_config.as_ref().unwrap())
                         })?;
-                        store.data_mut().wasi_config = Some(Arc::new(vars));
+                        store.data_mut().wasi_config = Some(provider);
                     }
                 }
             }
@@ -750,16 +805,7 @@ impl RunCommand {
                         bail!("Cannot enable wasi-keyvalue for core wasm modules");
                     }
                     CliLinker::Component(linker) => {
-                        let ctx = WasiKeyValueCtxBuilder::new()
-                            .in_memory_data(
-                                self.run
-                                    .common
-                                    .wasi
-                                    .keyvalue_in_memory_data
-                                    .iter()
-                                    .map(|v| (v.key.clone(), v.value.clone())),
-                            )
-                            .build();
+                        let ctx = self.run.wasi_keyvalue_ctx()?;
 
                         wasmtime_wasi_keyvalue::add_to_linker(linker, |h| {
                             let preview2_ctx =
@@ -798,10 +844,18 @@ impl RunCommand {
                 wasmtime_wasi_threads::add_to_linker(linker, store, &module, |host| {
                     host.wasi_threads.as_ref().unwrap()
                 })?;
-                store.data_mut().wasi_threads = Some(Arc::new(WasiThreadsCtx::new(
-                    module.clone(),
-                    Arc::new(linker.clone()),
-                )?));
+                let mut wasi_threads =
+                    WasiThreadsCtx::new(module.clone(), Arc::new(linker.clone()))?;
+                if let Some(max) = self.run.common.wasi.max_threads {
+                    wasi_threads.max_threads(max);
+                }
+                if let Some(idle_threads) = self.run.common.wasi.thread_pool {
+                    wasi_threads.thread_pool(idle_threads);
+                }
+                if let Some(host_threads) = self.run.common.wasi.green_threads {
+                    wasi_threads.green_threads(host_threads)?;
+                }
+                store.data_mut().wasi_threads = Some(Arc::new(wasi_threads));
             }
         }
 
@@ -821,7 +875,10 @@ impl RunCommand {
                     }
                 }
 
-                store.data_mut().wasi_http = Some(Arc::new(WasiHttpCtx::new()));
+                let mut http = WasiHttpCtx::new();
+                http.set_tls_config(self.run.wasi_http_tls_config()?);
+                http.set_egress_policy(self.run.wasi_http_egress_policy()?);
+                store.data_mut().wasi_http = Some(Arc::new(http));
             }
         }
 
@@ -829,6 +886,13 @@ impl RunCommand {
     }
 
     fn set_preview1_ctx(&self, store: &mut Store<Host>) -> Result<()> {
+        if self.record.is_some() || self.replay.is_some() {
+            bail!("--record and --replay are not supported with `-Spreview2=n` or wasi-threads");
+        }
+        if self.log_stdout_json {
+            bail!("--log-stdout-json is not supported with `-Spreview2=n` or wasi-threads");
+        }
+
         let mut builder = WasiCtxBuilder::new();
         builder.inherit_stdio().args(&self.compute_argv()?)?;
 
@@ -879,6 +943,31 @@ impl RunCommand {
         let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
         builder.inherit_stdio().args(&self.compute_argv()?);
         self.run.configure_wasip2(&mut builder)?;
+        if let Some(path) = &self.record {
+            let trace = wasmtime_wasi::Trace::create(path)
+                .with_context(|| format!("failed to create WASI trace `{}`", path.display()))?;
+            builder.trace(trace.clone());
+            store.data_mut().wasi_trace = Some(trace);
+        }
+        if let Some(path) = &self.replay {
+            let trace = wasmtime_wasi::Trace::open(path)
+                .with_context(|| format!("failed to open WASI trace `{}`", path.display()))?;
+            builder.trace(trace);
+        }
+        if self.log_stdout_json {
+            use wasmtime_wasi::{CaptureSink, CaptureStream};
+
+            let stdout = CaptureStream::new(
+                "stdout",
+                CaptureSink::JsonLines(Box::new(std::io::stdout())),
+            );
+            let stderr = CaptureStream::new(
+                "stderr",
+                CaptureSink::JsonLines(Box::new(std::io::stderr())),
+            );
+            builder.stdout(stdout.clone()).stderr(stderr.clone());
+            store.data_mut().stdio_captures = vec![stdout, stderr];
+        }
         let ctx = builder.build_p1();
         store.data_mut().preview2_ctx = Some(Arc::new(Mutex::new(ctx)));
         Ok(())
@@ -909,6 +998,13 @@ struct Host {
     // access.
     preview2_ctx: Option<Arc<Mutex<wasmtime_wasi::preview1::WasiP1Ctx>>>,
 
+    // The trace recorded with `--record`, flushed once the program finishes.
+    wasi_trace: Option<wasmtime_wasi::Trace>,
+
+    // The guest's stdout and stderr captured with `--log-stdout-json`, whose
+    // final lines are written once the program finishes.
+    stdio_captures: Vec<wasmtime_wasi::CaptureStream>,
+
     #[cfg(feature = "wasi-nn")]
     wasi_nn_wit: Option<Arc<wasmtime_wasi_nn::wit::WasiNnCtx>>,
     #[cfg(feature = "wasi-nn")]
@@ -927,7 +1023,7 @@ struct Host {
     guest_profiler: Option<Arc<wasmtime::GuestProfiler>>,
 
     #[cfg(feature = "wasi-config")]
-    wasi_config: Option<Arc<WasiConfigVariables>>,
+    wasi_config: Option<Arc<dyn ConfigProvider>>,
     #[cfg(feature = "wasi-keyvalue")]
     wasi_keyvalue: Option<Arc<WasiKeyValueCtx>>,
 }
@@ -1026,3 +1122,36 @@ fn write_core_dump(
         .with_context(|| format!("failed to write core dump file at `{path}`"))?;
     Ok(())
 }
+
+#[cfg(feature = "gc")]
+fn write_gc_heap_snapshot(store: &mut Store<Host>, path: &str) -> Result<()> {
+    let snapshot = store.gc_heap_snapshot()?;
+    std::fs::write(path, snapshot.to_v8_heap_snapshot())
+        .with_context(|| format!("failed to write GC heap snapshot at `{path}`"))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "gc"))]
+fn write_gc_heap_snapshot(_store: &mut Store<Host>, _path: &str) -> Result<()> {
+    bail!("support for GC heap snapshots disabled at compile time")
+}
+
+#[cfg(feature = "gc")]
+fn print_gc_stats(store: &Store<Host>) {
+    let stats = store.gc_stats();
+    eprintln!("GC statistics:");
+    eprintln!("  bytes allocated:       {}", stats.bytes_allocated);
+    eprintln!("  objects allocated:     {}", stats.objects_allocated);
+    eprintln!("  live bytes:            {}", stats.live_bytes);
+    eprintln!("  live objects:          {}", stats.live_objects);
+    eprintln!("  heap capacity:         {}", stats.heap_capacity);
+    eprintln!("  heap growths:          {}", stats.heap_growths);
+    eprintln!("  collections:           {}", stats.collections);
+    eprintln!("  collection time:       {:?}", stats.collection_time);
+    eprintln!("  activations table len: {}", stats.activations_table_len);
+}
+
+#[cfg(not(feature = "gc"))]
+fn print_gc_stats(_store: &Store<Host>) {
+    eprintln!("warning: support for GC statistics disabled at compile time");
+}
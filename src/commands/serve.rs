@@ -1,36 +1,52 @@
 use crate::common::{Profile, RunCommon, RunTarget};
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Parser;
+use metrics::Metrics;
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::{
     path::PathBuf,
+    pin::Pin,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc,
+        Arc, Mutex,
     },
+    task::{self, Poll},
+    time::{Duration, Instant},
 };
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 use wasmtime::component::Linker;
 use wasmtime::{Engine, Store, StoreLimits};
 use wasmtime_wasi::{IoView, StreamError, StreamResult, WasiCtx, WasiCtxBuilder, WasiView};
 use wasmtime_wasi_http::bindings::http::types::Scheme;
-use wasmtime_wasi_http::bindings::ProxyPre;
+use wasmtime_wasi_http::bindings::{Proxy, ProxyPre};
 use wasmtime_wasi_http::io::TokioIo;
+use wasmtime_wasi_http::policy::EgressPolicy;
+use wasmtime_wasi_http::pool::{ConnectionPool, ConnectionPoolLimits};
+use wasmtime_wasi_http::tls::TlsConfig;
 use wasmtime_wasi_http::{
     body::HyperOutgoingBody, WasiHttpCtx, WasiHttpView, DEFAULT_OUTGOING_BODY_BUFFER_CHUNKS,
     DEFAULT_OUTGOING_BODY_CHUNK_SIZE,
 };
 
 #[cfg(feature = "wasi-config")]
-use wasmtime_wasi_config::{WasiConfig, WasiConfigVariables};
+use wasmtime_wasi_config::{ConfigProvider, WasiConfig};
 #[cfg(feature = "wasi-keyvalue")]
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder};
+use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx};
 #[cfg(feature = "wasi-nn")]
-use wasmtime_wasi_nn::wit::WasiNnCtx;
+use wasmtime_wasi_nn::{wit::WasiNnCtx, Registry};
+
+mod metrics;
 
 struct Host {
     table: wasmtime::component::ResourceTable,
     ctx: WasiCtx,
     http: WasiHttpCtx,
+    /// The id of the request being handled, shared with the streams which
+    /// prefix the guest's output with it.
+    req_id: Arc<AtomicU64>,
     http_outgoing_body_buffer_chunks: Option<usize>,
     http_outgoing_body_chunk_size: Option<usize>,
 
@@ -40,7 +56,7 @@ struct Host {
     nn: Option<WasiNnCtx>,
 
     #[cfg(feature = "wasi-config")]
-    wasi_config: Option<WasiConfigVariables>,
+    wasi_config: Option<Arc<dyn ConfigProvider>>,
 
     #[cfg(feature = "wasi-keyvalue")]
     wasi_keyvalue: Option<WasiKeyValueCtx>,
@@ -93,9 +109,99 @@ pub struct ServeCommand {
     #[arg(long = "no-logging-prefix")]
     no_logging_prefix: bool,
 
+    /// Reuse each instance of the component for up to this many requests,
+    /// one at a time, instead of creating a new instance for every request.
+    ///
+    /// This avoids repeating expensive initialization in the component, but
+    /// lets requests observe state left behind by earlier requests. An
+    /// instance which traps is never reused. The `REQUEST_ID` environment
+    /// variable is the id of the first request handled by the instance.
+    #[arg(long = "instance-reuse", value_name = "N")]
+    instance_reuse: Option<NonZeroUsize>,
+
+    /// The maximum number of idle instances kept for reuse with
+    /// `--instance-reuse`.
+    #[arg(long = "max-idle-instances", value_name = "N", default_value_t = 16)]
+    max_idle_instances: usize,
+
+    /// Serve HTTPS with the PEM-encoded certificate chain in this file.
+    ///
+    /// Requires `--tls-key`.
+    #[arg(long = "tls-cert", value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM-encoded private key of the certificate given with
+    /// `--tls-cert`.
+    #[arg(long = "tls-key", value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Only serve HTTP/1.1.
+    ///
+    /// By default HTTP/2 is also served, negotiated with ALPN over TLS and
+    /// with prior knowledge over cleartext connections.
+    #[arg(long = "no-http2")]
+    no_http2: bool,
+
+    /// How long to wait for in-flight requests to complete after receiving
+    /// a signal to shut down, before exiting anyway.
+    ///
+    /// On receiving `SIGINT` or `SIGTERM` the server stops accepting new
+    /// connections and asks clients to close their open connections once
+    /// their current requests complete. A second signal exits immediately.
+    #[arg(
+        long = "shutdown-timeout",
+        value_name = "DURATION",
+        default_value = "30s",
+        value_parser = humantime::parse_duration,
+    )]
+    shutdown_timeout: Duration,
+
+    /// Serve metrics about requests and instances at `/metrics` on this
+    /// socket address, in the Prometheus text format.
+    ///
+    /// The metrics include request counts and latencies by status code,
+    /// traps, epoch timeouts, instantiation times, fuel consumed and the
+    /// occupancy of the pooling allocator.
+    #[arg(long = "metrics-addr", value_name = "SOCKADDR")]
+    metrics_addr: Option<SocketAddr>,
+
     /// The WebAssembly component to run.
     #[arg(value_name = "WASM", required = true)]
     component: PathBuf,
+
+    /// The `wasi-keyvalue` context shared by all requests, so that they all see
+    /// the same data.
+    #[cfg(feature = "wasi-keyvalue")]
+    #[arg(skip)]
+    wasi_keyvalue: Option<WasiKeyValueCtx>,
+
+    /// The wasi-nn graphs shared by all requests, so that each graph is only
+    /// loaded once.
+    #[cfg(feature = "wasi-nn")]
+    #[arg(skip)]
+    nn_registry: Option<wasmtime_wasi_nn::LazyRegistry>,
+
+    /// The provider of `wasi-config` variables shared by all requests, so
+    /// that a config file is only read again when it changes rather than for
+    /// every request.
+    #[cfg(feature = "wasi-config")]
+    #[arg(skip)]
+    wasi_config: Option<Arc<dyn ConfigProvider>>,
+
+    /// The pool of outgoing connections shared by all requests, so that they
+    /// can reuse each other's connections.
+    #[arg(skip)]
+    connection_pool: ConnectionPool,
+
+    /// The TLS configuration of outgoing requests, created once from the
+    /// `-S http-tls-*` flags.
+    #[arg(skip)]
+    tls_config: Arc<TlsConfig>,
+
+    /// The policy applied to outgoing requests, shared by all requests so
+    /// that its limit on concurrent requests applies to all of them together.
+    #[arg(skip)]
+    egress_policy: EgressPolicy,
 }
 
 impl ServeCommand {
@@ -134,19 +240,37 @@ impl ServeCommand {
             .enable_io()
             .build()?;
 
-        runtime.block_on(async move {
-            tokio::select! {
-                _ = tokio::signal::ctrl_c() => {
-                    Ok::<_, anyhow::Error>(())
-                }
+        runtime.block_on(self.serve())
+    }
 
-                res = self.serve() => {
-                    res
-                }
-            }
-        })?;
+    /// Create the acceptor of TLS connections configured with `--tls-cert`
+    /// and `--tls-key`, if any.
+    fn tls_acceptor(&self) -> Result<Option<TlsAcceptor>> {
+        let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+            return Ok(None);
+        };
 
-        Ok(())
+        #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+        {
+            let _ = (cert, key);
+            bail!("serving HTTPS is not supported on this platform");
+        }
+
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        {
+            let cert = std::fs::read(cert)
+                .with_context(|| format!("failed to read `{}`", cert.display()))?;
+            let key = std::fs::read(key)
+                .with_context(|| format!("failed to read `{}`", key.display()))?;
+            let mut alpn = Vec::new();
+            if !self.no_http2 {
+                alpn.push(b"h2".to_vec());
+            }
+            alpn.push(b"http/1.1".to_vec());
+            let config = tls_server_config(&cert, &key, alpn)
+                .context("invalid `--tls-cert` or `--tls-key`")?;
+            Ok(Some(TlsAcceptor::from(Arc::new(config))))
+        }
     }
 
     fn new_store(&self, engine: &Engine, req_id: u64) -> Result<Store<Host>> {
@@ -155,22 +279,25 @@ impl ServeCommand {
 
         builder.env("REQUEST_ID", req_id.to_string());
 
-        let stdout_prefix: String;
-        let stderr_prefix: String;
-        if self.no_logging_prefix {
-            stdout_prefix = "".to_string();
-            stderr_prefix = "".to_string();
-        } else {
-            stdout_prefix = format!("stdout [{req_id}] :: ");
-            stderr_prefix = format!("stderr [{req_id}] :: ");
-        }
-        builder.stdout(LogStream::new(stdout_prefix, Output::Stdout));
-        builder.stderr(LogStream::new(stderr_prefix, Output::Stderr));
+        let req_id = Arc::new(AtomicU64::new(req_id));
+        let prefix_id = (!self.no_logging_prefix).then(|| req_id.clone());
+        builder.stdout(LogStream::new(prefix_id.clone(), Output::Stdout));
+        builder.stderr(LogStream::new(prefix_id, Output::Stderr));
+
+        let mut http = WasiHttpCtx::new();
+        http.set_connection_pool(Some(self.connection_pool.clone()));
+        http.set_connection_pool_limits(ConnectionPoolLimits {
+            http2: true,
+            ..ConnectionPoolLimits::default()
+        });
+        http.set_tls_config(self.tls_config.clone());
+        http.set_egress_policy(self.egress_policy.clone());
 
         let mut host = Host {
             table: wasmtime::component::ResourceTable::new(),
             ctx: builder.build(),
-            http: WasiHttpCtx::new(),
+            http,
+            req_id,
             http_outgoing_body_buffer_chunks: self.run.common.wasi.http_outgoing_body_buffer_chunks,
             http_outgoing_body_chunk_size: self.run.common.wasi.http_outgoing_body_chunk_size,
 
@@ -187,67 +314,53 @@ impl ServeCommand {
         if self.run.common.wasi.nn == Some(true) {
             #[cfg(feature = "wasi-nn")]
             {
-                let graphs = self
-                    .run
-                    .common
-                    .wasi
-                    .nn_graph
-                    .iter()
-                    .map(|g| (g.format.clone(), g.dir.clone()))
-                    .collect::<Vec<_>>();
-                let (backends, registry) = wasmtime_wasi_nn::preload(&graphs)?;
-                host.nn.replace(WasiNnCtx::new(backends, registry));
+                let registry = self.nn_registry.clone().unwrap();
+                let backends = wasmtime_wasi_nn::backend::list();
+                host.nn
+                    .replace(WasiNnCtx::new(backends, Registry::from(registry)));
             }
         }
 
         if self.run.common.wasi.config == Some(true) {
             #[cfg(feature = "wasi-config")]
             {
-                let vars = WasiConfigVariables::from_iter(
-                    self.run
-                        .common
-                        .wasi
-                        .config_var
-                        .iter()
-                        .map(|v| (v.key.clone(), v.value.clone())),
-                );
-                host.wasi_config.replace(vars);
+                host.wasi_config = self.wasi_config.clone();
             }
         }
 
         if self.run.common.wasi.keyvalue == Some(true) {
             #[cfg(feature = "wasi-keyvalue")]
             {
-                let ctx = WasiKeyValueCtxBuilder::new()
-                    .in_memory_data(
-                        self.run
-                            .common
-                            .wasi
-                            .keyvalue_in_memory_data
-                            .iter()
-                            .map(|v| (v.key.clone(), v.value.clone())),
-                    )
-                    .build();
-                host.wasi_keyvalue.replace(ctx);
+                host.wasi_keyvalue = self.wasi_keyvalue.clone();
             }
         }
 
         let mut store = Store::new(engine, host);
 
+        store.data_mut().limits = self.run.store_limits();
+        store.limiter(|t| &mut t.limits);
+
+        Ok(store)
+    }
+
+    /// Prepare `store`, which is either new or reused, to handle the request
+    /// `req_id`.
+    fn start_request(&self, store: &mut Store<Host>, req_id: u64) -> Result<()> {
+        store.data().req_id.store(req_id, Ordering::Relaxed);
+
+        // The timeout applies to each request separately, even when an
+        // instance is reused.
         if self.run.common.wasm.timeout.is_some() {
             store.set_epoch_deadline(u64::from(EPOCH_PRECISION) + 1);
         }
 
-        store.data_mut().limits = self.run.store_limits();
-        store.limiter(|t| &mut t.limits);
-
         // If fuel has been configured, we want to add the configured
         // fuel amount to this store.
         if let Some(fuel) = self.run.common.wasm.fuel {
             store.set_fuel(fuel)?;
         }
 
-        Ok(store)
+        Ok(())
     }
 
     fn add_to_linker(&self, linker: &mut Linker<Host>) -> Result<()> {
@@ -303,8 +416,8 @@ impl ServeCommand {
             }
             #[cfg(feature = "wasi-config")]
             {
-                wasmtime_wasi_config::add_to_linker(linker, |h| {
-                    WasiConfig::from(h.wasi_config.as_ref().unwrap())
+                wasmtime_wasi_config::add_to_linker_async(linker, |h| {
+                    WasiConfig::new(&**h.wasi_config.as_ref().unwrap())
                 })?;
             }
         }
@@ -334,8 +447,6 @@ impl ServeCommand {
     }
 
     async fn serve(mut self) -> Result<()> {
-        use hyper::server::conn::http1;
-
         let mut config = self
             .run
             .common
@@ -363,6 +474,40 @@ impl ServeCommand {
 
         self.add_to_linker(&mut linker)?;
 
+        #[cfg(feature = "wasi-keyvalue")]
+        if self.run.common.wasi.keyvalue == Some(true) {
+            self.wasi_keyvalue = Some(self.run.wasi_keyvalue_ctx()?);
+        }
+
+        #[cfg(feature = "wasi-config")]
+        if self.run.common.wasi.config == Some(true) {
+            let (provider, file) = self.run.wasi_config_provider()?;
+            if let Some(file) = file {
+                spawn_config_file_reloader(file);
+            }
+            self.wasi_config = Some(provider);
+        }
+
+        #[cfg(feature = "wasi-nn")]
+        if self.run.common.wasi.nn == Some(true) {
+            let graphs = self
+                .run
+                .common
+                .wasi
+                .nn_graph
+                .iter()
+                .map(|g| (g.format.clone(), g.dir.clone()))
+                .collect::<Vec<_>>();
+            let mut registry = wasmtime_wasi_nn::preload_lazy(&graphs)?;
+            if let Some(bytes) = self.run.common.wasi.nn_graph_memory_budget {
+                registry.memory_budget(bytes);
+            }
+            self.nn_registry = Some(registry);
+        }
+
+        self.tls_config = self.run.wasi_http_tls_config()?;
+        self.egress_policy = self.run.wasi_http_egress_policy()?;
+
         let component = match self.run.load_module(&engine, &self.component)? {
             RunTarget::Core(_) => bail!("The serve command currently requires a component"),
             RunTarget::Component(c) => c,
@@ -387,7 +532,31 @@ impl ServeCommand {
         socket.bind(self.addr)?;
         let listener = socket.listen(100)?;
 
-        eprintln!("Serving HTTP on http://{}/", listener.local_addr()?);
+        let tls = self.tls_acceptor()?;
+        let http2 = !self.no_http2;
+        let shutdown_timeout = self.shutdown_timeout;
+
+        eprintln!(
+            "Serving HTTP on {}://{}/",
+            if tls.is_some() { "https" } else { "http" },
+            listener.local_addr()?
+        );
+
+        let metrics = Arc::new(Metrics::default());
+        if let Some(addr) = self.metrics_addr {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            eprintln!(
+                "Serving metrics on http://{}/metrics",
+                listener.local_addr()?
+            );
+            let metrics = metrics.clone();
+            let engine = engine.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = metrics::serve(listener, metrics, engine).await {
+                    eprintln!("error: failed to serve metrics: {e:?}");
+                }
+            });
+        }
 
         let _epoch_thread = if let Some(timeout) = self.run.common.wasm.timeout {
             Some(EpochThread::spawn(
@@ -400,25 +569,353 @@ impl ServeCommand {
 
         log::info!("Listening on {}", self.addr);
 
-        let handler = ProxyHandler::new(self, engine, instance);
+        let handler = ProxyHandler::new(self, engine, instance, metrics);
+
+        // Connections are told to shut down gracefully by dropping
+        // `shutdown_tx`, and are tracked in `connections` so that we can wait
+        // for them to finish.
+        let (shutdown_tx, shutdown_rx) = watch::channel(());
+        let mut connections = JoinSet::new();
+        let signal = shutdown_signal();
+        tokio::pin!(signal);
 
         loop {
-            let (stream, _) = listener.accept().await?;
-            let stream = TokioIo::new(stream);
-            let h = handler.clone();
-            tokio::task::spawn(async {
-                if let Err(e) = http1::Builder::new()
-                    .keep_alive(true)
-                    .serve_connection(
+            tokio::select! {
+                res = &mut signal => {
+                    res?;
+                    break;
+                }
+
+                // Reap connections which have finished so that they don't
+                // accumulate in `connections`.
+                Some(_) = connections.join_next() => {}
+
+                res = listener.accept() => {
+                    let (stream, _) = res?;
+                    connections.spawn(serve_connection(
                         stream,
-                        hyper::service::service_fn(move |req| handle_request(h.clone(), req)),
-                    )
-                    .await
-                {
-                    eprintln!("error: {e:?}");
+                        tls.clone(),
+                        http2,
+                        handler.clone(),
+                        shutdown_rx.clone(),
+                    ));
                 }
-            });
+            }
+        }
+
+        drop(listener);
+        if connections.is_empty() {
+            return Ok(());
+        }
+
+        eprintln!(
+            "Shutting down, waiting up to {} for {} connection(s) to finish",
+            humantime::format_duration(shutdown_timeout),
+            connections.len(),
+        );
+        drop(shutdown_tx);
+
+        let drain = async { while connections.join_next().await.is_some() {} };
+        tokio::select! {
+            () = drain => {}
+            () = tokio::time::sleep(shutdown_timeout) => {
+                eprintln!("Timed out waiting for connections to finish");
+            }
+            res = shutdown_signal() => res?,
+        }
+
+        Ok(())
+    }
+}
+
+/// How often the `-S config-file` is checked for changes.
+#[cfg(feature = "wasi-config")]
+const CONFIG_FILE_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reload the `-S config-file` whenever it changes, for as long as the server
+/// runs.
+#[cfg(feature = "wasi-config")]
+fn spawn_config_file_reloader(file: Arc<wasmtime_wasi_config::FileProvider>) {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(CONFIG_FILE_RELOAD_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // Only report the first of consecutive failures, such as the file
+        // being missing, rather than one every interval.
+        let mut failing = false;
+        loop {
+            interval.tick().await;
+            let file = file.clone();
+            match tokio::task::spawn_blocking(move || file.reload()).await {
+                Ok(Ok(_)) => failing = false,
+                Ok(Err(e)) => {
+                    if !failing {
+                        eprintln!("warning: failed to reload wasi-config file: {e:?}");
+                    }
+                    failing = true;
+                }
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+/// Wait for a signal to shut down the server: `SIGINT` (Ctrl-C) or, on Unix,
+/// `SIGTERM`.
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+type TlsAcceptor = tokio_rustls::TlsAcceptor;
+
+/// Create the `rustls` configuration with which HTTPS connections are
+/// accepted, identifying the server with the certificate chain in
+/// `cert_chain_pem` and the private key in `key_pem`, and offering
+/// `alpn_protocols`.
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+fn tls_server_config(
+    cert_chain_pem: &[u8],
+    key_pem: &[u8],
+    alpn_protocols: Vec<Vec<u8>>,
+) -> Result<tokio_rustls::rustls::ServerConfig> {
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio_rustls::rustls::ServerConfig;
+
+    let cert_chain = CertificateDer::pem_slice_iter(cert_chain_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .context("failed to parse certificate chain")?;
+    if cert_chain.is_empty() {
+        bail!("no certificates found in certificate chain");
+    }
+    let key = PrivateKeyDer::pem_slice_iter(key_pem)
+        .next()
+        .context("no private key found in PEM data")?
+        .context("failed to parse private key")?;
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)?;
+    config.alpn_protocols = alpn_protocols;
+    Ok(config)
+}
+
+/// TLS is not supported on these platforms, and `ServeCommand::tls_acceptor`
+/// never creates an acceptor.
+#[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+type TlsAcceptor = std::convert::Infallible;
+
+/// The prefix of the preface with which HTTP/2 clients with prior knowledge
+/// start cleartext connections, and which no HTTP/1.1 request starts with.
+const HTTP2_PREFACE_PREFIX: &[u8] = b"PRI";
+
+/// How long a client has to complete the TLS handshake, or to send the first
+/// bytes of a cleartext connection, before the connection is closed.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serve the requests on the connection `stream` until the client closes it,
+/// or until `shutdown` is signalled and the requests in flight complete.
+async fn serve_connection(
+    stream: tokio::net::TcpStream,
+    tls: Option<TlsAcceptor>,
+    http2: bool,
+    handler: ProxyHandler,
+    mut shutdown: watch::Receiver<()>,
+) {
+    let res = async {
+        match tls {
+            #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+            Some(tls) => {
+                let accept = async { Ok(tls.accept(stream).await?) };
+                let Some(stream) = handshake(accept, &mut shutdown).await? else {
+                    return Ok(());
+                };
+                let http2 = http2 && stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                serve_http(stream, http2, Scheme::Https, handler, shutdown).await
+            }
+            #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+            Some(tls) => match tls {},
+            None if http2 => {
+                let preface = read_http2_preface(stream);
+                let Some((http2, stream)) = handshake(preface, &mut shutdown).await? else {
+                    return Ok(());
+                };
+                serve_http(stream, http2, Scheme::Http, handler, shutdown).await
+            }
+            None => serve_http(stream, false, Scheme::Http, handler, shutdown).await,
+        }
+    };
+    if let Err(e) = res.await {
+        eprintln!("error: {e:?}");
+    }
+}
+
+/// Wait for `handshake` to finish setting up a connection, returning `None`
+/// if `shutdown` is signalled first.
+async fn handshake<T>(
+    handshake: impl std::future::Future<Output = Result<T>>,
+    shutdown: &mut watch::Receiver<()>,
+) -> Result<Option<T>> {
+    tokio::select! {
+        res = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake) => match res {
+            Ok(res) => res.map(Some),
+            Err(_) => bail!("timed out waiting for the client to start the connection"),
+        },
+        _ = shutdown.changed() => Ok(None),
+    }
+}
+
+/// Read the first bytes sent on a cleartext connection to tell whether the
+/// client speaks HTTP/2 with prior knowledge, returning the connection with
+/// those bytes put back in front of the rest of the stream.
+async fn read_http2_preface(stream: tokio::net::TcpStream) -> Result<(bool, PrefacedStream)> {
+    let mut buf = [0; HTTP2_PREFACE_PREFIX.len()];
+    let mut len = 0;
+    while len < buf.len() && buf[..len] == HTTP2_PREFACE_PREFIX[..len] {
+        // Wait for more data rather than returning early, as only part of the
+        // prefix may have arrived so far.
+        stream.readable().await?;
+        match stream.try_read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let http2 = buf[..len] == *HTTP2_PREFACE_PREFIX;
+    let stream = PrefacedStream {
+        prefix: buf[..len].to_vec(),
+        pos: 0,
+        stream,
+    };
+    Ok((http2, stream))
+}
+
+/// A cleartext connection which yields the bytes already read from it by
+/// [`read_http2_preface`] before reading any more.
+struct PrefacedStream {
+    prefix: Vec<u8>,
+    pos: usize,
+    stream: tokio::net::TcpStream,
+}
+
+impl AsyncRead for PrefacedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        if this.pos < this.prefix.len() {
+            let n = buf.remaining().min(this.prefix.len() - this.pos);
+            buf.put_slice(&this.prefix[this.pos..][..n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
         }
+        Pin::new(&mut this.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PrefacedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+async fn serve_http<I>(
+    stream: I,
+    http2: bool,
+    scheme: Scheme,
+    handler: ProxyHandler,
+    mut shutdown: watch::Receiver<()>,
+) -> Result<()>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    use hyper::server::conn::{http1, http2};
+
+    let stream = TokioIo::new(stream);
+    let service =
+        hyper::service::service_fn(move |req| handle_request(handler.clone(), scheme.clone(), req));
+
+    // Serve until the connection is closed, or until told to shut down in
+    // which case the connection is closed once its requests in flight
+    // complete.
+    macro_rules! serve {
+        ($conn:expr) => {{
+            let conn = $conn;
+            tokio::pin!(conn);
+            tokio::select! {
+                res = conn.as_mut() => return Ok(res?),
+                _ = shutdown.changed() => {}
+            }
+            conn.as_mut().graceful_shutdown();
+            conn.await?;
+        }};
+    }
+
+    if http2 {
+        serve!(http2::Builder::new(TokioExecutor).serve_connection(stream, service));
+    } else {
+        serve!(http1::Builder::new()
+            .keep_alive(true)
+            .serve_connection(stream, service));
+    }
+    Ok(())
+}
+
+/// The executor with which HTTP/2 connections spawn the tasks driving their
+/// streams.
+#[derive(Clone, Copy)]
+struct TokioExecutor;
+
+impl<F> hyper::rt::Executor<F> for TokioExecutor
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        tokio::task::spawn(future);
     }
 }
 
@@ -465,24 +962,78 @@ struct ProxyHandlerInner {
     engine: Engine,
     instance_pre: ProxyPre<Host>,
     next_id: AtomicU64,
+    idle_instances: Mutex<Vec<Instance>>,
+    metrics: Arc<Metrics>,
+}
+
+/// An instance of the component, which may be reused for further requests
+/// with `--instance-reuse`.
+struct Instance {
+    store: Store<Host>,
+    proxy: Proxy,
+    requests: usize,
 }
 
 impl ProxyHandlerInner {
     fn next_req_id(&self) -> u64 {
         self.next_id.fetch_add(1, Ordering::Relaxed)
     }
+
+    /// Take an idle instance, or create a new one, to handle `req_id`.
+    async fn instance(&self, req_id: u64) -> Result<Instance> {
+        let idle = self.idle_instances.lock().unwrap().pop();
+        let mut instance = match idle {
+            Some(instance) => instance,
+            None => {
+                let start = Instant::now();
+                let mut store = self.cmd.new_store(&self.engine, req_id)?;
+                let proxy = self.instance_pre.instantiate_async(&mut store).await?;
+                self.metrics.instantiation(start.elapsed());
+                Instance {
+                    store,
+                    proxy,
+                    requests: 0,
+                }
+            }
+        };
+        self.cmd.start_request(&mut instance.store, req_id)?;
+        instance.requests += 1;
+        Ok(instance)
+    }
+
+    /// Keep `instance`, which has successfully handled a request, for reuse
+    /// if it may handle more.
+    fn reuse_instance(&self, instance: Instance) {
+        let Some(max_requests) = self.cmd.instance_reuse else {
+            return;
+        };
+        if instance.requests >= max_requests.get() {
+            return;
+        }
+        let mut idle = self.idle_instances.lock().unwrap();
+        if idle.len() < self.cmd.max_idle_instances {
+            idle.push(instance);
+        }
+    }
 }
 
 #[derive(Clone)]
 struct ProxyHandler(Arc<ProxyHandlerInner>);
 
 impl ProxyHandler {
-    fn new(cmd: ServeCommand, engine: Engine, instance_pre: ProxyPre<Host>) -> Self {
+    fn new(
+        cmd: ServeCommand,
+        engine: Engine,
+        instance_pre: ProxyPre<Host>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
         Self(Arc::new(ProxyHandlerInner {
             cmd,
             engine,
             instance_pre,
             next_id: AtomicU64::from(0),
+            idle_instances: Mutex::new(Vec::new()),
+            metrics,
         }))
     }
 }
@@ -490,7 +1041,21 @@ impl ProxyHandler {
 type Request = hyper::Request<hyper::body::Incoming>;
 
 async fn handle_request(
+    handler: ProxyHandler,
+    scheme: Scheme,
+    req: Request,
+) -> Result<hyper::Response<HyperOutgoingBody>> {
+    let metrics = handler.0.metrics.clone();
+    let start = Instant::now();
+    let res = proxy_request(handler, scheme, req).await;
+    let status = res.as_ref().ok().map(|resp| resp.status());
+    metrics.request(status, start.elapsed());
+    res
+}
+
+async fn proxy_request(
     ProxyHandler(inner): ProxyHandler,
+    scheme: Scheme,
     req: Request,
 ) -> Result<hyper::Response<HyperOutgoingBody>> {
     let (sender, receiver) = tokio::sync::oneshot::channel();
@@ -503,22 +1068,36 @@ async fn handle_request(
         req.uri()
     );
 
-    let mut store = inner.cmd.new_store(&inner.engine, req_id)?;
+    let mut instance = inner.instance(req_id).await?;
 
-    let req = store.data_mut().new_incoming_request(Scheme::Http, req)?;
-    let out = store.data_mut().new_response_outparam(sender)?;
-    let proxy = inner.instance_pre.instantiate_async(&mut store).await?;
+    let req = instance
+        .store
+        .data_mut()
+        .new_incoming_request(scheme, req)?;
+    let out = instance.store.data_mut().new_response_outparam(sender)?;
 
     let task = tokio::task::spawn(async move {
-        if let Err(e) = proxy
+        let res = instance
+            .proxy
             .wasi_http_incoming_handler()
-            .call_handle(store, req, out)
-            .await
-        {
+            .call_handle(&mut instance.store, req, out)
+            .await;
+
+        if let Some(fuel) = inner.cmd.run.common.wasm.fuel {
+            if let Ok(remaining) = instance.store.get_fuel() {
+                inner.metrics.fuel_consumed(fuel.saturating_sub(remaining));
+            }
+        }
+
+        if let Err(e) = res {
             log::error!("[{req_id}] :: {:#?}", e);
+            inner.metrics.trap(&e);
             return Err(e);
         }
 
+        // The instance is only reused once it has completely finished
+        // handling this request, including streaming the response body.
+        inner.reuse_instance(instance);
         Ok(())
     });
 
@@ -562,19 +1141,32 @@ impl Output {
 
 #[derive(Clone)]
 struct LogStream {
-    prefix: String,
+    /// The id of the request being handled, with which each line of output
+    /// is prefixed, or `None` if output isn't prefixed.
+    req_id: Option<Arc<AtomicU64>>,
     output: Output,
     needs_prefix_on_next_write: bool,
 }
 
 impl LogStream {
-    fn new(prefix: String, output: Output) -> LogStream {
+    fn new(req_id: Option<Arc<AtomicU64>>, output: Output) -> LogStream {
         LogStream {
-            prefix,
+            req_id,
             output,
             needs_prefix_on_next_write: true,
         }
     }
+
+    fn prefix(&self) -> String {
+        let Some(req_id) = &self.req_id else {
+            return String::new();
+        };
+        let name = match self.output {
+            Output::Stdout => "stdout",
+            Output::Stderr => "stderr",
+        };
+        format!("{name} [{}] :: ", req_id.load(Ordering::Relaxed))
+    }
 }
 
 impl wasmtime_wasi::StdoutStream for LogStream {
@@ -599,7 +1191,7 @@ impl wasmtime_wasi::HostOutputStream for LogStream {
         while !bytes.is_empty() {
             if self.needs_prefix_on_next_write {
                 self.output
-                    .write_all(self.prefix.as_bytes())
+                    .write_all(self.prefix().as_bytes())
                     .map_err(StreamError::LastOperationFailed)?;
                 self.needs_prefix_on_next_write = false;
             }
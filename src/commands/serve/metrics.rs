@@ -0,0 +1,228 @@
+//! Metrics collected by `wasmtime serve`, and the endpoint serving them in the
+//! Prometheus text format.
+
+use anyhow::Result;
+use http_body_util::{BodyExt, Full};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wasmtime::{Engine, Trap};
+use wasmtime_wasi_http::io::TokioIo;
+
+/// The upper bounds, in seconds, of the buckets of the latency histograms.
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Default)]
+struct Histogram {
+    /// The number of observations in each bucket, not cumulative.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|b| secs <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, n) in BUCKETS.iter().zip(&self.buckets) {
+            cumulative += n;
+            writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {cumulative}"
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        )
+        .unwrap();
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        writeln!(out, "{name}_sum{labels} {}", self.sum).unwrap();
+        writeln!(out, "{name}_count{labels} {}", self.count).unwrap();
+    }
+}
+
+/// Metrics about the requests handled by `wasmtime serve` and the instances
+/// handling them.
+#[derive(Default)]
+pub(super) struct Metrics {
+    /// Request latencies, keyed by the response's status code, or `error` if
+    /// the guest failed to respond.
+    requests: Mutex<BTreeMap<String, Histogram>>,
+    instantiations: Mutex<Histogram>,
+    traps: AtomicU64,
+    epoch_timeouts: AtomicU64,
+    fuel_consumed: AtomicU64,
+}
+
+impl Metrics {
+    /// Record a request which took `duration` until the response's head was
+    /// ready, with the response's `status`, or `None` if there was no
+    /// response.
+    pub(super) fn request(&self, status: Option<hyper::StatusCode>, duration: Duration) {
+        let status = match status {
+            Some(status) => status.as_u16().to_string(),
+            None => "error".to_string(),
+        };
+        self.requests
+            .lock()
+            .unwrap()
+            .entry(status)
+            .or_default()
+            .observe(duration);
+    }
+
+    /// Record the creation of a new instance, which took `duration`.
+    pub(super) fn instantiation(&self, duration: Duration) {
+        self.instantiations.lock().unwrap().observe(duration);
+    }
+
+    /// Record the failure of a guest handling a request with `error`.
+    pub(super) fn trap(&self, error: &anyhow::Error) {
+        self.traps.fetch_add(1, Ordering::Relaxed);
+        if error.downcast_ref::<Trap>() == Some(&Trap::Interrupt) {
+            self.epoch_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record `fuel` consumed while handling a request.
+    pub(super) fn fuel_consumed(&self, fuel: u64) {
+        self.fuel_consumed.fetch_add(fuel, Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    fn render(&self, engine: &Engine) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP wasmtime_serve_requests_seconds Time until the response to a request is ready, by status code.\n");
+        out.push_str("# TYPE wasmtime_serve_requests_seconds histogram\n");
+        for (status, histogram) in self.requests.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "wasmtime_serve_requests_seconds",
+                &format!("status=\"{status}\""),
+            );
+        }
+
+        out.push_str("# HELP wasmtime_serve_instantiation_seconds Time to create a new instance of the component.\n");
+        out.push_str("# TYPE wasmtime_serve_instantiation_seconds histogram\n");
+        self.instantiations.lock().unwrap().render(
+            &mut out,
+            "wasmtime_serve_instantiation_seconds",
+            "",
+        );
+
+        let counters = [
+            (
+                "wasmtime_serve_traps_total",
+                "Requests whose handler trapped or failed.",
+                &self.traps,
+            ),
+            (
+                "wasmtime_serve_epoch_timeouts_total",
+                "Requests whose handler was interrupted by the `-W timeout` deadline.",
+                &self.epoch_timeouts,
+            ),
+            (
+                "wasmtime_serve_fuel_consumed_total",
+                "Fuel consumed by all requests, when fuel is enabled.",
+                &self.fuel_consumed,
+            ),
+        ];
+        for (name, help, value) in counters {
+            writeln!(out, "# HELP {name} {help}").unwrap();
+            writeln!(out, "# TYPE {name} counter").unwrap();
+            writeln!(out, "{name} {}", value.load(Ordering::Relaxed)).unwrap();
+        }
+
+        if let Some(pool) = engine.pooling_allocator_metrics() {
+            let gauges = [
+                (
+                    "wasmtime_pooling_core_instances",
+                    "Core instances allocated from the pooling allocator.",
+                    pool.core_instances(),
+                ),
+                (
+                    "wasmtime_pooling_component_instances",
+                    "Component instances allocated from the pooling allocator.",
+                    pool.component_instances(),
+                ),
+                (
+                    "wasmtime_pooling_memories",
+                    "Linear memories allocated from the pooling allocator.",
+                    pool.memories() as u64,
+                ),
+                (
+                    "wasmtime_pooling_tables",
+                    "Tables allocated from the pooling allocator.",
+                    pool.tables() as u64,
+                ),
+            ];
+            for (name, help, value) in gauges {
+                writeln!(out, "# HELP {name} {help}").unwrap();
+                writeln!(out, "# TYPE {name} gauge").unwrap();
+                writeln!(out, "{name} {value}").unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+/// Serve `metrics` at `/metrics` to the connections accepted by `listener`,
+/// until the returned future is dropped.
+pub(super) async fn serve(
+    listener: tokio::net::TcpListener,
+    metrics: Arc<Metrics>,
+    engine: Engine,
+) -> Result<()> {
+    use hyper::server::conn::http1;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+        let engine = engine.clone();
+        tokio::task::spawn(async move {
+            let service = hyper::service::service_fn(move |req| {
+                let response = if req.uri().path() == "/metrics" {
+                    hyper::Response::builder()
+                        .header(
+                            hyper::header::CONTENT_TYPE,
+                            "text/plain; version=0.0.4; charset=utf-8",
+                        )
+                        .body(Full::from(metrics.render(&engine)).boxed())
+                } else {
+                    hyper::Response::builder()
+                        .status(hyper::StatusCode::NOT_FOUND)
+                        .body(Full::default().boxed())
+                };
+                async move { response }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::warn!("error serving metrics: {e:?}");
+            }
+        });
+    }
+}
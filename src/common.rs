@@ -137,6 +137,150 @@ impl RunCommon {
         limits.build()
     }
 
+    /// Create the `wasi-keyvalue` context configured by the `-S keyvalue-*`
+    /// flags.
+    ///
+    /// Clones of the returned context share the same storage, so it should be
+    /// created once and cloned for each store that should see the same data.
+    #[cfg(feature = "wasi-keyvalue")]
+    pub fn wasi_keyvalue_ctx(&self) -> Result<wasmtime_wasi_keyvalue::WasiKeyValueCtx> {
+        let mut builder = wasmtime_wasi_keyvalue::WasiKeyValueCtxBuilder::new();
+        match &self.common.wasi.keyvalue_dir {
+            Some(dir) => {
+                if !self.common.wasi.keyvalue_in_memory_data.is_empty() {
+                    bail!("`-S keyvalue-in-memory-data` cannot be used with `-S keyvalue-dir`");
+                }
+                let backend = wasmtime_wasi_keyvalue::FileBackend::new(dir)
+                    .with_context(|| format!("failed to open wasi-keyvalue directory '{dir}'"))?;
+                builder = builder.backend(std::sync::Arc::new(backend));
+            }
+            None => {
+                builder = builder.in_memory_data(
+                    self.common
+                        .wasi
+                        .keyvalue_in_memory_data
+                        .iter()
+                        .map(|v| (v.key.clone(), v.value.clone())),
+                );
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// Create the provider of `wasi-config` variables configured by the
+    /// `-S config-*` flags, along with the provider for the `config-file`, if
+    /// any, for the caller to reload when appropriate.
+    ///
+    /// The provider should be created once and shared by all stores.
+    #[cfg(feature = "wasi-config")]
+    pub fn wasi_config_provider(
+        &self,
+    ) -> Result<(
+        std::sync::Arc<dyn wasmtime_wasi_config::ConfigProvider>,
+        Option<std::sync::Arc<wasmtime_wasi_config::FileProvider>>,
+    )> {
+        use std::sync::Arc;
+        use wasmtime_wasi_config::{EnvProvider, FileProvider, Layered, WasiConfigVariables};
+
+        let mut provider = Layered::new();
+        provider.push(WasiConfigVariables::from_iter(
+            self.common
+                .wasi
+                .config_var
+                .iter()
+                .map(|v| (v.key.clone(), v.value.clone())),
+        ));
+        if let Some(prefix) = &self.common.wasi.config_env_prefix {
+            provider.push(EnvProvider::new(prefix.clone()));
+        }
+        let file = match &self.common.wasi.config_file {
+            Some(path) => {
+                let file = FileProvider::new(path)
+                    .with_context(|| format!("failed to load wasi-config file '{path}'"))?;
+                let file = Arc::new(file);
+                provider.push(file.clone());
+                Some(file)
+            }
+            None => None,
+        };
+        Ok((Arc::new(provider), file))
+    }
+
+    /// Creates the TLS configuration for outgoing `wasi-http` requests from
+    /// the `-S http-tls-*` flags.
+    #[cfg(feature = "wasi-http")]
+    pub fn wasi_http_tls_config(
+        &self,
+    ) -> Result<std::sync::Arc<wasmtime_wasi_http::tls::TlsConfig>> {
+        let wasi = &self.common.wasi;
+        let mut config = wasmtime_wasi_http::tls::TlsConfig::new();
+        for path in &wasi.http_tls_root_cert {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("failed to read root certificate '{path}'"))?;
+            config
+                .add_root_certificates_pem(&pem)
+                .with_context(|| format!("failed to parse root certificate '{path}'"))?;
+        }
+        match (&wasi.http_tls_client_cert, &wasi.http_tls_client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = std::fs::read(cert_path)
+                    .with_context(|| format!("failed to read client certificate '{cert_path}'"))?;
+                let key = std::fs::read(key_path)
+                    .with_context(|| format!("failed to read client key '{key_path}'"))?;
+                config
+                    .client_identity_pem(&cert, &key)
+                    .context("failed to parse client identity")?;
+            }
+            (None, None) => {}
+            _ => bail!(
+                "`-S http-tls-client-cert` and `-S http-tls-client-key` must be used together"
+            ),
+        }
+        for pair in &wasi.http_tls_server_name {
+            config.server_name(&pair.key, &pair.value);
+        }
+        config.insecure_skip_verify(wasi.http_tls_insecure_skip_verify.unwrap_or(false));
+        Ok(std::sync::Arc::new(config))
+    }
+
+    /// Creates the policy applied to outgoing `wasi-http` requests from the
+    /// `-S http-allow-*` and `-S http-max-*` flags.
+    #[cfg(feature = "wasi-http")]
+    pub fn wasi_http_egress_policy(&self) -> Result<wasmtime_wasi_http::policy::EgressPolicy> {
+        let wasi = &self.common.wasi;
+        let mut policy = wasmtime_wasi_http::policy::EgressPolicy::new();
+        for authority in &wasi.http_allow_authority {
+            policy.allow_authority(authority)?;
+        }
+        for port in &wasi.http_allow_port {
+            policy.allow_port(*port);
+        }
+        for scheme in &wasi.http_allow_scheme {
+            match scheme.as_str() {
+                "http" | "https" => policy.allow_scheme(scheme),
+                _ => bail!(
+                    "invalid `-S http-allow-scheme` value `{scheme}`, expected `http` or `https`"
+                ),
+            };
+        }
+        if let Some(timeout) = wasi.http_max_connect_timeout {
+            policy.max_connect_timeout(timeout);
+        }
+        if let Some(timeout) = wasi.http_max_first_byte_timeout {
+            policy.max_first_byte_timeout(timeout);
+        }
+        if let Some(timeout) = wasi.http_max_between_bytes_timeout {
+            policy.max_between_bytes_timeout(timeout);
+        }
+        if let Some(size) = wasi.http_max_response_body_size {
+            policy.max_response_body_size(size);
+        }
+        if let Some(max) = wasi.http_max_concurrent_requests {
+            policy.max_concurrent_requests(max);
+        }
+        Ok(policy)
+    }
+
     pub fn ensure_allow_precompiled(&self) -> Result<()> {
         if self.allow_precompiled {
             Ok(())
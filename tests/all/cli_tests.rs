@@ -599,6 +599,90 @@ fn run_threads() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "wasi-threads")]
+#[test]
+fn run_threads_with_pool() -> Result<()> {
+    // Skip this test on platforms that don't support threads.
+    if crate::threads::engine().is_none() {
+        return Ok(());
+    }
+    let wasm = build_wasm("tests/all/cli_tests/threads.wat")?;
+    let stdout = run_wasmtime(&[
+        "run",
+        "-Wthreads",
+        "-Sthreads",
+        "-Sthread-pool=1",
+        "-Ccache=n",
+        wasm.path().to_str().unwrap(),
+    ])?;
+
+    assert_eq!(
+        stdout,
+        "Called _start\n\
+         Running wasi_thread_start\n\
+         Running wasi_thread_start\n\
+         Running wasi_thread_start\n\
+         Done\n"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "wasi-threads")]
+#[test]
+fn run_threads_with_max_threads() -> Result<()> {
+    // Skip this test on platforms that don't support threads.
+    if crate::threads::engine().is_none() {
+        return Ok(());
+    }
+    let wasm = build_wasm("tests/all/cli_tests/threads_max.wat")?;
+    let stdout = run_wasmtime(&[
+        "run",
+        "-Wthreads",
+        "-Sthreads",
+        "-Smax-threads=1",
+        "-Ccache=n",
+        wasm.path().to_str().unwrap(),
+    ])?;
+
+    assert_eq!(
+        stdout,
+        "Called _start\n\
+         Spawn failed\n\
+         Running wasi_thread_start\n\
+         Done\n"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "wasi-threads")]
+#[test]
+fn run_threads_with_green_threads() -> Result<()> {
+    // Skip this test on platforms that don't support threads.
+    if crate::threads::engine().is_none() {
+        return Ok(());
+    }
+    let wasm = build_wasm("tests/all/cli_tests/threads_green.wat")?;
+    let stdout = run_wasmtime(&[
+        "run",
+        "-Wthreads",
+        "-Sthreads",
+        "-Sgreen-threads=1",
+        "-Ccache=n",
+        wasm.path().to_str().unwrap(),
+    ])?;
+
+    assert_eq!(
+        stdout,
+        "Called _start\n\
+         Running wasi_thread_start\n\
+         Running wasi_thread_start\n\
+         Running wasi_thread_start\n\
+         Running wasi_thread_start\n\
+         Done\n"
+    );
+    Ok(())
+}
+
 #[cfg(feature = "wasi-threads")]
 #[test]
 fn run_simple_with_wasi_threads() -> Result<()> {
@@ -768,6 +852,36 @@ fn hello_with_preview2() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn log_stdout_json() -> Result<()> {
+    let wasm = build_wasm("tests/all/cli_tests/hello_wasi_snapshot1.wat")?;
+    let stdout = run_wasmtime(&[
+        "-Ccache=n",
+        "--log-stdout-json",
+        wasm.path().to_str().unwrap(),
+    ])?;
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1, "bad stdout: {stdout}");
+    let line: serde_json::Value = serde_json::from_str(lines[0])?;
+    assert_eq!(line["stream"], "stdout");
+    assert_eq!(line["line"], "Hello, world!");
+    assert!(line["timestamp"].is_string(), "bad line: {line}");
+    assert!(line.get("truncated").is_none(), "bad line: {line}");
+
+    // The historical preview1 implementation can't capture output.
+    let output = run_wasmtime_for_output(
+        &[
+            "-Ccache=n",
+            "-Spreview2=n",
+            "--log-stdout-json",
+            wasm.path().to_str().unwrap(),
+        ],
+        None,
+    )?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(not(feature = "component-model"), ignore)]
 fn component_missing_feature() -> Result<()> {
@@ -1207,6 +1321,39 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn cli_record_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trace = dir.path().join("stdin.trace");
+        let trace = trace.to_str().unwrap();
+
+        let mut child = get_wasmtime_command()?
+            .args(&["run", "-Wcomponent-model", "--record", trace])
+            .arg(CLI_STDIN_COMPONENT)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"So rested he by the Tumtum tree")
+            .unwrap();
+        let output = child.wait_with_output()?;
+        assert!(output.status.success());
+
+        // Stdin is replayed from the trace.
+        let output = get_wasmtime_command()?
+            .args(&["run", "-Wcomponent-model", "--replay", trace])
+            .arg(CLI_STDIN_COMPONENT)
+            .stdin(Stdio::null())
+            .output()?;
+        println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+        assert!(output.status.success());
+        Ok(())
+    }
+
     #[test]
     fn cli_splice_stdin() -> Result<()> {
         let mut child = get_wasmtime_command()?
@@ -1413,6 +1560,45 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn run_wasi_http_tls_flags() -> Result<()> {
+        let tls =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("crates/wasi-http/tests/all/tls");
+        let cert = tls.join("client.pem");
+        let key = tls.join("client-key.pem");
+
+        // A client certificate without its key is rejected.
+        let output = super::run_wasmtime_for_output(
+            &[
+                "-Ccache=no",
+                "-Scli,http,preview2",
+                &format!("-Shttp-tls-client-cert={}", cert.display()),
+                HTTP_OUTBOUND_REQUEST_RESPONSE_BUILD_COMPONENT,
+            ],
+            None,
+        )?;
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(
+            "`-S http-tls-client-cert` and `-S http-tls-client-key` must be used together"
+        ));
+
+        // Valid TLS flags don't otherwise affect running the component.
+        let output = super::run_wasmtime_for_output(
+            &[
+                "-Ccache=no",
+                "-Scli,http,preview2",
+                &format!("-Shttp-tls-root-cert={}", tls.join("ca.pem").display()),
+                &format!("-Shttp-tls-client-cert={}", cert.display()),
+                &format!("-Shttp-tls-client-key={}", key.display()),
+                "-Shttp-tls-server-name=localhost=test.internal",
+                HTTP_OUTBOUND_REQUEST_RESPONSE_BUILD_COMPONENT,
+            ],
+            None,
+        )?;
+        assert!(output.status.success());
+        Ok(())
+    }
+
     // Test to ensure that prints in the guest aren't buffered on the host by
     // accident. The test here will print something without a newline and then
     // wait for input on stdin, and the test here is to ensure that the
@@ -1724,6 +1910,173 @@ mod test_programs {
         Ok(())
     }
 
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    #[tokio::test]
+    async fn cli_serve_https() -> Result<()> {
+        use std::sync::Arc;
+        use std::time::Duration;
+        use wasmtime_wasi_http::tls::TlsConfig;
+        use wasmtime_wasi_http::types::{self, OutgoingRequestConfig};
+
+        let tls =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("crates/wasi-http/tests/all/tls");
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Scli");
+            cmd.arg("--env=FOO=bar");
+            cmd.arg(format!("--tls-cert={}", tls.join("server.pem").display()));
+            cmd.arg(format!(
+                "--tls-key={}",
+                tls.join("server-key.pem").display()
+            ));
+        })?;
+
+        let mut tls_config = TlsConfig::new();
+        tls_config
+            .builtin_roots(false)
+            .add_root_certificates_pem(&std::fs::read(tls.join("ca.pem"))?)?
+            .server_name("localhost", "test.internal");
+
+        for http2 in [true, false] {
+            let request = hyper::Request::builder()
+                .uri(format!("https://localhost:{}/", server.addr.port()))
+                .header("env", "FOO")
+                .body(
+                    http_body_util::Empty::new()
+                        .map_err(|_| unreachable!())
+                        .boxed(),
+                )?;
+            let mut config = OutgoingRequestConfig::new(
+                true,
+                Duration::from_secs(5),
+                Duration::from_secs(5),
+                Duration::from_secs(5),
+            );
+            config.pool_limits.http2 = http2;
+            config.tls_config = Arc::new(tls_config.clone());
+            let response = types::default_send_request_handler(request, config)
+                .await?
+                .resp;
+
+            assert!(response.status().is_success());
+            let expected = if http2 {
+                hyper::Version::HTTP_2
+            } else {
+                hyper::Version::HTTP_11
+            };
+            assert_eq!(response.version(), expected);
+            assert_eq!(
+                response.headers().get("env"),
+                Some(&HeaderValue::from_static("bar"))
+            );
+            response.into_body().collect().await?;
+        }
+
+        server.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_http2_prior_knowledge() -> Result<()> {
+        #[derive(Clone, Copy)]
+        struct TokioExecutor;
+
+        impl<F> hyper::rt::Executor<F> for TokioExecutor
+        where
+            F: std::future::Future + Send + 'static,
+            F::Output: Send + 'static,
+        {
+            fn execute(&self, future: F) {
+                tokio::task::spawn(future);
+            }
+        }
+
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Scli");
+            cmd.arg("--env=FOO=bar");
+        })?;
+
+        let tcp = TcpStream::connect(&server.addr).await?;
+        let tcp = wasmtime_wasi_http::io::TokioIo::new(tcp);
+        let (mut send, conn) =
+            hyper::client::conn::http2::handshake::<_, _, String>(TokioExecutor, tcp)
+                .await
+                .context("failed http2 handshake")?;
+        let conn_task = tokio::task::spawn(conn);
+
+        // Several requests are multiplexed over the one connection.
+        let requests = (0..3)
+            .map(|_| {
+                let mut send = send.clone();
+                tokio::task::spawn(async move {
+                    send.send_request(
+                        hyper::Request::builder()
+                            .uri("http://localhost/")
+                            .header("env", "FOO")
+                            .body(String::new())
+                            .unwrap(),
+                    )
+                    .await
+                })
+            })
+            .collect::<Vec<_>>();
+        for request in requests {
+            let response = request.await??;
+            assert!(response.status().is_success());
+            assert_eq!(response.version(), hyper::Version::HTTP_2);
+            assert_eq!(
+                response.headers().get("env"),
+                Some(&HeaderValue::from_static("bar"))
+            );
+        }
+        drop(send);
+        conn_task.await??;
+
+        server.finish()?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cli_serve_graceful_shutdown() -> Result<()> {
+        use std::time::{Duration, Instant};
+
+        let mut server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Scli");
+        })?;
+
+        // Leave a connection open after a request on it completes.
+        let (mut send, conn_task) = server.start_requests().await?;
+        let response = send
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert!(response.status().is_success());
+        response.into_body().collect().await?;
+
+        let child = server.child.as_mut().unwrap();
+        let pid = i32::try_from(child.id())?;
+        assert_eq!(unsafe { libc::kill(pid, libc::SIGTERM) }, 0);
+
+        // The server closes the idle connection and then exits by itself.
+        conn_task.await??;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while child.try_wait()?.is_none() {
+            assert!(
+                Instant::now() < deadline,
+                "server did not exit after SIGTERM"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let (_, err) = server.finish()?;
+        assert!(err.contains("Shutting down"), "unexpected stderr: {err}");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn cli_serve_outgoing_body_config() -> Result<()> {
         let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
@@ -2038,6 +2391,196 @@ after empty
         Ok(())
     }
 
+    #[test]
+    fn cli_config_file_and_env() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.json");
+        std::fs::write(&path, r#"{"hello": "world"}"#)?;
+        run_wasmtime(&[
+            "run",
+            "-Sconfig",
+            &format!("-Sconfig-file={}", path.display()),
+            CONFIG_GET_COMPONENT,
+        ])?;
+
+        // Variables from the environment take precedence over the file.
+        std::fs::write(&path, r#"{"hello": "file"}"#)?;
+        let output = get_wasmtime_command()?
+            .args(&[
+                "run",
+                "-Sconfig",
+                "-Sconfig-env-prefix=WASMTIME_TEST_CONFIG_",
+                &format!("-Sconfig-file={}", path.display()),
+                CONFIG_GET_COMPONENT,
+            ])
+            .env("WASMTIME_TEST_CONFIG_hello", "world")
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_config_file_reload() -> Result<()> {
+        use std::time::{Duration, Instant};
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "hello = \"world\"\n")?;
+        let server = WasmtimeServe::new(CLI_SERVE_CONFIG_COMPONENT, |cmd| {
+            cmd.arg("-Scli");
+            cmd.arg("-Sconfig");
+            cmd.arg(format!("-Sconfig-file={}", path.display()));
+        })?;
+
+        let get = || async {
+            let resp = server
+                .send_request(
+                    hyper::Request::builder()
+                        .uri("http://localhost/")
+                        .body(String::new())
+                        .context("failed to make request")?,
+                )
+                .await?;
+            assert!(resp.status().is_success());
+            Ok::<_, anyhow::Error>(resp.into_body())
+        };
+
+        assert_eq!(get().await?, "world");
+        std::fs::write(&path, "hello = \"changed world\"\n")?;
+
+        // The file is reloaded in the background, so wait for the change.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let body = get().await?;
+            if body == "changed world" {
+                break;
+            }
+            assert_eq!(body, "world");
+            assert!(Instant::now() < deadline, "config file was not reloaded");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        server.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_instance_reuse() -> Result<()> {
+        let server = WasmtimeServe::new(CLI_SERVE_INSTANCE_REUSE_COMPONENT, |cmd| {
+            cmd.arg("--instance-reuse=3");
+            cmd.arg("--max-idle-instances=1");
+        })?;
+
+        let requests = |path: &'static str| {
+            let server = &server;
+            async move {
+                let resp = server
+                    .send_request(
+                        hyper::Request::builder()
+                            .uri(format!("http://localhost{path}"))
+                            .body(String::new())
+                            .context("failed to make request")?,
+                    )
+                    .await?;
+                Ok::<_, anyhow::Error>(
+                    resp.headers()
+                        .get("requests")
+                        .map(|v| v.to_str().unwrap().to_string()),
+                )
+            }
+        };
+
+        // Requests made one after another are handled by the same instance
+        // until it has handled three of them. An instance only becomes idle
+        // once the guest returns, slightly after its response is sent, so
+        // give it a moment before each request.
+        let mut counts = Vec::new();
+        for _ in 0..6 {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            counts.push(requests("/").await?.unwrap().parse::<u32>()?);
+        }
+        assert!(counts.iter().all(|n| (1..=3).contains(n)), "{counts:?}");
+        assert!(counts.contains(&3), "{counts:?}");
+
+        // An instance which traps is discarded, and the server carries on.
+        assert!(requests("/trap").await.is_err());
+        assert!(requests("/").await?.is_some());
+
+        server.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_metrics() -> Result<()> {
+        let mut server = WasmtimeServe::new(CLI_SERVE_INSTANCE_REUSE_COMPONENT, |cmd| {
+            cmd.arg("--metrics-addr=127.0.0.1:0");
+            cmd.arg("-Opooling-allocator");
+            cmd.arg("-Wfuel=1000000000");
+        })?;
+
+        // The address of the metrics endpoint is printed on the line after
+        // the address of the server.
+        let mut stderr = BufReader::new(server.child.as_mut().unwrap().stderr.take().unwrap());
+        let mut line = String::new();
+        stderr.read_line(&mut line)?;
+        let metrics_addr: SocketAddr = line
+            .trim()
+            .strip_prefix("Serving metrics on http://")
+            .and_then(|s| s.strip_suffix("/metrics"))
+            .with_context(|| format!("unexpected output: {line}"))?
+            .parse()?;
+
+        for path in ["/", "/", "/trap"] {
+            let _ = server
+                .send_request(
+                    hyper::Request::builder()
+                        .uri(format!("http://localhost{path}"))
+                        .body(String::new())
+                        .context("failed to make request")?,
+                )
+                .await;
+        }
+
+        // Fuel is recorded once the guest returns, slightly after its
+        // response is sent.
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let metrics = WasmtimeServe {
+            child: None,
+            addr: metrics_addr,
+        };
+        let resp = metrics
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/metrics")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert!(resp.status().is_success());
+        let body = resp.body();
+        assert!(body.contains("wasmtime_serve_requests_seconds_count{status=\"200\"} 2\n"));
+        assert!(body.contains("wasmtime_serve_requests_seconds_count{status=\"error\"} 1\n"));
+        assert!(body.contains("wasmtime_serve_instantiation_seconds_count 3\n"));
+        assert!(body.contains("wasmtime_serve_traps_total 1\n"));
+        assert!(body.contains("wasmtime_serve_epoch_timeouts_total 0\n"));
+        assert!(!body.contains("wasmtime_serve_fuel_consumed_total 0\n"));
+        assert!(body.contains("wasmtime_pooling_memories "));
+
+        let resp = metrics
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert_eq!(resp.status(), hyper::StatusCode::NOT_FOUND);
+
+        server.finish()?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn cli_serve_keyvalue() -> Result<()> {
         let server = WasmtimeServe::new(CLI_SERVE_KEYVALUE_COMPONENT, |cmd| {
@@ -2070,6 +2613,29 @@ after empty
         ])?;
         Ok(())
     }
+
+    #[test]
+    fn cli_keyvalue_dir() -> Result<()> {
+        // Preset `atomics_key` in the default, empty-named bucket, using the
+        // file backend's escaped naming scheme.
+        let dir = tempfile::tempdir()?;
+        let bucket = dir.path().join("_");
+        std::fs::create_dir(&bucket)?;
+        std::fs::write(bucket.join("_atomics_key"), "5")?;
+
+        run_wasmtime(&[
+            "run",
+            "-Skeyvalue",
+            &format!("-Skeyvalue-dir={}", dir.path().display()),
+            KEYVALUE_MAIN_COMPONENT,
+        ])?;
+
+        // The component's writes were persisted to the directory.
+        assert_eq!(std::fs::read_to_string(bucket.join("_atomics_key"))?, "6");
+        assert_eq!(std::fs::read_to_string(bucket.join("_b1"))?, "v1");
+        assert!(!bucket.join("_a1").exists());
+        Ok(())
+    }
 }
 
 #[test]
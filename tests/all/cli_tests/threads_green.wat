@@ -0,0 +1,68 @@
+(module
+  (import "" "memory" (memory $shmem 1 1 shared))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $__wasi_fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi" "thread-spawn"
+    (func $__wasi_thread_spawn (param i32) (result i32)))
+
+  (func (export "_start")
+    (local $i i32)
+
+    ;; Print "Called _start".
+    (call $print (i32.const 32) (i32.const 14))
+
+    ;; Spawn threads which take turns in reverse order of spawning: the
+    ;; thread with start argument $i waits without a timeout until the i32 at
+    ;; address 128 equals $i. When guest threads block their host thread, the
+    ;; first ones spawned would keep the host threads to themselves.
+    (local.set $i (i32.const 4))
+    (loop $spawn
+      (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+      (drop (call $__wasi_thread_spawn (local.get $i)))
+      (br_if $spawn (local.get $i))
+    )
+
+    ;; Wait for all the threads to be done.
+    (loop $again
+      (local.set $i (i32.atomic.load (i32.const 128)))
+      (if (i32.lt_s (local.get $i) (i32.const 4))
+        (then
+          (drop (memory.atomic.wait32 (i32.const 128) (local.get $i) (i64.const -1)))
+          (br $again)))
+    )
+
+    ;; Print "Done".
+    (call $print (i32.const 64) (i32.const 5))
+  )
+
+  (func (export "wasi_thread_start") (param $tid i32) (param $start_arg i32)
+    (local $cur i32)
+    (loop $again
+      (local.set $cur (i32.atomic.load (i32.const 128)))
+      (if (i32.ne (local.get $cur) (local.get $start_arg))
+        (then
+          (drop (memory.atomic.wait32 (i32.const 128) (local.get $cur) (i64.const -1)))
+          (br $again)))
+    )
+    (call $print (i32.const 96) (i32.const 26))
+    (drop (i32.atomic.rmw.add (i32.const 128) (i32.const 1)))
+    (drop (memory.atomic.notify (i32.const 128) (i32.const -1)))
+  )
+
+  ;; A helper function for printing ptr-len strings.
+  (func $print (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 8) (local.get $len))
+    (i32.store (i32.const 4) (local.get $ptr))
+        (drop (call $__wasi_fd_write
+          (i32.const 1)
+          (i32.const 4)
+          (i32.const 1)
+          (i32.const 0)))
+  )
+
+  (export "memory" (memory $shmem))
+
+  (data (i32.const 32) "Called _start\0a")
+  (data (i32.const 64) "Done\0a")
+  (data (i32.const 96) "Running wasi_thread_start\0a")
+)
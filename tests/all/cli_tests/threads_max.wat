@@ -0,0 +1,62 @@
+(module
+  (import "" "memory" (memory $shmem 1 1 shared))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $__wasi_fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi" "thread-spawn"
+    (func $__wasi_thread_spawn (param i32) (result i32)))
+
+  (func (export "_start")
+    ;; Print "Called _start".
+    (call $print (i32.const 32) (i32.const 14))
+
+    ;; The first thread waits for us, so spawning a second one exceeds a
+    ;; limit of one thread.
+    (if (i32.lt_s (call $__wasi_thread_spawn (i32.const 0)) (i32.const 0))
+      (then (unreachable)))
+    (if (i32.lt_s (call $__wasi_thread_spawn (i32.const 0)) (i32.const 0))
+      ;; Print "Spawn failed".
+      (then (call $print (i32.const 160) (i32.const 13))))
+
+    ;; Let the first thread run.
+    (i32.atomic.store (i32.const 136) (i32.const 1))
+    (drop (memory.atomic.notify (i32.const 136) (i32.const 1)))
+
+    ;; Wait for the thread to notify us that it's done.
+    (loop $again
+      (drop (memory.atomic.wait32 (i32.const 128) (i32.const 0) (i64.const 1000000)))
+      (br_if $again (i32.eqz (i32.atomic.load (i32.const 128))))
+    )
+
+    ;; Print "Done".
+    (call $print (i32.const 64) (i32.const 5))
+  )
+
+  (func (export "wasi_thread_start") (param $tid i32) (param $start_arg i32)
+    ;; Wait for the main thread to let us run.
+    (loop $again
+      (drop (memory.atomic.wait32 (i32.const 136) (i32.const 0) (i64.const 1000000)))
+      (br_if $again (i32.eqz (i32.atomic.load (i32.const 136))))
+    )
+    (call $print (i32.const 96) (i32.const 26))
+    (drop (i32.atomic.rmw.add (i32.const 128) (i32.const 1)))
+    (drop (memory.atomic.notify (i32.const 128) (i32.const 1)))
+  )
+
+  ;; A helper function for printing ptr-len strings.
+  (func $print (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 8) (local.get $len))
+    (i32.store (i32.const 4) (local.get $ptr))
+        (drop (call $__wasi_fd_write
+          (i32.const 1)
+          (i32.const 4)
+          (i32.const 1)
+          (i32.const 0)))
+  )
+
+  (export "memory" (memory $shmem))
+
+  (data (i32.const 32) "Called _start\0a")
+  (data (i32.const 64) "Done\0a")
+  (data (i32.const 96) "Running wasi_thread_start\0a")
+  (data (i32.const 160) "Spawn failed\0a")
+)
@@ -1003,3 +1003,218 @@ fn ref_matches() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_snapshot() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+
+    let array_ty = ArrayType::new(
+        &engine,
+        FieldType::new(Mutability::Var, StorageType::ValType(ValType::ANYREF)),
+    );
+    let struct_ty = StructType::new(
+        &engine,
+        [
+            FieldType::new(Mutability::Const, StorageType::ValType(ValType::I32)),
+            FieldType::new(Mutability::Var, StorageType::ValType(ValType::EXTERNREF)),
+        ],
+    )?;
+
+    // Nothing has been allocated yet.
+    let snapshot = store.gc_heap_snapshot()?;
+    assert!(snapshot.objects().is_empty());
+    assert!(snapshot.roots().is_empty());
+
+    let externref = ExternRef::new(&mut store, "hello")?;
+    let struct_pre = StructRefPre::new(&mut store, struct_ty);
+    let s = StructRef::new(
+        &mut store,
+        &struct_pre,
+        &[Val::I32(42), Val::ExternRef(Some(externref))],
+    )?;
+    let array_pre = ArrayRefPre::new(&mut store, array_ty);
+    let array = ArrayRef::new_fixed(
+        &mut store,
+        &array_pre,
+        &[
+            Val::AnyRef(Some(s.to_anyref())),
+            Val::AnyRef(Some(AnyRef::from_i31(&mut store, I31::wrapping_u32(1)))),
+            Val::AnyRef(None),
+        ],
+    )?;
+    let _array = array.to_manually_rooted(&mut store)?;
+
+    let snapshot = store.gc_heap_snapshot()?;
+    let objects = snapshot.objects();
+    assert_eq!(objects.len(), 3);
+    assert!(snapshot
+        .roots()
+        .iter()
+        .all(|(kind, _)| *kind == GcRootKind::Host));
+
+    let array = objects
+        .iter()
+        .find(|o| o.kind() == GcObjectKind::Array)
+        .unwrap();
+    assert_eq!(array.edges().len(), 1);
+    let (edge, s) = array.edges()[0];
+    assert_eq!(edge, GcHeapSnapshotEdge::Element(0));
+
+    let s = &objects[s];
+    assert_eq!(s.kind(), GcObjectKind::Struct);
+    assert_eq!(s.type_name(), "(struct i32 (mut externref))");
+    let (edge, e) = s.edges()[0];
+    assert_eq!(edge, GcHeapSnapshotEdge::Field(1));
+    assert_eq!(objects[e].kind(), GcObjectKind::ExternRef);
+
+    assert!(snapshot.total_size() > 0);
+    let json = snapshot.to_v8_heap_snapshot();
+    assert!(json.contains(r#""node_count":7,"#));
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_stats() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+
+    // Nothing has been allocated yet, not even the GC heap.
+    assert_eq!(store.gc_stats(), GcStats::default());
+
+    {
+        let mut scope = RootScope::new(&mut store);
+        for i in 0..10 {
+            ExternRef::new(&mut scope, i)?;
+        }
+
+        let stats = scope.as_context().gc_stats();
+        assert_eq!(stats.objects_allocated, 10);
+        assert_eq!(stats.live_objects, 10);
+        assert!(stats.bytes_allocated > 0);
+        assert_eq!(stats.live_bytes, usize::try_from(stats.bytes_allocated)?);
+        assert!(stats.heap_capacity >= stats.live_bytes);
+        assert_eq!(stats.collections, 0);
+    }
+
+    // All of the `externref`s are unrooted now, so a collection reclaims them.
+    store.gc();
+    let stats = store.gc_stats();
+    assert_eq!(stats.objects_allocated, 10);
+    assert_eq!(stats.live_objects, 0);
+    assert_eq!(stats.live_bytes, 0);
+    assert_eq!(stats.collections, 1);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn weak_rooted_and_finalization() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, Vec::<u64>::new());
+    store.gc_finalization_callback(|mut store, token| store.data_mut().push(token));
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (global $g (export "g") (mut externref) (ref.null extern))
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let global = instance.get_global(&mut store, "g").unwrap();
+
+    let (weak_x, weak_y) = {
+        let mut scope = RootScope::new(&mut store);
+        let x = ExternRef::new(&mut scope, "x")?;
+        let y = ExternRef::new(&mut scope, "y")?;
+        let weak_x = WeakRooted::with_finalization_token(&mut scope, &x, 1)?;
+        let weak_y = WeakRooted::with_finalization_token(&mut scope, &y, 2)?;
+
+        // Weak references don't keep their referents alive, but `x` and `y`
+        // are still rooted here.
+        scope.as_context_mut().gc();
+        assert!(weak_x.upgrade(&mut scope).is_some());
+        assert!(!weak_y.is_reclaimed(&mut scope));
+
+        // Hand `y` to Wasm, which keeps it alive after the scope exits.
+        global.set(&mut scope, Val::ExternRef(Some(y)))?;
+        (weak_x, weak_y)
+    };
+
+    store.gc();
+    assert_eq!(store.data(), &[1]);
+    assert!(weak_x.is_reclaimed(&mut store));
+    assert!(weak_x.upgrade(&mut store).is_none());
+
+    {
+        let mut scope = RootScope::new(&mut store);
+        let y = weak_y.upgrade(&mut scope).expect("`y` is still alive");
+        let data = y.data(scope.as_context())?.expect("has host data");
+        assert_eq!(data.downcast_ref::<&str>(), Some(&"y"));
+    }
+
+    // Once Wasm drops its last reference, `y` is reclaimed too.
+    global.set(&mut store, Val::ExternRef(None))?;
+    store.gc();
+    assert_eq!(store.data(), &[1, 2]);
+    assert!(weak_y.upgrade(&mut store).is_none());
+
+    // Finalization tokens are only reported once.
+    store.gc();
+    assert_eq!(store.data(), &[1, 2]);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn unroot_weak_rooted() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, Vec::<u64>::new());
+    store.gc_finalization_callback(|mut store, token| store.data_mut().push(token));
+
+    let x = ExternRef::new_manually_rooted(&mut store, "x")?;
+    let weak_1 = WeakRooted::with_finalization_token(&mut store, &x, 1)?;
+    let weak_2 = WeakRooted::with_finalization_token(&mut store, &x, 2)?;
+
+    // Unrooting one weak reference doesn't affect the referent or its other
+    // weak references, and unrooting it again does nothing.
+    weak_1.unroot(&mut store);
+    weak_1.unroot(&mut store);
+    {
+        let mut scope = RootScope::new(&mut store);
+        assert!(weak_1.upgrade(&mut scope).is_none());
+        assert!(weak_2.upgrade(&mut scope).is_some());
+    }
+
+    // Only the remaining weak reference's token is reported once `x` is
+    // reclaimed.
+    x.unroot(&mut store);
+    store.gc();
+    assert_eq!(store.data(), &[2]);
+    assert!(weak_2.is_reclaimed(&mut store));
+    weak_2.unroot(&mut store);
+
+    Ok(())
+}
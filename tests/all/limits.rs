@@ -1175,3 +1175,90 @@ fn growth_trap() -> Result<()> {
 
     Ok(())
 }
+
+#[derive(Default)]
+struct GcHeapLimiter {
+    gc_heap_limit: usize,
+    requests: Vec<(usize, usize, usize)>,
+}
+
+impl ResourceLimiter for GcHeapLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn gc_heap_growing(&mut self, current: usize, desired: usize, maximum: usize) -> Result<bool> {
+        self.requests.push((current, desired, maximum));
+        Ok(desired <= self.gc_heap_limit)
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_growing() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    config.gc_heap_initial_size(64 << 10);
+    config.gc_heap_maximum_size(1 << 20);
+    let engine = Engine::new(&config)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $bytes (array (mut i8)))
+                (func (export "alloc") (param i32)
+                    (drop (array.new_default $bytes (local.get 0))))
+            )
+        "#,
+    )?;
+
+    let mut store = Store::new(
+        &engine,
+        GcHeapLimiter {
+            gc_heap_limit: 512 << 10,
+            ..Default::default()
+        },
+    );
+    store.limiter(|s| s as &mut dyn ResourceLimiter);
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let alloc = instance.get_typed_func::<i32, ()>(&mut store, "alloc")?;
+
+    // Too large for the initial heap, so the heap grows to fit it, rounded up
+    // to a multiple of 64KiB.
+    alloc.call(&mut store, 100_000)?;
+    assert_eq!(store.data().requests, [(64 << 10, 192 << 10, 1 << 20)]);
+    let stats = store.gc_stats();
+    assert_eq!(stats.heap_capacity, 192 << 10);
+    assert_eq!(stats.heap_growths, 1);
+
+    // Growing past the limiter's limit is rejected, and the allocation fails.
+    assert!(alloc.call(&mut store, 600_000).is_err());
+    assert_eq!(store.data().requests.len(), 2);
+    assert_eq!(store.data().requests[1].0, 192 << 10);
+    assert!(store.data().requests[1].1 > 512 << 10);
+    assert_eq!(store.gc_stats().heap_capacity, 192 << 10);
+
+    // The maximum size cannot be smaller than the initial size.
+    let mut config = config.clone();
+    config.gc_heap_maximum_size(32 << 10);
+    assert!(Engine::new(&config).is_err());
+
+    Ok(())
+}
@@ -437,6 +437,42 @@ fn total_core_instances_limit() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn pooling_allocator_metrics() -> Result<()> {
+    assert!(Engine::default().pooling_allocator_metrics().is_none());
+
+    let mut config = Config::new();
+    config.allocation_strategy(crate::small_pool_config());
+    config.memory_guard_size(0);
+    config.memory_reservation(1 << 16);
+
+    let engine = Engine::new(&config)?;
+    let metrics = engine.pooling_allocator_metrics().unwrap();
+    let module = Module::new(&engine, r#"(module (memory 1) (table 10 funcref))"#)?;
+
+    assert_eq!(metrics.core_instances(), 0);
+    assert_eq!(metrics.memories(), 0);
+    assert_eq!(metrics.tables(), 0);
+
+    {
+        let mut store = Store::new(&engine, ());
+        Instance::new(&mut store, &module, &[])?;
+        Instance::new(&mut store, &module, &[])?;
+
+        assert_eq!(metrics.core_instances(), 2);
+        assert_eq!(metrics.component_instances(), 0);
+        assert_eq!(metrics.memories(), 2);
+        assert_eq!(metrics.tables(), 2);
+    }
+
+    // Everything is deallocated along with the store.
+    assert_eq!(metrics.core_instances(), 0);
+    assert_eq!(metrics.memories(), 0);
+    assert_eq!(metrics.tables(), 0);
+
+    Ok(())
+}
+
 #[test]
 fn preserve_data_segments() -> Result<()> {
     let mut pool = crate::small_pool_config();
@@ -291,3 +291,46 @@ fn test_memory_size_accessibility() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn test_async_atomic_wait() -> Result<()> {
+    let wat = r#"(module
+        (import "env" "memory" (memory 1 1 shared))
+        (func (export "wait") (param i64) (result i32)
+            (memory.atomic.wait32 (i32.const 0) (i32.const 0) (local.get 0)))
+        (func (export "notify") (result i32)
+            (memory.atomic.notify (i32.const 0) (i32.const 1)))
+    )"#;
+    let mut config = Config::new();
+    config.wasm_threads(true);
+    config.async_support(true);
+    config.async_atomic_wait(true);
+    config.async_atomic_wait_timer(|timeout| Box::pin(tokio::time::sleep(timeout)));
+    let Ok(engine) = Engine::new(&config) else {
+        return Ok(());
+    };
+    let module = Module::new(&engine, wat)?;
+    let shared_memory = SharedMemory::new(&engine, MemoryType::shared(1, 1))?;
+    let mut waiter = Store::new(&engine, ());
+    let mut notifier = Store::new(&engine, ());
+    let instance = Instance::new_async(&mut waiter, &module, &[shared_memory.clone().into()]);
+    let waiter_instance = instance.await?;
+    let instance = Instance::new_async(&mut notifier, &module, &[shared_memory.into()]);
+    let notifier_instance = instance.await?;
+    let wait = waiter_instance.get_typed_func::<i64, i32>(&mut waiter, "wait")?;
+    let notify = notifier_instance.get_typed_func::<(), i32>(&mut notifier, "notify")?;
+
+    // Both stores run on this single thread: the wait must suspend rather than
+    // block for the notify to ever run.
+    let (waited, notified) = tokio::join!(wait.call_async(&mut waiter, -1), async {
+        tokio::task::yield_now().await;
+        notify.call_async(&mut notifier, ()).await
+    });
+    assert_eq!(waited?, 0);
+    assert_eq!(notified?, 1);
+
+    // Timeouts still apply to suspended waits.
+    assert_eq!(wait.call_async(&mut waiter, 1_000_000).await?, 2);
+    Ok(())
+}